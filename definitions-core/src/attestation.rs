@@ -0,0 +1,533 @@
+//! Manual attestation of entity properties.
+//!
+//! A definition declares which properties can be attested, and by whom, in
+//! `_osConfig.attestationPolicies`:
+//!
+//! ```json
+//! "attestationPolicies": [{
+//!   "name": "policyApproval",
+//!   "attestationProperties": { "policyNumber": "$.policyNumber", "fullName": "$.fullName" },
+//!   "type": "MANUAL",
+//!   "attestorPlugin": "did:internal:ClaimPluginActor?entity=Official"
+//! }]
+//! ```
+//!
+//! The owner of an entity raises a [`Claim`] on a policy with [`RaiseClaimCmd`], which takes a
+//! snapshot of the policy properties. The claim is routed to the entities of the policy
+//! `attestorEntity`, named directly or by the `entity` parameter of `attestorPlugin`, or to the
+//! one of them named by the requester, and one of their owners grants or rejects it with
+//! [`AttestClaimCmd`]. A granted snapshot is recorded in
+//! the `_osAttestedData` system field of the entity, keyed by policy name.
+//!
+//! The optional `conditions` of a policy further restrict its attestors, see
+//! [`crate::conditions`]. `ATTESTOR` is bound to the attestor entity and `REQUESTER` to the
+//! claimed entity.
+use crate::auto_attestation::auto_attestation_policies;
+use crate::conditions::{Condition, Subjects, ATTESTOR, REQUESTER};
+use crate::definitions_domain::{generate_id_from_title, DomainEvent, RegistryDefinition};
+use crate::json_path::{entity_node, JsonPath};
+use crate::ownership::{definition_roles, Principal};
+use crate::registry_domain::{
+    state_machine, EntityError, EntityId, EntityRecordStatus, RegistryEntityAction,
+    RegistryResource,
+};
+use crate::system_fields::SystemFields;
+use chrono::{SecondsFormat, Utc};
+use disintegrate::{event_types, union, Decision, StateMutate, StateQuery, StreamQuery};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Map, Value};
+use std::collections::BTreeMap;
+use strum_macros::Display;
+use utoipa::ToSchema;
+use uuid::Uuid;
+
+pub type ClaimId = Uuid;
+pub const ATTESTATION_POLICIES: &str = "attestationPolicies";
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "UPPERCASE")]
+pub enum AttestationType {
+    #[default]
+    Manual,
+    Automated,
+}
+
+/// An entry of `_osConfig.attestationPolicies`
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AttestationPolicy {
+    pub name: String,
+    /// Attested values by name, each resolved from a path of the entity
+    #[serde(default)]
+    pub attestation_properties: BTreeMap<String, String>,
+    /// Paths of the entity attested as is
+    #[serde(default)]
+    pub paths: Vec<String>,
+    #[serde(rename = "type", default)]
+    pub attestation_type: AttestationType,
+    pub attestor_entity: Option<String>,
+    pub attestor_plugin: Option<String>,
+    pub conditions: Option<String>,
+    /// Template of the credential issued for an attested claim, see [`crate::credentials`]
+    pub credential_template: Option<Value>,
+}
+
+impl AttestationPolicy {
+    /// Entity type of the attestors, `attestorEntity` or the `entity` of `attestorPlugin`
+    pub fn attestor_entity(&self) -> Option<String> {
+        self.attestor_entity.clone().or_else(|| {
+            let (_, query) = self.attestor_plugin.as_deref()?.split_once('?')?;
+            query
+                .split('&')
+                .find_map(|param| param.strip_prefix("entity="))
+                .map(str::to_string)
+        })
+    }
+
+    /// Parses `conditions`, if any
+    pub fn condition(&self) -> Result<Option<Condition>, EntityError> {
+        self.conditions
+            .as_deref()
+            .map(Condition::parse)
+            .transpose()
+            .map_err(|e| {
+                EntityError::InvalidSchema(format!(
+                    "{} `{}`: {}",
+                    ATTESTATION_POLICIES, self.name, e
+                ))
+            })
+    }
+
+    /// Resolves the attested properties against an entity node, missing values are `null`
+    pub fn snapshot(&self, node: &Value) -> Result<Value, EntityError> {
+        let paths = self.paths.iter().map(|path| (path, path));
+        let mut snapshot = Map::new();
+        for (name, path) in self.attestation_properties.iter().chain(paths) {
+            let path = JsonPath::parse(path).map_err(|e| {
+                EntityError::InvalidSchema(format!(
+                    "{} `{}`: {}",
+                    ATTESTATION_POLICIES, self.name, e
+                ))
+            })?;
+            snapshot.insert(name.clone(), path.get(node).cloned().unwrap_or(Value::Null));
+        }
+        Ok(Value::Object(snapshot))
+    }
+}
+
+/// Reads `_osConfig.attestationPolicies` of a schema
+pub fn attestation_policies(schema: &Value) -> Result<Vec<AttestationPolicy>, EntityError> {
+    let Some(policies) = schema
+        .get("_osConfig")
+        .and_then(|config| config.get(ATTESTATION_POLICIES))
+    else {
+        return Ok(vec![]);
+    };
+    serde_json::from_value(policies.clone())
+        .map_err(|e| EntityError::InvalidSchema(format!("{}: {}", ATTESTATION_POLICIES, e)))
+}
+
+/// Returns true when a schema declares at least one attestation policy
+pub fn has_attestation_policies(schema: &Value) -> bool {
+    schema
+        .get("_osConfig")
+        .and_then(|config| config.get(ATTESTATION_POLICIES))
+        .and_then(Value::as_array)
+        .is_some_and(|policies| !policies.is_empty())
+}
+
+/// Checks the attestation and auto attestation policies of a schema, returns one message per
+/// malformed policy
+pub fn validate_attestation_policies(schema: &Value) -> Vec<String> {
+    let mut errors: Vec<String> = match attestation_policies(schema) {
+        Ok(policies) => policies
+            .iter()
+            .filter_map(|policy| policy.condition().err())
+            .map(|e| e.to_string())
+            .collect(),
+        Err(e) => vec![e.to_string()],
+    };
+    match auto_attestation_policies(schema) {
+        Ok(policies) => errors.extend(
+            policies
+                .iter()
+                .filter_map(|policy| policy.validate().err())
+                .map(|e| e.to_string()),
+        ),
+        Err(e) => errors.push(e.to_string()),
+    }
+    errors
+}
+
+/// Finds the attestation policy `name` of a definition
+pub fn find_policy(
+    json_schema_string: &str,
+    entity_type: &str,
+    name: &str,
+) -> Result<AttestationPolicy, EntityError> {
+    let schema: Value = serde_json::from_str(json_schema_string)
+        .map_err(|e| EntityError::InvalidSchema(e.to_string()))?;
+    attestation_policies(&schema)?
+        .into_iter()
+        .find(|policy| policy.name == name)
+        .ok_or_else(|| {
+            EntityError::AttestationPolicyNotFound(entity_type.to_string(), name.to_string())
+        })
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, Default, Display)]
+pub enum ClaimStatus {
+    #[default]
+    None,
+    Open,
+    Attested,
+    Rejected,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum AttestationAction {
+    GrantClaim,
+    RejectClaim,
+}
+
+#[derive(Default, StateQuery, Clone, Debug, Serialize, Deserialize)]
+#[state_query(DomainEvent)]
+pub struct Claim {
+    #[id]
+    claim_id: ClaimId,
+    status: ClaimStatus,
+    entity_id: EntityId,
+    policy_name: String,
+    attestor_entity: String,
+    attestor_id: Option<EntityId>,
+    property_data: String,
+}
+
+impl Claim {
+    pub fn new(claim_id: ClaimId) -> Self {
+        Self {
+            claim_id,
+            ..Default::default()
+        }
+    }
+}
+
+impl StateMutate for Claim {
+    fn mutate(&mut self, event: Self::Event) {
+        match event {
+            DomainEvent::ClaimRaised {
+                entity_id,
+                policy_name,
+                attestor_entity,
+                attestor_id,
+                property_data,
+                ..
+            } => {
+                self.entity_id = entity_id;
+                self.policy_name = policy_name;
+                self.attestor_entity = attestor_entity;
+                self.attestor_id = attestor_id;
+                self.property_data = property_data;
+                self.status = ClaimStatus::Open;
+            }
+            DomainEvent::ClaimAttested { .. } => self.status = ClaimStatus::Attested,
+            DomainEvent::ClaimRejected { .. } => self.status = ClaimStatus::Rejected,
+            _ => {}
+        }
+    }
+}
+
+/// Raises a claim on the properties of an attestation policy of an entity
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, Default)]
+pub struct RaiseClaimCmd {
+    pub claim_id: ClaimId,
+    pub entity_id: EntityId,
+    pub entity_type: String,
+    pub policy_name: String,
+    /// The attestor entity to route the claim to, an entity of the policy `attestorEntity`
+    pub attestor_id: Option<EntityId>,
+    /// The requester, who must own the entity or hold one of the definition `roles`
+    pub principal: Principal,
+}
+
+impl Decision for RaiseClaimCmd {
+    type Event = DomainEvent;
+    type StateQuery = (
+        Claim,
+        RegistryResource,
+        RegistryResource,
+        RegistryDefinition,
+    );
+    type Error = EntityError;
+
+    fn state_query(&self) -> Self::StateQuery {
+        (
+            Claim::new(self.claim_id),
+            RegistryResource::new(self.entity_id),
+            RegistryResource::new(self.attestor_id.unwrap_or_default()),
+            RegistryDefinition::new(generate_id_from_title(&self.entity_type)),
+        )
+    }
+
+    fn validation_query<ID: disintegrate::EventId>(&self) -> Option<StreamQuery<ID, Self::Event>> {
+        let (claim, resource, attestor, def_state) = self.state_query();
+        Some(union!(
+            &claim,
+            &resource,
+            &attestor,
+            def_state.exclude_events(event_types!(DomainEvent, [DefUpdated]))
+        ))
+    }
+
+    fn process(
+        &self,
+        (claim, resource, attestor, def_state): &Self::StateQuery,
+    ) -> Result<Vec<Self::Event>, Self::Error> {
+        if claim.status != ClaimStatus::None {
+            return Err(EntityError::ClaimAlreadyExists(self.claim_id));
+        }
+        if resource.status == EntityRecordStatus::None || resource.entity_type != self.entity_type {
+            return Err(EntityError::EntityNotFound(self.entity_id));
+        }
+        if !state_machine(&resource.status, RegistryEntityAction::Modify) {
+            return Err(EntityError::ModifyNotAllowed(resource.status.clone()));
+        }
+        self.principal.authorize(
+            self.entity_id,
+            &resource.owners,
+            &definition_roles(&def_state.json_schema_string),
+        )?;
+
+        let policy = find_policy(
+            &def_state.json_schema_string,
+            &self.entity_type,
+            &self.policy_name,
+        )?;
+        if policy.attestation_type != AttestationType::Manual {
+            return Err(EntityError::ClaimNotAllowed(
+                policy.name,
+                "it is not a MANUAL policy".to_string(),
+            ));
+        }
+        let attestor_entity = policy.attestor_entity().ok_or_else(|| {
+            EntityError::ClaimNotAllowed(policy.name.clone(), "it has no attestor".to_string())
+        })?;
+        if let Some(attestor_id) = self.attestor_id {
+            if !state_machine(&attestor.status, RegistryEntityAction::Modify)
+                || attestor.entity_type != attestor_entity
+            {
+                return Err(EntityError::NotAnAttestor(attestor_id, attestor_entity));
+            }
+        }
+        let document: Value = serde_json::from_str(&resource.entity_body)
+            .map_err(|e| EntityError::InvalidJson(e.to_string()))?;
+        let snapshot = policy.snapshot(entity_node(&document, &self.entity_type))?;
+
+        let actor = self.principal.actor();
+        Ok(vec![DomainEvent::ClaimRaised {
+            claim_id: self.claim_id,
+            entity_id: self.entity_id,
+            entity_type: self.entity_type.clone(),
+            policy_name: policy.name,
+            attestor_entity,
+            attestor_id: self.attestor_id,
+            property_data: snapshot.to_string(),
+            raised_at: Utc::now(),
+            raised_by: actor.id.clone(),
+            on_behalf_of: actor.on_behalf_of.clone(),
+        }])
+    }
+}
+
+/// Grants or rejects an open claim.
+///
+/// The caller must own `attestor_id`, an entity of the `attestorEntity` of the claimed policy,
+/// and the one the claim was routed to if any.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AttestClaimCmd {
+    pub claim_id: ClaimId,
+    /// The entity the claim was raised on
+    pub entity_id: EntityId,
+    pub entity_type: String,
+    /// The attestor record of the caller
+    pub attestor_id: EntityId,
+    pub action: AttestationAction,
+    pub notes: Option<String>,
+    pub principal: Principal,
+}
+
+impl Decision for AttestClaimCmd {
+    type Event = DomainEvent;
+    type StateQuery = (
+        Claim,
+        RegistryResource,
+        RegistryResource,
+        RegistryDefinition,
+    );
+    type Error = EntityError;
+
+    fn state_query(&self) -> Self::StateQuery {
+        (
+            Claim::new(self.claim_id),
+            RegistryResource::new(self.entity_id),
+            RegistryResource::new(self.attestor_id),
+            RegistryDefinition::new(generate_id_from_title(&self.entity_type)),
+        )
+    }
+
+    fn validation_query<ID: disintegrate::EventId>(&self) -> Option<StreamQuery<ID, Self::Event>> {
+        let (claim, resource, attestor, def_state) = self.state_query();
+        Some(union!(
+            &claim,
+            &resource,
+            &attestor,
+            def_state.exclude_events(event_types!(DomainEvent, [DefUpdated]))
+        ))
+    }
+
+    fn process(
+        &self,
+        (claim, resource, attestor, def_state): &Self::StateQuery,
+    ) -> Result<Vec<Self::Event>, Self::Error> {
+        if claim.status == ClaimStatus::None || claim.entity_id != self.entity_id {
+            return Err(EntityError::ClaimNotFound(self.claim_id));
+        }
+        if claim.status != ClaimStatus::Open {
+            return Err(EntityError::ClaimAlreadyClosed(
+                self.claim_id,
+                claim.status.clone(),
+            ));
+        }
+        if !state_machine(&attestor.status, RegistryEntityAction::Modify)
+            || attestor.entity_type != claim.attestor_entity
+            || claim
+                .attestor_id
+                .is_some_and(|routed| routed != self.attestor_id)
+        {
+            return Err(EntityError::NotAnAttestor(
+                self.attestor_id,
+                claim.attestor_entity.clone(),
+            ));
+        }
+        if !self.principal.owns(&attestor.owners) {
+            return Err(EntityError::NotAuthorized(
+                self.principal.subject.clone(),
+                self.attestor_id,
+            ));
+        }
+        let policy = find_policy(
+            &def_state.json_schema_string,
+            &self.entity_type,
+            &claim.policy_name,
+        )?;
+        if let Some(condition) = policy.condition()? {
+            let attestor_document: Value = serde_json::from_str(&attestor.entity_body)
+                .map_err(|e| EntityError::InvalidJson(e.to_string()))?;
+            let requester_document: Value = serde_json::from_str(&resource.entity_body)
+                .map_err(|e| EntityError::InvalidJson(e.to_string()))?;
+            let subjects = Subjects::new()
+                .with(
+                    ATTESTOR,
+                    entity_node(&attestor_document, &attestor.entity_type),
+                )
+                .with(
+                    REQUESTER,
+                    entity_node(&requester_document, &self.entity_type),
+                );
+            let met = condition
+                .evaluate(&subjects)
+                .map_err(|e| EntityError::InvalidSchema(e.to_string()))?;
+            if !met {
+                return Err(EntityError::AttestationConditionNotMet(
+                    policy.name,
+                    self.attestor_id,
+                ));
+            }
+        }
+
+        let actor = self.principal.actor();
+        let now = Utc::now();
+        match self.action {
+            AttestationAction::RejectClaim => Ok(vec![DomainEvent::ClaimRejected {
+                claim_id: self.claim_id,
+                entity_id: self.entity_id,
+                attestor_id: self.attestor_id,
+                notes: self.notes.clone(),
+                rejected_at: now,
+                rejected_by: actor.id.clone(),
+                on_behalf_of: actor.on_behalf_of.clone(),
+            }]),
+            AttestationAction::GrantClaim => {
+                if !state_machine(&resource.status, RegistryEntityAction::Modify) {
+                    return Err(EntityError::ModifyNotAllowed(resource.status.clone()));
+                }
+                let data: Value = serde_json::from_str(&claim.property_data)
+                    .map_err(|e| EntityError::InvalidJson(e.to_string()))?;
+                let attestation = json!({
+                    "claimId": self.claim_id,
+                    "attestorId": self.attestor_id,
+                    "attestedBy": self.principal.subject,
+                    "attestedAt": now.to_rfc3339_opts(SecondsFormat::Millis, true),
+                    "data": data,
+                });
+                let entity_body =
+                    SystemFields::from_schema(&def_state.json_schema_string, &self.entity_type)?
+                        .attested(&resource.entity_body, &claim.policy_name, attestation)?;
+                Ok(vec![
+                    DomainEvent::ClaimAttested {
+                        claim_id: self.claim_id,
+                        entity_id: self.entity_id,
+                        attestor_id: self.attestor_id,
+                        notes: self.notes.clone(),
+                        attested_at: now,
+                        attested_by: actor.id.clone(),
+                        on_behalf_of: actor.on_behalf_of.clone(),
+                    },
+                    DomainEvent::EntityAttested {
+                        id: self.entity_id,
+                        entity_type: self.entity_type.clone(),
+                        entity_body,
+                        claim_id: self.claim_id,
+                        policy_name: claim.policy_name.clone(),
+                        attested_at: now,
+                        attested_by: actor.id.clone(),
+                        on_behalf_of: actor.on_behalf_of.clone(),
+                    },
+                ])
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_policies_are_read_from_the_schema() {
+        let schema = json!({
+            "_osConfig": {
+                "attestationPolicies": [{
+                    "name": "policyApproval",
+                    "attestationProperties": { "number": "$.policyNumber" },
+                    "type": "MANUAL",
+                    "attestorPlugin": "did:internal:ClaimPluginActor?entity=Official",
+                    "credentialTemplate": {}
+                }]
+            }
+        });
+
+        let policies = attestation_policies(&schema).unwrap();
+
+        assert!(has_attestation_policies(&schema));
+        assert_eq!(policies.len(), 1);
+        assert_eq!(policies[0].attestor_entity(), Some("Official".to_string()));
+        assert_eq!(
+            policies[0]
+                .snapshot(&json!({"policyNumber": "P-1", "holder": "Asha"}))
+                .unwrap(),
+            json!({"number": "P-1"})
+        );
+    }
+}
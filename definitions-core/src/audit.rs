@@ -0,0 +1,311 @@
+//! Actors of the commands and the audit trail of the events.
+//!
+//! Every command carries the [`Actor`] that issued it. Its id is recorded in the `*_by` field of
+//! the resulting events and, when the actor impersonates or was delegated by another subject,
+//! that subject is recorded in `on_behalf_of`. [`AuditEntry::of`] reads both back from any
+//! [`DomainEvent`] for the history of a definition or an entity.
+use crate::definitions_domain::DomainEvent;
+use chrono::{DateTime, Utc};
+use disintegrate::Event;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use uuid::Uuid;
+
+/// Who issued a command
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Actor {
+    /// The subject, client or impersonator performing the command
+    pub id: String,
+    /// The subject the command was performed for, when different from the actor
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub on_behalf_of: Option<String>,
+}
+
+impl Actor {
+    /// An actor acting for itself, such as a background job
+    pub fn new(id: impl Into<String>) -> Self {
+        Actor {
+            id: id.into(),
+            on_behalf_of: None,
+        }
+    }
+
+    /// An actor acting for `subject`
+    pub fn on_behalf_of(id: impl Into<String>, subject: impl Into<String>) -> Self {
+        Actor {
+            id: id.into(),
+            on_behalf_of: Some(subject.into()),
+        }
+    }
+}
+
+impl From<&str> for Actor {
+    fn from(id: &str) -> Self {
+        Actor::new(id)
+    }
+}
+
+impl From<String> for Actor {
+    fn from(id: String) -> Self {
+        Actor::new(id)
+    }
+}
+
+impl fmt::Display for Actor {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.on_behalf_of {
+            Some(subject) => write!(f, "{} on behalf of {}", self.id, subject),
+            None => f.write_str(&self.id),
+        }
+    }
+}
+
+/// Who did what and when, as recorded by an event
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AuditEntry {
+    /// The variant of the event, e.g. `EntityUpdated`
+    pub event_type: String,
+    /// The definition of the definition events, the entity of every other event
+    pub subject_id: Uuid,
+    pub actor: Actor,
+    pub occurred_at: DateTime<Utc>,
+}
+
+impl AuditEntry {
+    pub fn of(event: &DomainEvent) -> AuditEntry {
+        use DomainEvent::*;
+        let (subject_id, by, on_behalf_of, at) = match event {
+            DefCreated {
+                id,
+                created_by: by,
+                on_behalf_of,
+                created_at: at,
+                ..
+            }
+            | DefUpdated {
+                id,
+                updated_by: by,
+                on_behalf_of,
+                created_at: at,
+                ..
+            }
+            | DefDeleted {
+                id,
+                deleted_by: by,
+                on_behalf_of,
+                deleted_at: at,
+            }
+            | DefValidated {
+                id,
+                validated_by: by,
+                on_behalf_of,
+                validated_at: at,
+                ..
+            }
+            | DefValidatedFailed {
+                id,
+                validated_by: by,
+                on_behalf_of,
+                validated_at: at,
+                ..
+            }
+            | DefActivated {
+                id,
+                activated_by: by,
+                on_behalf_of,
+                activated_at: at,
+                ..
+            }
+            | DefDeactivated {
+                id,
+                deactivated_by: by,
+                on_behalf_of,
+                deactivated_at: at,
+            }
+            | EntityCreated {
+                id,
+                created_by: by,
+                on_behalf_of,
+                created_at: at,
+                ..
+            }
+            | EntityInvited {
+                id,
+                invited_by: by,
+                on_behalf_of,
+                invited_at: at,
+                ..
+            }
+            | EntityRegistered {
+                id,
+                registered_by: by,
+                on_behalf_of,
+                registered_at: at,
+                ..
+            }
+            | EntityUpdated {
+                id,
+                updated_by: by,
+                on_behalf_of,
+                updated_at: at,
+                ..
+            }
+            | EntityPropertyUpdated {
+                id,
+                created_by: by,
+                on_behalf_of,
+                updated_at: at,
+                ..
+            }
+            | EntityPropertyAdded {
+                id,
+                created_by: by,
+                on_behalf_of,
+                added_at: at,
+                ..
+            }
+            | EntityDeleted {
+                id,
+                deleted_by: by,
+                on_behalf_of,
+                deleted_at: at,
+            }
+            | EntityAttested {
+                id,
+                attested_by: by,
+                on_behalf_of,
+                attested_at: at,
+                ..
+            }
+            | EntityAutoAttested {
+                id,
+                attested_by: by,
+                on_behalf_of,
+                attested_at: at,
+                ..
+            }
+            | CredentialIssued {
+                id,
+                issued_by: by,
+                on_behalf_of,
+                issued_at: at,
+                ..
+            }
+            | CredentialRevoked {
+                id,
+                revoked_by: by,
+                on_behalf_of,
+                revoked_at: at,
+                ..
+            }
+            | CredentialSuspended {
+                id,
+                suspended_by: by,
+                on_behalf_of,
+                suspended_at: at,
+                ..
+            }
+            | CredentialReinstated {
+                id,
+                reinstated_by: by,
+                on_behalf_of,
+                reinstated_at: at,
+                ..
+            }
+            | ClaimRaised {
+                entity_id: id,
+                raised_by: by,
+                on_behalf_of,
+                raised_at: at,
+                ..
+            }
+            | ClaimAttested {
+                entity_id: id,
+                attested_by: by,
+                on_behalf_of,
+                attested_at: at,
+                ..
+            }
+            | ClaimRejected {
+                entity_id: id,
+                rejected_by: by,
+                on_behalf_of,
+                rejected_at: at,
+                ..
+            }
+            | ConsentRequested {
+                entity_id: id,
+                requested_by: by,
+                on_behalf_of,
+                requested_at: at,
+                ..
+            }
+            | ConsentGranted {
+                entity_id: id,
+                granted_by: by,
+                on_behalf_of,
+                granted_at: at,
+                ..
+            }
+            | ConsentDenied {
+                entity_id: id,
+                denied_by: by,
+                on_behalf_of,
+                denied_at: at,
+                ..
+            }
+            | ConsentRevoked {
+                entity_id: id,
+                revoked_by: by,
+                on_behalf_of,
+                revoked_at: at,
+                ..
+            }
+            | ConsentedDataAccessed {
+                entity_id: id,
+                accessed_by: by,
+                on_behalf_of,
+                accessed_at: at,
+                ..
+            } => (id, by, on_behalf_of, at),
+        };
+        AuditEntry {
+            event_type: event.name().to_string(),
+            subject_id: *subject_id,
+            actor: Actor {
+                id: by.clone(),
+                on_behalf_of: on_behalf_of.clone(),
+            },
+            occurred_at: *at,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_audit_entry_records_actor_and_on_behalf_of() {
+        let entity_id = Uuid::from_u128(7);
+        let event = DomainEvent::ConsentGranted {
+            consent_id: Uuid::from_u128(1),
+            entity_id,
+            expires_at: Utc::now(),
+            notes: None,
+            granted_at: Utc::now(),
+            granted_by: "support-agent".to_string(),
+            on_behalf_of: Some("owner-1".to_string()),
+        };
+
+        let entry = AuditEntry::of(&event);
+
+        assert_eq!(entry.event_type, "ConsentGranted");
+        assert_eq!(entry.subject_id, entity_id);
+        assert_eq!(entry.actor, Actor::on_behalf_of("support-agent", "owner-1"));
+        assert_eq!(
+            entry.actor.to_string(),
+            "support-agent on behalf of owner-1"
+        );
+    }
+}
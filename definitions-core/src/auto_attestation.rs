@@ -0,0 +1,206 @@
+//! Automatic attestation of entity properties by verifier plugins.
+//!
+//! A definition lists the properties verified without a human attestor in
+//! `_osConfig.autoAttestationPolicies`:
+//!
+//! ```json
+//! "autoAttestationPolicies": [{
+//!   "parentProperty": "identityDetails",
+//!   "property": "identityHolder",
+//!   "nodeRef": "$.identityDetails.identityHolder",
+//!   "valuePath": "$.identityDetails.identityHolder.value",
+//!   "typePath": "$.identityDetails.identityHolder.type"
+//! }]
+//! ```
+//!
+//! [`AutoAttestEntityCmd`] runs after an entity is created or updated. For every policy whose
+//! value and type are set, the [`VerifierPlugin`](crate::verifiers::VerifierPlugin) supporting the
+//! type verifies the value, and the outcome is recorded under the `property` name in the
+//! `_osAttestedData` system field with an `EntityAutoAttested` event. Values without a plugin
+//! for their type are left unattested.
+use crate::audit::Actor;
+use crate::definitions_domain::{generate_id_from_title, DomainEvent, RegistryDefinition};
+use crate::encryption::{EncryptedValue, FieldCipher};
+use crate::json_path::{entity_node, JsonPath};
+use crate::registry_domain::{
+    state_machine, EntityError, EntityId, RegistryEntityAction, RegistryResource,
+};
+use crate::system_fields::SystemFields;
+use crate::verifiers::{Verification, Verifiers};
+use chrono::{SecondsFormat, Utc};
+use disintegrate::{event_types, union, Decision, StreamQuery};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+
+pub const AUTO_ATTESTATION_POLICIES: &str = "autoAttestationPolicies";
+
+/// An entry of `_osConfig.autoAttestationPolicies`
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AutoAttestationPolicy {
+    pub parent_property: Option<String>,
+    /// Name the attestation is recorded under
+    pub property: String,
+    /// Path of the attested node
+    pub node_ref: String,
+    /// Path of the verified value
+    pub value_path: String,
+    /// Path of the identifier type, which selects the verifier plugin
+    pub type_path: String,
+}
+
+impl AutoAttestationPolicy {
+    fn path(&self, path: &str) -> Result<JsonPath, EntityError> {
+        JsonPath::parse(path).map_err(|e| {
+            EntityError::InvalidSchema(format!(
+                "{} `{}`: {}",
+                AUTO_ATTESTATION_POLICIES, self.property, e
+            ))
+        })
+    }
+
+    /// Checks that the paths of the policy are supported
+    pub fn validate(&self) -> Result<(), EntityError> {
+        for path in [&self.node_ref, &self.value_path, &self.type_path] {
+            self.path(path)?;
+        }
+        Ok(())
+    }
+}
+
+/// Reads `_osConfig.autoAttestationPolicies` of a schema
+pub fn auto_attestation_policies(
+    schema: &Value,
+) -> Result<Vec<AutoAttestationPolicy>, EntityError> {
+    let Some(policies) = schema
+        .get("_osConfig")
+        .and_then(|config| config.get(AUTO_ATTESTATION_POLICIES))
+    else {
+        return Ok(vec![]);
+    };
+    serde_json::from_value(policies.clone())
+        .map_err(|e| EntityError::InvalidSchema(format!("{}: {}", AUTO_ATTESTATION_POLICIES, e)))
+}
+
+/// Returns true when a schema declares at least one auto attestation policy
+pub fn has_auto_attestation_policies(schema: &Value) -> bool {
+    schema
+        .get("_osConfig")
+        .and_then(|config| config.get(AUTO_ATTESTATION_POLICIES))
+        .and_then(Value::as_array)
+        .is_some_and(|policies| !policies.is_empty())
+}
+
+/// Runs the auto attestation policies of the definition against the current entity body
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AutoAttestEntityCmd {
+    pub id: EntityId,
+    pub entity_type: String,
+    /// Decrypts values declared in `privateFields`
+    pub cipher: Option<FieldCipher>,
+    pub verifiers: Verifiers,
+    pub attested_by: Actor,
+}
+
+impl AutoAttestEntityCmd {
+    /// Resolves a string value of the entity node, decrypting it when needed
+    fn resolve(&self, node: &Value, path: &JsonPath) -> Result<Option<String>, EntityError> {
+        let value = match path.get(node) {
+            Some(value) => match EncryptedValue::from_value(value) {
+                Some(envelope) => self
+                    .cipher
+                    .as_ref()
+                    .ok_or_else(|| EntityError::EncryptionUnavailable(self.entity_type.clone()))?
+                    .decrypt(&envelope)
+                    .map_err(|e| EntityError::Encryption(e.to_string()))?,
+                None => value.clone(),
+            },
+            None => return Ok(None),
+        };
+        Ok(match value {
+            Value::String(value) if !value.trim().is_empty() => Some(value),
+            Value::Number(value) => Some(value.to_string()),
+            _ => None,
+        })
+    }
+}
+
+impl Decision for AutoAttestEntityCmd {
+    type Event = DomainEvent;
+    type StateQuery = (RegistryResource, RegistryDefinition);
+    type Error = EntityError;
+
+    fn state_query(&self) -> Self::StateQuery {
+        (
+            RegistryResource::new(self.id),
+            RegistryDefinition::new(generate_id_from_title(&self.entity_type)),
+        )
+    }
+
+    fn validation_query<ID: disintegrate::EventId>(&self) -> Option<StreamQuery<ID, Self::Event>> {
+        let (resource, def_state) = self.state_query();
+        Some(union!(
+            &resource,
+            def_state.exclude_events(event_types!(DomainEvent, [DefUpdated]))
+        ))
+    }
+
+    fn process(
+        &self,
+        (resource, def_state): &Self::StateQuery,
+    ) -> Result<Vec<Self::Event>, Self::Error> {
+        if !state_machine(&resource.status, RegistryEntityAction::Modify) {
+            return Err(EntityError::ModifyNotAllowed(resource.status.clone()));
+        }
+        let schema: Value = serde_json::from_str(&def_state.json_schema_string)
+            .map_err(|e| EntityError::InvalidSchema(e.to_string()))?;
+        let policies = auto_attestation_policies(&schema)?;
+        if policies.is_empty() {
+            return Ok(vec![]);
+        }
+        let system_fields =
+            SystemFields::from_schema(&def_state.json_schema_string, &self.entity_type)?;
+        let document: Value = serde_json::from_str(&resource.entity_body)
+            .map_err(|e| EntityError::InvalidJson(e.to_string()))?;
+        let node = entity_node(&document, &self.entity_type);
+
+        let now = Utc::now();
+        let mut entity_body = resource.entity_body.clone();
+        let mut events = vec![];
+        for policy in &policies {
+            let value = self.resolve(node, &policy.path(&policy.value_path)?)?;
+            let value_type = self.resolve(node, &policy.path(&policy.type_path)?)?;
+            let (Some(value), Some(value_type)) = (value, value_type) else {
+                continue;
+            };
+            let Some(verifier) = self.verifiers.find(&value_type) else {
+                continue;
+            };
+            let verification = verifier.verify(&value);
+            let mut attestation = json!({
+                "nodeRef": policy.node_ref,
+                "type": value_type,
+                "verifier": verifier.name(),
+                "verified": verification.is_verified(),
+                "attestedBy": self.attested_by.id,
+                "attestedAt": now.to_rfc3339_opts(SecondsFormat::Millis, true),
+            });
+            if let Verification::Failed(reason) = &verification {
+                attestation["reason"] = Value::String(reason.clone());
+            }
+            entity_body = system_fields.attested(&entity_body, &policy.property, attestation)?;
+            events.push(DomainEvent::EntityAutoAttested {
+                id: self.id,
+                entity_type: self.entity_type.clone(),
+                entity_body: entity_body.clone(),
+                property: policy.property.clone(),
+                verifier: verifier.name().to_string(),
+                verified: verification.is_verified(),
+                attested_at: now,
+                attested_by: self.attested_by.id.clone(),
+                on_behalf_of: self.attested_by.on_behalf_of.clone(),
+            });
+        }
+        Ok(events)
+    }
+}
@@ -0,0 +1,190 @@
+//! Printable certificates of entities, rendered from `_osConfig.certificateTemplates`.
+//!
+//! A definition names its certificate templates by the URI they are stored under:
+//!
+//! ```json
+//! "certificateTemplates": {
+//!   "first": "minio://Insurance/1-68619c95/email/documents/Insurancetemplate.html"
+//! }
+//! ```
+//!
+//! A template is an HTML or SVG document with Handlebars placeholders, rendered with the entity
+//! data, e.g. `{{policyNumber}}` or `{{fullName.firstName}}`, and its `osid`. Values are escaped,
+//! the QR code is available both as inline markup with `{{{qrCode}}}` and as a `data:` URI for an
+//! `<img>` with `{{qrCodeDataUri}}`. The QR code carries the signed credential of the entity when
+//! it fits, a URL verifying the credential otherwise, see [`QrContent`].
+use crate::registry_domain::{EntityError, EntityId};
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+use handlebars::Handlebars;
+use qrcode::render::svg;
+use qrcode::{EcLevel, QrCode};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use strum_macros::Display;
+use utoipa::ToSchema;
+
+pub const CERTIFICATE_TEMPLATES: &str = "certificateTemplates";
+/// Bytes a QR code holds at the medium error correction level
+pub const QR_CAPACITY: usize = 2331;
+const QR_MIN_SIZE: u32 = 200;
+
+/// What the QR code of a certificate carries
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Display, ToSchema)]
+#[serde(rename_all = "lowercase")]
+#[strum(serialize_all = "lowercase")]
+pub enum QrContent {
+    /// The signed credential, a VC-JWT or the JSON-LD document
+    Credential,
+    /// The URL verifying the credential
+    Url,
+}
+
+/// The media type of a rendered certificate, after the extension of its template
+pub fn certificate_media_type(template_uri: &str) -> &'static str {
+    if template_uri.to_lowercase().ends_with(".svg") {
+        "image/svg+xml"
+    } else {
+        "text/html; charset=utf-8"
+    }
+}
+
+/// The URI of the certificate template `name` of a definition
+pub fn certificate_template_uri(
+    json_schema_string: &str,
+    entity_type: &str,
+    name: &str,
+) -> Result<String, EntityError> {
+    let schema: Value = serde_json::from_str(json_schema_string)
+        .map_err(|e| EntityError::InvalidSchema(e.to_string()))?;
+    schema
+        .get("_osConfig")
+        .and_then(|config| config.get(CERTIFICATE_TEMPLATES))
+        .and_then(|templates| templates.get(name))
+        .and_then(Value::as_str)
+        .map(str::to_string)
+        .ok_or_else(|| {
+            EntityError::CertificateTemplateNotFound(entity_type.to_string(), name.to_string())
+        })
+}
+
+/// The content of the QR code: the credential when requested or when it fits, else the URL
+pub fn qr_payload<'a>(
+    credential: &'a str,
+    verification_url: &'a str,
+    content: Option<QrContent>,
+) -> Result<&'a str, EntityError> {
+    match content {
+        Some(QrContent::Url) => Ok(verification_url),
+        Some(QrContent::Credential) if credential.len() > QR_CAPACITY => {
+            Err(EntityError::CertificateNotAvailable(format!(
+                "the credential is {} bytes long, a QR code holds {}",
+                credential.len(),
+                QR_CAPACITY
+            )))
+        }
+        Some(QrContent::Credential) => Ok(credential),
+        None if credential.len() > QR_CAPACITY => Ok(verification_url),
+        None => Ok(credential),
+    }
+}
+
+/// The QR code of `payload` as an SVG document
+pub fn qr_code_svg(payload: &str) -> Result<String, EntityError> {
+    let code = QrCode::with_error_correction_level(payload, EcLevel::M)
+        .map_err(|e| EntityError::CertificateNotAvailable(e.to_string()))?;
+    Ok(code
+        .render::<svg::Color>()
+        .min_dimensions(QR_MIN_SIZE, QR_MIN_SIZE)
+        .build())
+}
+
+/// Renders a certificate template with the entity node and the QR code of `qr_payload`
+pub fn render_certificate(
+    template: &str,
+    entity: &Value,
+    entity_id: EntityId,
+    qr_payload: &str,
+) -> Result<String, EntityError> {
+    let qr_svg = qr_code_svg(qr_payload)?;
+    let mut data = match entity {
+        Value::Object(fields) => fields.clone(),
+        _ => Default::default(),
+    };
+    data.insert("osid".to_string(), json!(entity_id));
+    // The XML declaration is not allowed inside an HTML or SVG document
+    let inline = qr_svg
+        .find("<svg")
+        .map_or(qr_svg.as_str(), |start| &qr_svg[start..]);
+    data.insert("qrCode".to_string(), json!(inline));
+    data.insert(
+        "qrCodeDataUri".to_string(),
+        json!(format!(
+            "data:image/svg+xml;base64,{}",
+            BASE64.encode(&qr_svg)
+        )),
+    );
+    Handlebars::new()
+        .render_template(template, &Value::Object(data))
+        .map_err(|e| EntityError::InvalidCertificateTemplate(e.to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use uuid::Uuid;
+
+    #[test]
+    fn test_render_escapes_data_and_inlines_the_qr_code() {
+        let template = r#"<html><h1>{{fullName.firstName}}</h1><p>{{policyNumber}} {{osid}}</p>{{{qrCode}}}<img src="{{qrCodeDataUri}}"></html>"#;
+        let id = Uuid::now_v7();
+
+        let rendered = render_certificate(
+            template,
+            &json!({"fullName": {"firstName": "Asha <b>"}, "policyNumber": "P-1"}),
+            id,
+            "https://registry.example.com/api/v1/verify/1",
+        )
+        .unwrap();
+
+        assert!(rendered.contains("<h1>Asha &lt;b&gt;</h1>"), "{rendered}");
+        assert!(rendered.contains(&format!("<p>P-1 {id}</p>")));
+        assert!(rendered.contains("<svg"));
+        assert!(!rendered.contains("<?xml"));
+        assert!(rendered.contains(r#"<img src="data:image/svg+xml;base64,"#));
+    }
+
+    #[test]
+    fn test_long_credentials_fall_back_to_the_url() {
+        let long = "x".repeat(QR_CAPACITY + 1);
+        assert_eq!(qr_payload("eyJ", "https://v", None), Ok("eyJ"));
+        assert_eq!(qr_payload(&long, "https://v", None), Ok("https://v"));
+        assert_eq!(
+            qr_payload("eyJ", "https://v", Some(QrContent::Url)),
+            Ok("https://v")
+        );
+        assert!(qr_payload(&long, "https://v", Some(QrContent::Credential)).is_err());
+    }
+
+    #[test]
+    fn test_template_uri_of_the_definition() {
+        let schema =
+            json!({"_osConfig": {"certificateTemplates": {"first": "minio://Insurance/t.svg"}}})
+                .to_string();
+        assert_eq!(
+            certificate_template_uri(&schema, "Insurance", "first"),
+            Ok("minio://Insurance/t.svg".to_string())
+        );
+        assert_eq!(
+            certificate_template_uri(&schema, "Insurance", "second"),
+            Err(EntityError::CertificateTemplateNotFound(
+                "Insurance".to_string(),
+                "second".to_string()
+            ))
+        );
+        assert_eq!(
+            certificate_media_type("minio://Insurance/t.svg"),
+            "image/svg+xml"
+        );
+    }
+}
@@ -0,0 +1,690 @@
+//! Condition expressions, as used by the `conditions` of attestation policies.
+//!
+//! A condition combines JSONPath lookups against named subjects with a few operators:
+//!
+//! ```text
+//! (ATTESTOR#$.experience[*].instituteOSID#.contains(REQUESTER#$.instituteOSID#)
+//!   && ATTESTOR#$.experience[?(@.instituteOSID == REQUESTER#$.instituteOSID#)]['_osState']#.contains('PUBLISHED'))
+//! ```
+//!
+//! * `SUBJECT#<path>#` looks `<path>` up in the document bound to `SUBJECT`, see [`Subjects`].
+//!   Paths start at `$` and support `.key`, `['key', ...]`, `[index]`, the wildcards `*` and
+//!   `[*]`, and filters `[?(<condition>)]` in which `@` is the filtered element. A path with
+//!   wildcards or filters yields the array of its matches, any other path the value or `null`.
+//! * Literals: `'string'`, `"string"`, numbers, `true`, `false` and `null`.
+//! * `a.contains(b)`: substring for strings, membership for arrays. When `b` is an array any of
+//!   its elements will do.
+//! * `a.equalsIgnoreCase(b)`: case insensitive string equality, any element of arrays.
+//! * `==`, `!=`, `<`, `<=`, `>`, `>=`, `!`, `&&`, `||` and parentheses.
+//!
+//! Values used as booleans are true unless `false`, `null`, `0` or empty.
+use serde_json::{Number, Value};
+use std::collections::HashMap;
+use thiserror::Error;
+
+/// Subject bound to the attestor entity
+pub const ATTESTOR: &str = "ATTESTOR";
+/// Subject bound to the entity the claim is raised on
+pub const REQUESTER: &str = "REQUESTER";
+
+#[derive(Debug, Error, Clone, PartialEq, Eq)]
+pub enum ConditionError {
+    #[error("Syntax error at position {0}: {1}")]
+    Syntax(usize, String),
+    #[error("Unknown subject `{0}`")]
+    UnknownSubject(String),
+}
+
+/// The documents looked up by a condition, by subject name
+#[derive(Debug, Clone, Default)]
+pub struct Subjects<'a>(HashMap<&'a str, &'a Value>);
+
+impl<'a> Subjects<'a> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with(mut self, name: &'a str, document: &'a Value) -> Self {
+        self.0.insert(name, document);
+        self
+    }
+}
+
+/// A parsed condition
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Condition {
+    source: String,
+    expr: Expr,
+}
+
+impl Condition {
+    pub fn parse(source: &str) -> Result<Self, ConditionError> {
+        let mut parser = Parser {
+            chars: source.chars().collect(),
+            pos: 0,
+            filter_depth: 0,
+        };
+        let expr = parser.parse_or()?;
+        parser.skip_whitespace();
+        if parser.pos < parser.chars.len() {
+            return Err(parser.error("unexpected input"));
+        }
+        Ok(Self {
+            source: source.to_string(),
+            expr,
+        })
+    }
+
+    pub fn source(&self) -> &str {
+        &self.source
+    }
+
+    pub fn evaluate(&self, subjects: &Subjects) -> Result<bool, ConditionError> {
+        Ok(is_truthy(&evaluate(&self.expr, subjects, None)?))
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Expr {
+    Or(Box<Expr>, Box<Expr>),
+    And(Box<Expr>, Box<Expr>),
+    Not(Box<Expr>),
+    Compare(Box<Expr>, Comparison, Box<Expr>),
+    Call(Box<Expr>, Method, Box<Expr>),
+    Lookup(Subject, Vec<Segment>),
+    Literal(Value),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Comparison {
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Method {
+    Contains,
+    EqualsIgnoreCase,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Subject {
+    Named(String),
+    /// `@`, the element tested by a filter
+    Current,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Segment {
+    Keys(Vec<String>),
+    Index(usize),
+    Wildcard,
+    Filter(Box<Expr>),
+}
+
+struct Parser {
+    chars: Vec<char>,
+    pos: usize,
+    filter_depth: usize,
+}
+
+impl Parser {
+    fn error(&self, message: &str) -> ConditionError {
+        ConditionError::Syntax(self.pos, message.to_string())
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.pos).copied()
+    }
+
+    fn peek_at(&self, offset: usize) -> Option<char> {
+        self.chars.get(self.pos + offset).copied()
+    }
+
+    fn skip_whitespace(&mut self) {
+        while self.peek().is_some_and(char::is_whitespace) {
+            self.pos += 1;
+        }
+    }
+
+    /// Consumes `token` when the input continues with it
+    fn eat(&mut self, token: &str) -> bool {
+        let matches = token
+            .chars()
+            .enumerate()
+            .all(|(offset, c)| self.peek_at(offset) == Some(c));
+        if matches {
+            self.pos += token.chars().count();
+        }
+        matches
+    }
+
+    fn expect(&mut self, token: &str) -> Result<(), ConditionError> {
+        self.skip_whitespace();
+        if self.eat(token) {
+            Ok(())
+        } else {
+            Err(self.error(&format!("expected `{}`", token)))
+        }
+    }
+
+    fn parse_or(&mut self) -> Result<Expr, ConditionError> {
+        let mut left = self.parse_and()?;
+        loop {
+            self.skip_whitespace();
+            if !self.eat("||") {
+                return Ok(left);
+            }
+            left = Expr::Or(Box::new(left), Box::new(self.parse_and()?));
+        }
+    }
+
+    fn parse_and(&mut self) -> Result<Expr, ConditionError> {
+        let mut left = self.parse_unary()?;
+        loop {
+            self.skip_whitespace();
+            if !self.eat("&&") {
+                return Ok(left);
+            }
+            left = Expr::And(Box::new(left), Box::new(self.parse_unary()?));
+        }
+    }
+
+    fn parse_unary(&mut self) -> Result<Expr, ConditionError> {
+        self.skip_whitespace();
+        if self.peek() == Some('!') && self.peek_at(1) != Some('=') {
+            self.pos += 1;
+            return Ok(Expr::Not(Box::new(self.parse_unary()?)));
+        }
+        self.parse_comparison()
+    }
+
+    fn parse_comparison(&mut self) -> Result<Expr, ConditionError> {
+        let left = self.parse_postfix()?;
+        self.skip_whitespace();
+        let comparison = [
+            ("==", Comparison::Eq),
+            ("!=", Comparison::Ne),
+            ("<=", Comparison::Le),
+            (">=", Comparison::Ge),
+            ("<", Comparison::Lt),
+            (">", Comparison::Gt),
+        ]
+        .into_iter()
+        .find(|(token, _)| self.eat(token));
+        match comparison {
+            Some((_, comparison)) => Ok(Expr::Compare(
+                Box::new(left),
+                comparison,
+                Box::new(self.parse_postfix()?),
+            )),
+            None => Ok(left),
+        }
+    }
+
+    fn parse_postfix(&mut self) -> Result<Expr, ConditionError> {
+        let mut expr = self.parse_primary()?;
+        while self.peek() == Some('.') && self.peek_at(1).is_some_and(char::is_alphabetic) {
+            self.pos += 1;
+            let start = self.pos;
+            let name = self.identifier();
+            let method = match name.as_str() {
+                "contains" => Method::Contains,
+                "equalsIgnoreCase" => Method::EqualsIgnoreCase,
+                _ => {
+                    return Err(ConditionError::Syntax(
+                        start,
+                        format!("unknown method `{}`", name),
+                    ))
+                }
+            };
+            self.expect("(")?;
+            let argument = self.parse_or()?;
+            self.expect(")")?;
+            expr = Expr::Call(Box::new(expr), method, Box::new(argument));
+        }
+        Ok(expr)
+    }
+
+    fn parse_primary(&mut self) -> Result<Expr, ConditionError> {
+        self.skip_whitespace();
+        match self.peek() {
+            Some('(') => {
+                self.pos += 1;
+                let expr = self.parse_or()?;
+                self.expect(")")?;
+                Ok(expr)
+            }
+            Some(quote @ ('\'' | '"')) => Ok(Expr::Literal(Value::String(self.string(quote)?))),
+            Some('@') => {
+                if self.filter_depth == 0 {
+                    return Err(self.error("`@` is only allowed in filters"));
+                }
+                self.pos += 1;
+                Ok(Expr::Lookup(Subject::Current, self.segments()?))
+            }
+            Some(c) if c.is_ascii_digit() || c == '-' => self.number(),
+            Some(c) if c.is_alphabetic() || c == '_' => {
+                let name = self.identifier();
+                if self.eat("#") {
+                    if !self.eat("$") {
+                        return Err(self.error("expected `$`"));
+                    }
+                    let segments = self.segments()?;
+                    if !self.eat("#") {
+                        return Err(self.error("expected `#` closing the path"));
+                    }
+                    return Ok(Expr::Lookup(Subject::Named(name), segments));
+                }
+                match name.as_str() {
+                    "true" => Ok(Expr::Literal(Value::Bool(true))),
+                    "false" => Ok(Expr::Literal(Value::Bool(false))),
+                    "null" => Ok(Expr::Literal(Value::Null)),
+                    _ => Err(self.error(&format!("expected `#` after subject `{}`", name))),
+                }
+            }
+            Some(_) => Err(self.error("unexpected character")),
+            None => Err(self.error("unexpected end of condition")),
+        }
+    }
+
+    fn identifier(&mut self) -> String {
+        let start = self.pos;
+        while self.peek().is_some_and(|c| c.is_alphanumeric() || c == '_') {
+            self.pos += 1;
+        }
+        self.chars[start..self.pos].iter().collect()
+    }
+
+    fn string(&mut self, quote: char) -> Result<String, ConditionError> {
+        self.pos += 1;
+        let mut value = String::new();
+        loop {
+            match self.peek() {
+                Some('\\') => {
+                    self.pos += 1;
+                    match self.peek() {
+                        Some(c) => value.push(c),
+                        None => return Err(self.error("unterminated string")),
+                    }
+                }
+                Some(c) if c == quote => {
+                    self.pos += 1;
+                    return Ok(value);
+                }
+                Some(c) => value.push(c),
+                None => return Err(self.error("unterminated string")),
+            }
+            self.pos += 1;
+        }
+    }
+
+    fn number(&mut self) -> Result<Expr, ConditionError> {
+        let start = self.pos;
+        if self.peek() == Some('-') {
+            self.pos += 1;
+        }
+        while self.peek().is_some_and(|c| c.is_ascii_digit() || c == '.') {
+            self.pos += 1;
+        }
+        let text: String = self.chars[start..self.pos].iter().collect();
+        let number = match text.parse::<i64>() {
+            Ok(integer) => Number::from(integer),
+            Err(_) => text
+                .parse::<f64>()
+                .ok()
+                .and_then(Number::from_f64)
+                .ok_or_else(|| {
+                    ConditionError::Syntax(start, format!("invalid number `{}`", text))
+                })?,
+        };
+        Ok(Expr::Literal(Value::Number(number)))
+    }
+
+    /// Path segments following `$` or `@`
+    fn segments(&mut self) -> Result<Vec<Segment>, ConditionError> {
+        let mut segments = Vec::new();
+        loop {
+            match self.peek() {
+                Some('.') => match self.peek_at(1) {
+                    Some('[') => self.pos += 1,
+                    Some('*') => {
+                        self.pos += 2;
+                        segments.push(Segment::Wildcard);
+                    }
+                    _ => {
+                        let dot = self.pos;
+                        self.pos += 1;
+                        let key = self.key();
+                        if key.is_empty() {
+                            return Err(self.error("expected a key"));
+                        }
+                        // `@.name.contains(...)` calls a method on the path
+                        if self.peek() == Some('(') {
+                            self.pos = dot;
+                            return Ok(segments);
+                        }
+                        segments.push(Segment::Keys(vec![key]));
+                    }
+                },
+                Some('[') => {
+                    self.pos += 1;
+                    segments.push(self.bracket()?);
+                }
+                _ => return Ok(segments),
+            }
+        }
+    }
+
+    fn key(&mut self) -> String {
+        let start = self.pos;
+        while self
+            .peek()
+            .is_some_and(|c| c.is_alphanumeric() || c == '_' || c == '-' || c == '$')
+        {
+            self.pos += 1;
+        }
+        self.chars[start..self.pos].iter().collect()
+    }
+
+    /// The content of `[...]`, after the opening bracket
+    fn bracket(&mut self) -> Result<Segment, ConditionError> {
+        self.skip_whitespace();
+        let segment = match self.peek() {
+            Some('*') => {
+                self.pos += 1;
+                Segment::Wildcard
+            }
+            Some('?') => {
+                self.pos += 1;
+                self.expect("(")?;
+                self.filter_depth += 1;
+                let filter = self.parse_or();
+                self.filter_depth -= 1;
+                let filter = filter?;
+                self.expect(")")?;
+                Segment::Filter(Box::new(filter))
+            }
+            Some(quote @ ('\'' | '"')) => {
+                let mut keys = vec![self.string(quote)?];
+                loop {
+                    self.skip_whitespace();
+                    if !self.eat(",") {
+                        break;
+                    }
+                    self.skip_whitespace();
+                    match self.peek() {
+                        Some(quote @ ('\'' | '"')) => keys.push(self.string(quote)?),
+                        _ => return Err(self.error("expected a quoted key")),
+                    }
+                }
+                Segment::Keys(keys)
+            }
+            Some(c) if c.is_ascii_digit() => {
+                let start = self.pos;
+                while self.peek().is_some_and(|c| c.is_ascii_digit()) {
+                    self.pos += 1;
+                }
+                let text: String = self.chars[start..self.pos].iter().collect();
+                Segment::Index(
+                    text.parse()
+                        .map_err(|_| ConditionError::Syntax(start, "invalid index".to_string()))?,
+                )
+            }
+            _ => return Err(self.error("expected `*`, `?(`, a quoted key or an index")),
+        };
+        self.expect("]")?;
+        Ok(segment)
+    }
+}
+
+fn evaluate(
+    expr: &Expr,
+    subjects: &Subjects,
+    current: Option<&Value>,
+) -> Result<Value, ConditionError> {
+    Ok(match expr {
+        Expr::Or(left, right) => Value::Bool(
+            is_truthy(&evaluate(left, subjects, current)?)
+                || is_truthy(&evaluate(right, subjects, current)?),
+        ),
+        Expr::And(left, right) => Value::Bool(
+            is_truthy(&evaluate(left, subjects, current)?)
+                && is_truthy(&evaluate(right, subjects, current)?),
+        ),
+        Expr::Not(expr) => Value::Bool(!is_truthy(&evaluate(expr, subjects, current)?)),
+        Expr::Compare(left, comparison, right) => Value::Bool(compare(
+            &evaluate(left, subjects, current)?,
+            *comparison,
+            &evaluate(right, subjects, current)?,
+        )),
+        Expr::Call(receiver, method, argument) => {
+            let receiver = evaluate(receiver, subjects, current)?;
+            let argument = evaluate(argument, subjects, current)?;
+            Value::Bool(match method {
+                Method::Contains => contains(&receiver, &argument),
+                Method::EqualsIgnoreCase => equals_ignore_case(&receiver, &argument),
+            })
+        }
+        Expr::Lookup(subject, segments) => {
+            let root = match subject {
+                Subject::Named(name) => *subjects
+                    .0
+                    .get(name.as_str())
+                    .ok_or_else(|| ConditionError::UnknownSubject(name.clone()))?,
+                Subject::Current => current.unwrap_or(&Value::Null),
+            };
+            let mut nodes = vec![root];
+            for segment in segments {
+                nodes = select(nodes, segment, subjects)?;
+            }
+            let definite = segments.iter().all(|segment| match segment {
+                Segment::Keys(keys) => keys.len() == 1,
+                Segment::Index(_) => true,
+                _ => false,
+            });
+            if definite {
+                nodes
+                    .first()
+                    .map(|node| (*node).clone())
+                    .unwrap_or_default()
+            } else {
+                Value::Array(nodes.into_iter().cloned().collect())
+            }
+        }
+        Expr::Literal(value) => value.clone(),
+    })
+}
+
+fn select<'v>(
+    nodes: Vec<&'v Value>,
+    segment: &Segment,
+    subjects: &Subjects,
+) -> Result<Vec<&'v Value>, ConditionError> {
+    let mut selected = Vec::new();
+    for node in nodes {
+        match segment {
+            Segment::Keys(keys) => selected.extend(keys.iter().filter_map(|key| node.get(key))),
+            Segment::Index(index) => selected.extend(node.get(index)),
+            Segment::Wildcard => selected.extend(children(node)),
+            Segment::Filter(filter) => {
+                for child in children(node) {
+                    if is_truthy(&evaluate(filter, subjects, Some(child))?) {
+                        selected.push(child);
+                    }
+                }
+            }
+        }
+    }
+    Ok(selected)
+}
+
+fn children(node: &Value) -> Vec<&Value> {
+    match node {
+        Value::Array(items) => items.iter().collect(),
+        Value::Object(fields) => fields.values().collect(),
+        _ => vec![],
+    }
+}
+
+fn is_truthy(value: &Value) -> bool {
+    match value {
+        Value::Null => false,
+        Value::Bool(value) => *value,
+        Value::Number(number) => number.as_f64().is_some_and(|n| n != 0.0),
+        Value::String(value) => !value.is_empty(),
+        Value::Array(items) => !items.is_empty(),
+        Value::Object(_) => true,
+    }
+}
+
+fn compare(left: &Value, comparison: Comparison, right: &Value) -> bool {
+    let ordering = match (left, right) {
+        (Value::Number(left), Value::Number(right)) => left.as_f64().partial_cmp(&right.as_f64()),
+        (Value::String(left), Value::String(right)) => Some(left.cmp(right)),
+        _ => None,
+    };
+    match comparison {
+        Comparison::Eq => ordering.map_or(left == right, |ordering| ordering.is_eq()),
+        Comparison::Ne => ordering.map_or(left != right, |ordering| ordering.is_ne()),
+        Comparison::Lt => ordering.is_some_and(|ordering| ordering.is_lt()),
+        Comparison::Le => ordering.is_some_and(|ordering| ordering.is_le()),
+        Comparison::Gt => ordering.is_some_and(|ordering| ordering.is_gt()),
+        Comparison::Ge => ordering.is_some_and(|ordering| ordering.is_ge()),
+    }
+}
+
+/// The candidates of an argument, the elements of an array or the value itself
+fn candidates(argument: &Value) -> Vec<&Value> {
+    match argument {
+        Value::Array(items) => items.iter().collect(),
+        value => vec![value],
+    }
+}
+
+fn contains(receiver: &Value, argument: &Value) -> bool {
+    candidates(argument)
+        .into_iter()
+        .any(|candidate| match (receiver, candidate) {
+            (Value::Array(items), candidate) => items
+                .iter()
+                .any(|item| compare(item, Comparison::Eq, candidate)),
+            (Value::String(text), Value::String(candidate)) => text.contains(candidate.as_str()),
+            _ => false,
+        })
+}
+
+fn equals_ignore_case(receiver: &Value, argument: &Value) -> bool {
+    candidates(receiver).into_iter().any(|left| {
+        candidates(argument)
+            .into_iter()
+            .any(|right| match (left, right) {
+                (Value::String(left), Value::String(right)) => {
+                    left.to_lowercase() == right.to_lowercase()
+                }
+                _ => false,
+            })
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn teacher() -> Value {
+        json!({
+            "osid": "inst-1",
+            "Gender": "Male",
+            "experience": [
+                { "instituteOSID": "inst-1", "_osState": "PUBLISHED" },
+                { "instituteOSID": "inst-2", "_osState": "DRAFT" }
+            ]
+        })
+    }
+
+    fn evaluate(condition: &str, requester: &Value) -> bool {
+        let teacher = teacher();
+        Condition::parse(condition)
+            .unwrap()
+            .evaluate(
+                &Subjects::new()
+                    .with(ATTESTOR, &teacher)
+                    .with(REQUESTER, requester),
+            )
+            .unwrap()
+    }
+
+    #[test]
+    fn test_policy_conditions() {
+        let condition = "(ATTESTOR#$.experience.[*].instituteOSID#.contains(REQUESTER#$.instituteOSID#) && ATTESTOR#$.experience[?(@.instituteOSID == REQUESTER#$.instituteOSID#)]['_osState']#.contains('PUBLISHED'))";
+
+        assert!(evaluate(condition, &json!({"instituteOSID": "inst-1"})));
+        assert!(!evaluate(condition, &json!({"instituteOSID": "inst-2"})));
+        assert!(!evaluate(condition, &json!({"instituteOSID": "inst-3"})));
+        assert!(evaluate(
+            "(ATTESTOR#$.Gender#.equalsIgnoreCase('male'))",
+            &json!({})
+        ));
+        assert!(evaluate(
+            "(ATTESTOR#$.osid#.contains(REQUESTER#$.experience.*.instituteOSID#))",
+            &json!({"experience": [{"instituteOSID": "inst-9"}, {"instituteOSID": "inst-1"}]})
+        ));
+    }
+
+    #[test]
+    fn test_sample_schema_conditions_parse() {
+        for condition in [
+            "(ATTESTOR#$.[*]#.contains('board-cbse'))",
+            "(ATTESTOR#$.experience.[*].instituteOSID#.contains(REQUESTER#$.educationDetails.*.instituteOSID#))",
+            "(ATTESTOR#$.osid#.contains(REQUESTER#$.academicQualifications.*.instituteOSID#))",
+        ] {
+            assert!(Condition::parse(condition).is_ok(), "{condition}");
+        }
+    }
+
+    #[test]
+    fn test_operators() {
+        let requester = json!({"marks": 72, "board": "cbse", "tags": ["a", "b"]});
+
+        assert!(evaluate(
+            "REQUESTER#$.marks# >= 70 && REQUESTER#$.marks# < 80",
+            &requester
+        ));
+        assert!(evaluate(
+            "!(REQUESTER#$.board# == 'icse') || false",
+            &requester
+        ));
+        assert!(evaluate("REQUESTER#$.tags[1]# != \"a\"", &requester));
+        assert!(!evaluate("REQUESTER#$.missing#", &requester));
+    }
+
+    #[test]
+    fn test_syntax_errors() {
+        for (condition, position) in [
+            ("ATTESTOR#$.osid", 15),
+            ("ATTESTOR#$.osid#.startsWith('a')", 17),
+            ("(ATTESTOR#$.osid# == 'a'", 24),
+            ("@.osid == 'a'", 0),
+            ("ATTESTOR#$.experience[?(@.x == 1]#", 32),
+        ] {
+            match Condition::parse(condition) {
+                Err(ConditionError::Syntax(at, _)) => assert_eq!(at, position, "{condition}"),
+                other => panic!("Expected a syntax error for {condition}, got {other:?}"),
+            }
+        }
+    }
+
+    #[test]
+    fn test_unknown_subject() {
+        let condition = Condition::parse("USER#$.id# == 'a'").unwrap();
+        assert_eq!(
+            condition.evaluate(&Subjects::new()),
+            Err(ConditionError::UnknownSubject("USER".to_string()))
+        );
+    }
+}
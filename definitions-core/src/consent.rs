@@ -0,0 +1,396 @@
+//! Consent to share entity fields with third parties.
+//!
+//! A requester asks for some fields of an entity, addressed by path, with [`RequestConsentCmd`]:
+//!
+//! ```json
+//! { "entity_type": "Insurance", "fields": ["$.policyNumber", "$.fullName"], "purpose": "KYC" }
+//! ```
+//!
+//! One of the owners of the entity, as identified by `ownershipAttributes`, grants the consent
+//! until an expiry, denies it, or later revokes it with [`DecideConsentCmd`]. While a consent is
+//! granted and has not expired, the requester reads the consented fields with
+//! [`AccessConsentedDataCmd`], which records every access with a `ConsentedDataAccessed` event.
+use crate::definitions_domain::DomainEvent;
+use crate::json_path::{entity_node, JsonPath, PathSegment};
+use crate::ownership::Principal;
+use crate::registry_domain::{EntityError, EntityId, EntityRecordStatus, RegistryResource};
+use chrono::{DateTime, Utc};
+use disintegrate::{union, Decision, StateMutate, StateQuery, StreamQuery};
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use strum_macros::Display;
+use utoipa::ToSchema;
+use uuid::Uuid;
+
+pub type ConsentId = Uuid;
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, Default, Display)]
+pub enum ConsentStatus {
+    #[default]
+    None,
+    Requested,
+    Granted,
+    Denied,
+    Revoked,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, ToSchema, Display)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum ConsentAction {
+    /// Grants a requested consent until `expires_at`
+    #[strum(serialize = "grant")]
+    GrantConsent,
+    /// Denies a requested consent
+    #[strum(serialize = "deny")]
+    DenyConsent,
+    /// Withdraws a granted consent before it expires
+    #[strum(serialize = "revoke")]
+    RevokeConsent,
+}
+
+/// Parses the paths of a consent request, which must address properties rather than array items
+pub fn consent_paths(fields: &[String]) -> Result<Vec<JsonPath>, EntityError> {
+    if fields.is_empty() {
+        return Err(EntityError::InvalidConsent(
+            "at least one field must be requested".to_string(),
+        ));
+    }
+    fields
+        .iter()
+        .map(|field| {
+            let path = JsonPath::parse(field)
+                .map_err(|e| EntityError::InvalidConsent(format!("`{}`: {}", field, e)))?;
+            if path
+                .segments()
+                .iter()
+                .any(|segment| matches!(segment, PathSegment::Index(_)))
+            {
+                return Err(EntityError::InvalidConsent(format!(
+                    "`{}` does not address a property",
+                    field
+                )));
+            }
+            Ok(path)
+        })
+        .collect()
+}
+
+/// Returns true when the principal is the requester a consent shares the fields with, the
+/// subject an impersonating actor requested it for
+pub fn is_requester(principal: &Principal, requested_by: &str) -> bool {
+    !principal.subject.is_empty() && principal.subject == requested_by
+}
+
+/// The consented fields of an entity document, missing fields are left out
+pub fn consented_data(document: &Value, entity_type: &str, fields: &[String]) -> Value {
+    let node = entity_node(document, entity_type);
+    let mut data = Value::Object(Map::new());
+    for path in fields
+        .iter()
+        .filter_map(|field| JsonPath::parse(field).ok())
+    {
+        if let Some(value) = path.get(node) {
+            // Paths address properties only, writing them cannot fail
+            let _ = path.set(&mut data, value.clone());
+        }
+    }
+    data
+}
+
+#[derive(Default, StateQuery, Clone, Debug, Serialize, Deserialize)]
+#[state_query(DomainEvent)]
+pub struct Consent {
+    #[id]
+    consent_id: ConsentId,
+    status: ConsentStatus,
+    entity_id: EntityId,
+    fields: Vec<String>,
+    requested_by: String,
+    expires_at: Option<DateTime<Utc>>,
+}
+
+impl Consent {
+    pub fn new(consent_id: ConsentId) -> Self {
+        Self {
+            consent_id,
+            ..Default::default()
+        }
+    }
+}
+
+impl StateMutate for Consent {
+    fn mutate(&mut self, event: Self::Event) {
+        match event {
+            DomainEvent::ConsentRequested {
+                entity_id,
+                fields,
+                requested_by,
+                on_behalf_of,
+                ..
+            } => {
+                self.entity_id = entity_id;
+                self.fields = fields;
+                // The data is shared with the subject an impersonating actor requested it for
+                self.requested_by = on_behalf_of.unwrap_or(requested_by);
+                self.status = ConsentStatus::Requested;
+            }
+            DomainEvent::ConsentGranted { expires_at, .. } => {
+                self.expires_at = Some(expires_at);
+                self.status = ConsentStatus::Granted;
+            }
+            DomainEvent::ConsentDenied { .. } => self.status = ConsentStatus::Denied,
+            DomainEvent::ConsentRevoked { .. } => self.status = ConsentStatus::Revoked,
+            _ => {}
+        }
+    }
+}
+
+/// Asks the owners of an entity for some of its fields
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, Default)]
+pub struct RequestConsentCmd {
+    pub consent_id: ConsentId,
+    pub entity_id: EntityId,
+    pub entity_type: String,
+    /// Paths of the requested fields, relative to the entity node
+    pub fields: Vec<String>,
+    pub purpose: Option<String>,
+    /// The requester, the only one who may read the fields once the consent is granted
+    pub principal: Principal,
+}
+
+impl Decision for RequestConsentCmd {
+    type Event = DomainEvent;
+    type StateQuery = (Consent, RegistryResource);
+    type Error = EntityError;
+
+    fn state_query(&self) -> Self::StateQuery {
+        (
+            Consent::new(self.consent_id),
+            RegistryResource::new(self.entity_id),
+        )
+    }
+
+    fn validation_query<ID: disintegrate::EventId>(&self) -> Option<StreamQuery<ID, Self::Event>> {
+        let (consent, resource) = self.state_query();
+        Some(union!(&consent, &resource))
+    }
+
+    fn process(
+        &self,
+        (consent, resource): &Self::StateQuery,
+    ) -> Result<Vec<Self::Event>, Self::Error> {
+        if consent.status != ConsentStatus::None {
+            return Err(EntityError::ConsentAlreadyExists(self.consent_id));
+        }
+        if resource.status == EntityRecordStatus::None || resource.entity_type != self.entity_type {
+            return Err(EntityError::EntityNotFound(self.entity_id));
+        }
+        if self.principal.subject.is_empty() {
+            return Err(EntityError::InvalidConsent(
+                "the requester has no subject".to_string(),
+            ));
+        }
+        consent_paths(&self.fields)?;
+
+        let actor = self.principal.actor();
+        Ok(vec![DomainEvent::ConsentRequested {
+            consent_id: self.consent_id,
+            entity_id: self.entity_id,
+            entity_type: self.entity_type.clone(),
+            fields: self.fields.clone(),
+            purpose: self.purpose.clone(),
+            requested_at: Utc::now(),
+            requested_by: actor.id.clone(),
+            on_behalf_of: actor.on_behalf_of.clone(),
+        }])
+    }
+}
+
+/// Grants, denies or revokes a consent.
+///
+/// The caller must be one of the owners of the entity. Requested consents are granted or denied,
+/// granted consents are revoked.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DecideConsentCmd {
+    pub consent_id: ConsentId,
+    /// The entity the consent was requested on
+    pub entity_id: EntityId,
+    pub action: ConsentAction,
+    /// End of a granted consent, required to grant it
+    pub expires_at: Option<DateTime<Utc>>,
+    pub notes: Option<String>,
+    pub principal: Principal,
+}
+
+impl Decision for DecideConsentCmd {
+    type Event = DomainEvent;
+    type StateQuery = (Consent, RegistryResource);
+    type Error = EntityError;
+
+    fn state_query(&self) -> Self::StateQuery {
+        (
+            Consent::new(self.consent_id),
+            RegistryResource::new(self.entity_id),
+        )
+    }
+
+    fn validation_query<ID: disintegrate::EventId>(&self) -> Option<StreamQuery<ID, Self::Event>> {
+        let (consent, resource) = self.state_query();
+        Some(union!(&consent, &resource))
+    }
+
+    fn process(
+        &self,
+        (consent, resource): &Self::StateQuery,
+    ) -> Result<Vec<Self::Event>, Self::Error> {
+        if consent.status == ConsentStatus::None || consent.entity_id != self.entity_id {
+            return Err(EntityError::ConsentNotFound(self.consent_id));
+        }
+        if !self.principal.owns(&resource.owners) {
+            return Err(EntityError::NotAuthorized(
+                self.principal.subject.clone(),
+                self.entity_id,
+            ));
+        }
+        let expected = match self.action {
+            ConsentAction::GrantConsent | ConsentAction::DenyConsent => ConsentStatus::Requested,
+            ConsentAction::RevokeConsent => ConsentStatus::Granted,
+        };
+        if consent.status != expected {
+            return Err(EntityError::ConsentNotChanged(
+                self.consent_id,
+                self.action.to_string(),
+                consent.status.clone(),
+            ));
+        }
+
+        let actor = self.principal.actor();
+        let now = Utc::now();
+        let event = match self.action {
+            ConsentAction::GrantConsent => {
+                let expires_at = self.expires_at.ok_or_else(|| {
+                    EntityError::InvalidConsent("a granted consent must expire".to_string())
+                })?;
+                if expires_at <= now {
+                    return Err(EntityError::InvalidConsent(format!(
+                        "expiry {} is in the past",
+                        expires_at
+                    )));
+                }
+                DomainEvent::ConsentGranted {
+                    consent_id: self.consent_id,
+                    entity_id: self.entity_id,
+                    expires_at,
+                    notes: self.notes.clone(),
+                    granted_at: now,
+                    granted_by: actor.id.clone(),
+                    on_behalf_of: actor.on_behalf_of.clone(),
+                }
+            }
+            ConsentAction::DenyConsent => DomainEvent::ConsentDenied {
+                consent_id: self.consent_id,
+                entity_id: self.entity_id,
+                notes: self.notes.clone(),
+                denied_at: now,
+                denied_by: actor.id.clone(),
+                on_behalf_of: actor.on_behalf_of.clone(),
+            },
+            ConsentAction::RevokeConsent => DomainEvent::ConsentRevoked {
+                consent_id: self.consent_id,
+                entity_id: self.entity_id,
+                notes: self.notes.clone(),
+                revoked_at: now,
+                revoked_by: actor.id.clone(),
+                on_behalf_of: actor.on_behalf_of.clone(),
+            },
+        };
+        Ok(vec![event])
+    }
+}
+
+/// Records a read of the consented fields by the requester of a granted, unexpired consent
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AccessConsentedDataCmd {
+    pub consent_id: ConsentId,
+    pub principal: Principal,
+}
+
+impl Decision for AccessConsentedDataCmd {
+    type Event = DomainEvent;
+    type StateQuery = Consent;
+    type Error = EntityError;
+
+    fn state_query(&self) -> Self::StateQuery {
+        Consent::new(self.consent_id)
+    }
+
+    fn process(&self, consent: &Self::StateQuery) -> Result<Vec<Self::Event>, Self::Error> {
+        if consent.status == ConsentStatus::None {
+            return Err(EntityError::ConsentNotFound(self.consent_id));
+        }
+        if !is_requester(&self.principal, &consent.requested_by) {
+            return Err(EntityError::NotAuthorized(
+                self.principal.subject.clone(),
+                consent.entity_id,
+            ));
+        }
+        if consent.status != ConsentStatus::Granted {
+            return Err(EntityError::ConsentNotGranted(
+                self.consent_id,
+                consent.status.clone(),
+            ));
+        }
+        let now = Utc::now();
+        match consent.expires_at {
+            Some(expires_at) if expires_at > now => {}
+            expires_at => {
+                return Err(EntityError::ConsentExpired(
+                    self.consent_id,
+                    expires_at.unwrap_or(now),
+                ))
+            }
+        }
+
+        let actor = self.principal.actor();
+        Ok(vec![DomainEvent::ConsentedDataAccessed {
+            consent_id: self.consent_id,
+            entity_id: consent.entity_id,
+            fields: consent.fields.clone(),
+            accessed_at: now,
+            accessed_by: actor.id.clone(),
+            on_behalf_of: actor.on_behalf_of.clone(),
+        }])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_only_consented_fields_are_shared() {
+        let document = json!({"Insurance": {
+            "policyNumber": "P-1",
+            "fullName": {"firstName": "Asha", "lastName": "Rao"},
+            "mobile": "9876543210"
+        }});
+        let fields = vec![
+            "$.policyNumber".to_string(),
+            "/fullName/firstName".to_string(),
+            "$.missing".to_string(),
+        ];
+
+        assert_eq!(
+            consented_data(&document, "Insurance", &fields),
+            json!({"policyNumber": "P-1", "fullName": {"firstName": "Asha"}})
+        );
+    }
+
+    #[test]
+    fn test_consent_paths_address_properties() {
+        assert!(consent_paths(&["$.policyNumber".to_string()]).is_ok());
+        assert!(consent_paths(&[]).is_err());
+        assert!(consent_paths(&["$.benefits[0]".to_string()]).is_err());
+    }
+}
@@ -0,0 +1,516 @@
+//! W3C Verifiable Credentials issued from entity data.
+//!
+//! A definition declares the credential of its entities in `_osConfig.credentialTemplate`, and
+//! an attestation policy the credential of its attested claims in its own `credentialTemplate`:
+//!
+//! ```json
+//! "credentialTemplate": {
+//!   "@context": ["https://www.w3.org/2018/credentials/v1"],
+//!   "type": ["VerifiableCredential", "InsuranceCredential"],
+//!   "issuer": "Registry",
+//!   "expirationDate": "{{policyExpiresOn}}",
+//!   "credentialSubject": { "id": "did:{{osid}}", "policyNumber": "{{policyNumber}}" }
+//! }
+//! ```
+//!
+//! The issuer of a template without one, or naming the `Registry` placeholder, is the DID of the
+//! registry, see [`crate::did`].
+//!
+//! `{{name}}` placeholders are replaced by the top level property `name` of the entity, or a
+//! dotted path such as `{{identityDetails.fullName}}`; `{{osid}}` is the entity id. A string
+//! made of a single placeholder takes the value as is, arrays and numbers included, and is
+//! dropped when the value is missing. Policy credentials render the attested snapshot of the
+//! claim over the entity data.
+//!
+//! The rendered credential is signed by the [`IssuerKey`] of the registry, either as a
+//! `DataIntegrityProof` with the `eddsa-jcs-2022` cryptosuite ([`CredentialFormat::LdpVc`]) or
+//! as a VC-JWT ([`CredentialFormat::JwtVc`]) or an [SD-JWT](crate::sd_jwt) whose subject claims
+//! are selectively disclosed by the holder ([`CredentialFormat::SdJwtVc`]), and recorded with a
+//! `CredentialIssued` event.
+//! Credentials of definitions with `privateFields` are stored encrypted. Every credential embeds
+//! its entries of the registry [status lists](crate::status_list).
+use crate::attestation::find_policy;
+use crate::definitions_domain::{generate_id_from_title, DomainEvent, RegistryDefinition};
+use crate::encryption::{private_fields, FieldCipher};
+use crate::json_path::entity_node;
+use crate::ownership::{definition_roles, Principal};
+use crate::registry_domain::{EntityError, EntityId, EntityRecordStatus, RegistryResource};
+use crate::sd_jwt::sign_sd_jwt;
+use crate::signing::{canonical_json, IssuerKey, PublicKey, SigningError};
+use crate::status_list::{status_entries, StatusListAllocation, STATUS_LIST_CONTEXT};
+use crate::system_fields::{SystemFields, OS_ATTESTED_DATA};
+use chrono::{DateTime, SecondsFormat, Utc};
+use disintegrate::{event_types, union, Decision, StreamQuery};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Map, Value};
+use sha2::{Digest, Sha256};
+use strum_macros::Display;
+use utoipa::ToSchema;
+use uuid::Uuid;
+
+pub type CredentialId = Uuid;
+pub const CREDENTIAL_TEMPLATE: &str = "credentialTemplate";
+/// Issuer placeholder of templates, replaced by the DID of the registry
+pub const REGISTRY_ISSUER: &str = "Registry";
+pub const DATA_INTEGRITY_PROOF: &str = "DataIntegrityProof";
+pub const CRYPTOSUITE: &str = "eddsa-jcs-2022";
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, Display, ToSchema)]
+pub enum CredentialFormat {
+    /// JSON-LD credential with an embedded Data Integrity proof
+    #[default]
+    #[serde(rename = "ldp_vc")]
+    #[strum(serialize = "ldp_vc")]
+    LdpVc,
+    /// Credential signed as a JWT
+    #[serde(rename = "jwt_vc")]
+    #[strum(serialize = "jwt_vc")]
+    JwtVc,
+    /// Credential signed as an SD-JWT, its subject claims disclosed selectively
+    #[serde(rename = "vc+sd-jwt")]
+    #[strum(serialize = "vc+sd-jwt")]
+    SdJwtVc,
+}
+
+/// The credential template of a definition, or of one of its attestation policies
+pub fn credential_template(
+    json_schema_string: &str,
+    entity_type: &str,
+    policy_name: Option<&str>,
+) -> Result<Value, EntityError> {
+    let template = match policy_name {
+        Some(policy_name) => {
+            find_policy(json_schema_string, entity_type, policy_name)?.credential_template
+        }
+        None => serde_json::from_str::<Value>(json_schema_string)
+            .map_err(|e| EntityError::InvalidSchema(e.to_string()))?
+            .get("_osConfig")
+            .and_then(|config| config.get(CREDENTIAL_TEMPLATE))
+            .cloned(),
+    };
+    template
+        .filter(Value::is_object)
+        .ok_or_else(|| EntityError::CredentialTemplateNotFound(entity_type.to_string()))
+}
+
+/// Replaces the `{{name}}` placeholders of a template by the values of `data`
+pub fn render_template(template: &Value, data: &Value) -> Value {
+    render(template, data).unwrap_or(Value::Null)
+}
+
+fn render(template: &Value, data: &Value) -> Option<Value> {
+    match template {
+        Value::String(text) => {
+            if let Some(name) = text
+                .strip_prefix("{{")
+                .and_then(|rest| rest.strip_suffix("}}"))
+                .filter(|name| !name.contains("{{") && !name.contains("}}"))
+            {
+                return lookup(data, name.trim()).cloned();
+            }
+            let mut rendered = String::new();
+            let mut rest = text.as_str();
+            while let Some(start) = rest.find("{{") {
+                let Some(end) = rest[start..].find("}}") else {
+                    break;
+                };
+                rendered.push_str(&rest[..start]);
+                match lookup(data, rest[start + 2..start + end].trim()) {
+                    Some(Value::String(value)) => rendered.push_str(value),
+                    Some(Value::Null) | None => {}
+                    Some(value) => rendered.push_str(&value.to_string()),
+                }
+                rest = &rest[start + end + 2..];
+            }
+            rendered.push_str(rest);
+            Some(Value::String(rendered))
+        }
+        Value::Array(items) => Some(Value::Array(
+            items.iter().filter_map(|item| render(item, data)).collect(),
+        )),
+        Value::Object(fields) => Some(Value::Object(
+            fields
+                .iter()
+                .filter_map(|(key, value)| Some((key.clone(), render(value, data)?)))
+                .collect(),
+        )),
+        value => Some(value.clone()),
+    }
+}
+
+fn lookup<'a>(data: &'a Value, name: &str) -> Option<&'a Value> {
+    name.split('.')
+        .try_fold(data, |node, key| node.get(key))
+        .filter(|value| !value.is_null())
+}
+
+/// The `id` of the credential issuer, a string or an object with an `id`
+pub fn issuer_id(credential: &Value) -> Option<&str> {
+    match credential.get("issuer")? {
+        Value::String(issuer) => Some(issuer),
+        issuer => issuer.get("id")?.as_str(),
+    }
+}
+
+/// Adds a `DataIntegrityProof` with the `eddsa-jcs-2022` cryptosuite to a credential
+pub fn sign_data_integrity(
+    credential: &Value,
+    key: &IssuerKey,
+    created: DateTime<Utc>,
+) -> Result<Value, EntityError> {
+    let mut credential = credential.clone();
+    let Value::Object(fields) = &mut credential else {
+        return Err(EntityError::InvalidJson(
+            "a credential must be an object".to_string(),
+        ));
+    };
+    fields.remove("proof");
+    let issuer = issuer_id(&credential).unwrap_or(key.kid());
+    let mut proof = json!({
+        "type": DATA_INTEGRITY_PROOF,
+        "cryptosuite": CRYPTOSUITE,
+        "created": created.to_rfc3339_opts(SecondsFormat::Secs, true),
+        "verificationMethod": format!("{}#{}", issuer, key.kid()),
+        "proofPurpose": "assertionMethod",
+    });
+    if let Some(context) = credential.get("@context") {
+        proof["@context"] = context.clone();
+    }
+    let signature = key.sign_bytes(&proof_hash(&credential, &proof));
+    proof["proofValue"] = Value::String(format!("z{}", bs58::encode(signature).into_string()));
+    if let Value::Object(fields) = &mut credential {
+        fields.insert("proof".to_string(), proof);
+    }
+    Ok(credential)
+}
+
+/// Checks the `DataIntegrityProof` of a credential
+pub fn verify_data_integrity(credential: &Value, key: &PublicKey) -> Result<(), SigningError> {
+    let Some(Value::Object(proof)) = credential.get("proof") else {
+        return Err(SigningError::MissingSignature);
+    };
+    if proof.get("cryptosuite").and_then(Value::as_str) != Some(CRYPTOSUITE) {
+        return Err(SigningError::UnsupportedAlgorithm(
+            proof
+                .get("cryptosuite")
+                .map(Value::to_string)
+                .unwrap_or_default(),
+        ));
+    }
+    let method = proof
+        .get("verificationMethod")
+        .and_then(Value::as_str)
+        .unwrap_or_default();
+    if method != key.kid && method.rsplit_once('#').map(|(_, kid)| kid) != Some(key.kid.as_str()) {
+        return Err(SigningError::UnknownKey(method.to_string()));
+    }
+    let signature = proof
+        .get("proofValue")
+        .and_then(Value::as_str)
+        .and_then(|value| value.strip_prefix('z'))
+        .and_then(|value| bs58::decode(value).into_vec().ok())
+        .ok_or(SigningError::InvalidSignature)?;
+
+    let mut proof = proof.clone();
+    proof.remove("proofValue");
+    let mut document = credential.clone();
+    if let Value::Object(fields) = &mut document {
+        fields.remove("proof");
+    }
+    key.verify_bytes(&proof_hash(&document, &Value::Object(proof)), &signature)
+}
+
+/// The hash data of `eddsa-jcs-2022`: the hash of the proof options followed by the hash of
+/// the document
+fn proof_hash(document: &Value, proof: &Value) -> Vec<u8> {
+    let mut hash = Sha256::digest(canonical_json(proof).as_bytes()).to_vec();
+    hash.extend(Sha256::digest(canonical_json(document).as_bytes()));
+    hash
+}
+
+/// Signs a credential as a VC-JWT, the registered claims mirror the credential
+pub fn sign_vc_jwt(credential: &Value, key: &IssuerKey) -> String {
+    key.sign_jwt(&Value::Object(vc_jwt_claims(credential)))
+}
+
+/// The registered JWT claims of a credential, with the credential as the `vc` claim
+pub(crate) fn vc_jwt_claims(credential: &Value) -> Map<String, Value> {
+    let mut claims = Map::new();
+    if let Some(issuer) = issuer_id(credential) {
+        claims.insert("iss".to_string(), json!(issuer));
+    }
+    if let Some(subject) = credential
+        .get("credentialSubject")
+        .and_then(|subject| subject.get("id"))
+    {
+        claims.insert("sub".to_string(), subject.clone());
+    }
+    if let Some(id) = credential.get("id") {
+        claims.insert("jti".to_string(), id.clone());
+    }
+    for (claim, field) in [("nbf", "issuanceDate"), ("exp", "expirationDate")] {
+        if let Some(timestamp) = credential
+            .get(field)
+            .and_then(Value::as_str)
+            .and_then(|date| DateTime::parse_from_rfc3339(date).ok())
+        {
+            claims.insert(claim.to_string(), json!(timestamp.timestamp()));
+        }
+    }
+    claims.insert("vc".to_string(), credential.clone());
+    claims
+}
+
+/// Issues a credential of an entity, from the template of its definition or of an attested
+/// policy
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct IssueCredentialCmd {
+    pub credential_id: CredentialId,
+    pub entity_id: EntityId,
+    pub entity_type: String,
+    /// Issues the credential of this attestation policy, which must have been attested
+    pub policy_name: Option<String>,
+    pub format: CredentialFormat,
+    pub issuer: IssuerKey,
+    /// DID of the registry, the key id of `issuer` stands for it when missing
+    pub issuer_did: Option<String>,
+    /// Base URL the status lists are served under, see [`crate::status_list::status_list_url`]
+    pub status_list_url: String,
+    /// Decrypts the `privateFields` used by the template and encrypts the credential
+    pub cipher: Option<FieldCipher>,
+    pub principal: Principal,
+}
+
+impl IssueCredentialCmd {
+    /// The data the template is rendered with: the entity node, decrypted, with its `osid`,
+    /// under the attested snapshot of the policy if any
+    fn template_data(
+        &self,
+        resource: &RegistryResource,
+        schema: &str,
+    ) -> Result<Value, EntityError> {
+        let mut document: Value = serde_json::from_str(&resource.entity_body)
+            .map_err(|e| EntityError::InvalidJson(e.to_string()))?;
+        if let Some(cipher) = &self.cipher {
+            cipher
+                .decrypt_all(&mut document)
+                .map_err(|e| EntityError::Encryption(e.to_string()))?;
+        }
+        let mut data = entity_node(&document, &self.entity_type).clone();
+        if let Some(policy_name) = &self.policy_name {
+            let attested = SystemFields::from_schema(schema, &self.entity_type)?
+                .get(&document, OS_ATTESTED_DATA)
+                .and_then(|attested| attested.get(policy_name))
+                .and_then(|attestation| attestation.get("data"))
+                .and_then(Value::as_object)
+                .cloned()
+                .ok_or_else(|| {
+                    EntityError::CredentialNotAvailable(
+                        policy_name.clone(),
+                        "the policy has not been attested".to_string(),
+                    )
+                })?;
+            if let Value::Object(fields) = &mut data {
+                fields.extend(attested);
+            }
+        }
+        if let Value::Object(fields) = &mut data {
+            fields.insert("osid".to_string(), json!(self.entity_id));
+        }
+        Ok(data)
+    }
+}
+
+impl Decision for IssueCredentialCmd {
+    type Event = DomainEvent;
+    type StateQuery = (RegistryResource, RegistryDefinition, StatusListAllocation);
+    type Error = EntityError;
+
+    fn state_query(&self) -> Self::StateQuery {
+        (
+            RegistryResource::new(self.entity_id),
+            RegistryDefinition::new(generate_id_from_title(&self.entity_type)),
+            StatusListAllocation::default(),
+        )
+    }
+
+    fn validation_query<ID: disintegrate::EventId>(&self) -> Option<StreamQuery<ID, Self::Event>> {
+        let (resource, def_state, allocation) = self.state_query();
+        Some(union!(
+            &resource,
+            def_state.exclude_events(event_types!(DomainEvent, [DefUpdated])),
+            &allocation
+        ))
+    }
+
+    fn process(
+        &self,
+        (resource, def_state, allocation): &Self::StateQuery,
+    ) -> Result<Vec<Self::Event>, Self::Error> {
+        if matches!(
+            resource.status,
+            EntityRecordStatus::None | EntityRecordStatus::MarkedForDeletion
+        ) || resource.entity_type != self.entity_type
+        {
+            return Err(EntityError::EntityNotFound(self.entity_id));
+        }
+        self.principal.authorize(
+            self.entity_id,
+            &resource.owners,
+            &definition_roles(&def_state.json_schema_string),
+        )?;
+
+        let schema = &def_state.json_schema_string;
+        let template = credential_template(schema, &self.entity_type, self.policy_name.as_deref())?;
+        let mut credential = render_template(&template, &self.template_data(resource, schema)?);
+        let status_list_index = allocation.next_index()?;
+        let now = Utc::now();
+        if let Value::Object(fields) = &mut credential {
+            fields.insert(
+                "id".to_string(),
+                json!(format!("urn:uuid:{}", self.credential_id)),
+            );
+            let names_registry = match fields.get("issuer") {
+                None => true,
+                Some(issuer) => issuer.as_str() == Some(REGISTRY_ISSUER),
+            };
+            if names_registry {
+                let issuer = self.issuer_did.as_deref().unwrap_or(self.issuer.kid());
+                fields.insert("issuer".to_string(), json!(issuer));
+            }
+            fields
+                .entry("issuanceDate")
+                .or_insert_with(|| json!(now.to_rfc3339_opts(SecondsFormat::Secs, true)));
+            if let Some(Value::Array(contexts)) = fields.get_mut("@context") {
+                if !contexts.contains(&json!(STATUS_LIST_CONTEXT)) {
+                    contexts.push(json!(STATUS_LIST_CONTEXT));
+                }
+            }
+            fields.insert(
+                "credentialStatus".to_string(),
+                status_entries(&self.status_list_url, status_list_index),
+            );
+        }
+        let credential = match self.format {
+            CredentialFormat::LdpVc => {
+                sign_data_integrity(&credential, &self.issuer, now)?.to_string()
+            }
+            CredentialFormat::JwtVc => sign_vc_jwt(&credential, &self.issuer),
+            CredentialFormat::SdJwtVc => sign_sd_jwt(&credential, &self.issuer),
+        };
+
+        let parsed_schema: Value =
+            serde_json::from_str(schema).map_err(|e| EntityError::InvalidSchema(e.to_string()))?;
+        let encrypted = !private_fields(&parsed_schema).is_empty();
+        let credential = if encrypted {
+            let cipher = self
+                .cipher
+                .as_ref()
+                .ok_or_else(|| EntityError::EncryptionUnavailable(self.entity_type.clone()))?;
+            seal_credential(cipher, &credential)?
+        } else {
+            credential
+        };
+
+        let actor = self.principal.actor();
+        Ok(vec![DomainEvent::CredentialIssued {
+            id: self.entity_id,
+            credential_id: self.credential_id,
+            entity_type: self.entity_type.clone(),
+            policy_name: self.policy_name.clone(),
+            format: self.format,
+            credential,
+            encrypted,
+            status_list_index: Some(status_list_index),
+            issued_at: now,
+            issued_by: actor.id.clone(),
+            on_behalf_of: actor.on_behalf_of.clone(),
+        }])
+    }
+}
+
+/// Encrypts an issued credential, the result is an encrypted envelope
+pub fn seal_credential(cipher: &FieldCipher, credential: &str) -> Result<String, EntityError> {
+    let mut document = json!({ "credential": credential });
+    cipher
+        .encrypt_paths(&mut document, "", &["$.credential".to_string()])
+        .map_err(|e| EntityError::Encryption(e.to_string()))?;
+    Ok(document["credential"].to_string())
+}
+
+/// Decrypts a credential sealed with [`seal_credential`]
+pub fn open_credential(cipher: &FieldCipher, sealed: &str) -> Result<String, EntityError> {
+    let mut document = json!({
+        "credential": serde_json::from_str::<Value>(sealed)
+            .map_err(|e| EntityError::InvalidJson(e.to_string()))?
+    });
+    cipher
+        .decrypt_all(&mut document)
+        .map_err(|e| EntityError::Encryption(e.to_string()))?;
+    match document["credential"].take() {
+        Value::String(credential) => Ok(credential),
+        _ => Err(EntityError::Encryption(
+            "the sealed value is not a credential".to_string(),
+        )),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_render_template() {
+        let template = json!({
+            "issuanceDate": "{{policyIssuedOn}}",
+            "benefits": "{{benefits}}",
+            "credentialSubject": {
+                "id": "did:{{policyName}}:{{policyNumber}}",
+                "name": "{{identityDetails.fullName}}",
+                "missing": "{{missing}}"
+            }
+        });
+        let data = json!({
+            "policyName": "Life",
+            "policyNumber": 42,
+            "benefits": ["accident", "illness"],
+            "identityDetails": { "fullName": "Asha" }
+        });
+
+        assert_eq!(
+            render_template(&template, &data),
+            json!({
+                "benefits": ["accident", "illness"],
+                "credentialSubject": {
+                    "id": "did:Life:42",
+                    "name": "Asha"
+                }
+            })
+        );
+    }
+
+    #[test]
+    fn test_data_integrity_proof() {
+        let key = IssuerKey::from_seed("issuer-1", [3; 32]);
+        let credential = json!({
+            "@context": ["https://www.w3.org/2018/credentials/v1"],
+            "type": ["VerifiableCredential"],
+            "issuer": "did:example:registry",
+            "credentialSubject": { "name": "Asha" }
+        });
+
+        let signed = sign_data_integrity(&credential, &key, Utc::now()).unwrap();
+
+        assert_eq!(
+            signed["proof"]["verificationMethod"],
+            json!("did:example:registry#issuer-1")
+        );
+        assert_eq!(verify_data_integrity(&signed, &key.public_key()), Ok(()));
+        let mut tampered = signed;
+        tampered["credentialSubject"]["name"] = json!("Mallory");
+        assert_eq!(
+            verify_data_integrity(&tampered, &key.public_key()),
+            Err(SigningError::InvalidSignature)
+        );
+    }
+}
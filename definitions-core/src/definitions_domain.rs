@@ -1,5 +1,12 @@
 //TODO Json Schema Validation with REF
 //TODO RollBack Command
+use crate::attestation::{validate_attestation_policies, ClaimId};
+use crate::audit::Actor;
+use crate::consent::ConsentId;
+use crate::credentials::{CredentialFormat, CredentialId};
+use crate::migration::MigrationSpec;
+use crate::ownership::Owner;
+use crate::references::EntityReference;
 use crate::registry_domain::EntityId;
 use chrono::{DateTime, Utc};
 use disintegrate::{Decision, Event, StateMutate, StateQuery};
@@ -35,6 +42,13 @@ pub type DefId = Uuid;
 #[stream(DefStateEvent, [DefCreated, DefUpdated, DefDeleted, DefValidated, DefActivated,
 DefDeactivated]
 )]
+#[stream(EntityLifecycleEvent, [EntityCreated, EntityDeleted])]
+#[stream(CredentialIssuedEvent, [CredentialIssued])]
+#[stream(CredentialStatusEvent, [CredentialIssued, CredentialRevoked, CredentialSuspended,
+CredentialReinstated]
+)]
+/// The `*_by` field of every event records the actor that issued the command, and
+/// `on_behalf_of` the subject it acted for when impersonating, see [`crate::audit::Actor`].
 pub enum DomainEvent {
     DefCreated {
         #[id]
@@ -43,6 +57,8 @@ pub enum DomainEvent {
         definitions: Vec<String>,
         created_at: DateTime<Utc>,
         created_by: String,
+        #[serde(default)]
+        on_behalf_of: Option<String>,
         json_schema_string: String,
     },
     DefUpdated {
@@ -52,19 +68,28 @@ pub enum DomainEvent {
         definitions: Vec<String>,
         created_at: DateTime<Utc>,
         updated_by: String,
+        #[serde(default)]
+        on_behalf_of: Option<String>,
         json_schema_string: String,
+        /// Migration to apply to existing entities once this version is activated
+        #[serde(default)]
+        migration: Option<MigrationSpec>,
     },
     DefDeleted {
         #[id]
         id: DefId,
         deleted_at: DateTime<Utc>,
         deleted_by: String,
+        #[serde(default)]
+        on_behalf_of: Option<String>,
     },
     DefValidated {
         #[id]
         id: DefId,
         validated_at: DateTime<Utc>,
         validated_by: String,
+        #[serde(default)]
+        on_behalf_of: Option<String>,
         validation_result: String,
     },
     DefValidatedFailed {
@@ -72,6 +97,8 @@ pub enum DomainEvent {
         id: DefId,
         validated_at: DateTime<Utc>,
         validated_by: String,
+        #[serde(default)]
+        on_behalf_of: Option<String>,
         validation_result: String,
         validation_errors: Vec<String>,
     },
@@ -80,13 +107,21 @@ pub enum DomainEvent {
         id: DefId,
         activated_at: DateTime<Utc>,
         activated_by: String,
+        #[serde(default)]
+        on_behalf_of: Option<String>,
         json_schema_string: String,
+        #[serde(default)]
+        version: Version,
+        #[serde(default)]
+        migration: Option<MigrationSpec>,
     },
     DefDeactivated {
         #[id]
         id: DefId,
         deactivated_at: DateTime<Utc>,
         deactivated_by: String,
+        #[serde(default)]
+        on_behalf_of: Option<String>,
     },
     EntityCreated {
         #[id]
@@ -97,7 +132,15 @@ pub enum DomainEvent {
         entity_type: String,
         created_at: DateTime<Utc>,
         created_by: String,
+        #[serde(default)]
+        on_behalf_of: Option<String>,
         version: Version,
+        /// References to other entities held by the entity body
+        #[serde(default)]
+        references: Vec<EntityReference>,
+        /// Owners resolved from the `ownershipAttributes` of the definition
+        #[serde(default)]
+        owners: Vec<Owner>,
     },
     EntityInvited {
         #[id]
@@ -108,8 +151,26 @@ pub enum DomainEvent {
         entity_type: String,
         invited_at: DateTime<Utc>,
         invited_by: String,
+        #[serde(default)]
+        on_behalf_of: Option<String>,
         version: Version,
     },
+    /// A self-registered entity, pending until its owner confirms the one-time code, see
+    /// [`crate::registration`]
+    EntityRegistered {
+        #[id]
+        id: EntityId,
+        registry_def_id: DefId,
+        registry_def_version: Version,
+        entity_body: String,
+        entity_type: String,
+        registered_at: DateTime<Utc>,
+        registered_by: String,
+        #[serde(default)]
+        on_behalf_of: Option<String>,
+        references: Vec<EntityReference>,
+        owners: Vec<Owner>,
+    },
     EntityUpdated {
         #[id]
         id: EntityId,
@@ -119,7 +180,12 @@ pub enum DomainEvent {
         entity_type: String,
         updated_at: DateTime<Utc>,
         updated_by: String,
+        #[serde(default)]
+        on_behalf_of: Option<String>,
         version: Version,
+        /// References to other entities held by the entity body
+        #[serde(default)]
+        references: Vec<EntityReference>,
     },
     EntityPropertyUpdated {
         #[id]
@@ -130,6 +196,8 @@ pub enum DomainEvent {
         property_value: String,
         updated_at: DateTime<Utc>,
         created_by: String,
+        #[serde(default)]
+        on_behalf_of: Option<String>,
     },
     EntityPropertyAdded {
         #[id]
@@ -140,12 +208,199 @@ pub enum DomainEvent {
         property_value: String,
         added_at: DateTime<Utc>,
         created_by: String,
+        #[serde(default)]
+        on_behalf_of: Option<String>,
     },
     EntityDeleted {
         #[id]
         id: EntityId,
         deleted_at: DateTime<Utc>,
         deleted_by: String,
+        #[serde(default)]
+        on_behalf_of: Option<String>,
+    },
+    /// The attested snapshot of a granted claim was recorded in `_osAttestedData`
+    EntityAttested {
+        #[id]
+        id: EntityId,
+        entity_type: String,
+        entity_body: String,
+        claim_id: ClaimId,
+        policy_name: String,
+        attested_at: DateTime<Utc>,
+        attested_by: String,
+        #[serde(default)]
+        on_behalf_of: Option<String>,
+    },
+    /// A verifier plugin attested a property, the outcome was recorded in `_osAttestedData`
+    EntityAutoAttested {
+        #[id]
+        id: EntityId,
+        entity_type: String,
+        entity_body: String,
+        property: String,
+        verifier: String,
+        verified: bool,
+        attested_at: DateTime<Utc>,
+        attested_by: String,
+        #[serde(default)]
+        on_behalf_of: Option<String>,
+    },
+    /// A verifiable credential of the entity was issued, see [`crate::credentials`]
+    CredentialIssued {
+        #[id]
+        id: EntityId,
+        #[id]
+        credential_id: CredentialId,
+        entity_type: String,
+        /// The attestation policy whose template was used, the definition template otherwise
+        policy_name: Option<String>,
+        format: CredentialFormat,
+        /// The signed credential, an encrypted envelope when `encrypted`
+        credential: String,
+        encrypted: bool,
+        /// Position of the credential in the status lists, see [`crate::status_list`]
+        #[serde(default)]
+        status_list_index: Option<u64>,
+        issued_at: DateTime<Utc>,
+        issued_by: String,
+        #[serde(default)]
+        on_behalf_of: Option<String>,
+    },
+    /// The revocation bit of a credential was set, revocation is permanent
+    CredentialRevoked {
+        #[id]
+        id: EntityId,
+        #[id]
+        credential_id: CredentialId,
+        status_list_index: u64,
+        reason: Option<String>,
+        revoked_at: DateTime<Utc>,
+        revoked_by: String,
+        #[serde(default)]
+        on_behalf_of: Option<String>,
+    },
+    /// The suspension bit of a credential was set
+    CredentialSuspended {
+        #[id]
+        id: EntityId,
+        #[id]
+        credential_id: CredentialId,
+        status_list_index: u64,
+        reason: Option<String>,
+        suspended_at: DateTime<Utc>,
+        suspended_by: String,
+        #[serde(default)]
+        on_behalf_of: Option<String>,
+    },
+    /// The suspension bit of a credential was cleared
+    CredentialReinstated {
+        #[id]
+        id: EntityId,
+        #[id]
+        credential_id: CredentialId,
+        status_list_index: u64,
+        reason: Option<String>,
+        reinstated_at: DateTime<Utc>,
+        reinstated_by: String,
+        #[serde(default)]
+        on_behalf_of: Option<String>,
+    },
+    ClaimRaised {
+        #[id]
+        claim_id: ClaimId,
+        entity_id: EntityId,
+        entity_type: String,
+        policy_name: String,
+        /// Entity type of the attestors the claim is routed to
+        attestor_entity: String,
+        /// The attestor entity the claim is routed to, any entity of `attestor_entity` when unset
+        #[serde(default)]
+        attestor_id: Option<EntityId>,
+        /// Snapshot of the properties to attest, as JSON
+        property_data: String,
+        raised_at: DateTime<Utc>,
+        raised_by: String,
+        #[serde(default)]
+        on_behalf_of: Option<String>,
+    },
+    ClaimAttested {
+        #[id]
+        claim_id: ClaimId,
+        entity_id: EntityId,
+        attestor_id: EntityId,
+        notes: Option<String>,
+        attested_at: DateTime<Utc>,
+        attested_by: String,
+        #[serde(default)]
+        on_behalf_of: Option<String>,
+    },
+    ClaimRejected {
+        #[id]
+        claim_id: ClaimId,
+        entity_id: EntityId,
+        attestor_id: EntityId,
+        notes: Option<String>,
+        rejected_at: DateTime<Utc>,
+        rejected_by: String,
+        #[serde(default)]
+        on_behalf_of: Option<String>,
+    },
+    /// A requester asked for fields of an entity, see [`crate::consent`]
+    ConsentRequested {
+        #[id]
+        consent_id: ConsentId,
+        entity_id: EntityId,
+        entity_type: String,
+        /// Paths of the requested fields
+        fields: Vec<String>,
+        purpose: Option<String>,
+        requested_at: DateTime<Utc>,
+        requested_by: String,
+        #[serde(default)]
+        on_behalf_of: Option<String>,
+    },
+    ConsentGranted {
+        #[id]
+        consent_id: ConsentId,
+        entity_id: EntityId,
+        expires_at: DateTime<Utc>,
+        notes: Option<String>,
+        granted_at: DateTime<Utc>,
+        granted_by: String,
+        #[serde(default)]
+        on_behalf_of: Option<String>,
+    },
+    ConsentDenied {
+        #[id]
+        consent_id: ConsentId,
+        entity_id: EntityId,
+        notes: Option<String>,
+        denied_at: DateTime<Utc>,
+        denied_by: String,
+        #[serde(default)]
+        on_behalf_of: Option<String>,
+    },
+    ConsentRevoked {
+        #[id]
+        consent_id: ConsentId,
+        entity_id: EntityId,
+        notes: Option<String>,
+        revoked_at: DateTime<Utc>,
+        revoked_by: String,
+        #[serde(default)]
+        on_behalf_of: Option<String>,
+    },
+    /// The requester of a granted consent read the consented fields
+    ConsentedDataAccessed {
+        #[id]
+        consent_id: ConsentId,
+        entity_id: EntityId,
+        fields: Vec<String>,
+        accessed_at: DateTime<Utc>,
+        accessed_by: String,
+        #[serde(default)]
+        on_behalf_of: Option<String>,
     },
 }
 
@@ -178,6 +433,8 @@ pub enum DefError {
     InvalidUUID(String, String),
     #[error("Event type {0} not found")]
     EventNotFound(String),
+    #[error("Invalid Migration: {0}")]
+    InvalidMigration(String),
 }
 
 // start of mutations
@@ -207,6 +464,11 @@ pub struct RegistryDefinition {
     pub json_schema_string: String,
     pub title: String,
     pub version: Version,
+    /// Migration submitted with the last update, applied when the update is activated
+    pub pending_migration: Option<MigrationSpec>,
+    /// Migrations of the activated versions, oldest first
+    #[serde(default)]
+    pub migrations: Vec<(Version, MigrationSpec)>,
 }
 
 impl RegistryDefinition {
@@ -216,6 +478,14 @@ impl RegistryDefinition {
             ..Default::default()
         }
     }
+
+    /// The migrations activated after `version`, in the order they must be applied
+    pub fn migrations_since(&self, version: Version) -> impl Iterator<Item = &MigrationSpec> {
+        self.migrations
+            .iter()
+            .filter(move |(activated, _)| *activated > version)
+            .map(|(_, migration)| migration)
+    }
 }
 
 impl StateMutate for RegistryDefinition {
@@ -227,6 +497,7 @@ impl StateMutate for RegistryDefinition {
                 definitions: _,
                 created_at: _,
                 created_by: _,
+                on_behalf_of: _,
                 json_schema_string,
             } => {
                 self.record_status = DefRecordStatus::Draft;
@@ -240,11 +511,14 @@ impl StateMutate for RegistryDefinition {
                 definitions: _,
                 created_at: _,
                 updated_by: _,
+                on_behalf_of: _,
                 json_schema_string,
+                migration,
             } => {
                 self.record_status = DefRecordStatus::Draft;
                 self.json_schema_string = json_schema_string;
                 self.version = self.version.increment();
+                self.pending_migration = migration;
             }
             DomainEvent::DefValidated { .. } => {
                 self.record_status = DefRecordStatus::Valid;
@@ -253,10 +527,17 @@ impl StateMutate for RegistryDefinition {
                 self.record_status = DefRecordStatus::Invalid;
             }
             DomainEvent::DefActivated {
-                json_schema_string, ..
+                json_schema_string,
+                version,
+                migration,
+                ..
             } => {
                 self.record_status = DefRecordStatus::Active;
                 self.json_schema_string = json_schema_string;
+                self.pending_migration = None;
+                if let Some(migration) = migration {
+                    self.migrations.push((version, migration));
+                }
             }
             DomainEvent::DefDeactivated { .. } => {
                 self.record_status = DefRecordStatus::Deactivated;
@@ -276,7 +557,7 @@ pub struct CreateDefinitionCmd {
     pub id: DefId,
     pub title: String,
     pub definitions: Vec<String>,
-    pub created_by: String,
+    pub created_by: Actor,
     pub json_schema_string: String,
 }
 impl Decision for CreateDefinitionCmd {
@@ -303,7 +584,8 @@ impl Decision for CreateDefinitionCmd {
             title: def_title,
             definitions: self.definitions.clone(),
             created_at: Utc::now(),
-            created_by: self.created_by.clone(),
+            created_by: self.created_by.id.clone(),
+            on_behalf_of: self.created_by.on_behalf_of.clone(),
             json_schema_string: self.json_schema_string.clone(),
         }])
     }
@@ -313,8 +595,9 @@ pub struct UpdateDefinitionCmd {
     pub id: DefId,
     pub definitions: Vec<String>,
     pub created_at: DateTime<Utc>,
-    pub updated_by: String,
+    pub updated_by: Actor,
     pub json_schema_string: String,
+    pub migration: Option<MigrationSpec>,
 }
 
 impl Decision for UpdateDefinitionCmd {
@@ -333,13 +616,20 @@ impl Decision for UpdateDefinitionCmd {
         if def_title != state.title {
             return Err(DefError::TitleIsNotMutable(def_title, state.title.clone()));
         }
+        if let Some(migration) = &self.migration {
+            migration
+                .validate()
+                .map_err(|e| DefError::InvalidMigration(e.to_string()))?;
+        }
         Ok(vec![DomainEvent::DefUpdated {
             id: self.id,
             title: def_title,
             definitions: self.definitions.clone(),
             created_at: self.created_at,
-            updated_by: self.updated_by.clone(),
+            updated_by: self.updated_by.id.clone(),
+            on_behalf_of: self.updated_by.on_behalf_of.clone(),
             json_schema_string: self.json_schema_string.clone(),
+            migration: self.migration.clone(),
         }])
     }
 }
@@ -347,7 +637,7 @@ impl Decision for UpdateDefinitionCmd {
 pub struct ValidateDefinitionCmd {
     pub id: DefId,
     pub validated_at: DateTime<Utc>,
-    pub validated_by: String,
+    pub validated_by: Actor,
 }
 
 // Load the definition check if the
@@ -365,23 +655,41 @@ impl Decision for ValidateDefinitionCmd {
         }
 
         match read_title(&state.json_schema_string) {
-            Ok(result) if !result.is_empty() => Ok(vec![DomainEvent::DefValidated {
-                id: self.id,
-                validated_at: self.validated_at,
-                validated_by: self.validated_by.clone(),
-                validation_result: "Success".to_string(),
-            }]),
+            Ok(result) if !result.is_empty() => {
+                let validation_errors = serde_json::from_str(&state.json_schema_string)
+                    .map(|schema| validate_attestation_policies(&schema))
+                    .unwrap_or_default();
+                if !validation_errors.is_empty() {
+                    return Ok(vec![DomainEvent::DefValidatedFailed {
+                        id: self.id,
+                        validated_at: self.validated_at,
+                        validated_by: self.validated_by.id.clone(),
+                        on_behalf_of: self.validated_by.on_behalf_of.clone(),
+                        validation_result: "failure".to_string(),
+                        validation_errors,
+                    }]);
+                }
+                Ok(vec![DomainEvent::DefValidated {
+                    id: self.id,
+                    validated_at: self.validated_at,
+                    validated_by: self.validated_by.id.clone(),
+                    on_behalf_of: self.validated_by.on_behalf_of.clone(),
+                    validation_result: "Success".to_string(),
+                }])
+            }
             Ok(result) => Ok(vec![DomainEvent::DefValidatedFailed {
                 id: self.id,
                 validated_at: self.validated_at,
-                validated_by: self.validated_by.clone(),
+                validated_by: self.validated_by.id.clone(),
+                on_behalf_of: self.validated_by.on_behalf_of.clone(),
                 validation_result: result,
                 validation_errors: vec![],
             }]),
             Err(err) => Ok(vec![DomainEvent::DefValidatedFailed {
                 id: self.id,
                 validated_at: self.validated_at,
-                validated_by: self.validated_by.clone(),
+                validated_by: self.validated_by.id.clone(),
+                on_behalf_of: self.validated_by.on_behalf_of.clone(),
                 validation_result: "failure".to_string(),
                 validation_errors: vec![err.to_string()],
             }]),
@@ -392,7 +700,7 @@ impl Decision for ValidateDefinitionCmd {
 pub struct ActivateDefinitionCmd {
     pub id: DefId,
     pub activated_at: DateTime<Utc>,
-    pub activated_by: String,
+    pub activated_by: Actor,
 }
 impl Decision for ActivateDefinitionCmd {
     type Event = DomainEvent;
@@ -409,8 +717,11 @@ impl Decision for ActivateDefinitionCmd {
         Ok(vec![DomainEvent::DefActivated {
             id: self.id,
             activated_at: self.activated_at,
-            activated_by: self.activated_by.clone(),
+            activated_by: self.activated_by.id.clone(),
+            on_behalf_of: self.activated_by.on_behalf_of.clone(),
             json_schema_string: state.json_schema_string.clone(),
+            version: state.version,
+            migration: state.pending_migration.clone(),
         }])
     }
 }
@@ -418,7 +729,7 @@ impl Decision for ActivateDefinitionCmd {
 pub struct DeactivateDefinitionCmd {
     id: DefId,
     deactivated_at: DateTime<Utc>,
-    deactivated_by: String,
+    deactivated_by: Actor,
 }
 impl Decision for DeactivateDefinitionCmd {
     type Event = DomainEvent;
@@ -435,7 +746,8 @@ impl Decision for DeactivateDefinitionCmd {
         Ok(vec![DomainEvent::DefDeactivated {
             id: self.id,
             deactivated_at: self.deactivated_at,
-            deactivated_by: self.deactivated_by.clone(),
+            deactivated_by: self.deactivated_by.id.clone(),
+            on_behalf_of: self.deactivated_by.on_behalf_of.clone(),
         }])
     }
 }
@@ -443,7 +755,7 @@ impl Decision for DeactivateDefinitionCmd {
 pub struct DeleteDefinitionCmd {
     id: DefId,
     deleted_at: DateTime<Utc>,
-    deleted_by: String,
+    deleted_by: Actor,
 }
 impl Decision for DeleteDefinitionCmd {
     type Event = DomainEvent;
@@ -460,7 +772,8 @@ impl Decision for DeleteDefinitionCmd {
         Ok(vec![DomainEvent::DefDeleted {
             id: self.id,
             deleted_at: self.deleted_at,
-            deleted_by: self.deleted_by.clone(),
+            deleted_by: self.deleted_by.id.clone(),
+            on_behalf_of: self.deleted_by.on_behalf_of.clone(),
         }])
     }
 }
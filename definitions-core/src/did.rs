@@ -0,0 +1,310 @@
+//! Decentralized identifiers of the registry and of the parties it deals with.
+//!
+//! The registry is identified by a `did:web` derived from its public URL, e.g.
+//! `did:web:registry.example.com`, whose DID document is served at `/.well-known/did.json`.
+//! The document lists every issuer key, retired ones included so that old credentials keep
+//! verifying, and names the `did:key` of the current key in `alsoKnownAs`.
+//!
+//! Incoming identifiers are resolved with [`did_key_document`] for `did:key`, which needs no
+//! network access, and by fetching [`did_web_url`] for `did:web`. [`verification_keys`] reads
+//! the Ed25519 keys of a resolved document.
+use crate::signing::{IssuerKey, PublicKey};
+use base64::engine::general_purpose::URL_SAFE_NO_PAD as BASE64URL;
+use base64::Engine;
+use serde_json::{json, Value};
+use thiserror::Error;
+
+pub const DID_CONTEXT: &str = "https://www.w3.org/ns/did/v1";
+pub const JWS_2020_CONTEXT: &str = "https://w3id.org/security/suites/jws-2020/v1";
+pub const ED25519_2020_CONTEXT: &str = "https://w3id.org/security/suites/ed25519-2020/v1";
+pub const DID_DOCUMENT_PATH: &str = "/.well-known/did.json";
+/// Multicodec prefix of an Ed25519 public key, `0xed` as an unsigned varint
+const ED25519_MULTICODEC: [u8; 2] = [0xed, 0x01];
+
+#[derive(Debug, Error, PartialEq, Eq)]
+pub enum DidError {
+    #[error("Invalid DID `{0}`")]
+    InvalidDid(String),
+    #[error("DID method of `{0}` is not supported")]
+    UnsupportedMethod(String),
+    #[error("Invalid key in `{0}`")]
+    InvalidKey(String),
+    #[error("Failed to resolve `{0}`: {1}")]
+    Resolution(String, String),
+}
+
+/// The `did:key` of an Ed25519 public key
+pub fn did_key(key: &PublicKey) -> Result<String, DidError> {
+    let bytes = BASE64URL
+        .decode(&key.x)
+        .map_err(|_| DidError::InvalidKey(key.kid.clone()))?;
+    Ok(format!("did:key:{}", multibase_key(&bytes)))
+}
+
+/// The `did:web` of a URL, the port is percent encoded and the path becomes `:` separated
+pub fn did_web(url: &str) -> Result<String, DidError> {
+    let authority_and_path = url
+        .split_once("://")
+        .map_or(url, |(_, rest)| rest)
+        .trim_end_matches('/');
+    let mut segments = authority_and_path.split('/');
+    let host = segments
+        .next()
+        .filter(|host| !host.is_empty())
+        .ok_or_else(|| DidError::InvalidDid(url.to_string()))?;
+    let mut did = format!("did:web:{}", host.replace(':', "%3A"));
+    for segment in segments.filter(|segment| !segment.is_empty()) {
+        did.push(':');
+        did.push_str(segment);
+    }
+    Ok(did)
+}
+
+/// The URL of the DID document of a `did:web`, `/.well-known/did.json` when the DID has no path.
+/// Loopback hosts are fetched over plain HTTP, so that local deployments can be resolved.
+pub fn did_web_url(did: &str) -> Result<String, DidError> {
+    let did = did.split_once('#').map_or(did, |(did, _)| did);
+    let identifier = did
+        .strip_prefix("did:web:")
+        .ok_or_else(|| unsupported_or_invalid(did))?;
+    let mut segments = identifier.split(':');
+    let host = segments
+        .next()
+        .filter(|host| !host.is_empty())
+        .ok_or_else(|| DidError::InvalidDid(did.to_string()))?
+        .replace("%3A", ":")
+        .replace("%3a", ":");
+    let path: Vec<&str> = segments.collect();
+    let scheme = if is_loopback(&host) { "http" } else { "https" };
+    if path.is_empty() {
+        Ok(format!("{scheme}://{host}{DID_DOCUMENT_PATH}"))
+    } else {
+        Ok(format!("{scheme}://{host}/{}/did.json", path.join("/")))
+    }
+}
+
+/// The DID document of a `did:key`, derived from the identifier itself
+pub fn did_key_document(did: &str) -> Result<Value, DidError> {
+    let did = did.split_once('#').map_or(did, |(did, _)| did);
+    let fingerprint = did
+        .strip_prefix("did:key:")
+        .ok_or_else(|| unsupported_or_invalid(did))?;
+    let key = decode_multibase_key(fingerprint).ok_or_else(|| DidError::InvalidKey(did.into()))?;
+    let method = format!("{did}#{fingerprint}");
+    Ok(json!({
+        "@context": [DID_CONTEXT, ED25519_2020_CONTEXT],
+        "id": did,
+        "verificationMethod": [{
+            "id": method,
+            "type": "Ed25519VerificationKey2020",
+            "controller": did,
+            "publicKeyMultibase": multibase_key(&key),
+        }],
+        "assertionMethod": [method],
+        "authentication": [method],
+    }))
+}
+
+/// The Ed25519 keys of a DID document, `publicKeyJwk` or `publicKeyMultibase`. The `kid` of each
+/// key is the absolute id of its verification method, e.g. `did:web:example.com#key-1`.
+pub fn verification_keys(document: &Value) -> Vec<PublicKey> {
+    let did = document
+        .get("id")
+        .and_then(Value::as_str)
+        .unwrap_or_default();
+    let Some(Value::Array(methods)) = document.get("verificationMethod") else {
+        return vec![];
+    };
+    methods
+        .iter()
+        .filter_map(|method| {
+            let id = method.get("id")?.as_str()?;
+            let kid = if id.starts_with('#') {
+                format!("{did}{id}")
+            } else {
+                id.to_string()
+            };
+            let x = match (method.get("publicKeyJwk"), method.get("publicKeyMultibase")) {
+                (Some(jwk), _) => {
+                    if jwk.get("crv").and_then(Value::as_str) != Some("Ed25519") {
+                        return None;
+                    }
+                    jwk.get("x")?.as_str()?.to_string()
+                }
+                (None, Some(Value::String(multibase))) => {
+                    BASE64URL.encode(decode_multibase_key(multibase)?)
+                }
+                _ => return None,
+            };
+            Some(PublicKey { kid, x })
+        })
+        .collect()
+}
+
+/// The DID the registry issues credentials as, with its current and retired keys
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct IssuerIdentity {
+    did: String,
+    key: IssuerKey,
+    retired: Vec<PublicKey>,
+}
+
+impl IssuerIdentity {
+    pub fn new(did: impl Into<String>, key: IssuerKey, retired: Vec<PublicKey>) -> Self {
+        Self {
+            did: did.into(),
+            key,
+            retired,
+        }
+    }
+
+    pub fn did(&self) -> &str {
+        &self.did
+    }
+
+    /// The current key, signing new credentials and signatures
+    pub fn key(&self) -> &IssuerKey {
+        &self.key
+    }
+
+    /// The current key followed by the retired ones
+    pub fn public_keys(&self) -> Vec<PublicKey> {
+        let mut keys = vec![self.key.public_key()];
+        keys.extend(self.retired.iter().cloned());
+        keys
+    }
+
+    /// The DID document served at `/.well-known/did.json`. Every key is an assertion method,
+    /// only the current one authenticates the registry.
+    pub fn document(&self) -> Value {
+        let method_id = |key: &PublicKey| format!("{}#{}", self.did, key.kid);
+        let keys = self.public_keys();
+        let mut document = json!({
+            "@context": [DID_CONTEXT, JWS_2020_CONTEXT],
+            "id": self.did,
+            "verificationMethod": keys.iter().map(|key| json!({
+                "id": method_id(key),
+                "type": "JsonWebKey2020",
+                "controller": self.did,
+                "publicKeyJwk": key.to_jwk(),
+            })).collect::<Vec<_>>(),
+            "assertionMethod": keys.iter().map(method_id).collect::<Vec<_>>(),
+            "authentication": [method_id(&self.key.public_key())],
+        });
+        if let Ok(did_key) = did_key(&self.key.public_key()) {
+            document["alsoKnownAs"] = json!([did_key]);
+        }
+        document
+    }
+}
+
+fn multibase_key(key: &[u8]) -> String {
+    let mut bytes = ED25519_MULTICODEC.to_vec();
+    bytes.extend_from_slice(key);
+    format!("z{}", bs58::encode(bytes).into_string())
+}
+
+/// The raw 32 bytes of a base58btc multibase Ed25519 key, with or without its multicodec prefix
+fn decode_multibase_key(multibase: &str) -> Option<Vec<u8>> {
+    let bytes = bs58::decode(multibase.strip_prefix('z')?).into_vec().ok()?;
+    match bytes.len() {
+        34 if bytes[..2] == ED25519_MULTICODEC => Some(bytes[2..].to_vec()),
+        32 => Some(bytes),
+        _ => None,
+    }
+}
+
+fn unsupported_or_invalid(did: &str) -> DidError {
+    if did.starts_with("did:") {
+        DidError::UnsupportedMethod(did.to_string())
+    } else {
+        DidError::InvalidDid(did.to_string())
+    }
+}
+
+fn is_loopback(host: &str) -> bool {
+    let name = host.rsplit_once(':').map_or(host, |(name, _)| name);
+    matches!(name, "localhost" | "127.0.0.1" | "[::1]")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn issuer() -> IssuerKey {
+        IssuerKey::from_seed("issuer-1", [5; 32])
+    }
+
+    #[test]
+    fn test_did_key_round_trip() {
+        let did = did_key(&issuer().public_key()).unwrap();
+        assert!(did.starts_with("did:key:z6Mk"), "{did}");
+
+        let keys = verification_keys(&did_key_document(&did).unwrap());
+        let fingerprint = did.trim_start_matches("did:key:");
+        assert_eq!(
+            keys,
+            vec![PublicKey {
+                kid: format!("{did}#{fingerprint}"),
+                x: issuer().public_key().x,
+            }]
+        );
+        assert_eq!(
+            did_key_document("did:example:123"),
+            Err(DidError::UnsupportedMethod("did:example:123".to_string()))
+        );
+    }
+
+    #[test]
+    fn test_did_web_urls() {
+        assert_eq!(
+            did_web("https://registry.example.com/").unwrap(),
+            "did:web:registry.example.com"
+        );
+        assert_eq!(
+            did_web("http://localhost:8000/tenants/a").unwrap(),
+            "did:web:localhost%3A8000:tenants:a"
+        );
+        assert_eq!(
+            did_web_url("did:web:registry.example.com#key-1").unwrap(),
+            "https://registry.example.com/.well-known/did.json"
+        );
+        assert_eq!(
+            did_web_url("did:web:localhost%3A8000:tenants:a").unwrap(),
+            "http://localhost:8000/tenants/a/did.json"
+        );
+    }
+
+    #[test]
+    fn test_issuer_document_lists_retired_keys() {
+        let retired = IssuerKey::from_seed("issuer-0", [4; 32]).public_key();
+        let identity = IssuerIdentity::new("did:web:registry.example.com", issuer(), vec![retired]);
+
+        let document = identity.document();
+        assert_eq!(
+            document["assertionMethod"],
+            json!([
+                "did:web:registry.example.com#issuer-1",
+                "did:web:registry.example.com#issuer-0"
+            ])
+        );
+        assert_eq!(
+            document["authentication"],
+            json!(["did:web:registry.example.com#issuer-1"])
+        );
+        assert_eq!(
+            document["alsoKnownAs"],
+            json!([did_key(&issuer().public_key()).unwrap()])
+        );
+        assert_eq!(
+            verification_keys(&document)
+                .into_iter()
+                .map(|key| key.kid)
+                .collect::<Vec<_>>(),
+            vec![
+                "did:web:registry.example.com#issuer-1",
+                "did:web:registry.example.com#issuer-0"
+            ]
+        );
+    }
+}
@@ -0,0 +1,364 @@
+//! Field level encryption of `privateFields`.
+//!
+//! A definition lists the paths holding personal data in `_osConfig.privateFields`:
+//!
+//! ```json
+//! "privateFields": ["$.identityDetails.dob", "$.identityDetails.identityValue"]
+//! ```
+//!
+//! Before an entity body is written to an event, the value at each of these paths is replaced by
+//! an [`EncryptedValue`] envelope: the value is encrypted with AES-256-GCM under a fresh data key,
+//! and the data key itself is encrypted ("wrapped") with a key encryption key of a [`KeyStore`].
+//! Rotating the key encryption key therefore never requires re-encrypting entity data, old
+//! envelopes name the key that wrapped them.
+use crate::json_path::{entity_node_mut, JsonPath};
+use aes_gcm::aead::{Aead, KeyInit, OsRng};
+use aes_gcm::{AeadCore, Aes256Gcm, Key, Nonce};
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::fmt;
+use std::sync::Arc;
+use thiserror::Error;
+
+pub const PRIVATE_FIELDS: &str = "privateFields";
+/// Key of the object replacing an encrypted value in an entity body
+pub const ENCRYPTED_MARKER: &str = "$enc";
+pub const ALGORITHM: &str = "A256GCM";
+
+#[derive(Debug, Error, PartialEq, Eq)]
+pub enum EncryptionError {
+    #[error("Key `{0}` not found in keystore")]
+    KeyNotFound(String),
+    #[error("Keystore error: {0}")]
+    KeyStore(String),
+    #[error("Invalid path in privateFields: {0}")]
+    InvalidPath(String),
+    #[error("Encryption failed: {0}")]
+    Encrypt(String),
+    #[error("Decryption failed: {0}")]
+    Decrypt(String),
+}
+
+/// Source of the key encryption keys
+pub trait KeyStore: Send + Sync {
+    /// Id of the key used to wrap new data keys
+    fn current_key_id(&self) -> Result<String, EncryptionError>;
+    /// The 256 bit key with the given id
+    fn key(&self, key_id: &str) -> Result<[u8; 32], EncryptionError>;
+}
+
+/// Generates a random 256 bit key
+pub fn generate_key() -> [u8; 32] {
+    Aes256Gcm::generate_key(OsRng).into()
+}
+
+/// An encrypted JSON value
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct EncryptedValue {
+    pub alg: String,
+    /// Id of the key encryption key
+    pub kid: String,
+    /// The data key, wrapped with the key encryption key (nonce followed by ciphertext, base64)
+    pub key: String,
+    /// Nonce of the value ciphertext, base64
+    pub iv: String,
+    /// Ciphertext of the JSON serialized value, base64
+    pub data: String,
+}
+
+impl EncryptedValue {
+    /// Reads an envelope written by [`FieldCipher::encrypt_paths`]
+    pub fn from_value(value: &Value) -> Option<Self> {
+        let object = value.as_object()?;
+        if object.len() != 1 {
+            return None;
+        }
+        serde_json::from_value(object.get(ENCRYPTED_MARKER)?.clone()).ok()
+    }
+
+    pub fn to_value(&self) -> Value {
+        let mut object = serde_json::Map::new();
+        object.insert(
+            ENCRYPTED_MARKER.to_string(),
+            serde_json::to_value(self).unwrap_or_default(),
+        );
+        Value::Object(object)
+    }
+}
+
+/// Encrypts and decrypts entity fields with the keys of a [`KeyStore`]
+#[derive(Clone)]
+pub struct FieldCipher {
+    keystore: Arc<dyn KeyStore>,
+}
+
+impl fmt::Debug for FieldCipher {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("FieldCipher").finish_non_exhaustive()
+    }
+}
+
+/// Two ciphers are equal when they share the same keystore
+impl PartialEq for FieldCipher {
+    fn eq(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.keystore, &other.keystore)
+    }
+}
+
+impl Eq for FieldCipher {}
+
+impl FieldCipher {
+    pub fn new(keystore: Arc<dyn KeyStore>) -> Self {
+        Self { keystore }
+    }
+
+    /// Replaces the values at `paths` with encrypted envelopes.
+    ///
+    /// Paths are relative to the entity node, missing values and values that are already
+    /// encrypted are left alone. All values of one call share a single data key.
+    pub fn encrypt_paths(
+        &self,
+        document: &mut Value,
+        entity_type: &str,
+        paths: &[String],
+    ) -> Result<(), EncryptionError> {
+        let paths = paths
+            .iter()
+            .map(|path| {
+                JsonPath::parse(path).map_err(|e| EncryptionError::InvalidPath(e.to_string()))
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        let node = entity_node_mut(document, entity_type);
+        if !paths
+            .iter()
+            .any(|path| path.get(node).is_some_and(|value| !is_encrypted(value)))
+        {
+            return Ok(());
+        }
+
+        let kid = self.keystore.current_key_id()?;
+        let kek = self.keystore.key(&kid)?;
+        let data_key = Aes256Gcm::generate_key(OsRng);
+        let wrapped_key = seal(&kek.into(), &data_key)?;
+        let data_cipher = Aes256Gcm::new(&data_key);
+
+        for path in &paths {
+            let Some(value) = path.get_mut(node) else {
+                continue;
+            };
+            if value.is_null() || is_encrypted(value) {
+                continue;
+            }
+            let plaintext =
+                serde_json::to_vec(value).map_err(|e| EncryptionError::Encrypt(e.to_string()))?;
+            let nonce = Aes256Gcm::generate_nonce(&mut OsRng);
+            let ciphertext = data_cipher
+                .encrypt(&nonce, plaintext.as_slice())
+                .map_err(|e| EncryptionError::Encrypt(e.to_string()))?;
+            *value = EncryptedValue {
+                alg: ALGORITHM.to_string(),
+                kid: kid.clone(),
+                key: wrapped_key.clone(),
+                iv: BASE64.encode(nonce),
+                data: BASE64.encode(ciphertext),
+            }
+            .to_value();
+        }
+        Ok(())
+    }
+
+    /// Replaces every encrypted envelope of a document by its plaintext value
+    pub fn decrypt_all(&self, document: &mut Value) -> Result<(), EncryptionError> {
+        if let Some(envelope) = EncryptedValue::from_value(document) {
+            *document = self.decrypt(&envelope)?;
+            return Ok(());
+        }
+        match document {
+            Value::Object(fields) => {
+                for value in fields.values_mut() {
+                    self.decrypt_all(value)?;
+                }
+            }
+            Value::Array(items) => {
+                for item in items {
+                    self.decrypt_all(item)?;
+                }
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+
+    pub fn decrypt(&self, envelope: &EncryptedValue) -> Result<Value, EncryptionError> {
+        if envelope.alg != ALGORITHM {
+            return Err(EncryptionError::Decrypt(format!(
+                "unsupported algorithm `{}`",
+                envelope.alg
+            )));
+        }
+        let kek = self.keystore.key(&envelope.kid)?;
+        let data_key = open(&kek.into(), &envelope.key)?;
+        if data_key.len() != 32 {
+            return Err(EncryptionError::Decrypt("invalid data key".to_string()));
+        }
+        let data_cipher = Aes256Gcm::new(Key::<Aes256Gcm>::from_slice(&data_key));
+        let nonce = decode(&envelope.iv)?;
+        if nonce.len() != 12 {
+            return Err(EncryptionError::Decrypt("invalid nonce".to_string()));
+        }
+        let plaintext = data_cipher
+            .decrypt(
+                Nonce::from_slice(&nonce),
+                decode(&envelope.data)?.as_slice(),
+            )
+            .map_err(|e| EncryptionError::Decrypt(e.to_string()))?;
+        serde_json::from_slice(&plaintext).map_err(|e| EncryptionError::Decrypt(e.to_string()))
+    }
+}
+
+/// Returns true when `value` is an encrypted envelope
+pub fn is_encrypted(value: &Value) -> bool {
+    EncryptedValue::from_value(value).is_some()
+}
+
+/// Returns true when any value of the document is encrypted
+pub fn contains_encrypted(document: &Value) -> bool {
+    match document {
+        _ if is_encrypted(document) => true,
+        Value::Object(fields) => fields.values().any(contains_encrypted),
+        Value::Array(items) => items.iter().any(contains_encrypted),
+        _ => false,
+    }
+}
+
+/// Reads `_osConfig.privateFields` of a schema
+pub fn private_fields(schema: &Value) -> Vec<String> {
+    schema
+        .get("_osConfig")
+        .and_then(|config| config.get(PRIVATE_FIELDS))
+        .and_then(Value::as_array)
+        .map(|paths| {
+            paths
+                .iter()
+                .filter_map(Value::as_str)
+                .map(str::to_string)
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+/// Encrypts `plaintext` with a 256 bit key, e.g. to store key material at rest
+pub fn seal_with_key(key: &[u8; 32], plaintext: &[u8]) -> Result<String, EncryptionError> {
+    seal(Key::<Aes256Gcm>::from_slice(key), plaintext)
+}
+
+/// Decrypts the output of [`seal_with_key`]
+pub fn open_with_key(key: &[u8; 32], sealed: &str) -> Result<Vec<u8>, EncryptionError> {
+    open(Key::<Aes256Gcm>::from_slice(key), sealed)
+}
+
+/// Encrypts `plaintext` with `key`, returning the base64 of the nonce followed by the ciphertext
+fn seal(key: &Key<Aes256Gcm>, plaintext: &[u8]) -> Result<String, EncryptionError> {
+    let nonce = Aes256Gcm::generate_nonce(&mut OsRng);
+    let ciphertext = Aes256Gcm::new(key)
+        .encrypt(&nonce, plaintext)
+        .map_err(|e| EncryptionError::Encrypt(e.to_string()))?;
+    let mut sealed = nonce.to_vec();
+    sealed.extend(ciphertext);
+    Ok(BASE64.encode(sealed))
+}
+
+fn open(key: &Key<Aes256Gcm>, sealed: &str) -> Result<Vec<u8>, EncryptionError> {
+    let sealed = decode(sealed)?;
+    if sealed.len() < 12 {
+        return Err(EncryptionError::Decrypt("invalid wrapped key".to_string()));
+    }
+    let (nonce, ciphertext) = sealed.split_at(12);
+    Aes256Gcm::new(key)
+        .decrypt(Nonce::from_slice(nonce), ciphertext)
+        .map_err(|e| EncryptionError::Decrypt(e.to_string()))
+}
+
+fn decode(encoded: &str) -> Result<Vec<u8>, EncryptionError> {
+    BASE64
+        .decode(encoded)
+        .map_err(|e| EncryptionError::Decrypt(e.to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+    use std::collections::HashMap;
+
+    struct StaticKeyStore {
+        current: String,
+        keys: HashMap<String, [u8; 32]>,
+    }
+
+    impl KeyStore for StaticKeyStore {
+        fn current_key_id(&self) -> Result<String, EncryptionError> {
+            Ok(self.current.clone())
+        }
+
+        fn key(&self, key_id: &str) -> Result<[u8; 32], EncryptionError> {
+            self.keys
+                .get(key_id)
+                .copied()
+                .ok_or_else(|| EncryptionError::KeyNotFound(key_id.to_string()))
+        }
+    }
+
+    fn cipher() -> FieldCipher {
+        FieldCipher::new(Arc::new(StaticKeyStore {
+            current: "k1".to_string(),
+            keys: HashMap::from([("k1".to_string(), generate_key())]),
+        }))
+    }
+
+    #[test]
+    fn test_encrypt_and_decrypt_private_fields() {
+        let cipher = cipher();
+        let original = json!({"Student": {
+            "name": "John",
+            "identityDetails": { "dob": "2000-01-01", "identityValue": 1234 }
+        }});
+        let mut document = original.clone();
+
+        cipher
+            .encrypt_paths(
+                &mut document,
+                "Student",
+                &[
+                    "$.identityDetails.dob".to_string(),
+                    "/identityDetails/identityValue".to_string(),
+                    "$.identityDetails.missing".to_string(),
+                ],
+            )
+            .unwrap();
+
+        assert_eq!(document["Student"]["name"], json!("John"));
+        assert!(is_encrypted(&document["Student"]["identityDetails"]["dob"]));
+        assert!(is_encrypted(
+            &document["Student"]["identityDetails"]["identityValue"]
+        ));
+        assert!(!document.to_string().contains("2000-01-01"));
+
+        cipher.decrypt_all(&mut document).unwrap();
+        assert_eq!(document, original);
+    }
+
+    #[test]
+    fn test_decrypt_with_unknown_key_fails() {
+        let mut document = json!({"dob": "2000-01-01"});
+        cipher()
+            .encrypt_paths(&mut document, "Student", &["$.dob".to_string()])
+            .unwrap();
+
+        let result = cipher().decrypt_all(&mut document);
+
+        assert!(matches!(result, Err(EncryptionError::Decrypt(_))));
+    }
+}
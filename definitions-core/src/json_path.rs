@@ -0,0 +1,280 @@
+//! Minimal path support for addressing fields inside an entity document.
+//!
+//! Schemas reference entity fields in two notations:
+//! - JSONPath style, used by `privateFields` and friends: `$.identityDetails.dob`, `$.items[0].name`
+//! - JSON Pointer style, used by `ownershipAttributes`: `/contactDetails/email`
+//!
+//! Both are parsed into the same [`JsonPath`]. Paths are resolved against the *entity node*,
+//! i.e. when a document is wrapped in its entity type (`{"Student": {...}}`) the path is relative
+//! to the inner object.
+use serde_json::{Map, Value};
+use std::fmt;
+use thiserror::Error;
+
+#[derive(Debug, Error, PartialEq, Eq)]
+pub enum JsonPathError {
+    #[error("Invalid path `{0}`: {1}")]
+    InvalidPath(String, String),
+    #[error("Path `{0}` does not exist")]
+    PathNotFound(String),
+    #[error("Cannot write to `{0}`: parent is not an object")]
+    NotAnObject(String),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PathSegment {
+    Key(String),
+    Index(usize),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct JsonPath {
+    raw: String,
+    segments: Vec<PathSegment>,
+}
+
+impl fmt::Display for JsonPath {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.raw)
+    }
+}
+
+impl JsonPath {
+    /// Parses `$.a.b[0]`, `/a/b/0` or the bare `a.b` form.
+    pub fn parse(path: &str) -> Result<Self, JsonPathError> {
+        let trimmed = path.trim();
+        let segments = if let Some(pointer) = trimmed.strip_prefix('/') {
+            parse_pointer(pointer)
+        } else {
+            let dotted = trimmed.strip_prefix('$').unwrap_or(trimmed);
+            parse_dotted(path, dotted.strip_prefix('.').unwrap_or(dotted))?
+        };
+        if segments.is_empty() {
+            return Err(JsonPathError::InvalidPath(
+                path.to_string(),
+                "path is empty".to_string(),
+            ));
+        }
+        Ok(Self {
+            raw: path.to_string(),
+            segments,
+        })
+    }
+
+    pub fn segments(&self) -> &[PathSegment] {
+        &self.segments
+    }
+
+    /// The last object key of the path, if the path ends with a key
+    pub fn leaf_key(&self) -> Option<&str> {
+        match self.segments.last() {
+            Some(PathSegment::Key(key)) => Some(key),
+            _ => None,
+        }
+    }
+
+    /// Returns a path with the same parent and a different leaf key
+    pub fn with_leaf_key(&self, key: &str) -> Self {
+        let mut segments = self.segments.clone();
+        segments.pop();
+        segments.push(PathSegment::Key(key.to_string()));
+        let raw = format!(
+            "$.{}",
+            segments
+                .iter()
+                .map(|segment| match segment {
+                    PathSegment::Key(key) => key.clone(),
+                    PathSegment::Index(index) => format!("[{}]", index),
+                })
+                .collect::<Vec<_>>()
+                .join(".")
+                .replace(".[", "[")
+        );
+        Self { raw, segments }
+    }
+
+    pub fn get<'a>(&self, value: &'a Value) -> Option<&'a Value> {
+        self.segments
+            .iter()
+            .try_fold(value, |current, segment| match segment {
+                PathSegment::Key(key) => current.get(key),
+                PathSegment::Index(index) => current.get(index),
+            })
+    }
+
+    pub fn get_mut<'a>(&self, value: &'a mut Value) -> Option<&'a mut Value> {
+        self.segments
+            .iter()
+            .try_fold(value, |current, segment| match segment {
+                PathSegment::Key(key) => current.get_mut(key),
+                PathSegment::Index(index) => current.get_mut(index),
+            })
+    }
+
+    /// Removes the addressed value and returns it
+    pub fn remove(&self, value: &mut Value) -> Option<Value> {
+        let (last, parents) = self.segments.split_last()?;
+        let parent = Self {
+            raw: self.raw.clone(),
+            segments: parents.to_vec(),
+        };
+        let parent_value = parent.get_mut(value)?;
+        match (last, parent_value) {
+            (PathSegment::Key(key), Value::Object(map)) => map.remove(key),
+            (PathSegment::Index(index), Value::Array(items)) if *index < items.len() => {
+                Some(items.remove(*index))
+            }
+            _ => None,
+        }
+    }
+
+    /// Writes `new_value` at the path, creating intermediate objects as required
+    pub fn set(&self, value: &mut Value, new_value: Value) -> Result<(), JsonPathError> {
+        let (last, parents) = self
+            .segments
+            .split_last()
+            .ok_or_else(|| JsonPathError::PathNotFound(self.raw.clone()))?;
+        let mut current = value;
+        for segment in parents {
+            current = match segment {
+                PathSegment::Key(key) => {
+                    let map = current
+                        .as_object_mut()
+                        .ok_or_else(|| JsonPathError::NotAnObject(self.raw.clone()))?;
+                    map.entry(key.clone())
+                        .or_insert_with(|| Value::Object(Map::new()))
+                }
+                PathSegment::Index(index) => current
+                    .get_mut(index)
+                    .ok_or_else(|| JsonPathError::PathNotFound(self.raw.clone()))?,
+            };
+        }
+        match last {
+            PathSegment::Key(key) => {
+                current
+                    .as_object_mut()
+                    .ok_or_else(|| JsonPathError::NotAnObject(self.raw.clone()))?
+                    .insert(key.clone(), new_value);
+            }
+            PathSegment::Index(index) => {
+                let slot = current
+                    .get_mut(index)
+                    .ok_or_else(|| JsonPathError::PathNotFound(self.raw.clone()))?;
+                *slot = new_value;
+            }
+        }
+        Ok(())
+    }
+}
+
+fn parse_pointer(pointer: &str) -> Vec<PathSegment> {
+    pointer
+        .split('/')
+        .filter(|part| !part.is_empty())
+        .map(|part| {
+            let unescaped = part.replace("~1", "/").replace("~0", "~");
+            match unescaped.parse::<usize>() {
+                Ok(index) => PathSegment::Index(index),
+                Err(_) => PathSegment::Key(unescaped),
+            }
+        })
+        .collect()
+}
+
+fn parse_dotted(original: &str, dotted: &str) -> Result<Vec<PathSegment>, JsonPathError> {
+    let mut segments = Vec::new();
+    for part in dotted.split('.').filter(|part| !part.is_empty()) {
+        let (key, mut rest) = match part.find('[') {
+            Some(position) => (&part[..position], &part[position..]),
+            None => (part, ""),
+        };
+        if !key.is_empty() {
+            segments.push(PathSegment::Key(key.to_string()));
+        }
+        while let Some(stripped) = rest.strip_prefix('[') {
+            let end = stripped.find(']').ok_or_else(|| {
+                JsonPathError::InvalidPath(original.to_string(), "unclosed `[`".to_string())
+            })?;
+            let index = stripped[..end].trim().parse::<usize>().map_err(|_| {
+                JsonPathError::InvalidPath(
+                    original.to_string(),
+                    format!("`{}` is not an array index", &stripped[..end]),
+                )
+            })?;
+            segments.push(PathSegment::Index(index));
+            rest = &stripped[end + 1..];
+        }
+        if !rest.is_empty() {
+            return Err(JsonPathError::InvalidPath(
+                original.to_string(),
+                format!("unexpected `{}`", rest),
+            ));
+        }
+    }
+    Ok(segments)
+}
+
+/// Returns the object holding the entity attributes.
+///
+/// Registry documents are usually wrapped in their entity type, e.g. `{"Student": {...}}`.
+/// Paths declared in `_osConfig` are relative to the inner object.
+pub fn entity_node<'a>(document: &'a Value, entity_type: &str) -> &'a Value {
+    match document.get(entity_type) {
+        Some(inner) if inner.is_object() && is_single_key(document) => inner,
+        _ => document,
+    }
+}
+
+pub fn entity_node_mut<'a>(document: &'a mut Value, entity_type: &str) -> &'a mut Value {
+    if is_single_key(document) && document.get(entity_type).is_some_and(Value::is_object) {
+        &mut document[entity_type]
+    } else {
+        document
+    }
+}
+
+fn is_single_key(document: &Value) -> bool {
+    document.as_object().is_some_and(|map| map.len() == 1)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_parse_dotted_and_pointer_paths_are_equivalent() {
+        let dotted = JsonPath::parse("$.contactDetails.email").unwrap();
+        let pointer = JsonPath::parse("/contactDetails/email").unwrap();
+        assert_eq!(dotted.segments(), pointer.segments());
+        assert_eq!(
+            JsonPath::parse("$.items[1].name").unwrap().segments(),
+            &[
+                PathSegment::Key("items".to_string()),
+                PathSegment::Index(1),
+                PathSegment::Key("name".to_string())
+            ]
+        );
+        assert!(JsonPath::parse("$.items[x]").is_err());
+        assert!(JsonPath::parse("$").is_err());
+    }
+
+    #[test]
+    fn test_get_set_remove() {
+        let mut document = json!({"Student": {"identityDetails": {"dob": "2000-01-01"}}});
+        let node = entity_node_mut(&mut document, "Student");
+        let dob = JsonPath::parse("$.identityDetails.dob").unwrap();
+        assert_eq!(dob.get(node), Some(&json!("2000-01-01")));
+
+        let email = JsonPath::parse("/contactDetails/email").unwrap();
+        email.set(node, json!("a@b.com")).unwrap();
+        assert_eq!(email.get(node), Some(&json!("a@b.com")));
+
+        assert_eq!(dob.remove(node), Some(json!("2000-01-01")));
+        assert_eq!(dob.get(node), None);
+        assert_eq!(
+            document,
+            json!({"Student": {"identityDetails": {}, "contactDetails": {"email": "a@b.com"}}})
+        );
+    }
+}
@@ -1,3 +1,24 @@
+pub mod attestation;
+pub mod audit;
+pub mod auto_attestation;
 pub mod banking_domain;
+pub mod certificates;
+pub mod conditions;
+pub mod consent;
+pub mod credentials;
 pub mod definitions_domain;
+pub mod did;
+pub mod encryption;
+pub mod json_path;
+pub mod migration;
+pub mod ownership;
+pub mod references;
+pub mod registration;
 pub mod registry_domain;
+pub mod sd_jwt;
+pub mod signing;
+pub mod status_list;
+pub mod system_fields;
+pub mod verification;
+pub mod verifiers;
+pub mod visibility;
@@ -0,0 +1,121 @@
+//! Declarative migration of entity documents between definition versions.
+//!
+//! A [`MigrationSpec`] is attached to a definition update (`DefUpdated`) and is carried over to
+//! the following `DefActivated`. Existing entities are then rewritten by `MigrateEntityCmd`,
+//! which applies the operations in order and validates the result against the new schema. An
+//! entity several versions behind goes through the migration of each version activated since its
+//! own, oldest first.
+//!
+//! ```json
+//! {
+//!   "operations": [
+//!     { "op": "rename_field", "path": "$.identityDetails.name", "to": "fullName" },
+//!     { "op": "set_default", "path": "$.identityDetails.gender", "value": "Other" },
+//!     { "op": "move_path", "from": "$.contactDetails.address", "to": "$.address.line1" },
+//!     { "op": "drop_field", "path": "$.legacyCode" }
+//!   ]
+//! }
+//! ```
+use crate::json_path::{entity_node_mut, JsonPath, JsonPathError};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use thiserror::Error;
+use utoipa::ToSchema;
+
+#[derive(Debug, Error, PartialEq, Eq)]
+pub enum MigrationError {
+    #[error(transparent)]
+    Path(#[from] JsonPathError),
+    #[error("Operation {0} is invalid: {1}")]
+    InvalidOperation(usize, String),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, Default, ToSchema)]
+pub struct MigrationSpec {
+    pub operations: Vec<MigrationOp>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(tag = "op", rename_all = "snake_case")]
+pub enum MigrationOp {
+    /// Renames the last key of `path`, keeping it under the same parent
+    RenameField { path: String, to: String },
+    /// Sets `value` at `path` when the entity does not have a value there
+    SetDefault {
+        path: String,
+        #[schema(value_type = Object)]
+        value: Value,
+    },
+    /// Moves the value at `from` to `to`
+    MovePath { from: String, to: String },
+    /// Removes the value at `path`
+    DropField { path: String },
+}
+
+impl MigrationSpec {
+    /// Checks that every operation is well formed without touching any document
+    pub fn validate(&self) -> Result<(), MigrationError> {
+        for (position, operation) in self.operations.iter().enumerate() {
+            match operation {
+                MigrationOp::RenameField { path, to } => {
+                    let parsed = JsonPath::parse(path)?;
+                    if parsed.leaf_key().is_none() || to.trim().is_empty() || to.contains('.') {
+                        return Err(MigrationError::InvalidOperation(
+                            position,
+                            format!("cannot rename `{}` to `{}`", path, to),
+                        ));
+                    }
+                }
+                MigrationOp::SetDefault { path, .. } | MigrationOp::DropField { path } => {
+                    JsonPath::parse(path)?;
+                }
+                MigrationOp::MovePath { from, to } => {
+                    JsonPath::parse(from)?;
+                    JsonPath::parse(to)?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Applies the operations to the entity node of `document`.
+    ///
+    /// Returns `true` if the document was changed.
+    pub fn apply(&self, document: &mut Value, entity_type: &str) -> Result<bool, MigrationError> {
+        self.validate()?;
+        let node = entity_node_mut(document, entity_type);
+        let mut changed = false;
+        for operation in &self.operations {
+            changed |= match operation {
+                MigrationOp::RenameField { path, to } => {
+                    let from = JsonPath::parse(path)?;
+                    move_value(node, &from, &from.with_leaf_key(to))?
+                }
+                MigrationOp::SetDefault { path, value } => {
+                    let target = JsonPath::parse(path)?;
+                    if target.get(node).map_or(true, Value::is_null) {
+                        target.set(node, value.clone())?;
+                        true
+                    } else {
+                        false
+                    }
+                }
+                MigrationOp::MovePath { from, to } => {
+                    move_value(node, &JsonPath::parse(from)?, &JsonPath::parse(to)?)?
+                }
+                MigrationOp::DropField { path } => JsonPath::parse(path)?.remove(node).is_some(),
+            };
+        }
+        Ok(changed)
+    }
+}
+
+fn move_value(node: &mut Value, from: &JsonPath, to: &JsonPath) -> Result<bool, MigrationError> {
+    match from.remove(node) {
+        Some(value) => {
+            to.set(node, value)?;
+            Ok(true)
+        }
+        None => Ok(false),
+    }
+}
@@ -0,0 +1,307 @@
+//! Ownership of entities.
+//!
+//! A definition declares how the owner of an entity is identified with
+//! `_osConfig.ownershipAttributes`:
+//!
+//! ```json
+//! "ownershipAttributes": [
+//!   { "email": "/contactDetails/email", "mobile": "/contactDetails/mobile", "userId": "/contactDetails/mobile" }
+//! ]
+//! ```
+//!
+//! The paths are resolved against the entity body when the entity is created and the resulting
+//! [`Owner`]s are stored with the `EntityCreated` event. A [`Principal`] may modify an entity when
+//! it is one of its owners or holds one of the definition `roles`. The `anonymous` role opens a
+//! definition to every caller for reading and creating, it never grants the modification of an
+//! entity that has owners.
+use crate::audit::Actor;
+use crate::json_path::{entity_node, JsonPath};
+use crate::registry_domain::{EntityError, EntityId};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+pub const OWNERSHIP_ATTRIBUTES: &str = "ownershipAttributes";
+/// Role granted to every principal when listed in the definition `roles`
+pub const ANONYMOUS_ROLE: &str = "anonymous";
+
+/// Identity of an owner as found in the entity body
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Owner {
+    pub user_id: Option<String>,
+    pub email: Option<String>,
+    pub mobile: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct OwnershipAttribute {
+    user_id: Option<String>,
+    email: Option<String>,
+    mobile: Option<String>,
+}
+
+/// The caller of a command, as identified by its access token
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Principal {
+    /// The `sub` claim
+    pub subject: String,
+    pub email: Option<String>,
+    pub roles: Vec<String>,
+    /// The `permissions` claim
+    #[serde(default)]
+    pub permissions: Vec<String>,
+    /// The client the token was issued to, the `azp` or `client_id` claim
+    #[serde(default)]
+    pub client_id: Option<String>,
+    /// The impersonation chain of the `act` claim, the current actor first
+    #[serde(default)]
+    pub act: Vec<String>,
+}
+
+impl Principal {
+    /// The actor recorded in the events of the commands of the principal.
+    ///
+    /// An impersonating actor acts on behalf of the subject, a token without subject is
+    /// attributed to its client and an unauthenticated caller to `anonymous`.
+    pub fn actor(&self) -> Actor {
+        match (self.act.first(), self.subject.is_empty()) {
+            (Some(actor), false) => Actor::on_behalf_of(actor.as_str(), self.subject.as_str()),
+            (Some(actor), true) => Actor::new(actor.as_str()),
+            (None, false) => Actor::new(self.subject.as_str()),
+            (None, true) => Actor::new(self.client_id.as_deref().unwrap_or(ANONYMOUS_ROLE)),
+        }
+    }
+
+    /// Returns true when the principal matches the user id or the email of one of the owners
+    pub fn owns(&self, owners: &[Owner]) -> bool {
+        owners.iter().any(|owner| {
+            let same_user =
+                !self.subject.is_empty() && owner.user_id.as_deref() == Some(self.subject.as_str());
+            let same_email = match (&self.email, &owner.email) {
+                (Some(mine), Some(theirs)) => mine.eq_ignore_ascii_case(theirs),
+                _ => false,
+            };
+            same_user || same_email
+        })
+    }
+
+    /// Returns true when the principal holds one of `roles`, other than `anonymous`
+    pub fn has_any_role(&self, roles: &[String]) -> bool {
+        roles
+            .iter()
+            .any(|role| role != ANONYMOUS_ROLE && self.roles.contains(role))
+    }
+
+    /// Checks that the principal may modify an entity.
+    ///
+    /// Entities whose definition declares neither owners nor roles, besides `anonymous`, are not
+    /// restricted.
+    pub fn authorize(
+        &self,
+        entity_id: EntityId,
+        owners: &[Owner],
+        roles: &[String],
+    ) -> Result<(), EntityError> {
+        let unrestricted = owners.is_empty() && roles.iter().all(|role| role == ANONYMOUS_ROLE);
+        if unrestricted || self.owns(owners) || self.has_any_role(roles) {
+            return Ok(());
+        }
+        Err(EntityError::NotAuthorized(self.subject.clone(), entity_id))
+    }
+}
+
+/// Resolves the `ownershipAttributes` of a schema against an entity body.
+///
+/// Attributes that resolve to no value at all are skipped.
+pub fn owners_of(
+    json_schema_string: &str,
+    entity_type: &str,
+    entity_body: &str,
+) -> Result<Vec<Owner>, EntityError> {
+    let schema: Value = serde_json::from_str(json_schema_string)
+        .map_err(|e| EntityError::InvalidSchema(e.to_string()))?;
+    let Some(attributes) = schema
+        .get("_osConfig")
+        .and_then(|config| config.get(OWNERSHIP_ATTRIBUTES))
+    else {
+        return Ok(vec![]);
+    };
+    let attributes: Vec<OwnershipAttribute> = serde_json::from_value(attributes.clone())
+        .map_err(|e| EntityError::InvalidSchema(format!("{}: {}", OWNERSHIP_ATTRIBUTES, e)))?;
+    if attributes.is_empty() {
+        return Ok(vec![]);
+    }
+
+    let document: Value =
+        serde_json::from_str(entity_body).map_err(|e| EntityError::InvalidJson(e.to_string()))?;
+    let node = entity_node(&document, entity_type);
+    let mut owners = Vec::new();
+    for attribute in attributes {
+        let owner = Owner {
+            user_id: resolve(node, attribute.user_id.as_deref())?,
+            email: resolve(node, attribute.email.as_deref())?,
+            mobile: resolve(node, attribute.mobile.as_deref())?,
+        };
+        if owner != Owner::default() && !owners.contains(&owner) {
+            owners.push(owner);
+        }
+    }
+    Ok(owners)
+}
+
+/// Roles declared in `_osConfig.roles` of a schema
+pub fn definition_roles(json_schema_string: &str) -> Vec<String> {
+    serde_json::from_str::<Value>(json_schema_string)
+        .map(|schema| declared_roles(&schema))
+        .unwrap_or_default()
+}
+
+/// Reads `_osConfig.roles` of a parsed schema
+pub fn declared_roles(schema: &Value) -> Vec<String> {
+    config_strings(schema, "roles")
+}
+
+/// Reads `_osConfig.inviteRoles` of a parsed schema, the roles that may create entities
+pub fn declared_invite_roles(schema: &Value) -> Vec<String> {
+    config_strings(schema, "inviteRoles")
+}
+
+/// Returns true when the `roles` or `inviteRoles` of a parsed schema include `anonymous`, which
+/// opens the entity type to self-registration
+pub fn allows_self_registration(schema: &Value) -> bool {
+    declared_roles(schema)
+        .iter()
+        .chain(declared_invite_roles(schema).iter())
+        .any(|role| role == ANONYMOUS_ROLE)
+}
+
+fn config_strings(schema: &Value, name: &str) -> Vec<String> {
+    schema
+        .get("_osConfig")
+        .and_then(|config| config.get(name))
+        .and_then(Value::as_array)
+        .map(|roles| {
+            roles
+                .iter()
+                .filter_map(Value::as_str)
+                .map(str::to_string)
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+fn resolve(node: &Value, path: Option<&str>) -> Result<Option<String>, EntityError> {
+    let Some(path) = path else {
+        return Ok(None);
+    };
+    let path = JsonPath::parse(path)
+        .map_err(|e| EntityError::InvalidSchema(format!("{}: {}", OWNERSHIP_ATTRIBUTES, e)))?;
+    Ok(match path.get(node) {
+        Some(Value::String(value)) if !value.is_empty() => Some(value.clone()),
+        Some(Value::Number(value)) => Some(value.to_string()),
+        _ => None,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_owners_of_resolves_pointer_and_dotted_paths() {
+        let schema = json!({
+            "title": "Teacher",
+            "_osConfig": {
+                "ownershipAttributes": [
+                    { "email": "/contactDetails/email", "mobile": "$.contactDetails.mobile", "userId": "/contactDetails/mobile" }
+                ]
+            }
+        })
+        .to_string();
+        let body = json!({
+            "Teacher": { "contactDetails": { "email": "smith@example.com", "mobile": 9876543210u64 } }
+        })
+        .to_string();
+
+        let owners = owners_of(&schema, "Teacher", &body).unwrap();
+
+        assert_eq!(
+            owners,
+            vec![Owner {
+                user_id: Some("9876543210".to_string()),
+                email: Some("smith@example.com".to_string()),
+                mobile: Some("9876543210".to_string()),
+            }]
+        );
+    }
+
+    #[test]
+    fn test_principal_authorization() {
+        let owners = vec![Owner {
+            user_id: Some("auth0|smith".to_string()),
+            email: Some("smith@example.com".to_string()),
+            mobile: None,
+        }];
+        let roles = vec!["admin".to_string()];
+        let id = EntityId::nil();
+
+        let by_email = Principal {
+            subject: "other".to_string(),
+            email: Some("Smith@Example.com".to_string()),
+            roles: vec![],
+            ..Principal::default()
+        };
+        assert!(by_email.authorize(id, &owners, &roles).is_ok());
+
+        let admin = Principal {
+            subject: "admin".to_string(),
+            email: None,
+            roles: roles.clone(),
+            ..Principal::default()
+        };
+        assert!(admin.authorize(id, &owners, &roles).is_ok());
+
+        let stranger = Principal::default();
+        assert!(stranger.authorize(id, &owners, &roles).is_err());
+        assert!(stranger.authorize(id, &[], &[]).is_ok());
+        assert!(stranger
+            .authorize(id, &owners, &[ANONYMOUS_ROLE.to_string()])
+            .is_err());
+        assert!(stranger
+            .authorize(id, &[], &[ANONYMOUS_ROLE.to_string()])
+            .is_ok());
+        let claims_anonymous = Principal {
+            roles: vec![ANONYMOUS_ROLE.to_string()],
+            ..Principal::default()
+        };
+        assert!(claims_anonymous
+            .authorize(id, &owners, &[ANONYMOUS_ROLE.to_string()])
+            .is_err());
+    }
+
+    #[test]
+    fn test_principal_actor() {
+        let user = Principal {
+            subject: "auth0|smith".to_string(),
+            ..Principal::default()
+        };
+        assert_eq!(user.actor(), Actor::new("auth0|smith"));
+
+        let impersonated = Principal {
+            act: vec!["support-agent".to_string(), "helpdesk".to_string()],
+            ..user
+        };
+        assert_eq!(
+            impersonated.actor(),
+            Actor::on_behalf_of("support-agent", "auth0|smith")
+        );
+
+        let service = Principal {
+            client_id: Some("billing-service".to_string()),
+            ..Principal::default()
+        };
+        assert_eq!(service.actor(), Actor::new("billing-service"));
+        assert_eq!(Principal::default().actor(), Actor::new(ANONYMOUS_ROLE));
+    }
+}
@@ -0,0 +1,313 @@
+//! References between entities.
+//!
+//! A schema property annotated with `x-ref` holds the id of another entity:
+//!
+//! ```json
+//! "instituteId": { "type": "string", "x-ref": "Institute" }
+//! "teacherIds": { "type": "array", "items": { "type": "string", "x-ref": "Teacher" } }
+//! ```
+//!
+//! [`collect_references`] walks an entity document together with its schema and returns every
+//! reference found. [`ReferencedResources`] loads the referenced entities so that commands can
+//! check that they exist and are Active.
+use crate::definitions_domain::DomainEvent;
+use crate::registry_domain::{EntityError, EntityId, EntityRecordStatus};
+use disintegrate::{query, EventId, StateMutate, StateQuery, StreamQuery};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::BTreeMap;
+use uuid::Uuid;
+
+pub const REFERENCE_KEYWORD: &str = "x-ref";
+
+/// A reference from a field of an entity to another entity
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+pub struct EntityReference {
+    /// JSON pointer of the referencing field in the entity document
+    pub path: String,
+    /// Entity type named by the `x-ref` annotation
+    pub entity_type: String,
+    pub id: EntityId,
+}
+
+/// Returns the references held by `document` according to the `x-ref` annotations of `schema`
+pub fn collect_references(
+    schema: &Value,
+    document: &Value,
+) -> Result<Vec<EntityReference>, EntityError> {
+    let mut references = Vec::new();
+    walk(schema, schema, document, String::new(), &mut references)?;
+    references.sort();
+    Ok(references)
+}
+
+/// Parses a schema and an entity document and collects the references of the document
+pub fn references_of(
+    json_schema_string: &str,
+    entity_body: &str,
+) -> Result<Vec<EntityReference>, EntityError> {
+    let schema: Value = serde_json::from_str(json_schema_string)
+        .map_err(|e| EntityError::InvalidSchema(e.to_string()))?;
+    let document: Value =
+        serde_json::from_str(entity_body).map_err(|e| EntityError::InvalidJson(e.to_string()))?;
+    collect_references(&schema, &document)
+}
+
+/// Ids of all referenced entities, without duplicates
+pub fn referenced_ids(references: &[EntityReference]) -> Vec<EntityId> {
+    let mut ids: Vec<EntityId> = references.iter().map(|reference| reference.id).collect();
+    ids.sort();
+    ids.dedup();
+    ids
+}
+
+fn walk(
+    root: &Value,
+    schema: &Value,
+    document: &Value,
+    pointer: String,
+    references: &mut Vec<EntityReference>,
+) -> Result<(), EntityError> {
+    let schema = resolve(root, schema);
+
+    if let Some(entity_type) = schema.get(REFERENCE_KEYWORD).and_then(Value::as_str) {
+        if let Some(raw_id) = document.as_str() {
+            let id = Uuid::parse_str(raw_id).map_err(|e| {
+                EntityError::InvalidReference(pointer.clone(), format!("`{}`: {}", raw_id, e))
+            })?;
+            references.push(EntityReference {
+                path: pointer,
+                entity_type: entity_type.to_string(),
+                id,
+            });
+            return Ok(());
+        }
+    }
+
+    match document {
+        Value::Object(fields) => {
+            if let Some(properties) = schema.get("properties").and_then(Value::as_object) {
+                for (name, property_schema) in properties {
+                    if let Some(value) = fields.get(name) {
+                        walk(
+                            root,
+                            property_schema,
+                            value,
+                            format!("{}/{}", pointer, escape(name)),
+                            references,
+                        )?;
+                    }
+                }
+            }
+        }
+        Value::Array(items) => {
+            if let Some(item_schema) = schema.get("items") {
+                for (index, item) in items.iter().enumerate() {
+                    walk(
+                        root,
+                        item_schema,
+                        item,
+                        format!("{}/{}", pointer, index),
+                        references,
+                    )?;
+                }
+            }
+        }
+        _ => {}
+    }
+    Ok(())
+}
+
+/// Follows local `$ref`s such as `#/definitions/Student`
+fn resolve<'a>(root: &'a Value, schema: &'a Value) -> &'a Value {
+    let mut current = schema;
+    // Guards against `$ref` cycles that do not consume any of the document
+    for _ in 0..16 {
+        match current
+            .get("$ref")
+            .and_then(Value::as_str)
+            .and_then(|reference| reference.strip_prefix('#'))
+            .and_then(|pointer| root.pointer(pointer))
+        {
+            Some(target) => current = target,
+            None => break,
+        }
+    }
+    current
+}
+
+fn escape(key: &str) -> String {
+    key.replace('~', "~0").replace('/', "~1")
+}
+
+/// State of the entities referenced by a command.
+///
+/// Unlike the derived state queries this one is keyed by a dynamic set of ids, its stream query
+/// is the union of the streams of every referenced entity.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ReferencedResources {
+    ids: Vec<EntityId>,
+    resources: BTreeMap<EntityId, ReferencedResource>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ReferencedResource {
+    pub entity_type: String,
+    pub status: EntityRecordStatus,
+}
+
+impl ReferencedResources {
+    pub fn new(ids: Vec<EntityId>) -> Self {
+        Self {
+            ids,
+            resources: BTreeMap::new(),
+        }
+    }
+
+    pub fn get(&self, id: &EntityId) -> Option<&ReferencedResource> {
+        self.resources.get(id)
+    }
+
+    /// Checks every reference against the loaded state.
+    ///
+    /// References whose ids were not requested when the state was built are rejected, they
+    /// cannot be verified.
+    pub fn verify(&self, references: &[EntityReference]) -> Result<(), EntityError> {
+        for reference in references {
+            if !self.ids.contains(&reference.id) {
+                return Err(EntityError::InvalidReference(
+                    reference.path.clone(),
+                    format!("reference to {} was not declared", reference.id),
+                ));
+            }
+            let resource = self.get(&reference.id).ok_or_else(|| {
+                EntityError::ReferencedEntityNotFound(reference.path.clone(), reference.id)
+            })?;
+            if resource.entity_type != reference.entity_type {
+                return Err(EntityError::InvalidReference(
+                    reference.path.clone(),
+                    format!(
+                        "expected a `{}` but {} is a `{}`",
+                        reference.entity_type, reference.id, resource.entity_type
+                    ),
+                ));
+            }
+            if resource.status != EntityRecordStatus::Active {
+                return Err(EntityError::ReferencedEntityNotActive(
+                    reference.path.clone(),
+                    reference.id,
+                    resource.status.clone(),
+                ));
+            }
+        }
+        Ok(())
+    }
+}
+
+impl StateQuery for ReferencedResources {
+    const NAME: &'static str = "ReferencedResources";
+    type Event = DomainEvent;
+
+    fn query<ID: EventId>(&self) -> StreamQuery<ID, Self::Event> {
+        let mut ids = self.ids.iter();
+        // `query!(DomainEvent)` without a filter would match every event
+        let first = ids.next().copied().unwrap_or_else(Uuid::nil);
+        ids.fold(query!(DomainEvent; id == first), |stream, id| {
+            stream.union(&query!(DomainEvent; id == *id))
+        })
+    }
+}
+
+impl StateMutate for ReferencedResources {
+    fn mutate(&mut self, event: Self::Event) {
+        match event {
+            DomainEvent::EntityCreated {
+                id, entity_type, ..
+            } => {
+                self.resources.insert(
+                    id,
+                    ReferencedResource {
+                        entity_type,
+                        status: EntityRecordStatus::Active,
+                    },
+                );
+            }
+            DomainEvent::EntityInvited {
+                id, entity_type, ..
+            } => {
+                self.resources.insert(
+                    id,
+                    ReferencedResource {
+                        entity_type,
+                        status: EntityRecordStatus::Invited,
+                    },
+                );
+            }
+            DomainEvent::EntityDeleted { id, .. } => {
+                if let Some(resource) = self.resources.get_mut(&id) {
+                    resource.status = EntityRecordStatus::MarkedForDeletion;
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_collect_references_follows_refs_and_arrays() {
+        let schema = json!({
+            "title": "Student",
+            "properties": {
+                "Student": { "$ref": "#/definitions/Student" }
+            },
+            "definitions": {
+                "Student": {
+                    "properties": {
+                        "instituteId": { "type": "string", "x-ref": "Institute" },
+                        "mentors": {
+                            "type": "array",
+                            "items": { "type": "string", "x-ref": "Teacher" }
+                        }
+                    }
+                }
+            }
+        });
+        let institute = Uuid::now_v7();
+        let mentor = Uuid::now_v7();
+        let document = json!({
+            "Student": { "instituteId": institute.to_string(), "mentors": [mentor.to_string()] }
+        });
+
+        let references = collect_references(&schema, &document).unwrap();
+
+        assert_eq!(
+            references,
+            vec![
+                EntityReference {
+                    path: "/Student/instituteId".to_string(),
+                    entity_type: "Institute".to_string(),
+                    id: institute,
+                },
+                EntityReference {
+                    path: "/Student/mentors/0".to_string(),
+                    entity_type: "Teacher".to_string(),
+                    id: mentor,
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_collect_references_rejects_malformed_ids() {
+        let schema = json!({"properties": {"teacher": {"type": "string", "x-ref": "Teacher"}}});
+        let result = collect_references(&schema, &json!({"teacher": "smith"}));
+        assert!(
+            matches!(result, Err(EntityError::InvalidReference(path, _)) if path == "/teacher")
+        );
+    }
+}
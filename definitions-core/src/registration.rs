@@ -0,0 +1,279 @@
+//! Self-registration of entities whose definition is open to `anonymous`.
+//!
+//! A definition listing `anonymous` in its `roles` or `inviteRoles` lets anyone sign up as one of
+//! its entities. [`RegisterEntityCmd`] validates the entity like `CreateEntityCmd` but records it
+//! with an `EntityRegistered` event, which leaves it pending until the registrant proves control
+//! of the email or mobile of its owner, as found with `ownershipAttributes`. Once the one-time
+//! code sent there is confirmed, [`ConfirmRegistrationCmd`] creates the entity with an
+//! `EntityCreated` event, from which point it is projected and referenced like any other entity.
+//!
+//! The one-time codes are issued and checked by the caller of the commands, they are never
+//! recorded in the event store.
+use crate::audit::Actor;
+use crate::definitions_domain::{
+    generate_id_from_title, DefId, DefRecordStatus, DomainEvent, RegistryDefinition, Version,
+};
+use crate::encryption::FieldCipher;
+use crate::ownership::{allows_self_registration, Owner};
+use crate::references::{EntityReference, ReferencedResources};
+use crate::registry_domain::{
+    prepare_entity, state_machine, EntityError, EntityId, EntityRecordStatus, RegistryEntityAction,
+    RegistryResource,
+};
+use crate::signing::IssuerKey;
+use chrono::Utc;
+use disintegrate::{event_types, union, Decision, StateMutate, StateQuery, StreamQuery};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+/// Where the one-time code of a registration is sent
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum VerificationContact {
+    Email(String),
+    Mobile(String),
+}
+
+/// The contact the code of a registration is sent to: the first owner email, else mobile
+pub fn verification_contact(owners: &[Owner]) -> Option<VerificationContact> {
+    owners
+        .iter()
+        .find_map(|owner| owner.email.clone().map(VerificationContact::Email))
+        .or_else(|| {
+            owners
+                .iter()
+                .find_map(|owner| owner.mobile.clone().map(VerificationContact::Mobile))
+        })
+}
+
+/// Signs up an entity, pending until [`ConfirmRegistrationCmd`]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, Default)]
+pub struct RegisterEntityCmd {
+    pub id: EntityId,
+    pub entity_body: String,
+    pub entity_type: String,
+    pub registered_by: Actor,
+    /// Ids of the entities referenced by `entity_body`, see [`crate::references`]
+    pub referenced_ids: Vec<EntityId>,
+    /// Encrypts the `privateFields` of the definition, see [`crate::encryption`]
+    #[serde(skip)]
+    pub cipher: Option<FieldCipher>,
+    /// Signs the entity data when the definition declares `_osSignedData`, see [`crate::signing`]
+    #[serde(skip)]
+    pub signer: Option<IssuerKey>,
+}
+
+impl Decision for RegisterEntityCmd {
+    type Event = DomainEvent;
+    type StateQuery = (RegistryResource, RegistryDefinition, ReferencedResources);
+    type Error = EntityError;
+
+    fn state_query(&self) -> Self::StateQuery {
+        (
+            RegistryResource::new(self.id),
+            RegistryDefinition::new(generate_id_from_title(&self.entity_type)),
+            ReferencedResources::new(self.referenced_ids.clone()),
+        )
+    }
+
+    fn validation_query<ID: disintegrate::EventId>(&self) -> Option<StreamQuery<ID, Self::Event>> {
+        let (resource, def_state, referenced) = self.state_query();
+        Some(union!(
+            &resource,
+            def_state.exclude_events(event_types!(DomainEvent, [DefUpdated])),
+            &referenced
+        ))
+    }
+
+    fn process(
+        &self,
+        (resource, def_state, referenced): &Self::StateQuery,
+    ) -> Result<Vec<Self::Event>, Self::Error> {
+        if !state_machine(&resource.status, RegistryEntityAction::Create) {
+            return Err(EntityError::EntityAlreadyExists(
+                self.entity_type.clone(),
+                self.id,
+            ));
+        }
+        // An inactive definition is reported by `prepare_entity`
+        if def_state.record_status == DefRecordStatus::Active {
+            let schema: Value = serde_json::from_str(&def_state.json_schema_string)
+                .map_err(|e| EntityError::InvalidSchema(e.to_string()))?;
+            if !allows_self_registration(&schema) {
+                return Err(EntityError::SelfRegistrationNotAllowed(
+                    self.entity_type.clone(),
+                ));
+            }
+        }
+
+        let registered_at = Utc::now();
+        let prepared = prepare_entity(
+            def_state,
+            referenced,
+            &self.entity_type,
+            &self.entity_body,
+            &self.registered_by,
+            registered_at,
+            self.cipher.as_ref(),
+            self.signer.as_ref(),
+        )?;
+        if verification_contact(&prepared.owners).is_none() {
+            return Err(EntityError::NoVerificationContact(self.entity_type.clone()));
+        }
+
+        Ok(vec![DomainEvent::EntityRegistered {
+            id: self.id,
+            registry_def_id: def_state.id,
+            registry_def_version: def_state.version,
+            entity_body: prepared.entity_body,
+            entity_type: self.entity_type.clone(),
+            registered_at,
+            registered_by: self.registered_by.id.clone(),
+            on_behalf_of: self.registered_by.on_behalf_of.clone(),
+            references: prepared.references,
+            owners: prepared.owners,
+        }])
+    }
+}
+
+/// A registration as recorded by `EntityRegistered`
+#[derive(Default, StateQuery, Clone, Debug, Serialize, Deserialize)]
+#[state_query(DomainEvent)]
+pub struct Registration {
+    #[id]
+    id: EntityId,
+    status: EntityRecordStatus,
+    registry_def_id: DefId,
+    registry_def_version: Version,
+    entity_body: String,
+    entity_type: String,
+    references: Vec<EntityReference>,
+    owners: Vec<Owner>,
+}
+
+impl Registration {
+    pub fn new(id: EntityId) -> Self {
+        Self {
+            id,
+            ..Default::default()
+        }
+    }
+}
+
+impl StateMutate for Registration {
+    fn mutate(&mut self, event: Self::Event) {
+        match event {
+            DomainEvent::EntityRegistered {
+                registry_def_id,
+                registry_def_version,
+                entity_body,
+                entity_type,
+                references,
+                owners,
+                ..
+            } => {
+                self.registry_def_id = registry_def_id;
+                self.registry_def_version = registry_def_version;
+                self.entity_body = entity_body;
+                self.entity_type = entity_type;
+                self.references = references;
+                self.owners = owners;
+                self.status = EntityRecordStatus::PendingVerification;
+            }
+            DomainEvent::EntityCreated { .. } => self.status = EntityRecordStatus::Active,
+            _ => {}
+        }
+    }
+}
+
+/// Creates a registered entity once its owner confirmed the one-time code.
+///
+/// The definition must still be active, a registration pending while it was deactivated cannot be
+/// confirmed.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, Default)]
+pub struct ConfirmRegistrationCmd {
+    pub id: EntityId,
+    pub entity_type: String,
+    pub confirmed_by: Actor,
+}
+
+impl Decision for ConfirmRegistrationCmd {
+    type Event = DomainEvent;
+    type StateQuery = (Registration, RegistryDefinition);
+    type Error = EntityError;
+
+    fn state_query(&self) -> Self::StateQuery {
+        (
+            Registration::new(self.id),
+            RegistryDefinition::new(generate_id_from_title(&self.entity_type)),
+        )
+    }
+
+    fn validation_query<ID: disintegrate::EventId>(&self) -> Option<StreamQuery<ID, Self::Event>> {
+        let (registration, def_state) = self.state_query();
+        Some(union!(
+            &registration,
+            def_state.exclude_events(event_types!(DomainEvent, [DefUpdated]))
+        ))
+    }
+
+    fn process(
+        &self,
+        (registration, def_state): &Self::StateQuery,
+    ) -> Result<Vec<Self::Event>, Self::Error> {
+        if registration.status == EntityRecordStatus::None
+            || registration.entity_type != self.entity_type
+        {
+            return Err(EntityError::EntityNotFound(self.id));
+        }
+        if registration.status != EntityRecordStatus::PendingVerification {
+            return Err(EntityError::RegistrationAlreadyConfirmed(self.id));
+        }
+        if def_state.record_status != DefRecordStatus::Active {
+            return Err(EntityError::DefinitionNotInProperState(
+                DefRecordStatus::Active,
+                def_state.record_status.clone(),
+            ));
+        }
+
+        Ok(vec![DomainEvent::EntityCreated {
+            id: self.id,
+            registry_def_id: registration.registry_def_id,
+            registry_def_version: registration.registry_def_version,
+            entity_body: registration.entity_body.clone(),
+            entity_type: registration.entity_type.clone(),
+            created_at: Utc::now(),
+            created_by: self.confirmed_by.id.clone(),
+            on_behalf_of: self.confirmed_by.on_behalf_of.clone(),
+            version: Default::default(),
+            references: registration.references.clone(),
+            owners: registration.owners.clone(),
+        }])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_verification_contact_prefers_email() {
+        let mobile_only = Owner {
+            mobile: Some("+91-9876543210".to_string()),
+            ..Owner::default()
+        };
+        let with_email = Owner {
+            email: Some("john@example.com".to_string()),
+            ..Owner::default()
+        };
+
+        assert_eq!(
+            verification_contact(&[mobile_only.clone(), with_email]),
+            Some(VerificationContact::Email("john@example.com".to_string()))
+        );
+        assert_eq!(
+            verification_contact(&[mobile_only]),
+            Some(VerificationContact::Mobile("+91-9876543210".to_string()))
+        );
+        assert_eq!(verification_contact(&[Owner::default()]), None);
+    }
+}
@@ -1,7 +1,16 @@
 //TODO RollBack Command
+use crate::attestation::{ClaimId, ClaimStatus};
+use crate::audit::Actor;
+use crate::consent::{ConsentId, ConsentStatus};
+use crate::credentials::CredentialId;
 use crate::definitions_domain::{
     generate_id_from_title, DefId, DefRecordStatus, DomainEvent, RegistryDefinition, Version,
 };
+use crate::encryption::{contains_encrypted, private_fields, FieldCipher};
+use crate::ownership::{definition_roles, owners_of, Owner, Principal};
+use crate::references::{references_of, EntityReference, ReferencedResources};
+use crate::signing::{IssuerKey, SignedFields};
+use crate::system_fields::SystemFields;
 use chrono::{DateTime, Utc};
 use disintegrate::{event_types, union, Decision, StateMutate, StateQuery, StreamQuery};
 use log::debug;
@@ -32,6 +41,76 @@ pub enum EntityError {
     DeleteNotAllowed(EntityRecordStatus),
     #[error("Event type {0} not found")]
     EventNotFound(String),
+    #[error("Entity with id: {0} not found")]
+    EntityNotFound(EntityId),
+    #[error("Migration of entity {0} failed: {1}")]
+    MigrationFailed(EntityId, String),
+    #[error("Invalid reference at `{0}`: {1}")]
+    InvalidReference(String, String),
+    #[error("Entity {1} referenced at `{0}` does not exist")]
+    ReferencedEntityNotFound(String, EntityId),
+    #[error("Entity {1} referenced at `{0}` is in `{2}` state, expected `Active`")]
+    ReferencedEntityNotActive(String, EntityId, EntityRecordStatus),
+    #[error("`{0}` is not allowed to modify entity {1}")]
+    NotAuthorized(String, EntityId),
+    #[error("Encryption error: {0}")]
+    Encryption(String),
+    #[error("Entity type {0} declares privateFields but no field cipher is configured")]
+    EncryptionUnavailable(String),
+    #[error("Entity type {0} declares _osSignedData but no issuer key is configured")]
+    SigningUnavailable(String),
+    #[error("Entity type {0} has no attestation policy `{1}`")]
+    AttestationPolicyNotFound(String, String),
+    #[error("Attestation policy `{0}` cannot be claimed: {1}")]
+    ClaimNotAllowed(String, String),
+    #[error("Claim with id: {0} already exists")]
+    ClaimAlreadyExists(ClaimId),
+    #[error("Claim with id: {0} not found")]
+    ClaimNotFound(ClaimId),
+    #[error("Claim {0} is already `{1}`")]
+    ClaimAlreadyClosed(ClaimId, ClaimStatus),
+    #[error("Entity {0} is not a `{1}` attestor")]
+    NotAnAttestor(EntityId, String),
+    #[error("Conditions of attestation policy `{0}` are not met by attestor {1}")]
+    AttestationConditionNotMet(String, EntityId),
+    #[error("Entity type {0} has no credential template")]
+    CredentialTemplateNotFound(String),
+    #[error("Credential of `{0}` cannot be issued: {1}")]
+    CredentialNotAvailable(String, String),
+    #[error("Credential not found: {0}")]
+    CredentialNotFound(CredentialId),
+    #[error("Cannot {1} credential {0}: {2}")]
+    CredentialStatusNotChanged(CredentialId, String, String),
+    #[error("Credential {0} cannot be presented: {1}")]
+    CredentialNotPresentable(CredentialId, String),
+    #[error("Invalid disclosure: {0}")]
+    InvalidDisclosure(String),
+    #[error("The status lists are full, {0} credentials have been issued")]
+    StatusListExhausted(u64),
+    #[error("Entity type {0} has no certificate template `{1}`")]
+    CertificateTemplateNotFound(String, String),
+    #[error("Certificate cannot be rendered: {0}")]
+    CertificateNotAvailable(String),
+    #[error("Invalid certificate template: {0}")]
+    InvalidCertificateTemplate(String),
+    #[error("Invalid consent: {0}")]
+    InvalidConsent(String),
+    #[error("Consent with id: {0} already exists")]
+    ConsentAlreadyExists(ConsentId),
+    #[error("Consent with id: {0} not found")]
+    ConsentNotFound(ConsentId),
+    #[error("Cannot {1} consent {0} which is `{2}`")]
+    ConsentNotChanged(ConsentId, String, ConsentStatus),
+    #[error("Consent {0} is `{1}`, not granted")]
+    ConsentNotGranted(ConsentId, ConsentStatus),
+    #[error("Consent {0} expired at {1}")]
+    ConsentExpired(ConsentId, DateTime<Utc>),
+    #[error("Entity type {0} is not open to self-registration")]
+    SelfRegistrationNotAllowed(String),
+    #[error("Entity type {0} has no email or mobile owner to send a verification code to")]
+    NoVerificationContact(String),
+    #[error("Registration of entity {0} is already confirmed")]
+    RegistrationAlreadyConfirmed(EntityId),
 }
 
 #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, Default, Display)]
@@ -40,6 +119,8 @@ pub enum EntityRecordStatus {
     None,
     Active,
     Invited,
+    /// Self-registered, waiting for the owner to confirm the one-time code
+    PendingVerification,
     Modified,
     Deactivated,
     MarkedForDeletion,
@@ -50,14 +131,15 @@ pub enum EntityRecordStatus {
 pub struct RegistryResource {
     #[id]
     id: EntityId,
-    status: EntityRecordStatus,
+    pub(crate) status: EntityRecordStatus,
     /// Version of the definitions used to create or modify this resource
     registry_def_version: Version,
     registry_def_id: DefId,
     /// Version indicating a number of modifications happened to this resource
     version: Version,
-    entity_body: String,
-    entity_type: String,
+    pub(crate) entity_body: String,
+    pub(crate) entity_type: String,
+    pub(crate) owners: Vec<Owner>,
 }
 
 impl RegistryResource {
@@ -78,6 +160,7 @@ impl StateMutate for RegistryResource {
                 registry_def_version,
                 entity_body,
                 entity_type,
+                owners,
                 ..
             } => {
                 self.id = id;
@@ -85,6 +168,7 @@ impl StateMutate for RegistryResource {
                 self.registry_def_version = registry_def_version;
                 self.entity_body = entity_body;
                 self.entity_type = entity_type;
+                self.owners = owners;
                 self.status = EntityRecordStatus::Active;
             }
             DomainEvent::EntityInvited {
@@ -102,6 +186,37 @@ impl StateMutate for RegistryResource {
                 self.entity_type = entity_type;
                 self.status = EntityRecordStatus::Invited;
             }
+            DomainEvent::EntityRegistered {
+                id,
+                registry_def_id,
+                registry_def_version,
+                entity_body,
+                entity_type,
+                owners,
+                ..
+            } => {
+                self.id = id;
+                self.registry_def_id = registry_def_id;
+                self.registry_def_version = registry_def_version;
+                self.entity_body = entity_body;
+                self.entity_type = entity_type;
+                self.owners = owners;
+                self.status = EntityRecordStatus::PendingVerification;
+            }
+            DomainEvent::EntityUpdated {
+                registry_def_version,
+                entity_body,
+                version,
+                ..
+            } => {
+                self.registry_def_version = registry_def_version;
+                self.entity_body = entity_body;
+                self.version = version;
+            }
+            DomainEvent::EntityAttested { entity_body, .. }
+            | DomainEvent::EntityAutoAttested { entity_body, .. } => {
+                self.entity_body = entity_body;
+            }
             _ => {}
         }
     }
@@ -112,11 +227,19 @@ pub struct CreateEntityCmd {
     pub id: EntityId,
     pub entity_body: String,
     pub entity_type: String,
-    pub created_by: String,
+    pub created_by: Actor,
+    /// Ids of the entities referenced by `entity_body`, see [`crate::references`]
+    pub referenced_ids: Vec<EntityId>,
+    /// Encrypts the `privateFields` of the definition, see [`crate::encryption`]
+    #[serde(skip)]
+    pub cipher: Option<FieldCipher>,
+    /// Signs the entity data when the definition declares `_osSignedData`, see [`crate::signing`]
+    #[serde(skip)]
+    pub signer: Option<IssuerKey>,
 }
 impl Decision for CreateEntityCmd {
     type Event = DomainEvent;
-    type StateQuery = (RegistryResource, RegistryDefinition);
+    type StateQuery = (RegistryResource, RegistryDefinition, ReferencedResources);
     type Error = EntityError;
 
     // StateQuery should load schema_def which has an id same as the generate_id_from_title
@@ -124,19 +247,21 @@ impl Decision for CreateEntityCmd {
         (
             RegistryResource::new(self.id),
             RegistryDefinition::new(generate_id_from_title(&self.entity_type)),
+            ReferencedResources::new(self.referenced_ids.clone()),
         )
     }
 
     fn validation_query<ID: disintegrate::EventId>(&self) -> Option<StreamQuery<ID, Self::Event>> {
-        let (resource, def_state) = self.state_query();
+        let (resource, def_state, referenced) = self.state_query();
         Some(union!(
             &resource,
-            def_state.exclude_events(event_types!(DomainEvent, [DefUpdated]))
+            def_state.exclude_events(event_types!(DomainEvent, [DefUpdated])),
+            &referenced
         ))
     }
     fn process(
         &self,
-        (resource, def_state): &Self::StateQuery,
+        (resource, def_state, referenced): &Self::StateQuery,
     ) -> Result<Vec<Self::Event>, Self::Error> {
         if !state_machine(&resource.status, RegistryEntityAction::Create) {
             return Err(EntityError::EntityAlreadyExists(
@@ -144,64 +269,107 @@ impl Decision for CreateEntityCmd {
                 self.id,
             ));
         }
-        if def_state.record_status != DefRecordStatus::Active {
-            return Err(EntityError::DefinitionNotInProperState(
-                DefRecordStatus::Active,
-                def_state.record_status.clone(),
-            ));
-        }
-
-        let schema: serde_json::Value = serde_json::from_str(&def_state.json_schema_string)
-            .map_err(|e| EntityError::JsonSchemaError(self.entity_type.clone(), e.to_string()))?;
-        let instance: serde_json::Value = serde_json::from_str(&self.entity_body)
-            .map_err(|e| EntityError::JsonSchemaError(self.entity_type.clone(), e.to_string()))?;
-
-        let validator = jsonschema::draft7::new(&schema)
-            .map_err(|e| EntityError::JsonSchemaError(self.entity_type.clone(), e.to_string()))?;
-
-        let full_errors = validator
-            .iter_errors(&instance)
-            .map(|error| format!("Error: {} \tLocation: {}", error, error.instance_path))
-            .collect::<Vec<_>>()
-            .join("\n");
-
-        if !full_errors.is_empty() {
-            let pretty = serde_json::to_string_pretty(&schema).unwrap();
-            debug!("pretty schema = {}", pretty);
-            let pretty = serde_json::to_string_pretty(&instance).unwrap();
-            debug!("pretty instance = {}", pretty);
-        }
-        if !full_errors.is_empty() {
-            return Err(EntityError::JsonSchemaError(
-                self.entity_type.clone(),
-                full_errors,
-            ));
-        }
+        let created_at = Utc::now();
+        let prepared = prepare_entity(
+            def_state,
+            referenced,
+            &self.entity_type,
+            &self.entity_body,
+            &self.created_by,
+            created_at,
+            self.cipher.as_ref(),
+            self.signer.as_ref(),
+        )?;
 
         Ok(vec![DomainEvent::EntityCreated {
             id: self.id,
             registry_def_id: def_state.id,
             registry_def_version: def_state.version,
-            entity_body: self.entity_body.clone(),
+            entity_body: prepared.entity_body,
             entity_type: self.entity_type.to_string(),
-            created_at: Utc::now(),
-            created_by: self.created_by.clone(),
+            created_at,
+            created_by: self.created_by.id.clone(),
+            on_behalf_of: self.created_by.on_behalf_of.clone(),
             version: Default::default(),
+            references: prepared.references,
+            owners: prepared.owners,
         }])
     }
 }
 
+/// An entity body ready to be recorded, with what it references and who owns it
+pub(crate) struct PreparedEntity {
+    pub(crate) entity_body: String,
+    pub(crate) references: Vec<EntityReference>,
+    pub(crate) owners: Vec<Owner>,
+}
+
+/// Validates a new entity body against an active definition, then stamps its system fields,
+/// signs it and encrypts its private fields
+#[allow(clippy::too_many_arguments)]
+pub(crate) fn prepare_entity(
+    def_state: &RegistryDefinition,
+    referenced: &ReferencedResources,
+    entity_type: &str,
+    entity_body: &str,
+    created_by: &Actor,
+    created_at: DateTime<Utc>,
+    cipher: Option<&FieldCipher>,
+    signer: Option<&IssuerKey>,
+) -> Result<PreparedEntity, EntityError> {
+    if def_state.record_status != DefRecordStatus::Active {
+        return Err(EntityError::DefinitionNotInProperState(
+            DefRecordStatus::Active,
+            def_state.record_status.clone(),
+        ));
+    }
+
+    let system_fields = SystemFields::from_schema(&def_state.json_schema_string, entity_type)?;
+    let client_body = system_fields.strip(entity_body)?;
+    validate_entity_body(entity_type, &def_state.json_schema_string, &client_body)?;
+    let references = references_of(&def_state.json_schema_string, &client_body)?;
+    referenced.verify(&references)?;
+    let owners = owners_of(&def_state.json_schema_string, entity_type, &client_body)?;
+    let entity_body = system_fields.created(&client_body, created_at, &created_by.id)?;
+    let entity_body = sign_entity(
+        signer,
+        &def_state.json_schema_string,
+        entity_type,
+        &entity_body,
+    )?;
+    let entity_body = encrypt_private_fields(
+        cipher,
+        &def_state.json_schema_string,
+        entity_type,
+        &entity_body,
+    )?;
+    Ok(PreparedEntity {
+        entity_body,
+        references,
+        owners,
+    })
+}
+
 #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, Default)]
 pub struct ModifyEntityCmd {
     pub id: EntityId,
     pub entity_body: String,
     pub entity_type: String,
-    pub modified_by: String,
+    /// The caller, who must own the entity or hold one of the definition `roles`
+    pub principal: Principal,
+    /// Ids of the entities referenced by `entity_body`, see [`crate::references`]
+    pub referenced_ids: Vec<EntityId>,
+    /// Encrypts the `privateFields` of the definition, see [`crate::encryption`]
+    #[serde(skip)]
+    pub cipher: Option<FieldCipher>,
+    /// Signs the entity data when the definition declares `_osSignedData`, see [`crate::signing`]
+    #[serde(skip)]
+    pub signer: Option<IssuerKey>,
 }
 
 impl Decision for ModifyEntityCmd {
     type Event = DomainEvent;
-    type StateQuery = (RegistryResource, RegistryDefinition);
+    type StateQuery = (RegistryResource, RegistryDefinition, ReferencedResources);
     type Error = EntityError;
 
     // StateQuery should load schema_def which has an id  same as the generate_id_from_title
@@ -210,60 +378,188 @@ impl Decision for ModifyEntityCmd {
         (
             RegistryResource::new(self.id),
             RegistryDefinition::new(generate_id_from_title(&self.entity_type)),
+            ReferencedResources::new(self.referenced_ids.clone()),
         )
     }
 
     fn validation_query<ID: disintegrate::EventId>(&self) -> Option<StreamQuery<ID, Self::Event>> {
-        let (resource, def_state) = self.state_query();
+        let (resource, def_state, referenced) = self.state_query();
         Some(union!(
             &resource,
-            def_state.exclude_events(event_types!(DomainEvent, [DefUpdated]))
+            def_state.exclude_events(event_types!(DomainEvent, [DefUpdated])),
+            &referenced
         ))
     }
 
     fn process(
         &self,
-        (resource, def_state): &Self::StateQuery,
+        (resource, def_state, referenced): &Self::StateQuery,
     ) -> Result<Vec<Self::Event>, Self::Error> {
         if !state_machine(&resource.status, RegistryEntityAction::Modify) {
             return Err(EntityError::ModifyNotAllowed(resource.status.clone()));
         }
+        self.principal.authorize(
+            self.id,
+            &resource.owners,
+            &definition_roles(&def_state.json_schema_string),
+        )?;
 
-        let schema: serde_json::Value = serde_json::from_str(&def_state.json_schema_string)
-            .map_err(|e| EntityError::JsonSchemaError(self.entity_type.clone(), e.to_string()))?;
-        let instance: serde_json::Value = serde_json::from_str(&self.entity_body)
-            .map_err(|e| EntityError::JsonSchemaError(self.entity_type.clone(), e.to_string()))?;
+        let system_fields =
+            SystemFields::from_schema(&def_state.json_schema_string, &self.entity_type)?;
+        let client_body = system_fields.strip(&self.entity_body)?;
+        validate_entity_body(
+            &self.entity_type,
+            &def_state.json_schema_string,
+            &client_body,
+        )?;
+        let references = references_of(&def_state.json_schema_string, &client_body)?;
+        referenced.verify(&references)?;
+        let updated_at = Utc::now();
+        let actor = self.principal.actor();
+        let entity_body =
+            system_fields.updated(&client_body, &resource.entity_body, updated_at, &actor.id)?;
+        let entity_body = sign_entity(
+            self.signer.as_ref(),
+            &def_state.json_schema_string,
+            &self.entity_type,
+            &entity_body,
+        )?;
+        let entity_body = encrypt_private_fields(
+            self.cipher.as_ref(),
+            &def_state.json_schema_string,
+            &self.entity_type,
+            &entity_body,
+        )?;
+        Ok(vec![DomainEvent::EntityUpdated {
+            id: self.id,
+            registry_def_id: def_state.id,
+            registry_def_version: def_state.version,
+            entity_body,
+            entity_type: self.entity_type.to_string(),
+            updated_at,
+            updated_by: actor.id,
+            on_behalf_of: actor.on_behalf_of,
+            version: resource.version.increment(),
+            references,
+        }])
+    }
+}
 
-        let validator = jsonschema::draft7::new(&schema)
-            .map_err(|e| EntityError::JsonSchemaError(self.entity_type.clone(), e.to_string()))?;
+/// Rewrites an entity created with an older definition version so that it conforms to the
+/// currently active version.
+///
+/// The [`MigrationSpec`](crate::migration::MigrationSpec)s of the versions activated since the version of the entity are applied
+/// to the stored document in order, then the result is validated against the active schema.
+/// Entities already on the active version produce no events.
+/// References are re-collected from the migrated document but, as the referenced ids are not
+/// known up front, their existence is not checked again.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, Default)]
+pub struct MigrateEntityCmd {
+    pub id: EntityId,
+    pub entity_type: String,
+    pub migrated_by: Actor,
+    /// Decrypts the stored body and encrypts the migrated one, see [`crate::encryption`]
+    #[serde(skip)]
+    pub cipher: Option<FieldCipher>,
+    /// Signs the migrated entity data, see [`crate::signing`]
+    #[serde(skip)]
+    pub signer: Option<IssuerKey>,
+}
 
-        let full_errors = validator
-            .iter_errors(&instance)
-            .map(|error| format!("Error: {} \tLocation: {}", error, error.instance_path))
-            .collect::<Vec<_>>()
-            .join("\n");
+impl Decision for MigrateEntityCmd {
+    type Event = DomainEvent;
+    type StateQuery = (RegistryResource, RegistryDefinition);
+    type Error = EntityError;
 
-        if !full_errors.is_empty() {
-            let pretty = serde_json::to_string_pretty(&schema).unwrap();
-            debug!("pretty schema = {}", pretty);
-            let pretty = serde_json::to_string_pretty(&instance).unwrap();
-            debug!("pretty instance = {}", pretty);
+    fn state_query(&self) -> Self::StateQuery {
+        (
+            RegistryResource::new(self.id),
+            RegistryDefinition::new(generate_id_from_title(&self.entity_type)),
+        )
+    }
+
+    fn validation_query<ID: disintegrate::EventId>(&self) -> Option<StreamQuery<ID, Self::Event>> {
+        let (resource, def_state) = self.state_query();
+        Some(union!(
+            &resource,
+            def_state.exclude_events(event_types!(DomainEvent, [DefUpdated]))
+        ))
+    }
+
+    fn process(
+        &self,
+        (resource, def_state): &Self::StateQuery,
+    ) -> Result<Vec<Self::Event>, Self::Error> {
+        if resource.status == EntityRecordStatus::None {
+            return Err(EntityError::EntityNotFound(self.id));
         }
-        if !full_errors.is_empty() {
-            return Err(EntityError::JsonSchemaError(
-                self.entity_type.clone(),
-                full_errors,
+        if !state_machine(&resource.status, RegistryEntityAction::Modify) {
+            return Err(EntityError::ModifyNotAllowed(resource.status.clone()));
+        }
+        if def_state.record_status != DefRecordStatus::Active {
+            return Err(EntityError::DefinitionNotInProperState(
+                DefRecordStatus::Active,
+                def_state.record_status.clone(),
             ));
         }
+        if resource.registry_def_version >= def_state.version {
+            debug!(
+                "Entity {} is already on definition version {}",
+                self.id,
+                resource.registry_def_version.get()
+            );
+            return Ok(vec![]);
+        }
+
+        let mut document: serde_json::Value = serde_json::from_str(&resource.entity_body)
+            .map_err(|e| EntityError::InvalidJson(e.to_string()))?;
+        decrypt_stored(&mut document, self.cipher.as_ref(), &self.entity_type)?;
+        for migration in def_state.migrations_since(resource.registry_def_version) {
+            migration
+                .apply(&mut document, &self.entity_type)
+                .map_err(|e| EntityError::MigrationFailed(self.id, e.to_string()))?;
+        }
+        // System fields are validated out of band, as for client submitted bodies
+        let system_fields =
+            SystemFields::from_schema(&def_state.json_schema_string, &self.entity_type)?;
+        let migrated_body = system_fields.strip(&document.to_string())?;
+        validate_entity_body(
+            &self.entity_type,
+            &def_state.json_schema_string,
+            &migrated_body,
+        )?;
+        let references = references_of(&def_state.json_schema_string, &migrated_body)?;
+        let updated_at = Utc::now();
+        let entity_body = system_fields.updated(
+            &migrated_body,
+            &resource.entity_body,
+            updated_at,
+            &self.migrated_by.id,
+        )?;
+        let entity_body = sign_entity(
+            self.signer.as_ref(),
+            &def_state.json_schema_string,
+            &self.entity_type,
+            &entity_body,
+        )?;
+        let entity_body = encrypt_private_fields(
+            self.cipher.as_ref(),
+            &def_state.json_schema_string,
+            &self.entity_type,
+            &entity_body,
+        )?;
+
         Ok(vec![DomainEvent::EntityUpdated {
             id: self.id,
             registry_def_id: def_state.id,
             registry_def_version: def_state.version,
-            entity_body: self.entity_body.clone(),
-            entity_type: self.entity_type.to_string(),
-            updated_at: Utc::now(),
-            updated_by: self.modified_by.clone(),
+            entity_body,
+            entity_type: self.entity_type.clone(),
+            updated_at,
+            updated_by: self.migrated_by.id.clone(),
+            on_behalf_of: self.migrated_by.on_behalf_of.clone(),
             version: resource.version.increment(),
+            references,
         }])
     }
 }
@@ -271,7 +567,7 @@ impl Decision for ModifyEntityCmd {
 pub struct DeleteCmd {
     id: DefId,
     deleted_at: DateTime<Utc>,
-    deleted_by: String,
+    deleted_by: Actor,
 }
 impl Decision for DeleteCmd {
     type Event = DomainEvent;
@@ -289,11 +585,156 @@ impl Decision for DeleteCmd {
         Ok(vec![DomainEvent::EntityDeleted {
             id: self.id,
             deleted_at: self.deleted_at,
-            deleted_by: self.deleted_by.clone(),
+            deleted_by: self.deleted_by.id.clone(),
+            on_behalf_of: self.deleted_by.on_behalf_of.clone(),
         }])
     }
 }
 
+/// A single violation of the definition schema found in an entity document
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ValidationIssue {
+    /// The JSON schema keyword that failed, e.g. `required`, `enum` or `format`
+    pub kind: String,
+    /// JSON pointer to the offending value in the entity document
+    pub location: String,
+    pub message: String,
+}
+
+/// The validator applied to entity documents by `CreateEntityCmd`, `ModifyEntityCmd` and
+/// `MigrateEntityCmd`.
+///
+/// Compiling the schema once allows many documents to be checked against the same
+/// definition, e.g. when analysing the impact of a new schema version.
+pub struct EntitySchemaValidator {
+    entity_type: String,
+    validator: jsonschema::Validator,
+}
+
+impl EntitySchemaValidator {
+    pub fn new(entity_type: &str, json_schema_string: &str) -> Result<Self, EntityError> {
+        let schema: serde_json::Value = serde_json::from_str(json_schema_string)
+            .map_err(|e| EntityError::JsonSchemaError(entity_type.to_string(), e.to_string()))?;
+        let validator = jsonschema::draft7::new(&schema)
+            .map_err(|e| EntityError::JsonSchemaError(entity_type.to_string(), e.to_string()))?;
+        Ok(Self {
+            entity_type: entity_type.to_string(),
+            validator,
+        })
+    }
+
+    /// Lists every schema violation of `instance`, an empty list means the document is valid
+    pub fn issues(&self, instance: &serde_json::Value) -> Vec<ValidationIssue> {
+        self.validator
+            .iter_errors(instance)
+            .map(|error| ValidationIssue {
+                kind: error
+                    .schema_path
+                    .as_str()
+                    .rsplit('/')
+                    .next()
+                    .unwrap_or_default()
+                    .to_string(),
+                location: error.instance_path.to_string(),
+                message: error.to_string(),
+            })
+            .collect()
+    }
+
+    /// Validates a serialized entity document.
+    ///
+    /// All validation errors are reported together in a single [`EntityError::JsonSchemaError`].
+    pub fn validate(&self, entity_body: &str) -> Result<(), EntityError> {
+        let instance: serde_json::Value = serde_json::from_str(entity_body)
+            .map_err(|e| EntityError::JsonSchemaError(self.entity_type.clone(), e.to_string()))?;
+
+        let full_errors = self
+            .issues(&instance)
+            .iter()
+            .map(|issue| format!("Error: {} \tLocation: {}", issue.message, issue.location))
+            .collect::<Vec<_>>()
+            .join("\n");
+
+        if !full_errors.is_empty() {
+            debug!("pretty instance = {:#}", instance);
+            return Err(EntityError::JsonSchemaError(
+                self.entity_type.clone(),
+                full_errors,
+            ));
+        }
+        Ok(())
+    }
+}
+
+/// Signs a serialized entity body when the definition declares `_osSignedData`.
+///
+/// The plaintext is signed, before `privateFields` are encrypted, so that decrypted exports can
+/// be verified.
+fn sign_entity(
+    signer: Option<&IssuerKey>,
+    json_schema_string: &str,
+    entity_type: &str,
+    entity_body: &str,
+) -> Result<String, EntityError> {
+    let signed_fields = SignedFields::from_schema(json_schema_string, entity_type)?;
+    if !signed_fields.is_enabled() {
+        return Ok(entity_body.to_string());
+    }
+    let signer = signer.ok_or_else(|| EntityError::SigningUnavailable(entity_type.to_string()))?;
+    signed_fields.sign(entity_body, signer)
+}
+
+/// Decrypts the `privateFields` of a stored entity document.
+///
+/// Documents without encrypted fields do not require a cipher.
+pub fn decrypt_stored(
+    document: &mut serde_json::Value,
+    cipher: Option<&FieldCipher>,
+    entity_type: &str,
+) -> Result<(), EntityError> {
+    if !contains_encrypted(document) {
+        return Ok(());
+    }
+    cipher
+        .ok_or_else(|| EntityError::EncryptionUnavailable(entity_type.to_string()))?
+        .decrypt_all(document)
+        .map_err(|e| EntityError::Encryption(e.to_string()))
+}
+
+/// Encrypts the `privateFields` of the definition in a serialized entity body.
+///
+/// Definitions without private fields do not require a cipher.
+fn encrypt_private_fields(
+    cipher: Option<&FieldCipher>,
+    json_schema_string: &str,
+    entity_type: &str,
+    entity_body: &str,
+) -> Result<String, EntityError> {
+    let schema: serde_json::Value = serde_json::from_str(json_schema_string)
+        .map_err(|e| EntityError::InvalidSchema(e.to_string()))?;
+    let paths = private_fields(&schema);
+    if paths.is_empty() {
+        return Ok(entity_body.to_string());
+    }
+    let cipher =
+        cipher.ok_or_else(|| EntityError::EncryptionUnavailable(entity_type.to_string()))?;
+    let mut document: serde_json::Value =
+        serde_json::from_str(entity_body).map_err(|e| EntityError::InvalidJson(e.to_string()))?;
+    cipher
+        .encrypt_paths(&mut document, entity_type, &paths)
+        .map_err(|e| EntityError::Encryption(e.to_string()))?;
+    Ok(document.to_string())
+}
+
+/// Validates an entity document against the JSON schema of its definition.
+pub fn validate_entity_body(
+    entity_type: &str,
+    json_schema_string: &str,
+    entity_body: &str,
+) -> Result<(), EntityError> {
+    EntitySchemaValidator::new(entity_type, json_schema_string)?.validate(entity_body)
+}
+
 // Start of state machine
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub enum RegistryEntityAction {
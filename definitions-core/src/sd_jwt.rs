@@ -0,0 +1,306 @@
+//! Selective disclosure of credential claims with SD-JWT.
+//!
+//! A credential issued as [`CredentialFormat::SdJwtVc`](crate::credentials::CredentialFormat)
+//! is a VC-JWT whose `credentialSubject` keeps its `id` only, every other claim is replaced by
+//! the digest of a disclosure:
+//!
+//! ```text
+//! <issuer-signed JWT>~<disclosure>~<disclosure>~...~
+//! ```
+//!
+//! A disclosure is the base64url encoded JSON array `[salt, name, value]`, its digest the
+//! base64url encoded SHA-256 of the disclosure, listed in the `_sd` array of the subject. The
+//! holder presents a subset of the claims by dropping the other disclosures, see
+//! [`SdJwt::present`], without invalidating the issuer signature. The verifier restores the
+//! disclosed claims with [`SdJwt::disclose`].
+//!
+//! Presentations carry no key binding JWT: the wallet of the registry holds the credentials on
+//! behalf of their owners.
+use crate::credentials::vc_jwt_claims;
+use crate::signing::IssuerKey;
+use aes_gcm::aead::rand_core::RngCore;
+use aes_gcm::aead::OsRng;
+use base64::engine::general_purpose::URL_SAFE_NO_PAD as BASE64URL;
+use base64::Engine;
+use serde_json::{json, Value};
+use sha2::{Digest, Sha256};
+use std::fmt;
+use thiserror::Error;
+
+/// Hash algorithm of the disclosure digests, the `_sd_alg` claim
+pub const SD_ALG: &str = "sha-256";
+const SALT_BYTES: usize = 16;
+const SEPARATOR: char = '~';
+
+#[derive(Debug, Error, Clone, PartialEq, Eq)]
+pub enum SdJwtError {
+    #[error("Invalid SD-JWT: {0}")]
+    InvalidSdJwt(String),
+    #[error("Invalid disclosure `{0}`: {1}")]
+    InvalidDisclosure(String, String),
+    #[error("The disclosure of `{0}` is not part of the credential")]
+    UnknownDisclosure(String),
+    #[error("The credential has no selectively disclosable claim `{0}`")]
+    UnknownClaim(String),
+}
+
+/// A salted claim of the credential subject
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Disclosure {
+    encoded: String,
+    name: String,
+    value: Value,
+}
+
+impl Disclosure {
+    pub fn new(name: &str, value: Value) -> Self {
+        let mut salt = [0u8; SALT_BYTES];
+        OsRng.fill_bytes(&mut salt);
+        let encoded =
+            BASE64URL.encode(json!([BASE64URL.encode(salt), name, value.clone()]).to_string());
+        Self {
+            encoded,
+            name: name.to_string(),
+            value,
+        }
+    }
+
+    pub fn parse(encoded: &str) -> Result<Self, SdJwtError> {
+        let invalid =
+            |detail: &str| SdJwtError::InvalidDisclosure(encoded.to_string(), detail.into());
+        let bytes = BASE64URL
+            .decode(encoded)
+            .map_err(|_| invalid("not base64url"))?;
+        let parts: Vec<Value> =
+            serde_json::from_slice(&bytes).map_err(|_| invalid("not a JSON array"))?;
+        match parts.as_slice() {
+            [Value::String(_), Value::String(name), value] => Ok(Self {
+                encoded: encoded.to_string(),
+                name: name.clone(),
+                value: value.clone(),
+            }),
+            _ => Err(invalid("expected [salt, name, value]")),
+        }
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn value(&self) -> &Value {
+        &self.value
+    }
+
+    pub fn digest(&self) -> String {
+        BASE64URL.encode(Sha256::digest(self.encoded.as_bytes()))
+    }
+}
+
+/// An issuer-signed JWT with the disclosures of its concealed claims
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SdJwt {
+    jwt: String,
+    disclosures: Vec<Disclosure>,
+}
+
+impl SdJwt {
+    /// Parses the combined format, a trailing key binding JWT is not supported
+    pub fn parse(sd_jwt: &str) -> Result<Self, SdJwtError> {
+        let mut parts: Vec<&str> = sd_jwt.split(SEPARATOR).collect();
+        if parts.len() < 2 {
+            return Err(SdJwtError::InvalidSdJwt(
+                "expected `~` separated disclosures".to_string(),
+            ));
+        }
+        if !parts.pop().unwrap_or_default().is_empty() {
+            return Err(SdJwtError::InvalidSdJwt(
+                "key binding JWTs are not supported".to_string(),
+            ));
+        }
+        let jwt = parts.remove(0).to_string();
+        let disclosures = parts
+            .into_iter()
+            .map(Disclosure::parse)
+            .collect::<Result<_, _>>()?;
+        Ok(Self { jwt, disclosures })
+    }
+
+    /// The issuer-signed JWT
+    pub fn jwt(&self) -> &str {
+        &self.jwt
+    }
+
+    pub fn disclosures(&self) -> &[Disclosure] {
+        &self.disclosures
+    }
+
+    /// Names of the claims that can be disclosed
+    pub fn claim_names(&self) -> Vec<&str> {
+        self.disclosures.iter().map(Disclosure::name).collect()
+    }
+
+    /// Keeps the disclosures of the named claims only
+    pub fn present(&self, claims: &[String]) -> Result<SdJwt, SdJwtError> {
+        if let Some(unknown) = claims
+            .iter()
+            .find(|claim| !self.disclosures.iter().any(|d| d.name == **claim))
+        {
+            return Err(SdJwtError::UnknownClaim(unknown.clone()));
+        }
+        Ok(Self {
+            jwt: self.jwt.clone(),
+            disclosures: self
+                .disclosures
+                .iter()
+                .filter(|disclosure| claims.contains(&disclosure.name))
+                .cloned()
+                .collect(),
+        })
+    }
+
+    /// Restores the disclosed claims into the `credentialSubject` of the verified JWT `payload`.
+    /// Every disclosure must match a digest of the subject, once.
+    pub fn disclose(&self, payload: &Value) -> Result<Value, SdJwtError> {
+        let mut payload = payload.clone();
+        let claims = payload
+            .as_object_mut()
+            .ok_or_else(|| SdJwtError::InvalidSdJwt("the payload is not an object".to_string()))?;
+        match claims.remove("_sd_alg") {
+            Some(alg) if alg != SD_ALG => {
+                return Err(SdJwtError::InvalidSdJwt(format!(
+                    "unsupported digest algorithm {}",
+                    alg
+                )))
+            }
+            _ => {}
+        }
+        let subject = claims
+            .get_mut("vc")
+            .and_then(|vc| vc.get_mut("credentialSubject"))
+            .and_then(Value::as_object_mut)
+            .ok_or_else(|| {
+                SdJwtError::InvalidSdJwt("the `vc` claim has no credentialSubject".to_string())
+            })?;
+        let mut digests: Vec<String> = match subject.remove("_sd") {
+            Some(Value::Array(digests)) => digests
+                .into_iter()
+                .filter_map(|digest| digest.as_str().map(str::to_string))
+                .collect(),
+            _ => vec![],
+        };
+        for disclosure in &self.disclosures {
+            let Some(position) = digests.iter().position(|d| *d == disclosure.digest()) else {
+                return Err(SdJwtError::UnknownDisclosure(disclosure.name.clone()));
+            };
+            digests.swap_remove(position);
+            if subject.contains_key(&disclosure.name) {
+                return Err(SdJwtError::InvalidDisclosure(
+                    disclosure.encoded.clone(),
+                    format!("`{}` is already disclosed", disclosure.name),
+                ));
+            }
+            subject.insert(disclosure.name.clone(), disclosure.value.clone());
+        }
+        Ok(payload)
+    }
+}
+
+impl fmt::Display for SdJwt {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}{}", self.jwt, SEPARATOR)?;
+        for disclosure in &self.disclosures {
+            write!(f, "{}{}", disclosure.encoded, SEPARATOR)?;
+        }
+        Ok(())
+    }
+}
+
+/// True when a compact credential carries disclosures
+pub fn is_sd_jwt(credential: &str) -> bool {
+    credential.contains(SEPARATOR)
+}
+
+/// Signs a credential as an SD-JWT, every claim of its subject but `id` selectively disclosable
+pub fn sign_sd_jwt(credential: &Value, key: &IssuerKey) -> String {
+    let mut concealed = credential.clone();
+    let mut disclosures = vec![];
+    if let Some(Value::Object(subject)) = concealed.get_mut("credentialSubject") {
+        let names: Vec<String> = subject.keys().filter(|k| *k != "id").cloned().collect();
+        for name in names {
+            let value = subject.remove(&name).unwrap_or_default();
+            disclosures.push(Disclosure::new(&name, value));
+        }
+        let mut digests: Vec<String> = disclosures.iter().map(Disclosure::digest).collect();
+        // Sorted so that the order of the digests says nothing about the claims
+        digests.sort();
+        subject.insert("_sd".to_string(), json!(digests));
+    }
+    let mut claims = vc_jwt_claims(&concealed);
+    claims.insert("_sd_alg".to_string(), json!(SD_ALG));
+    SdJwt {
+        jwt: key.sign_jwt(&Value::Object(claims)),
+        disclosures,
+    }
+    .to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn credential() -> Value {
+        json!({
+            "@context": ["https://www.w3.org/2018/credentials/v1"],
+            "id": "urn:uuid:0196d2b4-3c2a-7d4e-8f00-000000000e01",
+            "type": ["VerifiableCredential", "InsuranceCredential"],
+            "issuer": "did:web:registry.example.com",
+            "credentialSubject": {
+                "id": "did:example:asha",
+                "policyNumber": "P-1",
+                "fullName": "Asha Rao",
+                "dateOfBirth": "1990-01-01"
+            }
+        })
+    }
+
+    #[test]
+    fn test_presentation_discloses_chosen_claims_only() {
+        let key = IssuerKey::from_seed("issuer-1", [9; 32]);
+        let issued = SdJwt::parse(&sign_sd_jwt(&credential(), &key)).unwrap();
+        let mut names = issued.claim_names();
+        names.sort();
+        assert_eq!(names, vec!["dateOfBirth", "fullName", "policyNumber"]);
+
+        let presented = issued.present(&["policyNumber".to_string()]).unwrap();
+        let parsed = SdJwt::parse(&presented.to_string()).unwrap();
+        let payload = key.public_key().verify(parsed.jwt()).unwrap();
+        assert!(!payload.to_string().contains("Asha Rao"));
+
+        let disclosed = parsed.disclose(&payload).unwrap();
+        assert_eq!(
+            disclosed["vc"]["credentialSubject"],
+            json!({"id": "did:example:asha", "policyNumber": "P-1"})
+        );
+        assert_eq!(
+            issued.present(&["mobile".to_string()]),
+            Err(SdJwtError::UnknownClaim("mobile".to_string()))
+        );
+    }
+
+    #[test]
+    fn test_forged_disclosure_is_rejected() {
+        let key = IssuerKey::from_seed("issuer-1", [9; 32]);
+        let issued = SdJwt::parse(&sign_sd_jwt(&credential(), &key)).unwrap();
+        let forged = SdJwt {
+            jwt: issued.jwt.clone(),
+            disclosures: vec![Disclosure::new("policyNumber", json!("P-2"))],
+        };
+        let payload = key.public_key().verify(forged.jwt()).unwrap();
+
+        assert_eq!(
+            forged.disclose(&payload),
+            Err(SdJwtError::UnknownDisclosure("policyNumber".to_string()))
+        );
+        assert!(SdJwt::parse(&format!("{}~kb.jwt.sig", issued.jwt)).is_err());
+    }
+}
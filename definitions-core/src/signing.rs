@@ -0,0 +1,493 @@
+//! Digital signatures of entity data.
+//!
+//! A definition has its entities signed by declaring `_osSignedData` in
+//! `_osConfig.systemFields`. The fields to sign are listed in `_osConfig.signedFields`, the whole
+//! entity is signed when the list is empty or missing:
+//!
+//! ```json
+//! "systemFields": ["_osSignedData"],
+//! "signedFields": ["$.identityDetails.fullName", "$.identityDetails.dob"]
+//! ```
+//!
+//! The signature is a compact JWS with the `EdDSA` algorithm, signed by the [`IssuerKey`] of the
+//! registry and stored in `_osSignedData`. It signs the canonical JSON of the signed data: the
+//! entity node without system fields, or an object mapping each signed path to its value. The JWS
+//! is detached (RFC 7515, appendix F), its payload segment is empty and the verifier recomputes
+//! the signed data from the entity, so that the plaintext of `privateFields` never leaks through
+//! the signature. Anyone holding the [`PublicKey`] of the issuer can check an exported record
+//! offline.
+use crate::encryption::generate_key;
+use crate::json_path::{entity_node, JsonPath};
+use crate::registry_domain::EntityError;
+use crate::system_fields::{SystemFields, OS_SIGNED_DATA};
+use base64::engine::general_purpose::URL_SAFE_NO_PAD as BASE64URL;
+use base64::Engine;
+use ed25519_dalek::{Signature, Signer, SigningKey, Verifier, VerifyingKey};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Map, Value};
+use std::fmt;
+use thiserror::Error;
+
+pub const SIGNED_FIELDS: &str = "signedFields";
+pub const JWS_ALGORITHM: &str = "EdDSA";
+
+#[derive(Debug, Error, PartialEq, Eq)]
+pub enum SigningError {
+    #[error("Invalid JWS: {0}")]
+    InvalidJws(String),
+    #[error("Unsupported JWS algorithm `{0}`")]
+    UnsupportedAlgorithm(String),
+    #[error("Signing key `{0}` is not known")]
+    UnknownKey(String),
+    #[error("Signature verification failed")]
+    InvalidSignature,
+    #[error("The signed data does not match the entity")]
+    PayloadMismatch,
+    #[error("The entity carries no signature")]
+    MissingSignature,
+}
+
+/// The Ed25519 key signing entity data on behalf of the registry
+#[derive(Clone)]
+pub struct IssuerKey {
+    kid: String,
+    key: SigningKey,
+}
+
+impl fmt::Debug for IssuerKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("IssuerKey")
+            .field("kid", &self.kid)
+            .finish_non_exhaustive()
+    }
+}
+
+impl PartialEq for IssuerKey {
+    fn eq(&self, other: &Self) -> bool {
+        self.kid == other.kid && self.key.verifying_key() == other.key.verifying_key()
+    }
+}
+
+impl Eq for IssuerKey {}
+
+impl IssuerKey {
+    /// Generates a new random key
+    pub fn generate(kid: &str) -> Self {
+        Self::from_seed(kid, generate_key())
+    }
+
+    pub fn from_seed(kid: &str, seed: [u8; 32]) -> Self {
+        Self {
+            kid: kid.to_string(),
+            key: SigningKey::from_bytes(&seed),
+        }
+    }
+
+    pub fn kid(&self) -> &str {
+        &self.kid
+    }
+
+    /// The 32 byte secret the key is derived from
+    pub fn seed(&self) -> [u8; 32] {
+        self.key.to_bytes()
+    }
+
+    pub fn public_key(&self) -> PublicKey {
+        PublicKey {
+            kid: self.kid.clone(),
+            x: BASE64URL.encode(self.key.verifying_key().as_bytes()),
+        }
+    }
+
+    /// Signs `payload` as a compact JWS
+    pub fn sign(&self, payload: &Value) -> String {
+        self.sign_with_header(json!({ "alg": JWS_ALGORITHM, "kid": self.kid }), payload)
+    }
+
+    /// Signs `payload` as a compact JWS with a detached payload, i.e. an empty payload segment
+    pub fn sign_detached(&self, payload: &Value) -> String {
+        let header = BASE64URL.encode(canonical_json(
+            &json!({ "alg": JWS_ALGORITHM, "kid": self.kid }),
+        ));
+        let signing_input = format!("{}.{}", header, BASE64URL.encode(canonical_json(payload)));
+        let signature = self.key.sign(signing_input.as_bytes());
+        format!("{}..{}", header, BASE64URL.encode(signature.to_bytes()))
+    }
+
+    /// Signs `claims` as a JWT, i.e. a compact JWS with the `JWT` type
+    pub fn sign_jwt(&self, claims: &Value) -> String {
+        self.sign_with_header(
+            json!({ "alg": JWS_ALGORITHM, "kid": self.kid, "typ": "JWT" }),
+            claims,
+        )
+    }
+
+    /// Signs raw bytes, returns the 64 byte Ed25519 signature
+    pub fn sign_bytes(&self, message: &[u8]) -> [u8; 64] {
+        self.key.sign(message).to_bytes()
+    }
+
+    fn sign_with_header(&self, header: Value, payload: &Value) -> String {
+        let signing_input = format!(
+            "{}.{}",
+            BASE64URL.encode(canonical_json(&header)),
+            BASE64URL.encode(canonical_json(payload))
+        );
+        let signature = self.key.sign(signing_input.as_bytes());
+        format!(
+            "{}.{}",
+            signing_input,
+            BASE64URL.encode(signature.to_bytes())
+        )
+    }
+}
+
+/// The public half of an [`IssuerKey`]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PublicKey {
+    pub kid: String,
+    /// The Ed25519 public key, base64url encoded as in a JWK
+    pub x: String,
+}
+
+impl PublicKey {
+    /// The key as an `OKP` JSON Web Key
+    pub fn to_jwk(&self) -> Value {
+        json!({
+            "kty": "OKP",
+            "crv": "Ed25519",
+            "alg": JWS_ALGORITHM,
+            "use": "sig",
+            "kid": self.kid,
+            "x": self.x,
+        })
+    }
+
+    /// Checks the signature of a compact JWS and returns its payload
+    pub fn verify(&self, jws: &str) -> Result<Value, SigningError> {
+        let (header, payload, signature) = split_jws(jws)?;
+        if header.alg != JWS_ALGORITHM {
+            return Err(SigningError::UnsupportedAlgorithm(header.alg));
+        }
+        if header.kid != self.kid {
+            return Err(SigningError::UnknownKey(header.kid));
+        }
+        let signing_input = &jws[..jws.len() - signature.len() - 1];
+        self.verify_bytes(signing_input.as_bytes(), &decode(signature)?)?;
+        serde_json::from_slice(&decode(payload)?)
+            .map_err(|e| SigningError::InvalidJws(e.to_string()))
+    }
+
+    /// Checks the signature of a compact JWS with a detached payload against `payload`
+    pub fn verify_detached(&self, jws: &str, payload: &Value) -> Result<(), SigningError> {
+        let jws = jws.trim();
+        let (header, detached, signature) = split_jws(jws)?;
+        if !detached.is_empty() {
+            return Err(SigningError::InvalidJws(
+                "expected a detached payload".to_string(),
+            ));
+        }
+        if header.alg != JWS_ALGORITHM {
+            return Err(SigningError::UnsupportedAlgorithm(header.alg));
+        }
+        if header.kid != self.kid {
+            return Err(SigningError::UnknownKey(header.kid));
+        }
+        let encoded_header = &jws[..jws.find('.').unwrap_or_default()];
+        let signing_input = format!(
+            "{}.{}",
+            encoded_header,
+            BASE64URL.encode(canonical_json(payload))
+        );
+        self.verify_bytes(signing_input.as_bytes(), &decode(signature)?)
+    }
+
+    /// Checks an Ed25519 signature of raw bytes
+    pub fn verify_bytes(&self, message: &[u8], signature: &[u8]) -> Result<(), SigningError> {
+        let key_bytes: [u8; 32] = decode(&self.x)?
+            .try_into()
+            .map_err(|_| SigningError::InvalidJws("invalid public key".to_string()))?;
+        let key = VerifyingKey::from_bytes(&key_bytes)
+            .map_err(|e| SigningError::InvalidJws(e.to_string()))?;
+        let signature_bytes: [u8; 64] = signature
+            .try_into()
+            .map_err(|_| SigningError::InvalidSignature)?;
+        key.verify(message, &Signature::from_bytes(&signature_bytes))
+            .map_err(|_| SigningError::InvalidSignature)
+    }
+}
+
+#[derive(Deserialize)]
+struct JwsHeader {
+    alg: String,
+    #[serde(default)]
+    kid: String,
+}
+
+/// Id of the key that signed a compact JWS
+pub fn jws_key_id(jws: &str) -> Result<String, SigningError> {
+    split_jws(jws).map(|(header, ..)| header.kid)
+}
+
+/// Payload of a compact JWS, without checking its signature
+pub fn jws_payload(jws: &str) -> Result<Value, SigningError> {
+    let (_, payload, _) = split_jws(jws)?;
+    serde_json::from_slice(&decode(payload)?).map_err(|e| SigningError::InvalidJws(e.to_string()))
+}
+
+fn split_jws(jws: &str) -> Result<(JwsHeader, &str, &str), SigningError> {
+    let mut parts = jws.trim().split('.');
+    let (Some(header), Some(payload), Some(signature), None) =
+        (parts.next(), parts.next(), parts.next(), parts.next())
+    else {
+        return Err(SigningError::InvalidJws(
+            "expected three dot separated parts".to_string(),
+        ));
+    };
+    let header: JwsHeader = serde_json::from_slice(&decode(header)?)
+        .map_err(|e| SigningError::InvalidJws(e.to_string()))?;
+    Ok((header, payload, signature))
+}
+
+fn decode(encoded: &str) -> Result<Vec<u8>, SigningError> {
+    BASE64URL
+        .decode(encoded)
+        .map_err(|e| SigningError::InvalidJws(e.to_string()))
+}
+
+/// Serializes a JSON value with the keys of every object in lexicographic order
+pub fn canonical_json(value: &Value) -> String {
+    match value {
+        Value::Object(fields) => {
+            let mut entries: Vec<(&String, &Value)> = fields.iter().collect();
+            entries.sort_by(|a, b| a.0.cmp(b.0));
+            let fields: Vec<String> = entries
+                .into_iter()
+                .map(|(key, value)| {
+                    format!("{}:{}", Value::from(key.as_str()), canonical_json(value))
+                })
+                .collect();
+            format!("{{{}}}", fields.join(","))
+        }
+        Value::Array(items) => {
+            let items: Vec<String> = items.iter().map(canonical_json).collect();
+            format!("[{}]", items.join(","))
+        }
+        _ => value.to_string(),
+    }
+}
+
+/// The signing configuration of a definition
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SignedFields {
+    entity_type: String,
+    system_fields: SystemFields,
+    paths: Vec<JsonPath>,
+}
+
+impl SignedFields {
+    pub fn from_schema(json_schema_string: &str, entity_type: &str) -> Result<Self, EntityError> {
+        let schema: Value = serde_json::from_str(json_schema_string)
+            .map_err(|e| EntityError::InvalidSchema(e.to_string()))?;
+        let paths = schema
+            .get("_osConfig")
+            .and_then(|config| config.get(SIGNED_FIELDS))
+            .and_then(Value::as_array)
+            .into_iter()
+            .flatten()
+            .filter_map(Value::as_str)
+            .map(|path| {
+                JsonPath::parse(path)
+                    .map_err(|e| EntityError::InvalidSchema(format!("{}: {}", SIGNED_FIELDS, e)))
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(Self {
+            entity_type: entity_type.to_string(),
+            system_fields: SystemFields::from_schema(json_schema_string, entity_type)?,
+            paths,
+        })
+    }
+
+    /// Returns true when the definition declares `_osSignedData`
+    pub fn is_enabled(&self) -> bool {
+        self.system_fields.declares(OS_SIGNED_DATA)
+    }
+
+    /// The data signed for an entity body
+    pub fn payload(&self, entity_body: &str) -> Result<Value, EntityError> {
+        let stripped = self.system_fields.strip(entity_body)?;
+        let document: Value =
+            serde_json::from_str(&stripped).map_err(|e| EntityError::InvalidJson(e.to_string()))?;
+        let node = entity_node(&document, &self.entity_type);
+        if self.paths.is_empty() {
+            return Ok(node.clone());
+        }
+        let mut signed = Map::new();
+        for path in &self.paths {
+            signed.insert(
+                path.to_string(),
+                path.get(node).cloned().unwrap_or(Value::Null),
+            );
+        }
+        Ok(Value::Object(signed))
+    }
+
+    /// Signs an entity body and stores the detached signature in `_osSignedData`
+    pub fn sign(&self, entity_body: &str, key: &IssuerKey) -> Result<String, EntityError> {
+        let signature = key.sign_detached(&self.payload(entity_body)?);
+        self.system_fields.signed(entity_body, &signature)
+    }
+
+    /// Verifies the signature of an entity body with one of `keys` and returns the signing key id.
+    ///
+    /// The signature is read from `_osSignedData` unless it is presented separately. A detached
+    /// signature that does not match the entity cannot tell a changed entity from a forged
+    /// signature, both are reported as [`SigningError::PayloadMismatch`]. Signatures carrying
+    /// their payload, made before signatures were detached, are still accepted.
+    pub fn verify(
+        &self,
+        entity_body: &str,
+        signature: Option<&str>,
+        keys: &[PublicKey],
+    ) -> Result<String, SigningError> {
+        let document: Value = serde_json::from_str(entity_body)
+            .map_err(|e| SigningError::InvalidJws(e.to_string()))?;
+        let signature = match signature {
+            Some(signature) => signature.to_string(),
+            None => self
+                .system_fields
+                .get(&document, OS_SIGNED_DATA)
+                .and_then(Value::as_str)
+                .map(str::to_string)
+                .ok_or(SigningError::MissingSignature)?,
+        };
+        let kid = jws_key_id(&signature)?;
+        let key = keys
+            .iter()
+            .find(|key| key.kid == kid)
+            .ok_or_else(|| SigningError::UnknownKey(kid.clone()))?;
+        let expected = self
+            .payload(entity_body)
+            .map_err(|e| SigningError::InvalidJws(e.to_string()))?;
+        let (_, payload, _) = split_jws(&signature)?;
+        if payload.is_empty() {
+            return match key.verify_detached(&signature, &expected) {
+                Err(SigningError::InvalidSignature) => Err(SigningError::PayloadMismatch),
+                verified => verified.map(|()| kid),
+            };
+        }
+        let signed = key.verify(&signature)?;
+        if canonical_json(&signed) != canonical_json(&expected) {
+            return Err(SigningError::PayloadMismatch);
+        }
+        Ok(kid)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn signed_fields(signed: Value) -> SignedFields {
+        let schema = json!({
+            "title": "Insurance",
+            "_osConfig": {
+                "systemFields": ["_osSignedData", "_osCreatedBy"],
+                "signedFields": signed
+            }
+        });
+        SignedFields::from_schema(&schema.to_string(), "Insurance").unwrap()
+    }
+
+    fn insurance() -> String {
+        json!({"Insurance": {"policyNumber": "P-1", "holder": {"name": "Asha"}, "_osCreatedBy": "alice"}})
+            .to_string()
+    }
+
+    #[test]
+    fn test_canonical_json_sorts_keys() {
+        assert_eq!(
+            canonical_json(&json!({"b": [{"d": 1, "c": "x"}], "a": null})),
+            r#"{"a":null,"b":[{"c":"x","d":1}]}"#
+        );
+    }
+
+    #[test]
+    fn test_sign_and_verify_whole_entity() {
+        let fields = signed_fields(json!([]));
+        let key = IssuerKey::generate("issuer-1");
+
+        let signed = fields.sign(&insurance(), &key).unwrap();
+
+        let document: Value = serde_json::from_str(&signed).unwrap();
+        let jws = document["Insurance"]["_osSignedData"].as_str().unwrap();
+        assert_eq!(jws.split('.').nth(1), Some(""));
+        assert_eq!(
+            key.public_key().verify_detached(
+                jws,
+                &json!({"policyNumber": "P-1", "holder": {"name": "Asha"}})
+            ),
+            Ok(())
+        );
+        assert_eq!(
+            key.public_key().verify_detached(
+                &format!(" {}\n", jws),
+                &json!({"policyNumber": "P-1", "holder": {"name": "Asha"}})
+            ),
+            Ok(())
+        );
+        assert_eq!(
+            fields.verify(&signed, None, &[key.public_key()]),
+            Ok("issuer-1".to_string())
+        );
+    }
+
+    #[test]
+    fn test_tampered_entity_is_rejected() {
+        let fields = signed_fields(json!(["$.holder.name"]));
+        let key = IssuerKey::generate("issuer-1");
+        let signed = fields.sign(&insurance(), &key).unwrap();
+
+        let tampered = signed.replace("Asha", "Ravi");
+        let unrelated = signed.replace("P-1", "P-2");
+
+        assert_eq!(
+            fields.verify(&tampered, None, &[key.public_key()]),
+            Err(SigningError::PayloadMismatch)
+        );
+        assert!(fields.verify(&unrelated, None, &[key.public_key()]).is_ok());
+        assert_eq!(
+            fields.verify(
+                &signed,
+                None,
+                &[IssuerKey::generate("issuer-2").public_key()]
+            ),
+            Err(SigningError::UnknownKey("issuer-1".to_string()))
+        );
+    }
+
+    #[test]
+    fn test_forged_signature_is_rejected() {
+        let fields = signed_fields(json!([]));
+        let key = IssuerKey::generate("issuer-1");
+        let forger = IssuerKey::from_seed("issuer-1", [7; 32]);
+        let signature = forger.sign(&fields.payload(&insurance()).unwrap());
+
+        assert_eq!(
+            fields.verify(&insurance(), Some(&signature), &[key.public_key()]),
+            Err(SigningError::InvalidSignature)
+        );
+    }
+
+    #[test]
+    fn test_signatures_carrying_their_payload_are_still_accepted() {
+        let fields = signed_fields(json!([]));
+        let key = IssuerKey::generate("issuer-1");
+        let attached = key.sign(&fields.payload(&insurance()).unwrap());
+
+        assert_eq!(
+            fields.verify(&insurance(), Some(&attached), &[key.public_key()]),
+            Ok("issuer-1".to_string())
+        );
+    }
+}
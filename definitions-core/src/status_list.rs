@@ -0,0 +1,477 @@
+//! Revocation and suspension of issued credentials with status lists.
+//!
+//! The registry manages one bitstring per [`StatusPurpose`], in the style of StatusList2021.
+//! Every credential issued by [`IssueCredentialCmd`](crate::credentials::IssueCredentialCmd) is
+//! given the next free index of the lists and embeds a `credentialStatus` entry per purpose:
+//!
+//! ```json
+//! "credentialStatus": [{
+//!   "id": "https://registry.example.com/api/v1/credentials/status/revocation#94567",
+//!   "type": "StatusList2021Entry",
+//!   "statusPurpose": "revocation",
+//!   "statusListIndex": "94567",
+//!   "statusListCredential": "https://registry.example.com/api/v1/credentials/status/revocation"
+//! }, ...]
+//! ```
+//!
+//! [`ChangeCredentialStatusCmd`] revokes, suspends or reinstates a credential by flipping its bit,
+//! and [`RevokeEntityCredentialsCmd`] revokes every credential of a deleted entity. Verifiers fetch
+//! the `statusListCredential`, a signed credential whose `encodedList` is the GZIP compressed,
+//! base64url encoded bitstring, built by [`status_list_credential`].
+use crate::audit::Actor;
+use crate::credentials::{sign_data_integrity, CredentialId};
+use crate::definitions_domain::{
+    generate_id_from_title, CredentialIssuedEvent, CredentialStatusEvent, DomainEvent,
+    RegistryDefinition,
+};
+use crate::ownership::{definition_roles, Principal};
+use crate::registry_domain::{EntityError, EntityId, RegistryResource};
+use crate::signing::IssuerKey;
+use base64::engine::general_purpose::URL_SAFE_NO_PAD as BASE64URL;
+use base64::Engine;
+use chrono::{DateTime, SecondsFormat, Utc};
+use disintegrate::{event_types, union, Decision, StateMutate, StateQuery, StreamQuery};
+use flate2::read::GzDecoder;
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::io::{Read, Write};
+use strum_macros::{Display, EnumString};
+use utoipa::ToSchema;
+
+/// Number of entries of a status list, 16KB uncompressed as recommended for herd privacy
+pub const STATUS_LIST_SIZE: u64 = 131_072;
+pub const STATUS_LIST_CONTEXT: &str = "https://w3id.org/vc/status-list/2021/v1";
+
+#[derive(
+    Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize, Display, EnumString, ToSchema,
+)]
+#[serde(rename_all = "lowercase")]
+#[strum(serialize_all = "lowercase")]
+pub enum StatusPurpose {
+    Revocation,
+    Suspension,
+}
+
+impl StatusPurpose {
+    pub const ALL: [StatusPurpose; 2] = [StatusPurpose::Revocation, StatusPurpose::Suspension];
+}
+
+/// A bitstring of [`STATUS_LIST_SIZE`] entries, index 0 is the most significant bit of the first
+/// byte
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StatusList {
+    bits: Vec<u8>,
+}
+
+impl Default for StatusList {
+    fn default() -> Self {
+        Self {
+            bits: vec![0; (STATUS_LIST_SIZE / 8) as usize],
+        }
+    }
+}
+
+impl StatusList {
+    /// A list with the bits of `indices` set, indices out of range are ignored
+    pub fn with_set(indices: impl IntoIterator<Item = u64>) -> Self {
+        let mut list = Self::default();
+        for index in indices {
+            list.set(index, true);
+        }
+        list
+    }
+
+    pub fn set(&mut self, index: u64, value: bool) {
+        if let Some(byte) = self.bits.get_mut((index / 8) as usize) {
+            let mask = 0x80 >> (index % 8);
+            if value {
+                *byte |= mask;
+            } else {
+                *byte &= !mask;
+            }
+        }
+    }
+
+    pub fn get(&self, index: u64) -> bool {
+        self.bits
+            .get((index / 8) as usize)
+            .is_some_and(|byte| byte & (0x80 >> (index % 8)) != 0)
+    }
+
+    /// The `encodedList` of the list: GZIP compressed, then base64url encoded
+    pub fn encode(&self) -> String {
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+        // Writing to a Vec cannot fail
+        let compressed = encoder
+            .write_all(&self.bits)
+            .and_then(|_| encoder.finish())
+            .unwrap_or_default();
+        BASE64URL.encode(compressed)
+    }
+
+    /// Reads an `encodedList`
+    pub fn decode(encoded: &str) -> Result<Self, EntityError> {
+        let compressed = BASE64URL
+            .decode(encoded.strip_prefix('u').unwrap_or(encoded))
+            .map_err(|e| EntityError::InvalidJson(format!("encodedList: {}", e)))?;
+        let mut bits = Vec::new();
+        GzDecoder::new(compressed.as_slice())
+            .read_to_end(&mut bits)
+            .map_err(|e| EntityError::InvalidJson(format!("encodedList: {}", e)))?;
+        Ok(Self { bits })
+    }
+}
+
+/// URL of the status list credential of a purpose
+pub fn status_list_url(base_url: &str, purpose: StatusPurpose) -> String {
+    format!("{}/{}", base_url.trim_end_matches('/'), purpose)
+}
+
+/// The `credentialStatus` entries of the credential at `index` of the lists
+pub fn status_entries(base_url: &str, index: u64) -> Value {
+    Value::Array(
+        StatusPurpose::ALL
+            .iter()
+            .map(|purpose| {
+                let list_url = status_list_url(base_url, *purpose);
+                json!({
+                    "id": format!("{}#{}", list_url, index),
+                    "type": "StatusList2021Entry",
+                    "statusPurpose": purpose,
+                    "statusListIndex": index.to_string(),
+                    "statusListCredential": list_url,
+                })
+            })
+            .collect(),
+    )
+}
+
+/// The status list credential of a purpose, issued by `issuer` and signed with `key`
+pub fn status_list_credential(
+    base_url: &str,
+    purpose: StatusPurpose,
+    list: &StatusList,
+    issuer: &str,
+    key: &IssuerKey,
+    issued_at: DateTime<Utc>,
+) -> Result<Value, EntityError> {
+    let list_url = status_list_url(base_url, purpose);
+    let credential = json!({
+        "@context": ["https://www.w3.org/2018/credentials/v1", STATUS_LIST_CONTEXT],
+        "id": list_url,
+        "type": ["VerifiableCredential", "StatusList2021Credential"],
+        "issuer": issuer,
+        "issuanceDate": issued_at.to_rfc3339_opts(SecondsFormat::Secs, true),
+        "credentialSubject": {
+            "id": format!("{}#list", list_url),
+            "type": "StatusList2021",
+            "statusPurpose": purpose,
+            "encodedList": list.encode(),
+        }
+    });
+    sign_data_integrity(&credential, key, issued_at)
+}
+
+/// The next free index of the status lists
+#[derive(Default, StateQuery, Clone, Debug, Serialize, Deserialize)]
+#[state_query(CredentialIssuedEvent)]
+pub struct StatusListAllocation {
+    next_index: u64,
+}
+
+impl StatusListAllocation {
+    /// Reserves the next index, once the lists are full no credential can be issued
+    pub fn next_index(&self) -> Result<u64, EntityError> {
+        if self.next_index >= STATUS_LIST_SIZE {
+            return Err(EntityError::StatusListExhausted(self.next_index));
+        }
+        Ok(self.next_index)
+    }
+}
+
+impl StateMutate for StatusListAllocation {
+    fn mutate(&mut self, event: Self::Event) {
+        if let CredentialIssuedEvent::CredentialIssued {
+            status_list_index: Some(index),
+            ..
+        } = event
+        {
+            self.next_index = self.next_index.max(index + 1);
+        }
+    }
+}
+
+/// Status of an issued credential
+#[derive(Default, StateQuery, Clone, Debug, Serialize, Deserialize)]
+#[state_query(CredentialStatusEvent)]
+pub struct CredentialStatus {
+    #[id]
+    credential_id: CredentialId,
+    issued: bool,
+    entity_id: EntityId,
+    status_list_index: Option<u64>,
+    revoked: bool,
+    suspended: bool,
+}
+
+impl CredentialStatus {
+    pub fn new(credential_id: CredentialId) -> Self {
+        Self {
+            credential_id,
+            ..Default::default()
+        }
+    }
+}
+
+impl StateMutate for CredentialStatus {
+    fn mutate(&mut self, event: Self::Event) {
+        match event {
+            CredentialStatusEvent::CredentialIssued {
+                id,
+                status_list_index,
+                ..
+            } => {
+                self.issued = true;
+                self.entity_id = id;
+                self.status_list_index = status_list_index;
+            }
+            CredentialStatusEvent::CredentialRevoked { .. } => self.revoked = true,
+            CredentialStatusEvent::CredentialSuspended { .. } => self.suspended = true,
+            CredentialStatusEvent::CredentialReinstated { .. } => self.suspended = false,
+        }
+    }
+}
+
+/// The credentials of an entity which can still be revoked
+#[derive(Default, StateQuery, Clone, Debug, Serialize, Deserialize)]
+#[state_query(CredentialStatusEvent)]
+pub struct EntityCredentials {
+    #[id]
+    id: EntityId,
+    revocable: Vec<(CredentialId, u64)>,
+}
+
+impl EntityCredentials {
+    pub fn new(id: EntityId) -> Self {
+        Self {
+            id,
+            ..Default::default()
+        }
+    }
+}
+
+impl StateMutate for EntityCredentials {
+    fn mutate(&mut self, event: Self::Event) {
+        match event {
+            CredentialStatusEvent::CredentialIssued {
+                credential_id,
+                status_list_index: Some(index),
+                ..
+            } => self.revocable.push((credential_id, index)),
+            CredentialStatusEvent::CredentialRevoked { credential_id, .. } => self
+                .revocable
+                .retain(|(revocable, _)| *revocable != credential_id),
+            _ => {}
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Display, ToSchema)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+#[strum(serialize_all = "lowercase")]
+pub enum CredentialStatusAction {
+    /// Sets the revocation bit, for good
+    Revoke,
+    /// Sets the suspension bit
+    Suspend,
+    /// Clears the suspension bit
+    Reinstate,
+}
+
+/// Revokes, suspends or reinstates an issued credential
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ChangeCredentialStatusCmd {
+    pub credential_id: CredentialId,
+    pub entity_id: EntityId,
+    pub entity_type: String,
+    pub action: CredentialStatusAction,
+    pub reason: Option<String>,
+    /// Must own the entity or hold one of the definition `roles`
+    pub principal: Principal,
+}
+
+impl Decision for ChangeCredentialStatusCmd {
+    type Event = DomainEvent;
+    type StateQuery = (CredentialStatus, RegistryResource, RegistryDefinition);
+    type Error = EntityError;
+
+    fn state_query(&self) -> Self::StateQuery {
+        (
+            CredentialStatus::new(self.credential_id),
+            RegistryResource::new(self.entity_id),
+            RegistryDefinition::new(generate_id_from_title(&self.entity_type)),
+        )
+    }
+
+    fn validation_query<ID: disintegrate::EventId>(&self) -> Option<StreamQuery<ID, Self::Event>> {
+        let (status, resource, def_state) = self.state_query();
+        Some(union!(
+            &status,
+            &resource,
+            def_state.exclude_events(event_types!(DomainEvent, [DefUpdated]))
+        ))
+    }
+
+    fn process(
+        &self,
+        (status, resource, def_state): &Self::StateQuery,
+    ) -> Result<Vec<Self::Event>, Self::Error> {
+        if !status.issued || status.entity_id != self.entity_id {
+            return Err(EntityError::CredentialNotFound(self.credential_id));
+        }
+        self.principal.authorize(
+            self.entity_id,
+            &resource.owners,
+            &definition_roles(&def_state.json_schema_string),
+        )?;
+        let not_changed = |reason: &str| {
+            EntityError::CredentialStatusNotChanged(
+                self.credential_id,
+                self.action.to_string(),
+                reason.to_string(),
+            )
+        };
+        let Some(status_list_index) = status.status_list_index else {
+            return Err(not_changed("it has no status list entry"));
+        };
+        if status.revoked {
+            return Err(not_changed("it has been revoked"));
+        }
+
+        let now = Utc::now();
+        let actor = self.principal.actor();
+        let event = match self.action {
+            CredentialStatusAction::Revoke => DomainEvent::CredentialRevoked {
+                id: self.entity_id,
+                credential_id: self.credential_id,
+                status_list_index,
+                reason: self.reason.clone(),
+                revoked_at: now,
+                revoked_by: actor.id.clone(),
+                on_behalf_of: actor.on_behalf_of.clone(),
+            },
+            CredentialStatusAction::Suspend if status.suspended => {
+                return Err(not_changed("it is already suspended"))
+            }
+            CredentialStatusAction::Suspend => DomainEvent::CredentialSuspended {
+                id: self.entity_id,
+                credential_id: self.credential_id,
+                status_list_index,
+                reason: self.reason.clone(),
+                suspended_at: now,
+                suspended_by: actor.id.clone(),
+                on_behalf_of: actor.on_behalf_of.clone(),
+            },
+            CredentialStatusAction::Reinstate if !status.suspended => {
+                return Err(not_changed("it is not suspended"))
+            }
+            CredentialStatusAction::Reinstate => DomainEvent::CredentialReinstated {
+                id: self.entity_id,
+                credential_id: self.credential_id,
+                status_list_index,
+                reason: self.reason.clone(),
+                reinstated_at: now,
+                reinstated_by: actor.id.clone(),
+                on_behalf_of: actor.on_behalf_of.clone(),
+            },
+        };
+        Ok(vec![event])
+    }
+}
+
+/// Revokes every credential of an entity, run by the registry once the entity is deleted
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RevokeEntityCredentialsCmd {
+    pub id: EntityId,
+    pub reason: Option<String>,
+    pub revoked_by: Actor,
+}
+
+impl Decision for RevokeEntityCredentialsCmd {
+    type Event = DomainEvent;
+    type StateQuery = EntityCredentials;
+    type Error = EntityError;
+
+    fn state_query(&self) -> Self::StateQuery {
+        EntityCredentials::new(self.id)
+    }
+
+    fn process(&self, credentials: &Self::StateQuery) -> Result<Vec<Self::Event>, Self::Error> {
+        let now = Utc::now();
+        Ok(credentials
+            .revocable
+            .iter()
+            .map(|(credential_id, index)| DomainEvent::CredentialRevoked {
+                id: self.id,
+                credential_id: *credential_id,
+                status_list_index: *index,
+                reason: self.reason.clone(),
+                revoked_at: now,
+                revoked_by: self.revoked_by.id.clone(),
+                on_behalf_of: self.revoked_by.on_behalf_of.clone(),
+            })
+            .collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::credentials::verify_data_integrity;
+
+    #[test]
+    fn test_status_list_round_trip() {
+        let list = StatusList::with_set([0, 7, 94_567, STATUS_LIST_SIZE]);
+
+        let decoded = StatusList::decode(&list.encode()).unwrap();
+
+        assert!(decoded.get(0) && decoded.get(7) && decoded.get(94_567));
+        assert!(!decoded.get(1) && !decoded.get(94_566));
+        assert_eq!(decoded, list);
+    }
+
+    #[test]
+    fn test_status_list_credential() {
+        let key = IssuerKey::from_seed("issuer-1", [5; 32]);
+        let list = StatusList::with_set([3]);
+
+        let credential = status_list_credential(
+            "https://registry.example.com/api/v1/credentials/status/",
+            StatusPurpose::Revocation,
+            &list,
+            "did:web:registry.example.com",
+            &key,
+            Utc::now(),
+        )
+        .unwrap();
+
+        assert_eq!(
+            credential["id"],
+            json!("https://registry.example.com/api/v1/credentials/status/revocation")
+        );
+        assert_eq!(
+            credential["credentialSubject"]["statusPurpose"],
+            json!("revocation")
+        );
+        let encoded = credential["credentialSubject"]["encodedList"]
+            .as_str()
+            .unwrap();
+        assert!(StatusList::decode(encoded).unwrap().get(3));
+        assert_eq!(
+            verify_data_integrity(&credential, &key.public_key()),
+            Ok(())
+        );
+    }
+}
@@ -0,0 +1,282 @@
+//! Server managed fields of an entity.
+//!
+//! A definition lists the fields owned by the registry in `_osConfig.systemFields`:
+//!
+//! ```json
+//! "systemFields": ["_osCreatedAt", "_osUpdatedAt", "_osCreatedBy", "_osUpdatedBy"]
+//! ```
+//!
+//! Clients cannot write these fields: the entity commands remove them from the submitted body
+//! before validation and then inject the values of the command, the event timestamp and the
+//! authenticated actor. Declared fields other than the audit fields, e.g. `_osSignedData`, are
+//! carried over from the stored body on updates. Names may be declared with or without the
+//! leading underscore. `_osAttestedData` is implied by `attestationPolicies` and
+//! `autoAttestationPolicies`.
+use crate::attestation::has_attestation_policies;
+use crate::auto_attestation::has_auto_attestation_policies;
+use crate::json_path::{entity_node, entity_node_mut};
+use crate::registry_domain::EntityError;
+use chrono::{DateTime, SecondsFormat, Utc};
+use serde_json::{Map, Value};
+
+pub const SYSTEM_FIELDS: &str = "systemFields";
+pub const OS_CREATED_AT: &str = "_osCreatedAt";
+pub const OS_UPDATED_AT: &str = "_osUpdatedAt";
+pub const OS_CREATED_BY: &str = "_osCreatedBy";
+pub const OS_UPDATED_BY: &str = "_osUpdatedBy";
+pub const OS_SIGNED_DATA: &str = "_osSignedData";
+pub const OS_ATTESTED_DATA: &str = "_osAttestedData";
+
+/// The system fields declared by a definition
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SystemFields {
+    entity_type: String,
+    names: Vec<String>,
+}
+
+impl SystemFields {
+    pub fn from_schema(json_schema_string: &str, entity_type: &str) -> Result<Self, EntityError> {
+        let schema: Value = serde_json::from_str(json_schema_string)
+            .map_err(|e| EntityError::InvalidSchema(e.to_string()))?;
+        let mut names = declared_system_fields(&schema);
+        if (has_attestation_policies(&schema) || has_auto_attestation_policies(&schema))
+            && !names
+                .iter()
+                .any(|name| is_same_field(name, OS_ATTESTED_DATA))
+        {
+            names.push(OS_ATTESTED_DATA.to_string());
+        }
+        Ok(Self {
+            entity_type: entity_type.to_string(),
+            names,
+        })
+    }
+
+    /// Names of the declared system fields, as written in the schema
+    pub fn names(&self) -> &[String] {
+        &self.names
+    }
+
+    /// Removes every declared system field from an entity body
+    pub fn strip(&self, entity_body: &str) -> Result<String, EntityError> {
+        self.edit(entity_body, |fields| {
+            for name in &self.names {
+                fields.remove(name);
+            }
+        })
+    }
+
+    /// Injects the creation audit fields into a stripped entity body
+    pub fn created(
+        &self,
+        entity_body: &str,
+        created_at: DateTime<Utc>,
+        created_by: &str,
+    ) -> Result<String, EntityError> {
+        let timestamp = Value::String(created_at.to_rfc3339_opts(SecondsFormat::Millis, true));
+        let actor = Value::String(created_by.to_string());
+        self.edit(entity_body, |fields| {
+            self.set(fields, OS_CREATED_AT, &timestamp);
+            self.set(fields, OS_UPDATED_AT, &timestamp);
+            self.set(fields, OS_CREATED_BY, &actor);
+            self.set(fields, OS_UPDATED_BY, &actor);
+        })
+    }
+
+    /// Injects the update audit fields into a stripped entity body.
+    ///
+    /// All other system fields, including the creation audit fields, keep the value they have
+    /// in `previous_body`.
+    pub fn updated(
+        &self,
+        entity_body: &str,
+        previous_body: &str,
+        updated_at: DateTime<Utc>,
+        updated_by: &str,
+    ) -> Result<String, EntityError> {
+        let mut previous: Value = serde_json::from_str(previous_body)
+            .map_err(|e| EntityError::InvalidJson(e.to_string()))?;
+        let previous = entity_node_mut(&mut previous, &self.entity_type).take();
+        let timestamp = Value::String(updated_at.to_rfc3339_opts(SecondsFormat::Millis, true));
+        let actor = Value::String(updated_by.to_string());
+        self.edit(entity_body, |fields| {
+            for name in &self.names {
+                if let Some(value) = previous.get(name) {
+                    fields.insert(name.clone(), value.clone());
+                }
+            }
+            self.set(fields, OS_UPDATED_AT, &timestamp);
+            self.set(fields, OS_UPDATED_BY, &actor);
+        })
+    }
+
+    /// Returns true when the well known field is declared, with or without the leading underscore
+    pub fn declares(&self, canonical: &str) -> bool {
+        self.names.iter().any(|name| is_same_field(name, canonical))
+    }
+
+    /// Value of a well known field in an entity document
+    pub fn get<'a>(&self, document: &'a Value, canonical: &str) -> Option<&'a Value> {
+        let node = entity_node(document, &self.entity_type);
+        self.names
+            .iter()
+            .filter(|name| is_same_field(name, canonical))
+            .find_map(|name| node.get(name))
+    }
+
+    /// Stores the signature of the entity data in `_osSignedData`, see [`crate::signing`]
+    pub fn signed(&self, entity_body: &str, signature: &str) -> Result<String, EntityError> {
+        let signature = Value::String(signature.to_string());
+        self.edit(entity_body, |fields| {
+            self.set(fields, OS_SIGNED_DATA, &signature);
+        })
+    }
+
+    /// Records the attestation of a policy in `_osAttestedData`, see [`crate::attestation`]
+    pub fn attested(
+        &self,
+        entity_body: &str,
+        policy_name: &str,
+        attestation: Value,
+    ) -> Result<String, EntityError> {
+        let Some(name) = self
+            .names
+            .iter()
+            .find(|name| is_same_field(name, OS_ATTESTED_DATA))
+        else {
+            return Ok(entity_body.to_string());
+        };
+        self.edit(entity_body, |fields| {
+            let attested = fields
+                .entry(name.clone())
+                .or_insert_with(|| Value::Object(Map::new()));
+            if !attested.is_object() {
+                *attested = Value::Object(Map::new());
+            }
+            if let Value::Object(attested) = attested {
+                attested.insert(policy_name.to_string(), attestation);
+            }
+        })
+    }
+
+    /// Sets a well known field under the name it was declared with, if it is declared
+    fn set(&self, fields: &mut Map<String, Value>, canonical: &str, value: &Value) {
+        if let Some(name) = self
+            .names
+            .iter()
+            .find(|name| is_same_field(name, canonical))
+        {
+            fields.insert(name.clone(), value.clone());
+        }
+    }
+
+    fn edit<F>(&self, entity_body: &str, f: F) -> Result<String, EntityError>
+    where
+        F: FnOnce(&mut Map<String, Value>),
+    {
+        if self.names.is_empty() {
+            return Ok(entity_body.to_string());
+        }
+        let mut document: Value = serde_json::from_str(entity_body)
+            .map_err(|e| EntityError::InvalidJson(e.to_string()))?;
+        if let Value::Object(fields) = entity_node_mut(&mut document, &self.entity_type) {
+            f(fields);
+        }
+        Ok(document.to_string())
+    }
+}
+
+/// Reads `_osConfig.systemFields` of a schema
+pub fn declared_system_fields(schema: &Value) -> Vec<String> {
+    schema
+        .get("_osConfig")
+        .and_then(|config| config.get(SYSTEM_FIELDS))
+        .and_then(Value::as_array)
+        .map(|names| {
+            names
+                .iter()
+                .filter_map(Value::as_str)
+                .map(str::to_string)
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+/// `osCreatedAt` and `_osCreatedAt` name the same field
+fn is_same_field(declared: &str, canonical: &str) -> bool {
+    declared.trim_start_matches('_') == canonical.trim_start_matches('_')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn system_fields() -> SystemFields {
+        let schema = json!({
+            "title": "Teacher",
+            "_osConfig": { "systemFields": ["osCreatedAt", "_osUpdatedAt", "_osCreatedBy", "_osUpdatedBy", "_osSignedData"] }
+        });
+        SystemFields::from_schema(&schema.to_string(), "Teacher").unwrap()
+    }
+
+    #[test]
+    fn test_created_strips_client_values_and_stamps() {
+        let fields = system_fields();
+        let body = json!({"Teacher": {"name": "Smith", "_osCreatedBy": "mallory", "_osSignedData": "forged"}});
+        let at = DateTime::parse_from_rfc3339("2025-01-01T10:00:00Z")
+            .unwrap()
+            .with_timezone(&Utc);
+
+        let stripped = fields.strip(&body.to_string()).unwrap();
+        let stored: Value =
+            serde_json::from_str(&fields.created(&stripped, at, "alice").unwrap()).unwrap();
+
+        assert_eq!(
+            stored,
+            json!({"Teacher": {
+                "name": "Smith",
+                "osCreatedAt": "2025-01-01T10:00:00.000Z",
+                "_osUpdatedAt": "2025-01-01T10:00:00.000Z",
+                "_osCreatedBy": "alice",
+                "_osUpdatedBy": "alice"
+            }})
+        );
+    }
+
+    #[test]
+    fn test_updated_preserves_creation_fields() {
+        let fields = system_fields();
+        let previous = json!({"Teacher": {
+            "name": "Smith",
+            "osCreatedAt": "2025-01-01T10:00:00.000Z",
+            "_osCreatedBy": "alice",
+            "_osSignedData": "signature"
+        }});
+        let body =
+            json!({"Teacher": {"name": "John Smith", "osCreatedAt": "1970-01-01T00:00:00Z"}});
+        let at = DateTime::parse_from_rfc3339("2025-02-01T10:00:00Z")
+            .unwrap()
+            .with_timezone(&Utc);
+
+        let stripped = fields.strip(&body.to_string()).unwrap();
+        let stored: Value = serde_json::from_str(
+            &fields
+                .updated(&stripped, &previous.to_string(), at, "bob")
+                .unwrap(),
+        )
+        .unwrap();
+
+        assert_eq!(
+            stored,
+            json!({"Teacher": {
+                "name": "John Smith",
+                "osCreatedAt": "2025-01-01T10:00:00.000Z",
+                "_osCreatedBy": "alice",
+                "_osSignedData": "signature",
+                "_osUpdatedAt": "2025-02-01T10:00:00.000Z",
+                "_osUpdatedBy": "bob"
+            }})
+        );
+    }
+}
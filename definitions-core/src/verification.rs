@@ -0,0 +1,775 @@
+//! Verification of credentials and presentations for relying parties.
+//!
+//! [`CredentialVerifier`] works offline, against the key material, status lists and active
+//! definitions it is given. It accepts a Verifiable Credential or a Verifiable Presentation,
+//! either as a JSON-LD document or as a compact JWT, and returns a [`Verdict`] listing the
+//! outcome of every check:
+//!
+//! - `signature`: the `DataIntegrityProof` or the JWS was made by one of the issuer keys
+//! - `validity`: the credential is neither expired nor used before its issuance date
+//! - `revocation` and `suspension`: the bit of the credential in its status lists is not set
+//! - `schema`: the credential names an active definition, through its `credentialSchema` id or
+//!   one of its types, e.g. `Insurance` or `InsuranceCredential`
+//!
+//! An [SD-JWT](crate::sd_jwt) credential is verified with the claims it discloses, which must all
+//! match a digest signed by the issuer. The verdict lists the disclosed `credentialSubject`.
+//!
+//! A presentation is verified when all the credentials it embeds are. Its own proof is made by
+//! the holder, so it is only checked when it was signed with a known key: a registry key, or a
+//! key of the [holder DID](holder_did) resolved by the caller and given as a holder key.
+use crate::credentials::{verify_data_integrity, CredentialFormat};
+use crate::definitions_domain::generate_id_from_title;
+use crate::sd_jwt::{is_sd_jwt, SdJwt};
+use crate::signing::{jws_key_id, jws_payload, PublicKey, SigningError};
+use crate::status_list::{StatusList, StatusPurpose};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::HashMap;
+use strum_macros::Display;
+use utoipa::ToSchema;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Display, ToSchema)]
+#[serde(rename_all = "lowercase")]
+#[strum(serialize_all = "lowercase")]
+pub enum CheckName {
+    Format,
+    Signature,
+    Validity,
+    Revocation,
+    Suspension,
+    Schema,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Display, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum CheckStatus {
+    Passed,
+    Failed,
+    /// The check does not apply, e.g. a credential without `credentialStatus`
+    Skipped,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+pub struct Check {
+    pub check: CheckName,
+    pub status: CheckStatus,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub detail: Option<String>,
+}
+
+impl Check {
+    fn passed(check: CheckName) -> Self {
+        Self {
+            check,
+            status: CheckStatus::Passed,
+            detail: None,
+        }
+    }
+
+    fn failed(check: CheckName, detail: impl Into<String>) -> Self {
+        Self {
+            check,
+            status: CheckStatus::Failed,
+            detail: Some(detail.into()),
+        }
+    }
+
+    fn skipped(check: CheckName, detail: impl Into<String>) -> Self {
+        Self {
+            check,
+            status: CheckStatus::Skipped,
+            detail: Some(detail.into()),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Display, ToSchema)]
+pub enum DocumentType {
+    VerifiableCredential,
+    VerifiablePresentation,
+}
+
+/// The outcome of a verification
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+pub struct Verdict {
+    /// True when no check failed, including the checks of the embedded credentials
+    pub verified: bool,
+    pub document_type: DocumentType,
+    pub format: CredentialFormat,
+    /// The `id` of the credential or presentation
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub id: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub issuer: Option<String>,
+    pub checks: Vec<Check>,
+    /// The `credentialSubject` of a selective disclosure credential, with the disclosed claims
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub disclosed: Option<Value>,
+    /// Verdicts of the credentials of a presentation
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    #[schema(no_recursion)]
+    pub credentials: Vec<Verdict>,
+}
+
+impl Verdict {
+    fn new(document_type: DocumentType, format: CredentialFormat, checks: Vec<Check>) -> Self {
+        Self {
+            verified: false,
+            document_type,
+            format,
+            id: None,
+            issuer: None,
+            checks,
+            disclosed: None,
+            credentials: vec![],
+        }
+        .settle()
+    }
+
+    fn settle(mut self) -> Self {
+        self.verified = self
+            .checks
+            .iter()
+            .all(|check| check.status != CheckStatus::Failed)
+            && self
+                .credentials
+                .iter()
+                .all(|credential| credential.verified);
+        self
+    }
+
+    pub fn check(&self, name: CheckName) -> Option<&Check> {
+        self.checks.iter().find(|check| check.check == name)
+    }
+}
+
+/// Verifies credentials and presentations against local key material
+#[derive(Debug, Clone)]
+pub struct CredentialVerifier {
+    keys: Vec<PublicKey>,
+    /// Keys of presentation holders, whose `kid` is the absolute verification method id
+    holder_keys: Vec<PublicKey>,
+    /// Status lists by the URL of their status list credential
+    status_lists: HashMap<String, StatusList>,
+    active_definitions: Vec<String>,
+    now: DateTime<Utc>,
+}
+
+impl CredentialVerifier {
+    pub fn new(keys: Vec<PublicKey>) -> Self {
+        Self {
+            keys,
+            holder_keys: vec![],
+            status_lists: HashMap::new(),
+            active_definitions: vec![],
+            now: Utc::now(),
+        }
+    }
+
+    pub fn with_status_list(mut self, url: impl Into<String>, list: StatusList) -> Self {
+        self.status_lists.insert(url.into(), list);
+        self
+    }
+
+    /// Adds keys of presentation holders, e.g. the [`crate::did::verification_keys`] of their DID
+    pub fn with_holder_keys(mut self, keys: impl IntoIterator<Item = PublicKey>) -> Self {
+        self.holder_keys.extend(keys);
+        self
+    }
+
+    /// Adds the title of an active definition
+    pub fn with_active_definition(mut self, title: impl Into<String>) -> Self {
+        self.active_definitions.push(title.into());
+        self
+    }
+
+    /// Verifies at the given time instead of now
+    pub fn at(mut self, now: DateTime<Utc>) -> Self {
+        self.now = now;
+        self
+    }
+
+    fn key(&self, kid: &str) -> Option<&PublicKey> {
+        self.keys.iter().find(|key| key.kid == kid)
+    }
+
+    /// The holder key of a verification method, given as an absolute id or as a fragment. The
+    /// key takes the id it was referred to by.
+    fn holder_key(&self, method: &str) -> Option<PublicKey> {
+        self.holder_keys
+            .iter()
+            .find(|key| key.kid == method || key_fragment(&key.kid) == method)
+            .map(|key| PublicKey {
+                kid: method.to_string(),
+                x: key.x.clone(),
+            })
+    }
+
+    /// Verifies a JSON-LD document, or a JWT given as a JSON string
+    pub fn verify(&self, document: &Value) -> Verdict {
+        match document {
+            Value::String(jwt) => self.verify_jwt(jwt),
+            Value::Object(_) if is_presentation(document) => self.verify_ldp_presentation(document),
+            Value::Object(_) => self.verify_ldp_credential(document),
+            _ => Verdict::new(
+                DocumentType::VerifiableCredential,
+                CredentialFormat::LdpVc,
+                vec![Check::failed(
+                    CheckName::Format,
+                    "expected a JSON-LD document or a JWT",
+                )],
+            ),
+        }
+    }
+
+    /// Verifies a compact JWT carrying a `vc` or a `vp` claim, or an SD-JWT credential
+    pub fn verify_jwt(&self, jwt: &str) -> Verdict {
+        if is_sd_jwt(jwt) {
+            return self.verify_sd_jwt(jwt);
+        }
+        let signed = jws_key_id(jwt).and_then(|kid| match self.key(&kid) {
+            Some(key) => key.verify(jwt),
+            None => match self.holder_key(&kid) {
+                Some(key) if is_presentation_jwt(jwt) => key.verify(jwt),
+                _ => Err(SigningError::UnknownKey(kid)),
+            },
+        });
+        let (claims, signature) = match signed {
+            Ok(claims) => (claims, Check::passed(CheckName::Signature)),
+            // Presentations are signed by their holder, their credentials are still checked
+            Err(SigningError::UnknownKey(_)) if is_presentation_jwt(jwt) => (
+                jws_payload(jwt).unwrap_or_default(),
+                Check::skipped(CheckName::Signature, "the holder key is not known"),
+            ),
+            Err(e @ SigningError::InvalidJws(_)) => {
+                return Verdict::new(
+                    DocumentType::VerifiableCredential,
+                    CredentialFormat::JwtVc,
+                    vec![Check::failed(CheckName::Format, e.to_string())],
+                )
+            }
+            Err(e) => {
+                return Verdict::new(
+                    DocumentType::VerifiableCredential,
+                    CredentialFormat::JwtVc,
+                    vec![Check::failed(CheckName::Signature, e.to_string())],
+                )
+            }
+        };
+
+        if let Some(presentation) = claims.get("vp") {
+            let mut verdict = self.presentation_verdict(presentation, CredentialFormat::JwtVc);
+            verdict.checks.insert(0, signature);
+            return verdict.settle();
+        }
+        let Some(credential) = claims.get("vc") else {
+            return Verdict::new(
+                DocumentType::VerifiableCredential,
+                CredentialFormat::JwtVc,
+                vec![Check::failed(
+                    CheckName::Format,
+                    "the JWT carries neither a `vc` nor a `vp` claim",
+                )],
+            );
+        };
+        self.jwt_credential_verdict(CredentialFormat::JwtVc, signature, &claims, credential)
+    }
+
+    /// Verifies an SD-JWT credential with the claims it discloses
+    fn verify_sd_jwt(&self, combined: &str) -> Verdict {
+        let failed = |check: CheckName, detail: String| {
+            Verdict::new(
+                DocumentType::VerifiableCredential,
+                CredentialFormat::SdJwtVc,
+                vec![Check::failed(check, detail)],
+            )
+        };
+        let sd_jwt = match SdJwt::parse(combined) {
+            Ok(sd_jwt) => sd_jwt,
+            Err(e) => return failed(CheckName::Format, e.to_string()),
+        };
+        let jwt = sd_jwt.jwt();
+        let signed = jws_key_id(jwt).and_then(|kid| match self.key(&kid) {
+            Some(key) => key.verify(jwt),
+            None => Err(SigningError::UnknownKey(kid)),
+        });
+        let claims = match signed {
+            Ok(claims) => claims,
+            Err(e @ SigningError::InvalidJws(_)) => {
+                return failed(CheckName::Format, e.to_string())
+            }
+            Err(e) => return failed(CheckName::Signature, e.to_string()),
+        };
+        // A disclosure without a signed digest was not issued
+        let claims = match sd_jwt.disclose(&claims) {
+            Ok(claims) => claims,
+            Err(e) => return failed(CheckName::Signature, e.to_string()),
+        };
+        let Some(credential) = claims.get("vc") else {
+            return failed(
+                CheckName::Format,
+                "the SD-JWT carries no `vc` claim".to_string(),
+            );
+        };
+        let mut verdict = self.jwt_credential_verdict(
+            CredentialFormat::SdJwtVc,
+            Check::passed(CheckName::Signature),
+            &claims,
+            credential,
+        );
+        verdict.disclosed = credential.get("credentialSubject").cloned();
+        verdict
+    }
+
+    fn jwt_credential_verdict(
+        &self,
+        format: CredentialFormat,
+        signature: Check,
+        claims: &Value,
+        credential: &Value,
+    ) -> Verdict {
+        let mut checks = vec![signature];
+        checks.extend(self.credential_checks(credential, Some(claims)));
+        let mut verdict = Verdict::new(DocumentType::VerifiableCredential, format, checks);
+        verdict.id = claims
+            .get("jti")
+            .or_else(|| credential.get("id"))
+            .and_then(Value::as_str)
+            .map(str::to_string);
+        verdict.issuer = claims
+            .get("iss")
+            .and_then(Value::as_str)
+            .or_else(|| crate::credentials::issuer_id(credential))
+            .map(str::to_string);
+        verdict
+    }
+
+    fn verify_ldp_credential(&self, credential: &Value) -> Verdict {
+        let mut checks = vec![self.proof_check(credential)];
+        checks.extend(self.credential_checks(credential, None));
+        let mut verdict = Verdict::new(
+            DocumentType::VerifiableCredential,
+            CredentialFormat::LdpVc,
+            checks,
+        );
+        verdict.id = credential
+            .get("id")
+            .and_then(Value::as_str)
+            .map(str::to_string);
+        verdict.issuer = crate::credentials::issuer_id(credential).map(str::to_string);
+        verdict
+    }
+
+    fn verify_ldp_presentation(&self, presentation: &Value) -> Verdict {
+        let mut verdict = self.presentation_verdict(presentation, CredentialFormat::LdpVc);
+        let holder_proof = match proof_method(presentation) {
+            Some(method) if self.key(key_fragment(method)).is_some() => {
+                self.proof_check(presentation)
+            }
+            Some(method) => match self.holder_key(method) {
+                Some(key) => signature_check(presentation, &key),
+                None => Check::skipped(CheckName::Signature, "the holder key is not known"),
+            },
+            None => Check::skipped(CheckName::Signature, "the presentation is not signed"),
+        };
+        verdict.checks.insert(0, holder_proof);
+        verdict.settle()
+    }
+
+    fn presentation_verdict(&self, presentation: &Value, format: CredentialFormat) -> Verdict {
+        let credentials: Vec<Verdict> = match presentation.get("verifiableCredential") {
+            Some(Value::Array(credentials)) => {
+                credentials.iter().map(|vc| self.verify(vc)).collect()
+            }
+            Some(credential) => vec![self.verify(credential)],
+            None => vec![],
+        };
+        let checks = if credentials.is_empty() {
+            vec![Check::failed(
+                CheckName::Format,
+                "the presentation embeds no credential",
+            )]
+        } else {
+            vec![Check::passed(CheckName::Format)]
+        };
+        let mut verdict = Verdict::new(DocumentType::VerifiablePresentation, format, checks);
+        verdict.id = presentation
+            .get("id")
+            .and_then(Value::as_str)
+            .map(str::to_string);
+        verdict.credentials = credentials;
+        verdict.settle()
+    }
+
+    fn proof_check(&self, document: &Value) -> Check {
+        let Some(kid) = proof_method(document).map(key_fragment) else {
+            return Check::failed(
+                CheckName::Signature,
+                SigningError::MissingSignature.to_string(),
+            );
+        };
+        let Some(key) = self.key(kid) else {
+            return Check::failed(
+                CheckName::Signature,
+                SigningError::UnknownKey(kid.to_string()).to_string(),
+            );
+        };
+        signature_check(document, key)
+    }
+
+    /// The checks of a credential besides its signature, `claims` are the JWT claims if any
+    fn credential_checks(&self, credential: &Value, claims: Option<&Value>) -> Vec<Check> {
+        let mut checks = vec![self.validity_check(credential, claims)];
+        checks.extend(
+            StatusPurpose::ALL
+                .iter()
+                .map(|purpose| self.status_check(credential, *purpose)),
+        );
+        checks.push(self.schema_check(credential));
+        checks
+    }
+
+    fn validity_check(&self, credential: &Value, claims: Option<&Value>) -> Check {
+        let date = |claim: &str, fields: [&str; 2]| {
+            claims
+                .and_then(|claims| claims.get(claim))
+                .and_then(Value::as_i64)
+                .and_then(|timestamp| DateTime::from_timestamp(timestamp, 0))
+                .or_else(|| {
+                    fields
+                        .iter()
+                        .filter_map(|field| credential.get(field)?.as_str())
+                        .find_map(|date| DateTime::parse_from_rfc3339(date).ok())
+                        .map(|date| date.with_timezone(&Utc))
+                })
+        };
+        if let Some(expires) = date("exp", ["expirationDate", "validUntil"]) {
+            if expires <= self.now {
+                return Check::failed(CheckName::Validity, format!("expired at {}", expires));
+            }
+        }
+        if let Some(valid_from) = date("nbf", ["issuanceDate", "validFrom"]) {
+            if valid_from > self.now {
+                return Check::failed(
+                    CheckName::Validity,
+                    format!("not valid before {}", valid_from),
+                );
+            }
+        }
+        Check::passed(CheckName::Validity)
+    }
+
+    fn status_check(&self, credential: &Value, purpose: StatusPurpose) -> Check {
+        let check = match purpose {
+            StatusPurpose::Revocation => CheckName::Revocation,
+            StatusPurpose::Suspension => CheckName::Suspension,
+        };
+        let entries = match credential.get("credentialStatus") {
+            Some(Value::Array(entries)) => entries.iter().collect(),
+            Some(entry) => vec![entry],
+            None => vec![],
+        };
+        let Some(entry) = entries.into_iter().find(|entry| {
+            entry.get("statusPurpose").and_then(Value::as_str) == Some(&purpose.to_string())
+        }) else {
+            return Check::skipped(check, format!("no {} status entry", purpose));
+        };
+        let url = entry
+            .get("statusListCredential")
+            .and_then(Value::as_str)
+            .unwrap_or_default();
+        let index = entry.get("statusListIndex").and_then(|index| match index {
+            Value::String(index) => index.parse::<u64>().ok(),
+            index => index.as_u64(),
+        });
+        let (Some(list), Some(index)) = (self.status_lists.get(url), index) else {
+            return Check::failed(check, format!("status list `{}` is not available", url));
+        };
+        if list.get(index) {
+            Check::failed(check, format!("the credential is {}", status_word(purpose)))
+        } else {
+            Check::passed(check)
+        }
+    }
+
+    fn schema_check(&self, credential: &Value) -> Check {
+        let mut candidates: Vec<&str> = match credential.get("type") {
+            Some(Value::Array(types)) => types.iter().filter_map(Value::as_str).collect(),
+            Some(Value::String(credential_type)) => vec![credential_type],
+            _ => vec![],
+        };
+        let schemas = match credential.get("credentialSchema") {
+            Some(Value::Array(schemas)) => schemas.iter().collect(),
+            Some(schema) => vec![schema],
+            None => vec![],
+        };
+        candidates.extend(
+            schemas
+                .into_iter()
+                .filter_map(|schema| schema.get("id")?.as_str())
+                .filter_map(|id| id.rsplit(['/', ':']).next()),
+        );
+        let matched = self.active_definitions.iter().find(|title| {
+            let def_id = generate_id_from_title(title).to_string();
+            candidates.iter().any(|candidate| {
+                *candidate == title.as_str()
+                    || *candidate == def_id
+                    || candidate.strip_suffix("Credential") == Some(title.as_str())
+            })
+        });
+        match matched {
+            Some(_) => Check::passed(CheckName::Schema),
+            None => Check::failed(
+                CheckName::Schema,
+                "the credential matches no active definition",
+            ),
+        }
+    }
+}
+
+fn status_word(purpose: StatusPurpose) -> &'static str {
+    match purpose {
+        StatusPurpose::Revocation => "revoked",
+        StatusPurpose::Suspension => "suspended",
+    }
+}
+
+fn is_presentation(document: &Value) -> bool {
+    match document.get("type") {
+        Some(Value::Array(types)) => types
+            .iter()
+            .any(|t| t.as_str() == Some("VerifiablePresentation")),
+        Some(Value::String(t)) => t == "VerifiablePresentation",
+        _ => false,
+    }
+}
+
+fn is_presentation_jwt(jwt: &str) -> bool {
+    jws_payload(jwt).is_ok_and(|claims| claims.get("vp").is_some())
+}
+
+/// The DID of the holder of a presentation, a JSON-LD document or a JWT given as a JSON string,
+/// taken from the verification method of its proof or the `kid` of its JWS header. Only the
+/// `did:key` and `did:web` methods are returned.
+pub fn holder_did(document: &Value) -> Option<String> {
+    let method = match document {
+        Value::String(jwt) if is_presentation_jwt(jwt) => jws_key_id(jwt)
+            .ok()
+            .filter(|kid| kid.starts_with("did:"))
+            .or_else(|| {
+                jws_payload(jwt)
+                    .ok()?
+                    .get("iss")?
+                    .as_str()
+                    .map(str::to_string)
+            })?,
+        Value::Object(_) if is_presentation(document) => proof_method(document)
+            .map(str::to_string)
+            .or_else(|| document.get("holder")?.as_str().map(str::to_string))?,
+        _ => return None,
+    };
+    let did = method
+        .split_once('#')
+        .map_or(method.as_str(), |(did, _)| did);
+    (did.starts_with("did:key:") || did.starts_with("did:web:")).then(|| did.to_string())
+}
+
+fn signature_check(document: &Value, key: &PublicKey) -> Check {
+    match verify_data_integrity(document, key) {
+        Ok(()) => Check::passed(CheckName::Signature),
+        Err(e) => Check::failed(CheckName::Signature, e.to_string()),
+    }
+}
+
+/// The `verificationMethod` of the proof of a document
+fn proof_method(document: &Value) -> Option<&str> {
+    document.get("proof")?.get("verificationMethod")?.as_str()
+}
+
+/// The key id of a verification method, the fragment of the method URL
+fn key_fragment(method: &str) -> &str {
+    method.rsplit_once('#').map_or(method, |(_, kid)| kid)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::credentials::{sign_data_integrity, sign_vc_jwt};
+    use crate::did::{did_key, did_key_document, verification_keys};
+    use crate::sd_jwt::sign_sd_jwt;
+    use crate::signing::IssuerKey;
+    use crate::status_list::{status_entries, status_list_url};
+    use serde_json::json;
+
+    const STATUS_LISTS: &str = "https://registry.example.com/api/v1/credentials/status";
+
+    fn issuer() -> IssuerKey {
+        IssuerKey::from_seed("issuer-1", [9; 32])
+    }
+
+    fn credential() -> Value {
+        json!({
+            "@context": ["https://www.w3.org/2018/credentials/v1"],
+            "id": "urn:uuid:0196d2b4-3c2a-7d4e-8f00-000000000e01",
+            "type": ["VerifiableCredential", "InsuranceCredential"],
+            "issuer": "did:web:registry.example.com",
+            "issuanceDate": "2025-01-01T00:00:00Z",
+            "expirationDate": "2030-01-01T00:00:00Z",
+            "credentialStatus": status_entries(STATUS_LISTS, 7),
+            "credentialSubject": { "policyNumber": "P-1" }
+        })
+    }
+
+    fn verifier(revoked: &[u64]) -> CredentialVerifier {
+        CredentialVerifier::new(vec![issuer().public_key()])
+            .with_status_list(
+                status_list_url(STATUS_LISTS, StatusPurpose::Revocation),
+                StatusList::with_set(revoked.iter().copied()),
+            )
+            .with_status_list(
+                status_list_url(STATUS_LISTS, StatusPurpose::Suspension),
+                StatusList::default(),
+            )
+            .with_active_definition("Insurance")
+            .at("2026-01-01T00:00:00Z".parse().unwrap())
+    }
+
+    fn failed(verdict: &Verdict) -> Vec<CheckName> {
+        verdict
+            .checks
+            .iter()
+            .filter(|check| check.status == CheckStatus::Failed)
+            .map(|check| check.check)
+            .collect()
+    }
+
+    #[test]
+    fn test_data_integrity_credential() {
+        let signed = sign_data_integrity(&credential(), &issuer(), Utc::now()).unwrap();
+
+        let verdict = verifier(&[]).verify(&signed);
+        assert!(verdict.verified, "{:?}", verdict);
+        assert_eq!(
+            verdict.issuer.as_deref(),
+            Some("did:web:registry.example.com")
+        );
+
+        let mut tampered = signed;
+        tampered["credentialSubject"]["policyNumber"] = json!("P-2");
+        assert_eq!(
+            failed(&verifier(&[]).verify(&tampered)),
+            vec![CheckName::Signature]
+        );
+    }
+
+    #[test]
+    fn test_revoked_expired_and_unknown_credentials() {
+        let signed = sign_data_integrity(&credential(), &issuer(), Utc::now()).unwrap();
+        assert_eq!(
+            failed(&verifier(&[7]).verify(&signed)),
+            vec![CheckName::Revocation]
+        );
+
+        let late = verifier(&[]).at("2031-01-01T00:00:00Z".parse().unwrap());
+        assert_eq!(failed(&late.verify(&signed)), vec![CheckName::Validity]);
+
+        let mut other = credential();
+        other["type"] = json!(["VerifiableCredential", "DegreeCredential"]);
+        let other = sign_data_integrity(&other, &issuer(), Utc::now()).unwrap();
+        assert_eq!(
+            failed(&verifier(&[]).verify(&other)),
+            vec![CheckName::Schema]
+        );
+
+        let stranger = IssuerKey::from_seed("issuer-2", [1; 32]);
+        let forged = sign_data_integrity(&credential(), &stranger, Utc::now()).unwrap();
+        assert_eq!(
+            failed(&verifier(&[]).verify(&forged)),
+            vec![CheckName::Signature]
+        );
+    }
+
+    #[test]
+    fn test_jwt_presentation() {
+        let jwt = sign_vc_jwt(&credential(), &issuer());
+        assert!(verifier(&[]).verify(&json!(jwt)).verified);
+
+        let holder = IssuerKey::from_seed("holder-1", [2; 32]);
+        let presentation = holder.sign_jwt(&json!({
+            "vp": {
+                "type": ["VerifiablePresentation"],
+                "verifiableCredential": [jwt]
+            }
+        }));
+
+        let verdict = verifier(&[]).verify_jwt(&presentation);
+        assert_eq!(verdict.document_type, DocumentType::VerifiablePresentation);
+        assert!(verdict.verified, "{:?}", verdict);
+        assert_eq!(
+            verdict
+                .check(CheckName::Signature)
+                .map(|check| check.status),
+            Some(CheckStatus::Skipped)
+        );
+
+        let verdict = verifier(&[7]).verify_jwt(&presentation);
+        assert!(!verdict.verified);
+        assert_eq!(failed(&verdict.credentials[0]), vec![CheckName::Revocation]);
+    }
+
+    #[test]
+    fn test_selectively_disclosed_credential() {
+        let mut credential = credential();
+        credential["credentialSubject"]["fullName"] = json!("Asha Rao");
+        let issued = SdJwt::parse(&sign_sd_jwt(&credential, &issuer())).unwrap();
+        let presented = issued.present(&["policyNumber".to_string()]).unwrap();
+
+        let verdict = verifier(&[]).verify(&json!(presented.to_string()));
+        assert!(verdict.verified, "{:?}", verdict);
+        assert_eq!(verdict.format, CredentialFormat::SdJwtVc);
+        assert_eq!(verdict.disclosed, Some(json!({"policyNumber": "P-1"})));
+
+        let verdict = verifier(&[7]).verify(&json!(presented.to_string()));
+        assert_eq!(failed(&verdict), vec![CheckName::Revocation]);
+
+        let tampered = format!(
+            "{}~{}~",
+            presented.jwt(),
+            "WyJzYWx0IiwicG9saWN5TnVtYmVyIiwiUC0yIl0"
+        );
+        let verdict = verifier(&[]).verify_jwt(&tampered);
+        assert_eq!(failed(&verdict), vec![CheckName::Signature]);
+    }
+
+    #[test]
+    fn test_presentation_of_a_did_key_holder() {
+        let jwt = sign_vc_jwt(&credential(), &issuer());
+        let seed = [2; 32];
+        let did = did_key(&IssuerKey::from_seed("holder", seed).public_key()).unwrap();
+        let holder = IssuerKey::from_seed(
+            &format!("{did}#{}", did.trim_start_matches("did:key:")),
+            seed,
+        );
+        let presentation = json!(holder.sign_jwt(&json!({
+            "iss": did,
+            "vp": {
+                "type": ["VerifiablePresentation"],
+                "verifiableCredential": [jwt]
+            }
+        })));
+
+        assert_eq!(holder_did(&presentation), Some(did.clone()));
+        let holder_keys = verification_keys(&did_key_document(&did).unwrap());
+        let verdict = verifier(&[])
+            .with_holder_keys(holder_keys)
+            .verify(&presentation);
+        assert!(verdict.verified, "{:?}", verdict);
+        assert_eq!(
+            verdict
+                .check(CheckName::Signature)
+                .map(|check| check.status),
+            Some(CheckStatus::Passed)
+        );
+    }
+}
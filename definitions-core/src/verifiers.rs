@@ -0,0 +1,205 @@
+//! Verifier plugins, used to attest entity properties without a human attestor.
+//!
+//! A [`VerifierPlugin`] checks a value of the identifier types it supports, like the number of
+//! an `AADHAR` card. The built-in plugins only run offline checks, such as check digits and
+//! formats. Plugins backed by an external registry implement the same trait and are registered
+//! with [`Verifiers::register`].
+use std::fmt;
+use std::sync::Arc;
+
+/// Outcome of a verification
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Verification {
+    Verified,
+    Failed(String),
+}
+
+impl Verification {
+    pub fn is_verified(&self) -> bool {
+        matches!(self, Verification::Verified)
+    }
+}
+
+pub trait VerifierPlugin: Send + Sync {
+    /// Name of the plugin, recorded with its attestations
+    fn name(&self) -> &str;
+
+    /// Returns true when the plugin verifies values of the identifier type
+    fn supports(&self, value_type: &str) -> bool;
+
+    fn verify(&self, value: &str) -> Verification;
+}
+
+/// The verifier plugins available to auto attestation, by order of registration
+#[derive(Clone, Default)]
+pub struct Verifiers {
+    plugins: Vec<Arc<dyn VerifierPlugin>>,
+}
+
+impl Verifiers {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The built-in plugins, [`AadhaarVerifier`] and [`PanVerifier`]
+    pub fn builtin() -> Self {
+        Self::new()
+            .register(Arc::new(AadhaarVerifier))
+            .register(Arc::new(PanVerifier))
+    }
+
+    pub fn register(mut self, plugin: Arc<dyn VerifierPlugin>) -> Self {
+        self.plugins.push(plugin);
+        self
+    }
+
+    /// The first plugin that supports the identifier type
+    pub fn find(&self, value_type: &str) -> Option<&dyn VerifierPlugin> {
+        self.plugins
+            .iter()
+            .find(|plugin| plugin.supports(value_type))
+            .map(|plugin| plugin.as_ref())
+    }
+}
+
+impl fmt::Debug for Verifiers {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_list()
+            .entries(self.plugins.iter().map(|plugin| plugin.name()))
+            .finish()
+    }
+}
+
+impl PartialEq for Verifiers {
+    fn eq(&self, other: &Self) -> bool {
+        self.plugins.len() == other.plugins.len()
+            && self
+                .plugins
+                .iter()
+                .zip(&other.plugins)
+                .all(|(a, b)| Arc::ptr_eq(a, b))
+    }
+}
+
+impl Eq for Verifiers {}
+
+/// Aadhaar numbers: 12 digits, not starting with 0 or 1, with a Verhoeff check digit
+pub struct AadhaarVerifier;
+
+impl VerifierPlugin for AadhaarVerifier {
+    fn name(&self) -> &str {
+        "aadhaar-verhoeff"
+    }
+
+    fn supports(&self, value_type: &str) -> bool {
+        value_type.eq_ignore_ascii_case("AADHAR") || value_type.eq_ignore_ascii_case("AADHAAR")
+    }
+
+    fn verify(&self, value: &str) -> Verification {
+        let digits: String = value.chars().filter(|c| !matches!(c, ' ' | '-')).collect();
+        if digits.len() != 12 || !digits.chars().all(|c| c.is_ascii_digit()) {
+            return Verification::Failed("expected 12 digits".to_string());
+        }
+        if digits.starts_with(['0', '1']) {
+            return Verification::Failed("cannot start with 0 or 1".to_string());
+        }
+        if !verhoeff_is_valid(&digits) {
+            return Verification::Failed("invalid check digit".to_string());
+        }
+        Verification::Verified
+    }
+}
+
+/// Permanent Account Numbers: five letters, four digits and a letter. The fourth letter is the
+/// holder type.
+pub struct PanVerifier;
+
+impl VerifierPlugin for PanVerifier {
+    fn name(&self) -> &str {
+        "pan-format"
+    }
+
+    fn supports(&self, value_type: &str) -> bool {
+        value_type.eq_ignore_ascii_case("PAN")
+    }
+
+    fn verify(&self, value: &str) -> Verification {
+        let pan = value.trim().as_bytes();
+        let well_formed = pan.len() == 10
+            && pan[..5].iter().all(u8::is_ascii_uppercase)
+            && pan[5..9].iter().all(u8::is_ascii_digit)
+            && pan[9].is_ascii_uppercase();
+        if !well_formed {
+            return Verification::Failed("expected the format AAAAA9999A".to_string());
+        }
+        if !b"ABCFGHJLPT".contains(&pan[3]) {
+            return Verification::Failed("unknown holder type".to_string());
+        }
+        Verification::Verified
+    }
+}
+
+const VERHOEFF_D: [[u8; 10]; 10] = [
+    [0, 1, 2, 3, 4, 5, 6, 7, 8, 9],
+    [1, 2, 3, 4, 0, 6, 7, 8, 9, 5],
+    [2, 3, 4, 0, 1, 7, 8, 9, 5, 6],
+    [3, 4, 0, 1, 2, 8, 9, 5, 6, 7],
+    [4, 0, 1, 2, 3, 9, 5, 6, 7, 8],
+    [5, 9, 8, 7, 6, 0, 4, 3, 2, 1],
+    [6, 5, 9, 8, 7, 1, 0, 4, 3, 2],
+    [7, 6, 5, 9, 8, 2, 1, 0, 4, 3],
+    [8, 7, 6, 5, 9, 3, 2, 1, 0, 4],
+    [9, 8, 7, 6, 5, 4, 3, 2, 1, 0],
+];
+
+const VERHOEFF_P: [[u8; 10]; 8] = [
+    [0, 1, 2, 3, 4, 5, 6, 7, 8, 9],
+    [1, 5, 7, 6, 2, 8, 3, 0, 9, 4],
+    [5, 8, 0, 3, 7, 9, 6, 1, 4, 2],
+    [8, 9, 1, 6, 0, 4, 3, 5, 2, 7],
+    [9, 4, 5, 3, 1, 2, 6, 8, 7, 0],
+    [4, 2, 8, 6, 5, 7, 3, 9, 0, 1],
+    [2, 7, 9, 3, 8, 0, 6, 4, 1, 5],
+    [7, 0, 4, 6, 9, 1, 3, 2, 5, 8],
+];
+
+/// Returns true when the last digit of `digits` is its Verhoeff check digit
+pub fn verhoeff_is_valid(digits: &str) -> bool {
+    let mut checksum = 0u8;
+    for (position, c) in digits.chars().rev().enumerate() {
+        let Some(digit) = c.to_digit(10) else {
+            return false;
+        };
+        checksum = VERHOEFF_D[checksum as usize][VERHOEFF_P[position % 8][digit as usize] as usize];
+    }
+    !digits.is_empty() && checksum == 0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_verhoeff() {
+        assert!(verhoeff_is_valid("2363"));
+        assert!(!verhoeff_is_valid("2364"));
+        assert!(!verhoeff_is_valid(""));
+    }
+
+    #[test]
+    fn test_builtin_verifiers() {
+        let verifiers = Verifiers::builtin();
+
+        let aadhaar = verifiers.find("AADHAR").unwrap();
+        assert_eq!(aadhaar.verify("4991 1866 5246"), Verification::Verified);
+        assert!(!aadhaar.verify("499118665247").is_verified());
+        assert!(!aadhaar.verify("099118665246").is_verified());
+
+        let pan = verifiers.find("pan").unwrap();
+        assert_eq!(pan.verify("ABCPE1234F"), Verification::Verified);
+        assert!(!pan.verify("ABCXE1234F").is_verified());
+        assert!(!pan.verify("ABCPE12345").is_verified());
+
+        assert!(verifiers.find("LICENSE").is_none());
+    }
+}
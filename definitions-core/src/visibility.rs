@@ -0,0 +1,155 @@
+//! Visibility of `internalFields` in read APIs.
+//!
+//! A definition lists the paths that are for registry staff only in `_osConfig.internalFields`:
+//!
+//! ```json
+//! "internalFields": ["$.contactDetails.email", "$.contactDetails.mobile"]
+//! ```
+//!
+//! These fields are removed from the entities returned to a [`Principal`] unless it holds the
+//! [`READ_INTERNAL_FIELDS`] permission or one of the definition `roles`. The `anonymous` role does
+//! not grant access to internal fields.
+use crate::json_path::{entity_node_mut, JsonPath};
+use crate::ownership::{declared_roles, Principal, ANONYMOUS_ROLE};
+use crate::registry_domain::EntityError;
+use serde_json::Value;
+
+pub const INTERNAL_FIELDS: &str = "internalFields";
+/// Permission granting read access to the internal fields of every entity type
+pub const READ_INTERNAL_FIELDS: &str = "read:internal_fields";
+
+/// The internal fields declared by a definition, with the roles allowed to read them
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct InternalFields {
+    entity_type: String,
+    paths: Vec<JsonPath>,
+    roles: Vec<String>,
+}
+
+impl InternalFields {
+    pub fn from_schema(schema: &Value, entity_type: &str) -> Result<Self, EntityError> {
+        let paths = declared_internal_fields(schema)
+            .iter()
+            .map(|path| {
+                JsonPath::parse(path)
+                    .map_err(|e| EntityError::InvalidSchema(format!("{}: {}", INTERNAL_FIELDS, e)))
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(Self {
+            entity_type: entity_type.to_string(),
+            paths,
+            roles: declared_roles(schema),
+        })
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.paths.is_empty()
+    }
+
+    /// Returns true when the principal may read the internal fields
+    pub fn readable_by(&self, principal: Option<&Principal>) -> bool {
+        let Some(principal) = principal else {
+            return self.is_empty();
+        };
+        self.is_empty()
+            || principal
+                .permissions
+                .iter()
+                .any(|permission| permission == READ_INTERNAL_FIELDS)
+            || principal
+                .roles
+                .iter()
+                .any(|role| role != ANONYMOUS_ROLE && self.roles.contains(role))
+    }
+
+    /// Removes the internal fields from an entity document
+    pub fn remove(&self, document: &mut Value) {
+        let node = entity_node_mut(document, &self.entity_type);
+        for path in &self.paths {
+            path.remove(node);
+        }
+    }
+
+    /// Removes the internal fields unless the principal may read them
+    pub fn filter(&self, document: &mut Value, principal: Option<&Principal>) {
+        if !self.readable_by(principal) {
+            self.remove(document);
+        }
+    }
+}
+
+/// Reads `_osConfig.internalFields` of a schema
+pub fn declared_internal_fields(schema: &Value) -> Vec<String> {
+    schema
+        .get("_osConfig")
+        .and_then(|config| config.get(INTERNAL_FIELDS))
+        .and_then(Value::as_array)
+        .map(|paths| {
+            paths
+                .iter()
+                .filter_map(Value::as_str)
+                .map(str::to_string)
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn student_schema() -> Value {
+        json!({
+            "title": "Student",
+            "_osConfig": {
+                "roles": ["admin", "anonymous"],
+                "internalFields": ["$.contactDetails.email", "/contactDetails/mobile"]
+            }
+        })
+    }
+
+    fn student() -> Value {
+        json!({"Student": {
+            "name": "John",
+            "contactDetails": { "email": "john@example.com", "mobile": "9876543210", "city": "Pune" }
+        }})
+    }
+
+    #[test]
+    fn test_internal_fields_are_removed_for_other_callers() {
+        let internal = InternalFields::from_schema(&student_schema(), "Student").unwrap();
+        let anonymous_role = Principal {
+            roles: vec!["anonymous".to_string()],
+            ..Default::default()
+        };
+
+        for principal in [None, Some(&anonymous_role)] {
+            let mut document = student();
+            internal.filter(&mut document, principal);
+            assert_eq!(
+                document,
+                json!({"Student": {"name": "John", "contactDetails": { "city": "Pune" }}})
+            );
+        }
+    }
+
+    #[test]
+    fn test_internal_fields_are_kept_for_roles_and_permission() {
+        let internal = InternalFields::from_schema(&student_schema(), "Student").unwrap();
+        let admin = Principal {
+            roles: vec!["admin".to_string()],
+            ..Default::default()
+        };
+        let auditor = Principal {
+            permissions: vec![READ_INTERNAL_FIELDS.to_string()],
+            ..Default::default()
+        };
+
+        for principal in [&admin, &auditor] {
+            let mut document = student();
+            internal.filter(&mut document, Some(principal));
+            assert_eq!(document, student());
+        }
+    }
+}
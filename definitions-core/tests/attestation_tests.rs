@@ -0,0 +1,337 @@
+mod common;
+
+#[cfg(test)]
+mod test {
+    use crate::common::test_harness::SimpleTestHarness;
+    use crate::common::{active_definition, get_created_at};
+    use definitions_core::attestation::{
+        AttestClaimCmd, AttestationAction, ClaimStatus, RaiseClaimCmd,
+    };
+    use definitions_core::definitions_domain::{generate_id_from_title, DomainEvent, Version};
+    use definitions_core::ownership::{Owner, Principal};
+    use definitions_core::registry_domain::EntityError;
+    use serde_json::{json, Value};
+    use uuid::Uuid;
+
+    fn insurance_id() -> Uuid {
+        Uuid::parse_str("0196d2b4-3c2a-7d4e-8f00-000000000a01").unwrap()
+    }
+
+    fn official_id() -> Uuid {
+        Uuid::parse_str("0196d2b4-3c2a-7d4e-8f00-000000000a02").unwrap()
+    }
+
+    fn claim_id() -> Uuid {
+        Uuid::parse_str("0196d2b4-3c2a-7d4e-8f00-000000000a03").unwrap()
+    }
+
+    fn insurance_schema() -> String {
+        json!({
+            "title": "Insurance",
+            "type": "object",
+            "properties": {
+                "Insurance": {
+                    "type": "object",
+                    "properties": {
+                        "policyNumber": { "type": "string" },
+                        "email": { "type": "string" }
+                    }
+                }
+            },
+            "_osConfig": {
+                "ownershipAttributes": [{ "email": "$.email", "userId": "$.email" }],
+                "attestationPolicies": [{
+                    "name": "policyApproval",
+                    "attestationProperties": { "policyNumber": "$.policyNumber" },
+                    "type": "MANUAL",
+                    "attestorPlugin": "did:internal:ClaimPluginActor?entity=Official"
+                }]
+            }
+        })
+        .to_string()
+    }
+
+    fn official_schema() -> String {
+        json!({
+            "title": "Official",
+            "type": "object",
+            "properties": {
+                "Official": {
+                    "type": "object",
+                    "properties": { "email": { "type": "string" } }
+                }
+            },
+            "_osConfig": { "ownershipAttributes": [{ "email": "$.email" }] }
+        })
+        .to_string()
+    }
+
+    fn entity_created(id: Uuid, entity_type: &str, body: Value, email: &str) -> DomainEvent {
+        DomainEvent::EntityCreated {
+            id,
+            registry_def_id: generate_id_from_title(entity_type),
+            registry_def_version: Version::default(),
+            entity_body: body.to_string(),
+            entity_type: entity_type.to_string(),
+            created_at: get_created_at(),
+            created_by: "test_user".to_string(),
+            on_behalf_of: None,
+            version: Version::default(),
+            references: vec![],
+            owners: vec![Owner {
+                user_id: None,
+                email: Some(email.to_string()),
+                mobile: None,
+            }],
+        }
+    }
+
+    fn history() -> Vec<DomainEvent> {
+        let mut history = active_definition("Insurance", insurance_schema());
+        history.extend(active_definition("Official", official_schema()));
+        history.push(entity_created(
+            insurance_id(),
+            "Insurance",
+            json!({"Insurance": {"policyNumber": "P-1", "email": "asha@example.com"}}),
+            "asha@example.com",
+        ));
+        history.push(entity_created(
+            official_id(),
+            "Official",
+            json!({"Official": {"email": "officer@example.com"}}),
+            "officer@example.com",
+        ));
+        history
+    }
+
+    fn principal(email: &str) -> Principal {
+        Principal {
+            subject: format!("auth0|{email}"),
+            email: Some(email.to_string()),
+            ..Default::default()
+        }
+    }
+
+    fn raise_cmd(email: &str) -> RaiseClaimCmd {
+        RaiseClaimCmd {
+            claim_id: claim_id(),
+            entity_id: insurance_id(),
+            entity_type: "Insurance".to_string(),
+            policy_name: "policyApproval".to_string(),
+            attestor_id: None,
+            principal: principal(email),
+        }
+    }
+
+    fn claim_raised() -> DomainEvent {
+        DomainEvent::ClaimRaised {
+            claim_id: claim_id(),
+            entity_id: insurance_id(),
+            entity_type: "Insurance".to_string(),
+            policy_name: "policyApproval".to_string(),
+            attestor_entity: "Official".to_string(),
+            attestor_id: None,
+            property_data: json!({"policyNumber": "P-1"}).to_string(),
+            raised_at: get_created_at(),
+            raised_by: "auth0|asha@example.com".to_string(),
+            on_behalf_of: None,
+        }
+    }
+
+    fn attest_cmd(action: AttestationAction, attestor_id: Uuid, email: &str) -> AttestClaimCmd {
+        AttestClaimCmd {
+            claim_id: claim_id(),
+            entity_id: insurance_id(),
+            entity_type: "Insurance".to_string(),
+            attestor_id,
+            action,
+            notes: Some("checked".to_string()),
+            principal: principal(email),
+        }
+    }
+
+    #[test]
+    fn test_owner_raises_claim_routed_to_attestor_entity() {
+        SimpleTestHarness::given(history())
+            .when(raise_cmd("asha@example.com"))
+            .then_assert(|events| {
+                if let DomainEvent::ClaimRaised {
+                    attestor_entity,
+                    property_data,
+                    raised_by,
+                    ..
+                } = &events[0]
+                {
+                    assert_eq!(attestor_entity, "Official");
+                    assert_eq!(property_data, &json!({"policyNumber": "P-1"}).to_string());
+                    assert_eq!(raised_by, "auth0|asha@example.com");
+                } else {
+                    panic!("Expected ClaimRaised, got {:?}", events[0]);
+                }
+            });
+    }
+
+    #[test]
+    fn test_claim_routed_to_an_attestor_is_decided_by_it_only() {
+        let mut cmd = raise_cmd("asha@example.com");
+        cmd.attestor_id = Some(insurance_id());
+        SimpleTestHarness::given(history())
+            .when(cmd.clone())
+            .then_err(EntityError::NotAnAttestor(
+                insurance_id(),
+                "Official".to_string(),
+            ));
+
+        cmd.attestor_id = Some(official_id());
+        SimpleTestHarness::given(history())
+            .when(cmd)
+            .then_assert(|events| {
+                assert!(matches!(
+                    events[0],
+                    DomainEvent::ClaimRaised {
+                        attestor_id: Some(attestor_id),
+                        ..
+                    } if attestor_id == official_id()
+                ));
+            });
+
+        let other_official = Uuid::parse_str("0196d2b4-3c2a-7d4e-8f00-000000000a04").unwrap();
+        let mut given = history();
+        given.push(entity_created(
+            other_official,
+            "Official",
+            json!({"Official": {"email": "other@example.com"}}),
+            "other@example.com",
+        ));
+        let mut routed = claim_raised();
+        if let DomainEvent::ClaimRaised { attestor_id, .. } = &mut routed {
+            *attestor_id = Some(official_id());
+        }
+        given.push(routed);
+        SimpleTestHarness::given(given)
+            .when(attest_cmd(
+                AttestationAction::GrantClaim,
+                other_official,
+                "other@example.com",
+            ))
+            .then_err(EntityError::NotAnAttestor(
+                other_official,
+                "Official".to_string(),
+            ));
+    }
+
+    #[test]
+    fn test_claim_by_other_user_is_rejected() {
+        SimpleTestHarness::given(history())
+            .when(raise_cmd("mallory@example.com"))
+            .then_err(EntityError::NotAuthorized(
+                "auth0|mallory@example.com".to_string(),
+                insurance_id(),
+            ));
+    }
+
+    #[test]
+    fn test_unknown_policy_is_rejected() {
+        let mut cmd = raise_cmd("asha@example.com");
+        cmd.policy_name = "missing".to_string();
+        SimpleTestHarness::given(history()).when(cmd).then_err(
+            EntityError::AttestationPolicyNotFound("Insurance".to_string(), "missing".to_string()),
+        );
+    }
+
+    #[test]
+    fn test_granted_claim_stores_attested_data() {
+        let mut given = history();
+        given.push(claim_raised());
+        SimpleTestHarness::given(given)
+            .when(attest_cmd(
+                AttestationAction::GrantClaim,
+                official_id(),
+                "officer@example.com",
+            ))
+            .then_assert(|events| {
+                assert!(matches!(events[0], DomainEvent::ClaimAttested { .. }));
+                if let DomainEvent::EntityAttested { entity_body, .. } = &events[1] {
+                    let body: Value = serde_json::from_str(entity_body).unwrap();
+                    let attested = &body["Insurance"]["_osAttestedData"]["policyApproval"];
+                    assert_eq!(attested["data"], json!({"policyNumber": "P-1"}));
+                    assert_eq!(attested["attestorId"], json!(official_id()));
+                    assert_eq!(body["Insurance"]["policyNumber"], json!("P-1"));
+                } else {
+                    panic!("Expected EntityAttested, got {:?}", events[1]);
+                }
+            });
+    }
+
+    #[test]
+    fn test_only_owners_of_attestor_entities_decide() {
+        let mut given = history();
+        given.push(claim_raised());
+        SimpleTestHarness::given(given.clone())
+            .when(attest_cmd(
+                AttestationAction::GrantClaim,
+                official_id(),
+                "asha@example.com",
+            ))
+            .then_err(EntityError::NotAuthorized(
+                "auth0|asha@example.com".to_string(),
+                official_id(),
+            ));
+        SimpleTestHarness::given(given)
+            .when(attest_cmd(
+                AttestationAction::GrantClaim,
+                insurance_id(),
+                "asha@example.com",
+            ))
+            .then_err(EntityError::NotAnAttestor(
+                insurance_id(),
+                "Official".to_string(),
+            ));
+    }
+
+    #[test]
+    fn test_policy_conditions_restrict_attestors() {
+        let mut schema: Value = serde_json::from_str(&insurance_schema()).unwrap();
+        schema["_osConfig"]["attestationPolicies"][0]["conditions"] =
+            json!("(ATTESTOR#$.email#.contains('@insurer.example.com'))");
+        let mut given = active_definition("Insurance", schema.to_string());
+        given.extend(history().into_iter().skip(3));
+        given.push(claim_raised());
+
+        SimpleTestHarness::given(given)
+            .when(attest_cmd(
+                AttestationAction::RejectClaim,
+                official_id(),
+                "officer@example.com",
+            ))
+            .then_err(EntityError::AttestationConditionNotMet(
+                "policyApproval".to_string(),
+                official_id(),
+            ));
+    }
+
+    #[test]
+    fn test_closed_claim_cannot_be_decided_again() {
+        let mut given = history();
+        given.push(claim_raised());
+        given.push(DomainEvent::ClaimRejected {
+            claim_id: claim_id(),
+            entity_id: insurance_id(),
+            attestor_id: official_id(),
+            notes: None,
+            rejected_at: get_created_at(),
+            rejected_by: "auth0|officer@example.com".to_string(),
+            on_behalf_of: None,
+        });
+        SimpleTestHarness::given(given)
+            .when(attest_cmd(
+                AttestationAction::GrantClaim,
+                official_id(),
+                "officer@example.com",
+            ))
+            .then_err(EntityError::ClaimAlreadyClosed(
+                claim_id(),
+                ClaimStatus::Rejected,
+            ));
+    }
+}
@@ -0,0 +1,137 @@
+mod common;
+
+#[cfg(test)]
+mod test {
+    use crate::common::test_harness::SimpleTestHarness;
+    use crate::common::{active_definition, cipher, get_created_at};
+    use definitions_core::auto_attestation::AutoAttestEntityCmd;
+    use definitions_core::definitions_domain::{generate_id_from_title, DomainEvent, Version};
+    use definitions_core::verifiers::Verifiers;
+    use serde_json::{json, Value};
+    use uuid::Uuid;
+
+    fn student_id() -> Uuid {
+        Uuid::parse_str("0196d2b4-3c2a-7d4e-8f00-000000000b01").unwrap()
+    }
+
+    fn student_schema() -> String {
+        json!({
+            "title": "Student",
+            "type": "object",
+            "properties": {
+                "Student": {
+                    "type": "object",
+                    "properties": {
+                        "identityDetails": {
+                            "type": "object",
+                            "properties": {
+                                "fullName": { "type": "string" },
+                                "identityHolder": {
+                                    "type": "object",
+                                    "properties": {
+                                        "type": { "type": "string" },
+                                        "value": { "type": "string" }
+                                    }
+                                }
+                            }
+                        }
+                    }
+                }
+            },
+            "_osConfig": {
+                "privateFields": ["$.identityDetails.identityHolder.value"],
+                "autoAttestationPolicies": [{
+                    "parentProperty": "identityDetails",
+                    "property": "identityHolder",
+                    "nodeRef": "$.identityDetails.identityHolder",
+                    "valuePath": "$.identityDetails.identityHolder.value",
+                    "typePath": "$.identityDetails.identityHolder.type"
+                }]
+            }
+        })
+        .to_string()
+    }
+
+    fn history(id_type: &str, id_value: &str) -> Vec<DomainEvent> {
+        let def_id = generate_id_from_title("Student");
+        let mut body = json!({"Student": {"identityDetails": {
+            "fullName": "Asha",
+            "identityHolder": { "type": id_type, "value": id_value }
+        }}});
+        cipher()
+            .encrypt_paths(
+                &mut body,
+                "Student",
+                &["$.identityDetails.identityHolder.value".to_string()],
+            )
+            .unwrap();
+        let mut history = active_definition("Student", student_schema());
+        history.push(DomainEvent::EntityCreated {
+            id: student_id(),
+            registry_def_id: def_id,
+            registry_def_version: Version::default(),
+            entity_body: body.to_string(),
+            entity_type: "Student".to_string(),
+            created_at: get_created_at(),
+            created_by: "test_user".to_string(),
+            on_behalf_of: None,
+            version: Version::default(),
+            references: vec![],
+            owners: vec![],
+        });
+        history
+    }
+
+    fn auto_attest_cmd() -> AutoAttestEntityCmd {
+        AutoAttestEntityCmd {
+            id: student_id(),
+            entity_type: "Student".to_string(),
+            cipher: Some(cipher()),
+            verifiers: Verifiers::builtin(),
+            attested_by: "auto_attestation".into(),
+        }
+    }
+
+    fn attested_identity(event: &DomainEvent) -> Value {
+        if let DomainEvent::EntityAutoAttested { entity_body, .. } = event {
+            let body: Value = serde_json::from_str(entity_body).unwrap();
+            body["Student"]["_osAttestedData"]["identityHolder"].clone()
+        } else {
+            panic!("Expected EntityAutoAttested, got {:?}", event);
+        }
+    }
+
+    #[test]
+    fn test_valid_aadhaar_is_attested() {
+        SimpleTestHarness::given(history("AADHAR", "499118665246"))
+            .when(auto_attest_cmd())
+            .then_assert(|events| {
+                assert_eq!(events.len(), 1);
+                let attested = attested_identity(&events[0]);
+                assert_eq!(attested["verified"], json!(true));
+                assert_eq!(attested["verifier"], json!("aadhaar-verhoeff"));
+                assert_eq!(
+                    attested["nodeRef"],
+                    json!("$.identityDetails.identityHolder")
+                );
+            });
+    }
+
+    #[test]
+    fn test_invalid_check_digit_is_recorded() {
+        SimpleTestHarness::given(history("AADHAR", "499118665247"))
+            .when(auto_attest_cmd())
+            .then_assert(|events| {
+                let attested = attested_identity(&events[0]);
+                assert_eq!(attested["verified"], json!(false));
+                assert_eq!(attested["reason"], json!("invalid check digit"));
+            });
+    }
+
+    #[test]
+    fn test_types_without_verifier_are_skipped() {
+        SimpleTestHarness::given(history("LICENSE", "MH-1420110062821"))
+            .when(auto_attest_cmd())
+            .then(vec![]);
+    }
+}
@@ -5,6 +5,7 @@ mod birth_certificate_tests {
     use crate::common::test_harness::SimpleTestHarness;
     use chrono::Utc;
     use definitions_core::definitions_domain::{generate_id_from_title, DomainEvent};
+    use definitions_core::ownership::Principal;
     use definitions_core::registry_domain::{CreateEntityCmd, ModifyEntityCmd};
     use std::fs;
     use std::path::Path;
@@ -32,7 +33,10 @@ mod birth_certificate_tests {
             id: Uuid::now_v7(),
             entity_body: valid_birth_certificate_entity_json(),
             entity_type: "BirthCertificate".to_string(),
-            created_by: "Admin".to_string(),
+            created_by: "Admin".into(),
+            referenced_ids: vec![],
+            cipher: None,
+            signer: None,
         }
     }
 
@@ -43,6 +47,7 @@ mod birth_certificate_tests {
             definitions: vec!["BirthCertificate".to_string()],
             created_at: get_created_at(),
             created_by: "Admin".to_string(),
+            on_behalf_of: None,
             json_schema_string: read_birth_certificate_schema().unwrap(),
         }
     }
@@ -71,6 +76,7 @@ mod birth_certificate_tests {
             id: generate_id_from_title("BirthCertificate"),
             validated_at: Utc::now(),
             validated_by: "Admin".to_string(),
+            on_behalf_of: None,
             validation_result: "Success".to_string(),
         }
     }
@@ -80,7 +86,10 @@ mod birth_certificate_tests {
             id: generate_id_from_title("BirthCertificate"),
             activated_at: Utc::now(),
             activated_by: "Admin".to_string(),
+            on_behalf_of: None,
             json_schema_string: read_birth_certificate_schema().unwrap(),
+            version: Default::default(),
+            migration: None,
         }
     }
     pub fn entity_created_birth_certificate_event() -> DomainEvent {
@@ -93,7 +102,10 @@ mod birth_certificate_tests {
             entity_type: "BirthCertificate".to_string(),
             created_at: Utc::now(),
             created_by: "Admin".to_string(),
+            on_behalf_of: None,
             version: Default::default(),
+            references: vec![],
+            owners: vec![],
         }
     }
 
@@ -103,7 +115,13 @@ mod birth_certificate_tests {
             id,
             entity_body: valid_birth_certificate_entity_json(),
             entity_type: "BirthCertificate".to_string(),
-            modified_by: "Admin".to_string(),
+            principal: Principal {
+                subject: "Admin".to_string(),
+                ..Default::default()
+            },
+            referenced_ids: vec![],
+            cipher: None,
+            signer: None,
         }
     }
     #[test]
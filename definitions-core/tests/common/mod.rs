@@ -4,13 +4,77 @@ pub mod test_harness;
 // #[cfg(test)]
 use chrono::{DateTime, Utc};
 use definitions_core::definitions_domain::DomainEvent::DefUpdated;
+use definitions_core::definitions_domain::Version;
 use definitions_core::definitions_domain::{
     generate_id_from_title, CreateDefinitionCmd, DomainEvent, UpdateDefinitionCmd,
     ValidateDefinitionCmd,
 };
+use definitions_core::encryption::{generate_key, EncryptionError, FieldCipher, KeyStore};
 use definitions_core::registry_domain::CreateEntityCmd;
+use std::sync::{Arc, OnceLock};
 use uuid::Uuid;
 
+/// [`KeyStore`] holding a single key, `test`
+pub struct SingleKeyStore(pub [u8; 32]);
+
+impl KeyStore for SingleKeyStore {
+    fn current_key_id(&self) -> Result<String, EncryptionError> {
+        Ok("test".to_string())
+    }
+
+    fn key(&self, key_id: &str) -> Result<[u8; 32], EncryptionError> {
+        match key_id {
+            "test" => Ok(self.0),
+            _ => Err(EncryptionError::KeyNotFound(key_id.to_string())),
+        }
+    }
+}
+
+/// The cipher of the tests, the same key for the whole test binary
+pub fn cipher() -> FieldCipher {
+    static CIPHER: OnceLock<FieldCipher> = OnceLock::new();
+    CIPHER
+        .get_or_init(|| FieldCipher::new(Arc::new(SingleKeyStore(generate_key()))))
+        .clone()
+}
+
+/// Id of the entity under test
+pub fn entity_id() -> Uuid {
+    Uuid::parse_str("0196d2b4-3c2a-7d4e-8f00-0000000000e1").unwrap()
+}
+
+/// Creates, validates and activates the first version of a definition
+pub fn active_definition(title: &str, schema: String) -> Vec<DomainEvent> {
+    let id = generate_id_from_title(title);
+    vec![
+        DomainEvent::DefCreated {
+            id,
+            title: title.to_string(),
+            definitions: vec![],
+            created_at: get_created_at(),
+            created_by: "test_user".to_string(),
+            on_behalf_of: None,
+            json_schema_string: schema.clone(),
+        },
+        DomainEvent::DefValidated {
+            id,
+            validated_at: get_created_at(),
+            validated_by: "test_user".to_string(),
+            on_behalf_of: None,
+            validation_result: "Success".to_string(),
+        },
+        DomainEvent::DefActivated {
+            id,
+            activated_at: get_created_at(),
+            activated_by: "test_user".to_string(),
+            on_behalf_of: None,
+            json_schema_string: schema,
+            version: Version::default(),
+            migration: None,
+        },
+    ]
+}
+
 pub fn get_valid_json_string() -> String {
     r###"
         {
@@ -137,7 +201,7 @@ pub fn create_def_cmd_1() -> CreateDefinitionCmd {
         id: generate_id_from_title("test_title"),
         title: "test_title".to_string(),
         definitions: vec!["test_def".to_string()],
-        created_by: "test_created_by".to_string(),
+        created_by: "test_created_by".into(),
         json_schema_string: get_valid_json_string(),
     }
 }
@@ -152,6 +216,7 @@ pub fn get_expected_def_created_simple() -> DomainEvent {
         definitions: vec!["test_def".to_string()],
         created_at: get_created_at(),
         created_by: "test_created_by".to_string(),
+        on_behalf_of: None,
         json_schema_string: get_valid_json_string(),
     }
 }
@@ -163,6 +228,7 @@ pub fn get_def_created_invalid_json() -> DomainEvent {
         definitions: vec!["test_def".to_string()],
         created_at: get_created_at(),
         created_by: "test_created_by".to_string(),
+        on_behalf_of: None,
         json_schema_string: get_in_valid_json_string(),
     }
 }
@@ -174,6 +240,7 @@ pub fn def_created_valid_json_draft() -> DomainEvent {
         definitions: vec!["test_def".to_string()],
         created_at: get_created_at(),
         created_by: "test_created_by".to_string(),
+        on_behalf_of: None,
         json_schema_string: get_valid_json_string(),
     }
 }
@@ -183,6 +250,7 @@ pub fn def_validated_valid_json() -> DomainEvent {
         id: generate_id_from_title("test_title"),
         validated_at: get_created_at(),
         validated_by: "test_user".to_string(),
+        on_behalf_of: None,
         validation_result: "Success".to_string(),
     }
 }
@@ -192,7 +260,10 @@ pub fn def_activated_valid_json() -> DomainEvent {
         id: generate_id_from_title("test_title"),
         activated_at: get_created_at(),
         activated_by: "".to_string(),
+        on_behalf_of: None,
         json_schema_string: get_valid_json_string(),
+        version: Default::default(),
+        migration: None,
     }
 }
 
@@ -203,6 +274,7 @@ pub fn get_def_created_valid_student_json() -> DomainEvent {
         definitions: vec!["test_def".to_string()],
         created_at: get_created_at(),
         created_by: "test_created_by".to_string(),
+        on_behalf_of: None,
         json_schema_string: get_valid_student_schema_string(),
     }
 }
@@ -212,6 +284,7 @@ pub fn get_def_validated_valid_student_json() -> DomainEvent {
         id: generate_id_from_title("Student"),
         validated_at: Utc::now(),
         validated_by: "test_user".to_string(),
+        on_behalf_of: None,
         validation_result: "Success".to_string(),
     }
 }
@@ -221,7 +294,10 @@ pub fn get_def_activated_valid_student_json() -> DomainEvent {
         id: generate_id_from_title("Student"),
         activated_at: Utc::now(),
         activated_by: "".to_string(),
+        on_behalf_of: None,
         json_schema_string: get_valid_student_schema_string(),
+        version: Default::default(),
+        migration: None,
     }
 }
 
@@ -232,6 +308,7 @@ pub fn get_def_created_empty_title() -> DomainEvent {
         definitions: vec!["test_def".to_string()],
         created_at: get_created_at(),
         created_by: "test_created_by".to_string(),
+        on_behalf_of: None,
         json_schema_string: get_json_string_empty_title(),
     }
 }
@@ -240,7 +317,7 @@ pub fn get_validate_def_cmd() -> ValidateDefinitionCmd {
     ValidateDefinitionCmd {
         id: generate_id_from_title("test_title"),
         validated_at: get_created_at(),
-        validated_by: "test_validated_by".to_string(),
+        validated_by: "test_validated_by".into(),
     }
 }
 
@@ -249,8 +326,9 @@ pub fn get_update_def_cmd_mutate() -> UpdateDefinitionCmd {
         id: generate_id_from_title("test_title"),
         definitions: vec!["test_def".to_string()],
         created_at: get_created_at(),
-        updated_by: "test_updated_by".to_string(),
+        updated_by: "test_updated_by".into(),
         json_schema_string: get_updated_json_string(),
+        migration: None,
     }
 }
 
@@ -259,8 +337,9 @@ pub fn get_update_title_def_cmd() -> UpdateDefinitionCmd {
         id: generate_id_from_title("test_title"),
         definitions: vec!["test_def".to_string()],
         created_at: get_created_at(),
-        updated_by: "test_updated_by".to_string(),
+        updated_by: "test_updated_by".into(),
         json_schema_string: get_updated_json_string_test_title(),
+        migration: None,
     }
 }
 pub fn get_update_def_cmd() -> UpdateDefinitionCmd {
@@ -268,8 +347,9 @@ pub fn get_update_def_cmd() -> UpdateDefinitionCmd {
         id: generate_id_from_title("test_title"),
         definitions: vec!["test_def".to_string()],
         created_at: get_created_at(),
-        updated_by: "test_updated_by".to_string(),
+        updated_by: "test_updated_by".into(),
         json_schema_string: get_updated_json_string_test_title(),
+        migration: None,
     }
 }
 
@@ -280,6 +360,7 @@ pub fn get_expected_def_created_empty_json() -> DomainEvent {
         definitions: vec!["test_def".to_string()],
         created_at: get_created_at(),
         created_by: "test_created_by".to_string(),
+        on_behalf_of: None,
         json_schema_string: "".to_string(),
     }
 }
@@ -289,6 +370,7 @@ pub fn get_expected_validation_failed() -> DomainEvent {
         id: generate_id_from_title("test_title"),
         validated_at: get_created_at(),
         validated_by: "test_validated_by".to_string(),
+        on_behalf_of: None,
         validation_result: "failure".to_string(),
         validation_errors: vec!["Invalid Schema: Schema is empty".to_string()],
     }
@@ -299,6 +381,7 @@ pub fn get_expected_validation_failed_invalid_json() -> DomainEvent {
         id: generate_id_from_title("test_title"),
         validated_at: get_created_at(),
         validated_by: "test_validated_by".to_string(),
+        on_behalf_of: None,
         validation_result: "failure".to_string(),
         validation_errors: vec!["Invalid Json: expected value at line 3 column 23".to_string()],
     }
@@ -309,6 +392,7 @@ pub fn get_expected_validation_failed_empty_title() -> DomainEvent {
         id: generate_id_from_title("test_title"),
         validated_at: get_created_at(),
         validated_by: "test_validated_by".to_string(),
+        on_behalf_of: None,
         validation_result: "failure".to_string(),
         validation_errors: vec!["Invalid Schema: Title is empty".to_string()],
     }
@@ -319,6 +403,7 @@ pub fn get_expected_validation_success() -> DomainEvent {
         id: generate_id_from_title("test_title"),
         validated_at: get_created_at(),
         validated_by: "test_validated_by".to_string(),
+        on_behalf_of: None,
         validation_result: "Success".to_string(),
     }
 }
@@ -330,7 +415,9 @@ pub fn get_expected_def_updated() -> DomainEvent {
         definitions: vec!["test_def".to_string()],
         created_at: get_created_at(),
         updated_by: "test_updated_by".to_string(),
+        on_behalf_of: None,
         json_schema_string: get_updated_json_string(),
+        migration: None,
     }
 }
 
@@ -341,6 +428,7 @@ pub fn get_expected_def_created_valid_json() -> DomainEvent {
         definitions: vec!["test_def".to_string()],
         created_at: get_created_at(),
         created_by: "test_created_by".to_string(),
+        on_behalf_of: None,
         json_schema_string: get_valid_json_string(),
     }
 }
@@ -368,7 +456,10 @@ pub fn get_create_entity_cmd() -> CreateEntityCmd {
         id: Uuid::now_v7(),
         entity_body: get_valid_student_document(),
         entity_type: "Student".to_string(),
-        created_by: "test_user".to_string(),
+        created_by: "test_user".into(),
+        referenced_ids: vec![],
+        cipher: None,
+        signer: None,
     }
 }
 
@@ -393,6 +484,9 @@ pub fn get_create_entity_cmd_with_invalid_student() -> CreateEntityCmd {
         id: Uuid::now_v7(),
         entity_body: invalid_student_document,
         entity_type: "Student".to_string(),
-        created_by: "test_user".to_string(),
+        created_by: "test_user".into(),
+        referenced_ids: vec![],
+        cipher: None,
+        signer: None,
     }
 }
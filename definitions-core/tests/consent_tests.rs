@@ -0,0 +1,214 @@
+mod common;
+
+#[cfg(test)]
+mod test {
+    use crate::common::get_created_at;
+    use crate::common::test_harness::SimpleTestHarness;
+    use chrono::{Duration, Utc};
+    use definitions_core::consent::{
+        AccessConsentedDataCmd, ConsentAction, ConsentStatus, DecideConsentCmd, RequestConsentCmd,
+    };
+    use definitions_core::definitions_domain::{generate_id_from_title, DomainEvent, Version};
+    use definitions_core::ownership::{Owner, Principal};
+    use definitions_core::registry_domain::EntityError;
+    use serde_json::json;
+    use uuid::Uuid;
+
+    fn insurance_id() -> Uuid {
+        Uuid::parse_str("0196d2b4-3c2a-7d4e-8f00-000000000c01").unwrap()
+    }
+
+    fn consent_id() -> Uuid {
+        Uuid::parse_str("0196d2b4-3c2a-7d4e-8f00-000000000c02").unwrap()
+    }
+
+    fn history() -> Vec<DomainEvent> {
+        vec![DomainEvent::EntityCreated {
+            id: insurance_id(),
+            registry_def_id: generate_id_from_title("Insurance"),
+            registry_def_version: Version::default(),
+            entity_body: json!({"Insurance": {"policyNumber": "P-1", "email": "asha@example.com"}})
+                .to_string(),
+            entity_type: "Insurance".to_string(),
+            created_at: get_created_at(),
+            created_by: "test_user".to_string(),
+            on_behalf_of: None,
+            version: Version::default(),
+            references: vec![],
+            owners: vec![Owner {
+                user_id: None,
+                email: Some("asha@example.com".to_string()),
+                mobile: None,
+            }],
+        }]
+    }
+
+    fn principal(email: &str) -> Principal {
+        Principal {
+            subject: format!("auth0|{email}"),
+            email: Some(email.to_string()),
+            ..Default::default()
+        }
+    }
+
+    fn consent_requested() -> DomainEvent {
+        DomainEvent::ConsentRequested {
+            consent_id: consent_id(),
+            entity_id: insurance_id(),
+            entity_type: "Insurance".to_string(),
+            fields: vec!["$.policyNumber".to_string()],
+            purpose: Some("KYC".to_string()),
+            requested_at: get_created_at(),
+            requested_by: "auth0|bank@example.com".to_string(),
+            on_behalf_of: None,
+        }
+    }
+
+    fn consent_granted(expires_in: Duration) -> DomainEvent {
+        DomainEvent::ConsentGranted {
+            consent_id: consent_id(),
+            entity_id: insurance_id(),
+            expires_at: Utc::now() + expires_in,
+            notes: None,
+            granted_at: get_created_at(),
+            granted_by: "auth0|asha@example.com".to_string(),
+            on_behalf_of: None,
+        }
+    }
+
+    fn decide_cmd(action: ConsentAction, email: &str) -> DecideConsentCmd {
+        DecideConsentCmd {
+            consent_id: consent_id(),
+            entity_id: insurance_id(),
+            action,
+            expires_at: Some(Utc::now() + Duration::days(30)),
+            notes: None,
+            principal: principal(email),
+        }
+    }
+
+    fn access_cmd(email: &str) -> AccessConsentedDataCmd {
+        AccessConsentedDataCmd {
+            consent_id: consent_id(),
+            principal: principal(email),
+        }
+    }
+
+    #[test]
+    fn test_requester_asks_for_fields() {
+        SimpleTestHarness::given(history())
+            .when(RequestConsentCmd {
+                consent_id: consent_id(),
+                entity_id: insurance_id(),
+                entity_type: "Insurance".to_string(),
+                fields: vec!["$.policyNumber".to_string()],
+                purpose: Some("KYC".to_string()),
+                principal: principal("bank@example.com"),
+            })
+            .then_assert(|events| {
+                if let DomainEvent::ConsentRequested {
+                    fields,
+                    requested_by,
+                    ..
+                } = &events[0]
+                {
+                    assert_eq!(fields, &vec!["$.policyNumber".to_string()]);
+                    assert_eq!(requested_by, "auth0|bank@example.com");
+                } else {
+                    panic!("Expected ConsentRequested, got {:?}", events[0]);
+                }
+            });
+    }
+
+    #[test]
+    fn test_only_owners_grant_consent() {
+        let mut given = history();
+        given.push(consent_requested());
+        SimpleTestHarness::given(given.clone())
+            .when(decide_cmd(ConsentAction::GrantConsent, "asha@example.com"))
+            .then_assert(|events| {
+                assert!(matches!(events[0], DomainEvent::ConsentGranted { .. }));
+            });
+        SimpleTestHarness::given(given)
+            .when(decide_cmd(ConsentAction::GrantConsent, "bank@example.com"))
+            .then_err(EntityError::NotAuthorized(
+                "auth0|bank@example.com".to_string(),
+                insurance_id(),
+            ));
+    }
+
+    #[test]
+    fn test_granted_consent_is_revoked_not_granted_again() {
+        let mut given = history();
+        given.push(consent_requested());
+        given.push(consent_granted(Duration::days(1)));
+        SimpleTestHarness::given(given.clone())
+            .when(decide_cmd(ConsentAction::GrantConsent, "asha@example.com"))
+            .then_err(EntityError::ConsentNotChanged(
+                consent_id(),
+                "grant".to_string(),
+                ConsentStatus::Granted,
+            ));
+        SimpleTestHarness::given(given)
+            .when(decide_cmd(ConsentAction::RevokeConsent, "asha@example.com"))
+            .then_assert(|events| {
+                assert!(matches!(events[0], DomainEvent::ConsentRevoked { .. }));
+            });
+    }
+
+    #[test]
+    fn test_requester_access_is_recorded() {
+        let mut given = history();
+        given.push(consent_requested());
+        given.push(consent_granted(Duration::days(1)));
+        SimpleTestHarness::given(given.clone())
+            .when(access_cmd("bank@example.com"))
+            .then_assert(|events| {
+                if let DomainEvent::ConsentedDataAccessed {
+                    accessed_by,
+                    fields,
+                    ..
+                } = &events[0]
+                {
+                    assert_eq!(accessed_by, "auth0|bank@example.com");
+                    assert_eq!(fields, &vec!["$.policyNumber".to_string()]);
+                } else {
+                    panic!("Expected ConsentedDataAccessed, got {:?}", events[0]);
+                }
+            });
+        SimpleTestHarness::given(given)
+            .when(access_cmd("mallory@example.com"))
+            .then_err(EntityError::NotAuthorized(
+                "auth0|mallory@example.com".to_string(),
+                insurance_id(),
+            ));
+    }
+
+    #[test]
+    fn test_no_access_once_revoked_or_expired() {
+        let mut revoked = history();
+        revoked.push(consent_requested());
+        revoked.push(consent_granted(Duration::days(1)));
+        revoked.push(DomainEvent::ConsentRevoked {
+            consent_id: consent_id(),
+            entity_id: insurance_id(),
+            notes: None,
+            revoked_at: get_created_at(),
+            revoked_by: "auth0|asha@example.com".to_string(),
+            on_behalf_of: None,
+        });
+        SimpleTestHarness::given(revoked)
+            .when(access_cmd("bank@example.com"))
+            .then_err(EntityError::ConsentNotGranted(
+                consent_id(),
+                ConsentStatus::Revoked,
+            ));
+
+        let mut expired = history();
+        expired.push(consent_requested());
+        expired.push(consent_granted(Duration::seconds(-1)));
+        SimpleTestHarness::given(expired)
+            .when(access_cmd("bank@example.com"))
+            .then_err_assert(|e| assert!(matches!(e, EntityError::ConsentExpired(..))));
+    }
+}
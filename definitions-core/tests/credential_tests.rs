@@ -0,0 +1,305 @@
+mod common;
+
+#[cfg(test)]
+mod test {
+    use crate::common::test_harness::SimpleTestHarness;
+    use crate::common::{active_definition, get_created_at};
+    use definitions_core::credentials::{
+        verify_data_integrity, CredentialFormat, IssueCredentialCmd,
+    };
+    use definitions_core::definitions_domain::{generate_id_from_title, DomainEvent, Version};
+    use definitions_core::ownership::{Owner, Principal};
+    use definitions_core::registry_domain::EntityError;
+    use definitions_core::sd_jwt::SdJwt;
+    use definitions_core::signing::IssuerKey;
+    use serde_json::{json, Value};
+    use uuid::Uuid;
+
+    const STATUS_LIST_URL: &str = "https://registry.example.com/api/v1/credentials/status";
+
+    fn insurance_id() -> Uuid {
+        Uuid::parse_str("0196d2b4-3c2a-7d4e-8f00-000000000c01").unwrap()
+    }
+
+    fn credential_id() -> Uuid {
+        Uuid::parse_str("0196d2b4-3c2a-7d4e-8f00-000000000c02").unwrap()
+    }
+
+    fn issuer() -> IssuerKey {
+        IssuerKey::from_seed("registry-1", [7; 32])
+    }
+
+    fn insurance_schema() -> String {
+        json!({
+            "title": "Insurance",
+            "type": "object",
+            "properties": {
+                "Insurance": {
+                    "type": "object",
+                    "properties": {
+                        "policyNumber": { "type": "string" },
+                        "fullName": { "type": "string" },
+                        "email": { "type": "string" }
+                    }
+                }
+            },
+            "_osConfig": {
+                "ownershipAttributes": [{ "email": "$.email", "userId": "$.email" }],
+                "credentialTemplate": {
+                    "@context": ["https://www.w3.org/2018/credentials/v1"],
+                    "type": ["VerifiableCredential", "InsuranceCredential"],
+                    "issuer": "did:web:registry.example.com",
+                    "credentialSubject": {
+                        "id": "did:{{osid}}",
+                        "name": "{{fullName}}",
+                        "policyNumber": "{{policyNumber}}"
+                    }
+                },
+                "attestationPolicies": [{
+                    "name": "policyApproval",
+                    "attestationProperties": { "policyNumber": "$.policyNumber" },
+                    "type": "MANUAL",
+                    "attestorPlugin": "did:internal:ClaimPluginActor?entity=Official",
+                    "credentialTemplate": {
+                        "@context": ["https://www.w3.org/2018/credentials/v1"],
+                        "type": ["VerifiableCredential", "PolicyApprovalCredential"],
+                        "credentialSubject": { "approvedPolicy": "{{policyNumber}}" }
+                    }
+                }]
+            }
+        })
+        .to_string()
+    }
+
+    fn history(entity_body: Value) -> Vec<DomainEvent> {
+        let def_id = generate_id_from_title("Insurance");
+        let mut history = active_definition("Insurance", insurance_schema());
+        history.extend([DomainEvent::EntityCreated {
+            id: insurance_id(),
+            registry_def_id: def_id,
+            registry_def_version: Version::default(),
+            entity_body: entity_body.to_string(),
+            entity_type: "Insurance".to_string(),
+            created_at: get_created_at(),
+            created_by: "test_user".to_string(),
+            on_behalf_of: None,
+            version: Version::default(),
+            references: vec![],
+            owners: vec![Owner {
+                user_id: None,
+                email: Some("asha@example.com".to_string()),
+                mobile: None,
+            }],
+        }]);
+        history
+    }
+
+    fn insurance() -> Value {
+        json!({"Insurance": {"policyNumber": "P-1", "fullName": "Asha", "email": "asha@example.com"}})
+    }
+
+    fn issue_cmd(
+        format: CredentialFormat,
+        policy_name: Option<&str>,
+        email: &str,
+    ) -> IssueCredentialCmd {
+        IssueCredentialCmd {
+            credential_id: credential_id(),
+            entity_id: insurance_id(),
+            entity_type: "Insurance".to_string(),
+            policy_name: policy_name.map(str::to_string),
+            format,
+            issuer: issuer(),
+            issuer_did: None,
+            status_list_url: STATUS_LIST_URL.to_string(),
+            cipher: None,
+            principal: Principal {
+                subject: format!("auth0|{email}"),
+                email: Some(email.to_string()),
+                ..Default::default()
+            },
+        }
+    }
+
+    fn issued_credential(event: &DomainEvent) -> String {
+        if let DomainEvent::CredentialIssued {
+            credential,
+            encrypted,
+            ..
+        } = event
+        {
+            assert!(!encrypted);
+            credential.clone()
+        } else {
+            panic!("Expected CredentialIssued, got {:?}", event);
+        }
+    }
+
+    #[test]
+    fn test_issue_data_integrity_credential() {
+        SimpleTestHarness::given(history(insurance()))
+            .when(issue_cmd(CredentialFormat::LdpVc, None, "asha@example.com"))
+            .then_assert(|events| {
+                let credential: Value =
+                    serde_json::from_str(&issued_credential(&events[0])).unwrap();
+                assert_eq!(
+                    credential["credentialSubject"],
+                    json!({
+                        "id": format!("did:{}", insurance_id()),
+                        "name": "Asha",
+                        "policyNumber": "P-1"
+                    })
+                );
+                assert_eq!(
+                    credential["id"],
+                    json!(format!("urn:uuid:{}", credential_id()))
+                );
+                assert_eq!(
+                    credential["credentialStatus"][0]["statusListCredential"],
+                    json!(format!("{}/revocation", STATUS_LIST_URL))
+                );
+                assert_eq!(
+                    verify_data_integrity(&credential, &issuer().public_key()),
+                    Ok(())
+                );
+            });
+    }
+
+    #[test]
+    fn test_credentials_take_the_next_status_list_index() {
+        let mut given = history(insurance());
+        given.push(DomainEvent::CredentialIssued {
+            id: insurance_id(),
+            credential_id: Uuid::now_v7(),
+            entity_type: "Insurance".to_string(),
+            policy_name: None,
+            format: CredentialFormat::JwtVc,
+            credential: String::new(),
+            encrypted: false,
+            status_list_index: Some(41),
+            issued_at: get_created_at(),
+            issued_by: "test_user".to_string(),
+            on_behalf_of: None,
+        });
+        SimpleTestHarness::given(given)
+            .when(issue_cmd(CredentialFormat::LdpVc, None, "asha@example.com"))
+            .then_assert(|events| {
+                assert!(matches!(
+                    events[0],
+                    DomainEvent::CredentialIssued {
+                        status_list_index: Some(42),
+                        ..
+                    }
+                ));
+            });
+    }
+
+    #[test]
+    fn test_issue_vc_jwt() {
+        SimpleTestHarness::given(history(insurance()))
+            .when(issue_cmd(CredentialFormat::JwtVc, None, "asha@example.com"))
+            .then_assert(|events| {
+                let claims = issuer()
+                    .public_key()
+                    .verify(&issued_credential(&events[0]))
+                    .unwrap();
+                assert_eq!(claims["iss"], json!("did:web:registry.example.com"));
+                assert_eq!(claims["sub"], json!(format!("did:{}", insurance_id())));
+                assert_eq!(claims["vc"]["credentialSubject"]["name"], json!("Asha"));
+            });
+    }
+
+    #[test]
+    fn test_issue_sd_jwt_conceals_subject_claims() {
+        SimpleTestHarness::given(history(insurance()))
+            .when(issue_cmd(
+                CredentialFormat::SdJwtVc,
+                None,
+                "asha@example.com",
+            ))
+            .then_assert(|events| {
+                let sd_jwt = SdJwt::parse(&issued_credential(&events[0])).unwrap();
+                let claims = issuer().public_key().verify(sd_jwt.jwt()).unwrap();
+                assert!(claims["vc"]["credentialSubject"].get("name").is_none());
+                assert!(sd_jwt.claim_names().contains(&"name"));
+
+                let presented = sd_jwt.present(&["name".to_string()]).unwrap();
+                let disclosed = presented.disclose(&claims).unwrap();
+                assert_eq!(disclosed["vc"]["credentialSubject"]["name"], json!("Asha"));
+            });
+    }
+
+    #[test]
+    fn test_policy_credential_requires_attestation() {
+        SimpleTestHarness::given(history(insurance()))
+            .when(issue_cmd(
+                CredentialFormat::LdpVc,
+                Some("policyApproval"),
+                "asha@example.com",
+            ))
+            .then_err(EntityError::CredentialNotAvailable(
+                "policyApproval".to_string(),
+                "the policy has not been attested".to_string(),
+            ));
+    }
+
+    #[test]
+    fn test_policy_credential_renders_attested_data() {
+        let mut body = insurance();
+        body["Insurance"]["_osAttestedData"] =
+            json!({"policyApproval": {"data": {"policyNumber": "P-0"}}});
+        SimpleTestHarness::given(history(body))
+            .when(issue_cmd(
+                CredentialFormat::LdpVc,
+                Some("policyApproval"),
+                "asha@example.com",
+            ))
+            .then_assert(|events| {
+                let credential: Value =
+                    serde_json::from_str(&issued_credential(&events[0])).unwrap();
+                assert_eq!(
+                    credential["credentialSubject"],
+                    json!({"approvedPolicy": "P-0"})
+                );
+                assert_eq!(credential["issuer"], json!("registry-1"));
+            });
+    }
+
+    #[test]
+    fn test_registry_issuer_is_the_registry_did() {
+        let mut cmd = issue_cmd(
+            CredentialFormat::LdpVc,
+            Some("policyApproval"),
+            "asha@example.com",
+        );
+        cmd.issuer_did = Some("did:web:registry.example.com".to_string());
+        let mut body = insurance();
+        body["Insurance"]["_osAttestedData"] =
+            json!({"policyApproval": {"data": {"policyNumber": "P-0"}}});
+        SimpleTestHarness::given(history(body))
+            .when(cmd)
+            .then_assert(|events| {
+                let credential: Value =
+                    serde_json::from_str(&issued_credential(&events[0])).unwrap();
+                assert_eq!(credential["issuer"], json!("did:web:registry.example.com"));
+                assert_eq!(
+                    credential["proof"]["verificationMethod"],
+                    json!("did:web:registry.example.com#registry-1")
+                );
+            });
+    }
+
+    #[test]
+    fn test_only_owners_issue_credentials() {
+        SimpleTestHarness::given(history(insurance()))
+            .when(issue_cmd(
+                CredentialFormat::LdpVc,
+                None,
+                "mallory@example.com",
+            ))
+            .then_err(EntityError::NotAuthorized(
+                "auth0|mallory@example.com".to_string(),
+                insurance_id(),
+            ));
+    }
+}
@@ -97,6 +97,41 @@ mod test {
             .then([get_expected_validation_failed_empty_title()]);
     }
 
+    #[test]
+    fn test_validate_with_malformed_attestation_condition() {
+        let mut schema: Value = serde_json::from_str(&get_valid_json_string()).unwrap();
+        schema["_osConfig"] = serde_json::json!({
+            "attestationPolicies": [{
+                "name": "genderCheck",
+                "attestationProperties": { "name": "$.name" },
+                "type": "MANUAL",
+                "attestorEntity": "Teacher",
+                "conditions": "(ATTESTOR#$.Gender#.equalsIgnoreCase('male')"
+            }]
+        });
+        let mut def_created = def_created_valid_json_draft();
+        if let DomainEvent::DefCreated {
+            json_schema_string, ..
+        } = &mut def_created
+        {
+            *json_schema_string = schema.to_string();
+        }
+
+        SimpleTestHarness::given([def_created])
+            .when(get_validate_def_cmd())
+            .then_assert(|events| {
+                if let DomainEvent::DefValidatedFailed {
+                    validation_errors, ..
+                } = &events[0]
+                {
+                    assert_eq!(validation_errors.len(), 1);
+                    assert!(validation_errors[0].contains("genderCheck"));
+                } else {
+                    panic!("Expected DefValidatedFailed, got {:?}", events[0]);
+                }
+            });
+    }
+
     #[test]
     fn test_mutate_tile_should_fail() {
         disintegrate::TestHarness::given([
@@ -0,0 +1,195 @@
+mod common;
+
+#[cfg(test)]
+mod test {
+    use crate::common::test_harness::SimpleTestHarness;
+    use crate::common::{active_definition, cipher, entity_id, get_created_at};
+    use base64::engine::general_purpose::URL_SAFE_NO_PAD as BASE64URL;
+    use base64::Engine;
+    use definitions_core::definitions_domain::{generate_id_from_title, DomainEvent, Version};
+    use definitions_core::encryption::{is_encrypted, FieldCipher};
+    use definitions_core::registry_domain::{CreateEntityCmd, EntityError, MigrateEntityCmd};
+    use definitions_core::signing::{IssuerKey, SignedFields};
+    use serde_json::{json, Value};
+
+    fn patient_schema() -> String {
+        json!({
+            "title": "Patient",
+            "type": "object",
+            "properties": {
+                "Patient": {
+                    "type": "object",
+                    "properties": {
+                        "name": { "type": "string" },
+                        "dob": { "type": "string", "format": "date" }
+                    },
+                    "required": ["name", "dob"]
+                }
+            },
+            "_osConfig": { "privateFields": ["$.dob"] }
+        })
+        .to_string()
+    }
+
+    fn signed_patient_schema() -> String {
+        let mut schema: Value = serde_json::from_str(&patient_schema()).unwrap();
+        schema["properties"]["Patient"]["properties"]["mobile"] = json!({ "type": "string" });
+        schema["_osConfig"] = json!({
+            "privateFields": ["$.dob"],
+            "internalFields": ["$.mobile"],
+            "systemFields": ["_osSignedData"]
+        });
+        schema.to_string()
+    }
+
+    fn patient_body() -> String {
+        json!({"Patient": {"name": "Asha", "dob": "1990-05-17"}}).to_string()
+    }
+
+    fn create_cmd(cipher: Option<FieldCipher>) -> CreateEntityCmd {
+        CreateEntityCmd {
+            id: entity_id(),
+            entity_body: patient_body(),
+            entity_type: "Patient".to_string(),
+            created_by: "test_user".into(),
+            referenced_ids: vec![],
+            cipher,
+            signer: None,
+        }
+    }
+
+    #[test]
+    fn test_create_entity_encrypts_private_fields() {
+        SimpleTestHarness::given(active_definition("Patient", patient_schema()))
+            .when(create_cmd(Some(cipher())))
+            .then_assert(|events| {
+                if let DomainEvent::EntityCreated { entity_body, .. } = &events[0] {
+                    assert!(!entity_body.contains("1990-05-17"));
+                    let mut body: Value = serde_json::from_str(entity_body).unwrap();
+                    assert_eq!(body["Patient"]["name"], json!("Asha"));
+                    assert!(is_encrypted(&body["Patient"]["dob"]));
+                    cipher().decrypt_all(&mut body).unwrap();
+                    assert_eq!(body["Patient"]["dob"], json!("1990-05-17"));
+                } else {
+                    panic!("Expected EntityCreated, got {:?}", events[0]);
+                }
+            });
+    }
+
+    #[test]
+    fn test_signature_does_not_disclose_private_or_internal_fields() {
+        let key = IssuerKey::from_seed("issuer-1", [7; 32]);
+        SimpleTestHarness::given(active_definition("Patient", signed_patient_schema()))
+            .when(CreateEntityCmd {
+                entity_body: json!({"Patient": {"name": "Asha", "dob": "1990-05-17", "mobile": "+91-9876543210"}})
+                    .to_string(),
+                signer: Some(key.clone()),
+                ..create_cmd(Some(cipher()))
+            })
+            .then_assert(|events| {
+                if let DomainEvent::EntityCreated { entity_body, .. } = &events[0] {
+                    let body: Value = serde_json::from_str(entity_body).unwrap();
+                    let jws = body["Patient"]["_osSignedData"].as_str().unwrap();
+                    let decoded: Vec<String> = jws
+                        .split('.')
+                        .map(|segment| {
+                            String::from_utf8_lossy(&BASE64URL.decode(segment).unwrap()).to_string()
+                        })
+                        .collect();
+                    assert_eq!(decoded[1], "");
+                    for segment in &decoded {
+                        assert!(!segment.contains("1990-05-17"));
+                        assert!(!segment.contains("9876543210"));
+                    }
+
+                    let mut decrypted = body;
+                    cipher().decrypt_all(&mut decrypted).unwrap();
+                    let signed_fields =
+                        SignedFields::from_schema(&signed_patient_schema(), "Patient").unwrap();
+                    assert_eq!(
+                        signed_fields.verify(&decrypted.to_string(), None, &[key.public_key()]),
+                        Ok("issuer-1".to_string())
+                    );
+                } else {
+                    panic!("Expected EntityCreated, got {:?}", events[0]);
+                }
+            });
+    }
+
+    #[test]
+    fn test_create_entity_without_cipher_is_rejected() {
+        SimpleTestHarness::given(active_definition("Patient", patient_schema()))
+            .when(create_cmd(None))
+            .then_err(EntityError::EncryptionUnavailable("Patient".to_string()));
+    }
+
+    #[test]
+    fn test_migrate_entity_decrypts_and_re_encrypts() {
+        let mut stored: Value = serde_json::from_str(&patient_body()).unwrap();
+        cipher()
+            .encrypt_paths(&mut stored, "Patient", &["$.dob".to_string()])
+            .unwrap();
+        let def_id = generate_id_from_title("Patient");
+        let mut history = active_definition("Patient", patient_schema());
+        history.extend([
+            DomainEvent::EntityCreated {
+                id: entity_id(),
+                registry_def_id: def_id,
+                registry_def_version: Version::default(),
+                entity_body: stored.to_string(),
+                entity_type: "Patient".to_string(),
+                created_at: get_created_at(),
+                created_by: "test_user".to_string(),
+                on_behalf_of: None,
+                version: Version::default(),
+                references: vec![],
+                owners: vec![],
+            },
+            DomainEvent::DefUpdated {
+                id: def_id,
+                title: "Patient".to_string(),
+                definitions: vec![],
+                created_at: get_created_at(),
+                updated_by: "test_user".to_string(),
+                on_behalf_of: None,
+                json_schema_string: patient_schema(),
+                migration: None,
+            },
+            DomainEvent::DefValidated {
+                id: def_id,
+                validated_at: get_created_at(),
+                validated_by: "test_user".to_string(),
+                on_behalf_of: None,
+                validation_result: "Success".to_string(),
+            },
+            DomainEvent::DefActivated {
+                id: def_id,
+                activated_at: get_created_at(),
+                activated_by: "test_user".to_string(),
+                on_behalf_of: None,
+                json_schema_string: patient_schema(),
+                version: Version::default().increment(),
+                migration: None,
+            },
+        ]);
+
+        SimpleTestHarness::given(history)
+            .when(MigrateEntityCmd {
+                id: entity_id(),
+                entity_type: "Patient".to_string(),
+                migrated_by: "entity_migration".into(),
+                cipher: Some(cipher()),
+                signer: None,
+            })
+            .then_assert(|events| {
+                if let DomainEvent::EntityUpdated { entity_body, .. } = &events[0] {
+                    let mut body: Value = serde_json::from_str(entity_body).unwrap();
+                    assert!(is_encrypted(&body["Patient"]["dob"]));
+                    cipher().decrypt_all(&mut body).unwrap();
+                    assert_eq!(body["Patient"]["dob"], json!("1990-05-17"));
+                } else {
+                    panic!("Expected EntityUpdated, got {:?}", events[0]);
+                }
+            });
+    }
+}
@@ -0,0 +1,309 @@
+mod common;
+
+#[cfg(test)]
+mod test {
+    use crate::common::test_harness::SimpleTestHarness;
+    use crate::common::{
+        entity_id, get_created_at, get_def_activated_valid_student_json,
+        get_def_created_valid_student_json, get_def_validated_valid_student_json,
+        get_valid_student_document, get_valid_student_schema_string,
+    };
+    use definitions_core::definitions_domain::{
+        generate_id_from_title, ActivateDefinitionCmd, DefError, DomainEvent, UpdateDefinitionCmd,
+        Version,
+    };
+    use definitions_core::migration::{MigrationOp, MigrationSpec};
+    use definitions_core::registry_domain::{EntityError, MigrateEntityCmd};
+    use serde_json::{json, Value};
+
+    /// Version 2 of the Student schema renames `fullName` to `name`
+    fn student_schema_v2() -> String {
+        get_valid_student_schema_string().replace("\"fullName\"", "\"name\"")
+    }
+
+    fn rename_full_name() -> MigrationSpec {
+        MigrationSpec {
+            operations: vec![
+                MigrationOp::RenameField {
+                    path: "$.identityDetails.fullName".to_string(),
+                    to: "name".to_string(),
+                },
+                MigrationOp::SetDefault {
+                    path: "$.contactDetails.country".to_string(),
+                    value: json!("IN"),
+                },
+            ],
+        }
+    }
+
+    /// Version 3 of the Student schema renames `name` to `displayName`
+    fn student_schema_v3() -> String {
+        get_valid_student_schema_string().replace("\"fullName\"", "\"displayName\"")
+    }
+
+    fn rename_name() -> MigrationSpec {
+        MigrationSpec {
+            operations: vec![MigrationOp::RenameField {
+                path: "$.identityDetails.name".to_string(),
+                to: "displayName".to_string(),
+            }],
+        }
+    }
+
+    fn history_with_v2_active() -> Vec<DomainEvent> {
+        history_with_v2_activated(Some(rename_full_name()))
+    }
+
+    fn history_with_v2_activated(migration: Option<MigrationSpec>) -> Vec<DomainEvent> {
+        let def_id = generate_id_from_title("Student");
+        vec![
+            get_def_created_valid_student_json(),
+            get_def_validated_valid_student_json(),
+            get_def_activated_valid_student_json(),
+            DomainEvent::EntityCreated {
+                id: entity_id(),
+                registry_def_id: def_id,
+                registry_def_version: Version::default(),
+                entity_body: get_valid_student_document(),
+                entity_type: "Student".to_string(),
+                created_at: get_created_at(),
+                created_by: "test_user".to_string(),
+                on_behalf_of: None,
+                version: Version::default(),
+                references: vec![],
+                owners: vec![],
+            },
+            DomainEvent::DefUpdated {
+                id: def_id,
+                title: "Student".to_string(),
+                definitions: vec![],
+                created_at: get_created_at(),
+                updated_by: "test_user".to_string(),
+                on_behalf_of: None,
+                json_schema_string: student_schema_v2(),
+                migration: Some(rename_full_name()),
+            },
+            get_def_validated_valid_student_json(),
+            DomainEvent::DefActivated {
+                id: def_id,
+                activated_at: get_created_at(),
+                activated_by: "test_user".to_string(),
+                on_behalf_of: None,
+                json_schema_string: student_schema_v2(),
+                version: Version::default().increment(),
+                migration,
+            },
+        ]
+    }
+
+    /// Activates version 3 on top of [`history_with_v2_active`], the entity is still on version 1
+    fn history_with_v3_active() -> Vec<DomainEvent> {
+        let mut history = history_with_v2_active();
+        history.extend(v3_activation());
+        history
+    }
+
+    /// Updates and activates version 3 of the Student definition
+    fn v3_activation() -> Vec<DomainEvent> {
+        let def_id = generate_id_from_title("Student");
+        vec![
+            DomainEvent::DefUpdated {
+                id: def_id,
+                title: "Student".to_string(),
+                definitions: vec![],
+                created_at: get_created_at(),
+                updated_by: "test_user".to_string(),
+                on_behalf_of: None,
+                json_schema_string: student_schema_v3(),
+                migration: Some(rename_name()),
+            },
+            get_def_validated_valid_student_json(),
+            DomainEvent::DefActivated {
+                id: def_id,
+                activated_at: get_created_at(),
+                activated_by: "test_user".to_string(),
+                on_behalf_of: None,
+                json_schema_string: student_schema_v3(),
+                version: Version::default().increment().increment(),
+                migration: Some(rename_name()),
+            },
+        ]
+    }
+
+    fn migrate_cmd() -> MigrateEntityCmd {
+        MigrateEntityCmd {
+            id: entity_id(),
+            entity_type: "Student".to_string(),
+            migrated_by: "entity_migration".into(),
+            cipher: None,
+            signer: None,
+        }
+    }
+
+    #[test]
+    fn test_migrate_entity_applies_spec_and_bumps_versions() {
+        SimpleTestHarness::given(history_with_v2_active())
+            .when(migrate_cmd())
+            .then_assert(|events| {
+                assert_eq!(events.len(), 1);
+                if let DomainEvent::EntityUpdated {
+                    registry_def_version,
+                    entity_body,
+                    version,
+                    updated_by,
+                    ..
+                } = &events[0]
+                {
+                    assert_eq!(registry_def_version.get(), 2);
+                    assert_eq!(version.get(), 2);
+                    assert_eq!(updated_by, "entity_migration");
+                    let body: Value = serde_json::from_str(entity_body).unwrap();
+                    assert_eq!(body["Student"]["identityDetails"]["name"], json!("John"));
+                    assert!(body["Student"]["identityDetails"].get("fullName").is_none());
+                    assert_eq!(body["Student"]["contactDetails"]["country"], json!("IN"));
+                } else {
+                    panic!("Expected EntityUpdated, got {:?}", events[0]);
+                }
+            });
+    }
+
+    #[test]
+    fn test_migrate_entity_applies_each_version_since_its_own_in_order() {
+        SimpleTestHarness::given(history_with_v3_active())
+            .when(migrate_cmd())
+            .then_assert(|events| {
+                assert_eq!(events.len(), 1);
+                if let DomainEvent::EntityUpdated {
+                    registry_def_version,
+                    entity_body,
+                    ..
+                } = &events[0]
+                {
+                    assert_eq!(registry_def_version.get(), 3);
+                    let body: Value = serde_json::from_str(entity_body).unwrap();
+                    let identity = &body["Student"]["identityDetails"];
+                    assert_eq!(identity["displayName"], json!("John"));
+                    assert!(identity.get("fullName").is_none());
+                    assert!(identity.get("name").is_none());
+                    assert_eq!(body["Student"]["contactDetails"]["country"], json!("IN"));
+                } else {
+                    panic!("Expected EntityUpdated, got {:?}", events[0]);
+                }
+            });
+    }
+
+    #[test]
+    fn test_migrate_entity_on_the_previous_version_applies_the_latest_migration_only() {
+        let mut history = history_with_v2_active();
+        history.push(DomainEvent::EntityUpdated {
+            id: entity_id(),
+            registry_def_id: generate_id_from_title("Student"),
+            registry_def_version: Version::default().increment(),
+            entity_body: get_valid_student_document().replace("\"fullName\"", "\"name\""),
+            entity_type: "Student".to_string(),
+            updated_at: get_created_at(),
+            updated_by: "entity_migration".to_string(),
+            on_behalf_of: None,
+            version: Version::default().increment(),
+            references: vec![],
+        });
+        history.extend(v3_activation());
+        SimpleTestHarness::given(history)
+            .when(migrate_cmd())
+            .then_assert(|events| {
+                if let DomainEvent::EntityUpdated { entity_body, .. } = &events[0] {
+                    let body: Value = serde_json::from_str(entity_body).unwrap();
+                    assert_eq!(
+                        body["Student"]["identityDetails"]["displayName"],
+                        json!("John")
+                    );
+                    assert!(body["Student"]["contactDetails"].get("country").is_none());
+                } else {
+                    panic!("Expected EntityUpdated, got {:?}", events[0]);
+                }
+            });
+    }
+
+    #[test]
+    fn test_migrate_entity_fails_when_result_does_not_match_new_schema() {
+        SimpleTestHarness::given(history_with_v2_activated(None))
+            .when(migrate_cmd())
+            .then_err_assert(|err| {
+                assert!(
+                    matches!(err, EntityError::JsonSchemaError(entity_type, _) if entity_type == "Student")
+                );
+            });
+    }
+
+    #[test]
+    fn test_migrate_entity_is_noop_when_already_on_active_version() {
+        let mut history = history_with_v2_active();
+        history.push(DomainEvent::EntityUpdated {
+            id: entity_id(),
+            registry_def_id: generate_id_from_title("Student"),
+            registry_def_version: Version::default().increment(),
+            entity_body: get_valid_student_document().replace("\"fullName\"", "\"name\""),
+            entity_type: "Student".to_string(),
+            updated_at: get_created_at(),
+            updated_by: "entity_migration".to_string(),
+            on_behalf_of: None,
+            version: Version::default().increment(),
+            references: vec![],
+        });
+        SimpleTestHarness::given(history)
+            .when(migrate_cmd())
+            .then(vec![]);
+    }
+
+    #[test]
+    fn test_migrate_unknown_entity() {
+        let mut history = history_with_v2_active();
+        history.remove(3);
+        SimpleTestHarness::given(history)
+            .when(migrate_cmd())
+            .then_err(EntityError::EntityNotFound(entity_id()));
+    }
+
+    #[test]
+    fn test_update_definition_rejects_invalid_migration() {
+        let invalid_migration = MigrationSpec {
+            operations: vec![MigrationOp::RenameField {
+                path: "$.identityDetails[0]".to_string(),
+                to: "name".to_string(),
+            }],
+        };
+        SimpleTestHarness::given([
+            get_def_created_valid_student_json(),
+            get_def_validated_valid_student_json(),
+            get_def_activated_valid_student_json(),
+        ])
+        .when(UpdateDefinitionCmd {
+            id: generate_id_from_title("Student"),
+            definitions: vec![],
+            created_at: get_created_at(),
+            updated_by: "test_user".into(),
+            json_schema_string: student_schema_v2(),
+            migration: Some(invalid_migration),
+        })
+        .then_err_assert(|err| assert!(matches!(err, DefError::InvalidMigration(_))));
+    }
+
+    #[test]
+    fn test_activation_carries_pending_migration() {
+        let mut history = history_with_v2_active();
+        history.pop();
+        SimpleTestHarness::given(history)
+            .when(ActivateDefinitionCmd {
+                id: generate_id_from_title("Student"),
+                activated_at: get_created_at(),
+                activated_by: "test_user".into(),
+            })
+            .then_assert(|events| {
+                assert!(matches!(
+                    &events[0],
+                    DomainEvent::DefActivated { version, migration: Some(spec), .. }
+                        if version.get() == 2 && spec == &rename_full_name()
+                ));
+            });
+    }
+}
@@ -0,0 +1,199 @@
+mod common;
+
+#[cfg(test)]
+mod test {
+    use crate::common::test_harness::SimpleTestHarness;
+    use crate::common::{active_definition, entity_id, get_created_at};
+    use definitions_core::definitions_domain::{generate_id_from_title, DomainEvent, Version};
+    use definitions_core::ownership::{Owner, Principal};
+    use definitions_core::registry_domain::{CreateEntityCmd, EntityError, ModifyEntityCmd};
+    use serde_json::{json, Value};
+
+    fn teacher_schema() -> String {
+        teacher_schema_with_roles(json!(["admin"]))
+    }
+
+    fn teacher_schema_with_roles(roles: Value) -> String {
+        json!({
+            "title": "Teacher",
+            "type": "object",
+            "properties": {
+                "Teacher": {
+                    "type": "object",
+                    "properties": {
+                        "name": { "type": "string" },
+                        "contactDetails": {
+                            "type": "object",
+                            "properties": {
+                                "email": { "type": "string" },
+                                "mobile": { "type": "string" }
+                            }
+                        }
+                    },
+                    "required": ["name"]
+                }
+            },
+            "_osConfig": {
+                "roles": roles,
+                "ownershipAttributes": [
+                    { "email": "/contactDetails/email", "mobile": "/contactDetails/mobile", "userId": "/contactDetails/mobile" }
+                ]
+            }
+        })
+        .to_string()
+    }
+
+    fn teacher_body(name: &str) -> String {
+        json!({
+            "Teacher": {
+                "name": name,
+                "contactDetails": { "email": "smith@example.com", "mobile": "9876543210" }
+            }
+        })
+        .to_string()
+    }
+
+    fn smith() -> Owner {
+        Owner {
+            user_id: Some("9876543210".to_string()),
+            email: Some("smith@example.com".to_string()),
+            mobile: Some("9876543210".to_string()),
+        }
+    }
+
+    fn history_with_teacher() -> Vec<DomainEvent> {
+        history_with_teacher_of(teacher_schema())
+    }
+
+    fn history_with_teacher_of(schema: String) -> Vec<DomainEvent> {
+        let mut history = active_definition("Teacher", schema);
+        history.push(DomainEvent::EntityCreated {
+            id: entity_id(),
+            registry_def_id: generate_id_from_title("Teacher"),
+            registry_def_version: Version::default(),
+            entity_body: teacher_body("Smith"),
+            entity_type: "Teacher".to_string(),
+            created_at: get_created_at(),
+            created_by: "test_user".to_string(),
+            on_behalf_of: None,
+            version: Version::default(),
+            references: vec![],
+            owners: vec![smith()],
+        });
+        history
+    }
+
+    fn modify_cmd(principal: Principal) -> ModifyEntityCmd {
+        ModifyEntityCmd {
+            id: entity_id(),
+            entity_body: teacher_body("John Smith"),
+            entity_type: "Teacher".to_string(),
+            principal,
+            referenced_ids: vec![],
+            cipher: None,
+            signer: None,
+        }
+    }
+
+    #[test]
+    fn test_create_entity_binds_owners() {
+        SimpleTestHarness::given(active_definition("Teacher", teacher_schema()))
+            .when(CreateEntityCmd {
+                id: entity_id(),
+                entity_body: teacher_body("Smith"),
+                entity_type: "Teacher".to_string(),
+                created_by: "test_user".into(),
+                referenced_ids: vec![],
+                cipher: None,
+                signer: None,
+            })
+            .then_assert(|events| {
+                assert!(matches!(
+                    &events[0],
+                    DomainEvent::EntityCreated { owners, .. } if owners == &vec![smith()]
+                ));
+            });
+    }
+
+    #[test]
+    fn test_owner_can_modify_entity() {
+        SimpleTestHarness::given(history_with_teacher())
+            .when(modify_cmd(Principal {
+                subject: "auth0|smith".to_string(),
+                email: Some("smith@example.com".to_string()),
+                roles: vec![],
+                permissions: vec![],
+                ..Principal::default()
+            }))
+            .then_assert(|events| {
+                assert!(matches!(&events[0], DomainEvent::EntityUpdated { .. }));
+            });
+    }
+
+    #[test]
+    fn test_impersonated_update_records_actor_and_owner() {
+        SimpleTestHarness::given(history_with_teacher())
+            .when(modify_cmd(Principal {
+                subject: "auth0|smith".to_string(),
+                email: Some("smith@example.com".to_string()),
+                act: vec!["support-agent".to_string()],
+                ..Principal::default()
+            }))
+            .then_assert(|events| {
+                assert!(matches!(
+                    &events[0],
+                    DomainEvent::EntityUpdated { updated_by, on_behalf_of, .. }
+                        if updated_by == "support-agent"
+                            && on_behalf_of.as_deref() == Some("auth0|smith")
+                ));
+            });
+    }
+
+    #[test]
+    fn test_role_holder_can_modify_entity() {
+        SimpleTestHarness::given(history_with_teacher())
+            .when(modify_cmd(Principal {
+                subject: "auth0|registrar".to_string(),
+                email: None,
+                roles: vec!["admin".to_string()],
+                permissions: vec![],
+                ..Principal::default()
+            }))
+            .then_assert(|events| {
+                assert!(matches!(&events[0], DomainEvent::EntityUpdated { .. }));
+            });
+    }
+
+    #[test]
+    fn test_stranger_cannot_modify_entity() {
+        SimpleTestHarness::given(history_with_teacher())
+            .when(modify_cmd(Principal {
+                subject: "auth0|someone".to_string(),
+                email: Some("someone@example.com".to_string()),
+                roles: vec!["teacher".to_string()],
+                permissions: vec![],
+                ..Principal::default()
+            }))
+            .then_err(EntityError::NotAuthorized(
+                "auth0|someone".to_string(),
+                entity_id(),
+            ));
+    }
+
+    #[test]
+    fn test_anonymous_role_does_not_let_a_stranger_modify_an_owned_entity() {
+        SimpleTestHarness::given(history_with_teacher_of(teacher_schema_with_roles(json!([
+            "anonymous"
+        ]))))
+        .when(modify_cmd(Principal {
+            subject: "auth0|someone".to_string(),
+            email: Some("someone@example.com".to_string()),
+            roles: vec!["anonymous".to_string()],
+            ..Principal::default()
+        }))
+        .then_err(EntityError::NotAuthorized(
+            "auth0|someone".to_string(),
+            entity_id(),
+        ));
+    }
+}
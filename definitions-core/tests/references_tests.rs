@@ -0,0 +1,136 @@
+mod common;
+
+#[cfg(test)]
+mod test {
+    use crate::common::test_harness::SimpleTestHarness;
+    use crate::common::{active_definition, get_created_at};
+    use definitions_core::definitions_domain::{generate_id_from_title, DomainEvent, Version};
+    use definitions_core::references::EntityReference;
+    use definitions_core::registry_domain::{CreateEntityCmd, EntityError, EntityRecordStatus};
+    use serde_json::json;
+    use uuid::Uuid;
+
+    fn teacher_id() -> Uuid {
+        Uuid::parse_str("0196d2b4-3c2a-7d4e-8f00-0000000000aa").unwrap()
+    }
+
+    fn class_schema() -> String {
+        json!({
+            "title": "Classroom",
+            "type": "object",
+            "properties": {
+                "Classroom": {
+                    "type": "object",
+                    "properties": {
+                        "name": { "type": "string" },
+                        "teacher": { "type": "string", "x-ref": "Teacher" }
+                    },
+                    "required": ["name", "teacher"]
+                }
+            }
+        })
+        .to_string()
+    }
+
+    fn teacher_created(entity_type: &str) -> DomainEvent {
+        DomainEvent::EntityCreated {
+            id: teacher_id(),
+            registry_def_id: generate_id_from_title(entity_type),
+            registry_def_version: Version::default(),
+            entity_body: json!({ entity_type: {"name": "Smith"} }).to_string(),
+            entity_type: entity_type.to_string(),
+            created_at: get_created_at(),
+            created_by: "test_user".to_string(),
+            on_behalf_of: None,
+            version: Version::default(),
+            references: vec![],
+            owners: vec![],
+        }
+    }
+
+    fn create_class_cmd(referenced_ids: Vec<Uuid>) -> CreateEntityCmd {
+        CreateEntityCmd {
+            id: Uuid::now_v7(),
+            entity_body: json!({"Classroom": {"name": "6A", "teacher": teacher_id().to_string()}})
+                .to_string(),
+            entity_type: "Classroom".to_string(),
+            created_by: "test_user".into(),
+            referenced_ids,
+            cipher: None,
+            signer: None,
+        }
+    }
+
+    #[test]
+    fn test_create_entity_with_active_reference() {
+        let mut history = active_definition("Classroom", class_schema());
+        history.push(teacher_created("Teacher"));
+        SimpleTestHarness::given(history)
+            .when(create_class_cmd(vec![teacher_id()]))
+            .then_assert(|events| {
+                assert!(matches!(
+                    &events[0],
+                    DomainEvent::EntityCreated { references, .. } if references == &vec![EntityReference {
+                        path: "/Classroom/teacher".to_string(),
+                        entity_type: "Teacher".to_string(),
+                        id: teacher_id(),
+                    }]
+                ));
+            });
+    }
+
+    #[test]
+    fn test_create_entity_with_missing_reference() {
+        SimpleTestHarness::given(active_definition("Classroom", class_schema()))
+            .when(create_class_cmd(vec![teacher_id()]))
+            .then_err(EntityError::ReferencedEntityNotFound(
+                "/Classroom/teacher".to_string(),
+                teacher_id(),
+            ));
+    }
+
+    #[test]
+    fn test_create_entity_with_reference_to_wrong_type() {
+        let mut history = active_definition("Classroom", class_schema());
+        history.push(teacher_created("Student"));
+        SimpleTestHarness::given(history)
+            .when(create_class_cmd(vec![teacher_id()]))
+            .then_err_assert(|err| {
+                assert!(matches!(err, EntityError::InvalidReference(path, _) if path == "/Classroom/teacher"))
+            });
+    }
+
+    #[test]
+    fn test_create_entity_with_reference_to_invited_entity() {
+        let mut history = active_definition("Classroom", class_schema());
+        history.push(DomainEvent::EntityInvited {
+            id: teacher_id(),
+            registry_def_id: generate_id_from_title("Teacher"),
+            registry_def_version: Version::default(),
+            entity_body: "{}".to_string(),
+            entity_type: "Teacher".to_string(),
+            invited_at: get_created_at(),
+            invited_by: "test_user".to_string(),
+            on_behalf_of: None,
+            version: Version::default(),
+        });
+        SimpleTestHarness::given(history)
+            .when(create_class_cmd(vec![teacher_id()]))
+            .then_err(EntityError::ReferencedEntityNotActive(
+                "/Classroom/teacher".to_string(),
+                teacher_id(),
+                EntityRecordStatus::Invited,
+            ));
+    }
+
+    #[test]
+    fn test_create_entity_rejects_undeclared_reference() {
+        let mut history = active_definition("Classroom", class_schema());
+        history.push(teacher_created("Teacher"));
+        SimpleTestHarness::given(history)
+            .when(create_class_cmd(vec![]))
+            .then_err_assert(|err| {
+                assert!(matches!(err, EntityError::InvalidReference(path, _) if path == "/Classroom/teacher"))
+            });
+    }
+}
@@ -0,0 +1,198 @@
+mod common;
+
+#[cfg(test)]
+mod test {
+    use crate::common::test_harness::SimpleTestHarness;
+    use crate::common::{active_definition, entity_id, get_created_at};
+    use definitions_core::audit::Actor;
+    use definitions_core::definitions_domain::{
+        generate_id_from_title, DefRecordStatus, DomainEvent, Version,
+    };
+    use definitions_core::ownership::Owner;
+    use definitions_core::registration::{ConfirmRegistrationCmd, RegisterEntityCmd};
+    use definitions_core::registry_domain::EntityError;
+    use serde_json::{json, Value};
+
+    fn student_schema(roles: Value) -> String {
+        json!({
+            "title": "Student",
+            "type": "object",
+            "properties": {
+                "Student": {
+                    "type": "object",
+                    "properties": {
+                        "name": { "type": "string" },
+                        "contactDetails": {
+                            "type": "object",
+                            "properties": {
+                                "email": { "type": "string" },
+                                "mobile": { "type": "string" }
+                            }
+                        }
+                    },
+                    "required": ["name"]
+                }
+            },
+            "_osConfig": {
+                "roles": roles,
+                "ownershipAttributes": [
+                    { "email": "/contactDetails/email", "mobile": "/contactDetails/mobile" }
+                ]
+            }
+        })
+        .to_string()
+    }
+
+    fn student_body(contact: Value) -> String {
+        json!({ "Student": { "name": "John", "contactDetails": contact } }).to_string()
+    }
+
+    fn register_cmd(contact: Value) -> RegisterEntityCmd {
+        RegisterEntityCmd {
+            id: entity_id(),
+            entity_body: student_body(contact),
+            entity_type: "Student".to_string(),
+            registered_by: Actor::new("anonymous"),
+            ..Default::default()
+        }
+    }
+
+    fn registered() -> DomainEvent {
+        DomainEvent::EntityRegistered {
+            id: entity_id(),
+            registry_def_id: generate_id_from_title("Student"),
+            registry_def_version: Version::default(),
+            entity_body: student_body(json!({ "email": "john@example.com" })),
+            entity_type: "Student".to_string(),
+            registered_at: get_created_at(),
+            registered_by: "anonymous".to_string(),
+            on_behalf_of: None,
+            references: vec![],
+            owners: vec![Owner {
+                email: Some("john@example.com".to_string()),
+                ..Owner::default()
+            }],
+        }
+    }
+
+    fn confirm_cmd() -> ConfirmRegistrationCmd {
+        ConfirmRegistrationCmd {
+            id: entity_id(),
+            entity_type: "Student".to_string(),
+            confirmed_by: Actor::new("anonymous"),
+        }
+    }
+
+    #[test]
+    fn test_registration_is_pending_with_its_owner() {
+        SimpleTestHarness::given(active_definition(
+            "Student",
+            student_schema(json!(["anonymous"])),
+        ))
+        .when(register_cmd(json!({ "email": "john@example.com" })))
+        .then_assert(|events| {
+            if let DomainEvent::EntityRegistered {
+                owners,
+                registered_by,
+                ..
+            } = &events[0]
+            {
+                assert_eq!(owners[0].email.as_deref(), Some("john@example.com"));
+                assert_eq!(registered_by, "anonymous");
+            } else {
+                panic!("Expected EntityRegistered, got {:?}", events[0]);
+            }
+        });
+    }
+
+    #[test]
+    fn test_registration_requires_an_anonymous_role() {
+        SimpleTestHarness::given(active_definition(
+            "Student",
+            student_schema(json!(["admin"])),
+        ))
+        .when(register_cmd(json!({ "email": "john@example.com" })))
+        .then_err(EntityError::SelfRegistrationNotAllowed(
+            "Student".to_string(),
+        ));
+    }
+
+    #[test]
+    fn test_registration_requires_a_contact() {
+        SimpleTestHarness::given(active_definition(
+            "Student",
+            student_schema(json!(["anonymous"])),
+        ))
+        .when(register_cmd(json!({})))
+        .then_err(EntityError::NoVerificationContact("Student".to_string()));
+    }
+
+    #[test]
+    fn test_confirmation_creates_the_entity_once() {
+        let mut history = active_definition("Student", student_schema(json!(["anonymous"])));
+        history.push(registered());
+        SimpleTestHarness::given(history.clone())
+            .when(confirm_cmd())
+            .then_assert(|events| {
+                if let DomainEvent::EntityCreated { id, owners, .. } = &events[0] {
+                    assert_eq!(id, &entity_id());
+                    assert_eq!(owners.len(), 1);
+                } else {
+                    panic!("Expected EntityCreated, got {:?}", events[0]);
+                }
+            });
+
+        let DomainEvent::EntityRegistered {
+            entity_body,
+            owners,
+            ..
+        } = registered()
+        else {
+            unreachable!()
+        };
+        history.push(DomainEvent::EntityCreated {
+            id: entity_id(),
+            registry_def_id: generate_id_from_title("Student"),
+            registry_def_version: Version::default(),
+            entity_body,
+            entity_type: "Student".to_string(),
+            created_at: get_created_at(),
+            created_by: "anonymous".to_string(),
+            on_behalf_of: None,
+            version: Version::default(),
+            references: vec![],
+            owners,
+        });
+        SimpleTestHarness::given(history)
+            .when(confirm_cmd())
+            .then_err(EntityError::RegistrationAlreadyConfirmed(entity_id()));
+    }
+
+    #[test]
+    fn test_unknown_registration_is_not_found() {
+        SimpleTestHarness::given(active_definition(
+            "Student",
+            student_schema(json!(["anonymous"])),
+        ))
+        .when(confirm_cmd())
+        .then_err(EntityError::EntityNotFound(entity_id()));
+    }
+
+    #[test]
+    fn test_confirmation_requires_an_active_definition() {
+        let mut history = active_definition("Student", student_schema(json!(["anonymous"])));
+        history.push(registered());
+        history.push(DomainEvent::DefDeactivated {
+            id: generate_id_from_title("Student"),
+            deactivated_at: get_created_at(),
+            deactivated_by: "admin".to_string(),
+            on_behalf_of: None,
+        });
+        SimpleTestHarness::given(history)
+            .when(confirm_cmd())
+            .then_err(EntityError::DefinitionNotInProperState(
+                DefRecordStatus::Active,
+                DefRecordStatus::Deactivated,
+            ));
+    }
+}
@@ -0,0 +1,94 @@
+mod common;
+
+#[cfg(test)]
+mod test {
+    use crate::common::test_harness::SimpleTestHarness;
+    use crate::common::{active_definition, entity_id};
+    use definitions_core::definitions_domain::DomainEvent;
+    use definitions_core::registry_domain::{CreateEntityCmd, EntityError};
+    use definitions_core::signing::{IssuerKey, SignedFields, SigningError};
+    use serde_json::{json, Value};
+
+    fn issuer_key() -> IssuerKey {
+        IssuerKey::from_seed("issuer-1", [7; 32])
+    }
+
+    fn insurance_schema() -> String {
+        json!({
+            "title": "Insurance",
+            "type": "object",
+            "properties": {
+                "Insurance": {
+                    "type": "object",
+                    "properties": {
+                        "policyNumber": { "type": "string" },
+                        "holder": { "type": "string" },
+                        "notes": { "type": "string" }
+                    },
+                    "required": ["policyNumber", "holder"]
+                }
+            },
+            "_osConfig": {
+                "systemFields": ["_osCreatedAt", "_osSignedData"],
+                "signedFields": ["$.policyNumber", "$.holder"]
+            }
+        })
+        .to_string()
+    }
+
+    fn create_cmd(signer: Option<IssuerKey>) -> CreateEntityCmd {
+        CreateEntityCmd {
+            id: entity_id(),
+            entity_body:
+                json!({"Insurance": {"policyNumber": "P-1", "holder": "Asha", "notes": "x"}})
+                    .to_string(),
+            entity_type: "Insurance".to_string(),
+            created_by: "test_user".into(),
+            referenced_ids: vec![],
+            cipher: None,
+            signer,
+        }
+    }
+
+    #[test]
+    fn test_create_entity_signs_signed_fields() {
+        SimpleTestHarness::given(active_definition("Insurance", insurance_schema()))
+            .when(create_cmd(Some(issuer_key())))
+            .then_assert(|events| {
+                if let DomainEvent::EntityCreated { entity_body, .. } = &events[0] {
+                    let body: Value = serde_json::from_str(entity_body).unwrap();
+                    assert!(body["Insurance"]["_osSignedData"].is_string());
+
+                    let signed_fields =
+                        SignedFields::from_schema(&insurance_schema(), "Insurance").unwrap();
+                    let keys = [issuer_key().public_key()];
+                    assert_eq!(
+                        signed_fields.verify(entity_body, None, &keys),
+                        Ok("issuer-1".to_string())
+                    );
+
+                    let mut notes_changed = body.clone();
+                    notes_changed["Insurance"]["notes"] = json!("y");
+                    assert!(signed_fields
+                        .verify(&notes_changed.to_string(), None, &keys)
+                        .is_ok());
+
+                    let mut tampered = body;
+                    tampered["Insurance"]["holder"] = json!("Mallory");
+                    assert_eq!(
+                        signed_fields.verify(&tampered.to_string(), None, &keys),
+                        Err(SigningError::PayloadMismatch)
+                    );
+                } else {
+                    panic!("Expected EntityCreated, got {:?}", events[0]);
+                }
+            });
+    }
+
+    #[test]
+    fn test_create_entity_without_signer_is_rejected() {
+        SimpleTestHarness::given(active_definition("Insurance", insurance_schema()))
+            .when(create_cmd(None))
+            .then_err(EntityError::SigningUnavailable("Insurance".to_string()));
+    }
+}
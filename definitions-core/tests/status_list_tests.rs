@@ -0,0 +1,182 @@
+mod common;
+
+#[cfg(test)]
+mod test {
+    use crate::common::test_harness::SimpleTestHarness;
+    use crate::common::{active_definition, get_created_at};
+    use definitions_core::credentials::CredentialFormat;
+    use definitions_core::definitions_domain::{generate_id_from_title, DomainEvent, Version};
+    use definitions_core::ownership::{Owner, Principal};
+    use definitions_core::registry_domain::EntityError;
+    use definitions_core::status_list::{
+        ChangeCredentialStatusCmd, CredentialStatusAction, RevokeEntityCredentialsCmd,
+    };
+    use serde_json::json;
+    use uuid::Uuid;
+
+    fn insurance_id() -> Uuid {
+        Uuid::parse_str("0196d2b4-3c2a-7d4e-8f00-000000000d01").unwrap()
+    }
+
+    fn credential_id(n: u8) -> Uuid {
+        Uuid::parse_str(&format!(
+            "0196d2b4-3c2a-7d4e-8f00-000000000d{:02x}",
+            0x10 + n
+        ))
+        .unwrap()
+    }
+
+    fn insurance_schema() -> String {
+        json!({
+            "title": "Insurance",
+            "type": "object",
+            "properties": {
+                "Insurance": {
+                    "type": "object",
+                    "properties": { "email": { "type": "string" } }
+                }
+            },
+            "_osConfig": {
+                "ownershipAttributes": [{ "email": "$.email", "userId": "$.email" }]
+            }
+        })
+        .to_string()
+    }
+
+    fn history() -> Vec<DomainEvent> {
+        let def_id = generate_id_from_title("Insurance");
+        let mut history = active_definition("Insurance", insurance_schema());
+        history.extend([
+            DomainEvent::EntityCreated {
+                id: insurance_id(),
+                registry_def_id: def_id,
+                registry_def_version: Version::default(),
+                entity_body: json!({"Insurance": {"email": "asha@example.com"}}).to_string(),
+                entity_type: "Insurance".to_string(),
+                created_at: get_created_at(),
+                created_by: "test_user".to_string(),
+                on_behalf_of: None,
+                version: Version::default(),
+                references: vec![],
+                owners: vec![Owner {
+                    user_id: None,
+                    email: Some("asha@example.com".to_string()),
+                    mobile: None,
+                }],
+            },
+            credential_issued(1, Some(0)),
+            credential_issued(2, Some(1)),
+        ]);
+        history
+    }
+
+    fn credential_issued(n: u8, status_list_index: Option<u64>) -> DomainEvent {
+        DomainEvent::CredentialIssued {
+            id: insurance_id(),
+            credential_id: credential_id(n),
+            entity_type: "Insurance".to_string(),
+            policy_name: None,
+            format: CredentialFormat::LdpVc,
+            credential: "{}".to_string(),
+            encrypted: false,
+            status_list_index,
+            issued_at: get_created_at(),
+            issued_by: "auth0|asha@example.com".to_string(),
+            on_behalf_of: None,
+        }
+    }
+
+    fn status_cmd(n: u8, action: CredentialStatusAction) -> ChangeCredentialStatusCmd {
+        ChangeCredentialStatusCmd {
+            credential_id: credential_id(n),
+            entity_id: insurance_id(),
+            entity_type: "Insurance".to_string(),
+            action,
+            reason: Some("policy expired".to_string()),
+            principal: Principal {
+                subject: "auth0|asha@example.com".to_string(),
+                email: Some("asha@example.com".to_string()),
+                ..Default::default()
+            },
+        }
+    }
+
+    fn revoked(n: u8, status_list_index: u64) -> DomainEvent {
+        DomainEvent::CredentialRevoked {
+            id: insurance_id(),
+            credential_id: credential_id(n),
+            status_list_index,
+            reason: Some("policy expired".to_string()),
+            revoked_at: get_created_at(),
+            revoked_by: "auth0|asha@example.com".to_string(),
+            on_behalf_of: None,
+        }
+    }
+
+    #[test]
+    fn test_revoke_sets_the_index_of_the_credential() {
+        SimpleTestHarness::given(history())
+            .when(status_cmd(2, CredentialStatusAction::Revoke))
+            .then_assert(|events| {
+                assert!(matches!(
+                    events[..],
+                    [DomainEvent::CredentialRevoked {
+                        status_list_index: 1,
+                        ..
+                    }]
+                ));
+            });
+    }
+
+    #[test]
+    fn test_revoked_credentials_cannot_be_changed() {
+        let mut given = history();
+        given.push(revoked(1, 0));
+        SimpleTestHarness::given(given)
+            .when(status_cmd(1, CredentialStatusAction::Suspend))
+            .then_err(EntityError::CredentialStatusNotChanged(
+                credential_id(1),
+                "suspend".to_string(),
+                "it has been revoked".to_string(),
+            ));
+    }
+
+    #[test]
+    fn test_reinstate_requires_a_suspension() {
+        SimpleTestHarness::given(history())
+            .when(status_cmd(1, CredentialStatusAction::Reinstate))
+            .then_err(EntityError::CredentialStatusNotChanged(
+                credential_id(1),
+                "reinstate".to_string(),
+                "it is not suspended".to_string(),
+            ));
+    }
+
+    #[test]
+    fn test_unknown_credentials_are_not_found() {
+        SimpleTestHarness::given(history())
+            .when(status_cmd(3, CredentialStatusAction::Revoke))
+            .then_err(EntityError::CredentialNotFound(credential_id(3)));
+    }
+
+    #[test]
+    fn test_deleted_entities_revoke_remaining_credentials() {
+        let mut given = history();
+        given.push(revoked(1, 0));
+        given.push(credential_issued(3, None));
+        SimpleTestHarness::given(given)
+            .when(RevokeEntityCredentialsCmd {
+                id: insurance_id(),
+                reason: None,
+                revoked_by: "credential_revocation".into(),
+            })
+            .then_assert(|events| {
+                assert_eq!(events.len(), 1);
+                assert!(matches!(
+                    &events[0],
+                    DomainEvent::CredentialRevoked { credential_id: id, status_list_index: 1, .. }
+                        if *id == credential_id(2)
+                ));
+            });
+    }
+}
@@ -0,0 +1,130 @@
+mod common;
+
+#[cfg(test)]
+mod test {
+    use crate::common::test_harness::SimpleTestHarness;
+    use crate::common::{active_definition, entity_id, get_created_at};
+    use chrono::SecondsFormat;
+    use definitions_core::definitions_domain::{generate_id_from_title, DomainEvent, Version};
+    use definitions_core::ownership::Principal;
+    use definitions_core::registry_domain::{CreateEntityCmd, ModifyEntityCmd};
+    use serde_json::{json, Value};
+
+    fn course_schema() -> String {
+        json!({
+            "title": "Course",
+            "type": "object",
+            "properties": {
+                "Course": {
+                    "type": "object",
+                    "properties": { "name": { "type": "string" } },
+                    "required": ["name"],
+                    "additionalProperties": false
+                }
+            },
+            "_osConfig": {
+                "systemFields": ["_osCreatedAt", "_osUpdatedAt", "_osCreatedBy", "_osUpdatedBy"]
+            }
+        })
+        .to_string()
+    }
+
+    #[test]
+    fn test_create_entity_injects_system_fields() {
+        SimpleTestHarness::given(active_definition("Course", course_schema()))
+            .when(CreateEntityCmd {
+                id: entity_id(),
+                entity_body: json!({"Course": {"name": "Rust", "_osCreatedBy": "mallory"}})
+                    .to_string(),
+                entity_type: "Course".to_string(),
+                created_by: "alice".into(),
+                referenced_ids: vec![],
+                cipher: None,
+                signer: None,
+            })
+            .then_assert(|events| {
+                if let DomainEvent::EntityCreated {
+                    entity_body,
+                    created_at,
+                    ..
+                } = &events[0]
+                {
+                    let body: Value = serde_json::from_str(entity_body).unwrap();
+                    let timestamp = created_at.to_rfc3339_opts(SecondsFormat::Millis, true);
+                    assert_eq!(
+                        body,
+                        json!({"Course": {
+                            "name": "Rust",
+                            "_osCreatedAt": timestamp,
+                            "_osUpdatedAt": timestamp,
+                            "_osCreatedBy": "alice",
+                            "_osUpdatedBy": "alice"
+                        }})
+                    );
+                } else {
+                    panic!("Expected EntityCreated, got {:?}", events[0]);
+                }
+            });
+    }
+
+    #[test]
+    fn test_modify_entity_preserves_creation_fields() {
+        let mut history = active_definition("Course", course_schema());
+        history.push(DomainEvent::EntityCreated {
+            id: entity_id(),
+            registry_def_id: generate_id_from_title("Course"),
+            registry_def_version: Version::default(),
+            entity_body: json!({"Course": {
+                "name": "Rust",
+                "_osCreatedAt": "2025-01-01T10:00:00.000Z",
+                "_osUpdatedAt": "2025-01-01T10:00:00.000Z",
+                "_osCreatedBy": "alice",
+                "_osUpdatedBy": "alice"
+            }})
+            .to_string(),
+            entity_type: "Course".to_string(),
+            created_at: get_created_at(),
+            created_by: "alice".to_string(),
+            on_behalf_of: None,
+            version: Version::default(),
+            references: vec![],
+            owners: vec![],
+        });
+        SimpleTestHarness::given(history)
+            .when(ModifyEntityCmd {
+                id: entity_id(),
+                entity_body: json!({"Course": {"name": "Advanced Rust", "_osCreatedAt": "1970-01-01T00:00:00.000Z"}})
+                    .to_string(),
+                entity_type: "Course".to_string(),
+                principal: Principal {
+                    subject: "bob".to_string(),
+                    ..Principal::default()
+                },
+                referenced_ids: vec![],
+                cipher: None,
+                signer: None,
+            })
+            .then_assert(|events| {
+                if let DomainEvent::EntityUpdated {
+                    entity_body,
+                    updated_at,
+                    ..
+                } = &events[0]
+                {
+                    let body: Value = serde_json::from_str(entity_body).unwrap();
+                    assert_eq!(
+                        body,
+                        json!({"Course": {
+                            "name": "Advanced Rust",
+                            "_osCreatedAt": "2025-01-01T10:00:00.000Z",
+                            "_osUpdatedAt": updated_at.to_rfc3339_opts(SecondsFormat::Millis, true),
+                            "_osCreatedBy": "alice",
+                            "_osUpdatedBy": "bob"
+                        }})
+                    );
+                } else {
+                    panic!("Expected EntityUpdated, got {:?}", events[0]);
+                }
+            });
+    }
+}
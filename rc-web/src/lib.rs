@@ -7,7 +7,10 @@ use definitions_core::definitions_domain::{DefError, DomainEvent};
 use definitions_core::registry_domain::EntityError;
 use disintegrate::{DecisionError, NoSnapshot};
 use disintegrate_postgres::PgDecisionMaker;
+use middleware::authorization::AccessError;
 use serde::Serialize;
+use services::api_keys::ApiKeyError;
+use services::registration_codes::RegistrationError;
 
 pub mod config;
 pub mod errors;
@@ -29,6 +32,15 @@ pub enum DError {
 
     #[error(transparent)]
     Entity(#[from] DecisionError<EntityError>),
+
+    #[error(transparent)]
+    Access(#[from] AccessError),
+
+    #[error(transparent)]
+    ApiKey(#[from] ApiKeyError),
+
+    #[error(transparent)]
+    Registration(#[from] RegistrationError),
     // You may have other variants as needed
 }
 
@@ -46,12 +58,67 @@ impl error::ResponseError for DError {
             },
             DError::Entity(entity_error) => match entity_error {
                 DecisionError::Domain(entity_error) => match entity_error {
-                    EntityError::EntityAlreadyExists(..) => StatusCode::CONFLICT,
+                    EntityError::EntityAlreadyExists(..)
+                    | EntityError::ClaimAlreadyExists(..)
+                    | EntityError::ClaimAlreadyClosed(..)
+                    | EntityError::CredentialNotAvailable(..)
+                    | EntityError::CredentialStatusNotChanged(..)
+                    | EntityError::CredentialNotPresentable(..)
+                    | EntityError::StatusListExhausted(..)
+                    | EntityError::CertificateNotAvailable(..)
+                    | EntityError::ConsentAlreadyExists(..)
+                    | EntityError::ConsentNotChanged(..)
+                    | EntityError::RegistrationAlreadyConfirmed(..) => StatusCode::CONFLICT,
+                    EntityError::EntityNotFound(..)
+                    | EntityError::ClaimNotFound(..)
+                    | EntityError::AttestationPolicyNotFound(..)
+                    | EntityError::CredentialTemplateNotFound(..)
+                    | EntityError::CredentialNotFound(..)
+                    | EntityError::CertificateTemplateNotFound(..)
+                    | EntityError::ConsentNotFound(..) => StatusCode::NOT_FOUND,
+                    EntityError::NotAuthorized(..)
+                    | EntityError::NotAnAttestor(..)
+                    | EntityError::AttestationConditionNotMet(..)
+                    | EntityError::ConsentNotGranted(..)
+                    | EntityError::ConsentExpired(..)
+                    | EntityError::SelfRegistrationNotAllowed(..) => StatusCode::FORBIDDEN,
+                    EntityError::Encryption(..)
+                    | EntityError::EncryptionUnavailable(..)
+                    | EntityError::SigningUnavailable(..) => StatusCode::INTERNAL_SERVER_ERROR,
                     _ => StatusCode::BAD_REQUEST,
                 },
                 DecisionError::EventStore(_) => StatusCode::INTERNAL_SERVER_ERROR,
                 DecisionError::StateStore(_) => StatusCode::INTERNAL_SERVER_ERROR,
             },
+            DError::Access(access_error) => match access_error {
+                AccessError::Unauthenticated(..) => StatusCode::UNAUTHORIZED,
+                AccessError::Forbidden(..) => StatusCode::FORBIDDEN,
+                AccessError::UnknownEntityType(..) => StatusCode::NOT_FOUND,
+            },
+            DError::ApiKey(api_key_error) => match api_key_error {
+                ApiKeyError::AccountNotFound(..) | ApiKeyError::KeyNotFound(..) => {
+                    StatusCode::NOT_FOUND
+                }
+                ApiKeyError::NameTaken(..) | ApiKeyError::AccountDisabled(..) => {
+                    StatusCode::CONFLICT
+                }
+                ApiKeyError::InvalidExpiry(_) => StatusCode::BAD_REQUEST,
+                ApiKeyError::Database(_) => StatusCode::INTERNAL_SERVER_ERROR,
+                _ => StatusCode::UNAUTHORIZED,
+            },
+            DError::Registration(registration_error) => match registration_error {
+                RegistrationError::NotFound(..) => StatusCode::NOT_FOUND,
+                RegistrationError::Expired(..) | RegistrationError::InvalidCode => {
+                    StatusCode::BAD_REQUEST
+                }
+                RegistrationError::TooManyAttempts(..) | RegistrationError::ResendTooSoon(..) => {
+                    StatusCode::TOO_MANY_REQUESTS
+                }
+                RegistrationError::Notification(_) => StatusCode::BAD_GATEWAY,
+                RegistrationError::Template(_) | RegistrationError::Database(_) => {
+                    StatusCode::INTERNAL_SERVER_ERROR
+                }
+            },
         }
     }
 
@@ -2,19 +2,35 @@ use actix_web::web::Data;
 use actix_web::{web, App};
 use anyhow::Context;
 use definitions_core::definitions_domain::*;
+use definitions_core::did::{did_web, IssuerIdentity};
+use definitions_core::encryption::FieldCipher;
+use definitions_core::verifiers::Verifiers;
 use disintegrate::NoSnapshot;
 use disintegrate_postgres::{PgEventListener, PgEventListenerConfig, PgEventStore};
 use log::error;
+use rc_web::projections::auto_attestation::AutoAttestationJob;
+use rc_web::projections::credential_revocation::CredentialRevocationJob;
 use rc_web::projections::definitions_read_model;
 use rc_web::projections::definitions_read_model::ReadModelProjection;
-use rc_web::routes::{api_routes, health_check};
-use rc_web::{middleware, COMMANDS, DEFINITIONS, ENTITY, HEALTH, QUERY};
+use rc_web::projections::entity_migration::EntityMigrationJob;
+use rc_web::projections::notifications::NotificationJob;
+use rc_web::routes::{api_routes, did_routes, health_check};
+use rc_web::services::api_keys::ApiKeyStore;
+use rc_web::services::blob_store::{BlobStore, FileBlobStore};
+use rc_web::services::did_resolver::DidResolver;
+use rc_web::services::keystore::FileKeyStore;
+use rc_web::services::notification_outbox::NotificationOutbox;
+use rc_web::services::notification_templates::NotificationTemplates;
+use rc_web::services::notifier::{notifier_from_env, Notifier};
+use rc_web::services::registration_codes::RegistrationCodes;
+use rc_web::services::token_verifier::TokenVerifier;
+use rc_web::{base_url, middleware, COMMANDS, DEFINITIONS, ENTITY, HEALTH, QUERY};
 use sqlx::postgres::PgConnectOptions;
 use sqlx::PgPool;
 use std::env;
 use std::sync::Arc;
 use std::time::Duration;
-use utoipa::openapi::security::{HttpAuthScheme, SecurityScheme};
+use utoipa::openapi::security::{ApiKey, ApiKeyValue, HttpAuthScheme, SecurityScheme};
 use utoipa::OpenApi;
 use utoipa_rapidoc::RapiDoc;
 use utoipa_scalar::{Scalar, Servable};
@@ -23,23 +39,60 @@ use utoipa_swagger_ui::SwaggerUi;
 #[derive(OpenApi)]
 #[openapi(
     modifiers(&SecurityAddon),
-    security(("bearer_auth" = [])),
+    security(("bearer_auth" = []), ("api_key" = [])),
     info(
         title = "RC Web API",
         description = "Rest endpoints for the RC Web API",
     ),
     paths(
         rc_web::routes::definition_routes::create_def,
+        rc_web::routes::definition_routes::update_def,
+        rc_web::routes::definition_routes::impact_analysis,
         rc_web::routes::definition_routes::activate_def,
         rc_web::routes::definition_routes::get_definitions,
         rc_web::routes::definition_routes::get_definitions_by_id,
+        rc_web::routes::definition_routes::get_migration_report,
+        rc_web::routes::definition_routes::get_definition_history,
         rc_web::routes::entity_routes::create_entity,
+        rc_web::routes::entity_routes::update_entity,
+        rc_web::routes::entity_routes::get_my_entities,
+        rc_web::routes::signature_routes::verify_signature,
+        rc_web::routes::signature_routes::get_signing_keys,
+        rc_web::routes::claim_routes::raise_claim,
+        rc_web::routes::claim_routes::get_claims,
+        rc_web::routes::claim_routes::attest_claim,
+        rc_web::routes::credential_routes::issue_credential,
+        rc_web::routes::credential_routes::get_credentials,
+        rc_web::routes::credential_routes::change_credential_status,
+        rc_web::routes::credential_routes::get_status_list,
+        rc_web::routes::consent_routes::request_consent,
+        rc_web::routes::consent_routes::get_consents,
+        rc_web::routes::consent_routes::decide_consent,
+        rc_web::routes::consent_routes::get_consented_data,
+        rc_web::routes::consent_routes::get_consent_access_log,
+        rc_web::routes::wallet_routes::get_wallet_credentials,
+        rc_web::routes::wallet_routes::present_credential,
+        rc_web::routes::verification_routes::verify,
+        rc_web::routes::verification_routes::verify_issued_credential,
+        rc_web::routes::certificate_routes::get_certificate,
+        rc_web::routes::certificate_routes::put_certificate_template,
+        rc_web::routes::did_routes::get_did_document,
         rc_web::routes::health_check::hello,
         rc_web::routes::health_check::echo,
         rc_web::routes::health_check::healthz,
         rc_web::routes::health_check::readyz,
         rc_web::routes::entity_routes::get_entities,
-        rc_web::routes::entity_routes::get_entity_by_id
+        rc_web::routes::entity_routes::get_entity_by_id,
+        rc_web::routes::entity_routes::get_entity_history,
+        rc_web::routes::registration_routes::register_entity,
+        rc_web::routes::registration_routes::confirm_registration,
+        rc_web::routes::registration_routes::resend_registration_code,
+        rc_web::routes::service_account_routes::create_service_account,
+        rc_web::routes::service_account_routes::get_service_accounts,
+        rc_web::routes::service_account_routes::rotate_api_key,
+        rc_web::routes::service_account_routes::revoke_api_key,
+        rc_web::routes::service_account_routes::disable_service_account,
+        rc_web::routes::notification_routes::get_notifications
     ),
     tags(
     (name = DEFINITIONS, description = "Manage Definitions and Schemas"),
@@ -53,7 +106,7 @@ use utoipa_swagger_ui::SwaggerUi;
 )]
 pub struct ApiDoc;
 
-/// Add the bearer_auth security scheme
+/// Add the bearer_auth and api_key security schemes and the permissions they carry
 struct SecurityAddon;
 
 impl utoipa::Modify for SecurityAddon {
@@ -64,9 +117,22 @@ impl utoipa::Modify for SecurityAddon {
                 utoipa::openapi::security::HttpBuilder::new()
                     .scheme(HttpAuthScheme::Bearer)
                     .bearer_format("JWT")
+                    .description(Some(
+                        "Definition commands require the `write:definitions` permission, \
+                         `admin:registry` grants every operation. Entities require one of the \
+                         `roles` of their definition, `anonymous` making them public.",
+                    ))
                     .build(),
             ),
         );
+        openapi.components.as_mut().unwrap().add_security_scheme(
+            "api_key",
+            SecurityScheme::ApiKey(ApiKey::Header(ApiKeyValue::with_description(
+                "X-API-Key",
+                "API key of a service account, granted the scopes and roles of the account \
+                 in place of the permissions and roles of a bearer token.",
+            ))),
+        );
     }
 }
 
@@ -107,14 +173,72 @@ async fn main() -> anyhow::Result<()> {
         event_store.clone(),
         NoSnapshot,
     ));
+    let keystore = FileKeyStore::from_env().context("Failed to open the keystore")?;
+    if let Ok(max_age) = env::var("ISSUER_KEY_MAX_AGE_DAYS") {
+        let max_age = max_age
+            .parse::<i64>()
+            .context("ISSUER_KEY_MAX_AGE_DAYS must be a number of days")?;
+        keystore
+            .rotate_issuer_key_older_than(chrono::Duration::days(max_age))
+            .context("Failed to rotate the issuer key")?;
+    }
+    let issuer_key = keystore
+        .issuer_key()
+        .context("Failed to load the issuer key")?;
+    let issuer_identity = IssuerIdentity::new(
+        did_web(base_url()).context("Failed to derive the registry DID")?,
+        issuer_key.clone(),
+        keystore
+            .retired_issuer_keys()
+            .context("Failed to load the retired issuer keys")?,
+    );
+    let cipher = FieldCipher::new(Arc::new(keystore));
+    let blob_store: Arc<dyn BlobStore> = Arc::new(FileBlobStore::from_env());
+    let token_verifier =
+        Data::new(TokenVerifier::from_env().context("Failed to configure the token verifier")?);
+    token_verifier
+        .jwks_cache()
+        .spawn_refresh(Duration::from_secs(60));
+    let api_key_store = ApiKeyStore::new(shared_pool.clone())
+        .await
+        .context("Failed to prepare the service account tables")?;
+    let notification_templates = Arc::new(
+        NotificationTemplates::from_env().context("Failed to load the notification templates")?,
+    );
+    let registration_codes =
+        RegistrationCodes::new(shared_pool.clone(), notification_templates.clone())
+            .await
+            .context("Failed to prepare the registration codes table")?;
+    let notifier: Arc<dyn Notifier> =
+        notifier_from_env().context("Failed to configure the notifier")?;
+    let notification_outbox = NotificationOutbox::new(shared_pool.clone())
+        .await
+        .context("Failed to prepare the notifications table")?;
+    notification_outbox.spawn_delivery(notifier.clone(), Duration::from_secs(5));
     let api = Arc::new(ApiDoc::openapi());
     let client_origin_url = Arc::new(client_origin_url);
 
     let listener_event_store = event_store.clone();
     let listener_pool = shared_pool.clone();
+    let listener_decision_maker = (*decision_maker).clone();
+    let listener_cipher = cipher.clone();
+    let listener_issuer_key = issuer_key.clone();
+    let auto_attestation_job = AutoAttestationJob::new(
+        (*decision_maker).clone(),
+        cipher.clone(),
+        Verifiers::builtin(),
+    );
+    let credential_revocation_job = CredentialRevocationJob::new((*decision_maker).clone());
+    let notification_job = NotificationJob::new(
+        shared_pool.clone(),
+        notification_outbox.clone(),
+        notification_templates,
+    )
+    .await
+    .context("Failed to prepare the notification listener")?;
 
     tokio::spawn(async move {
-        let listener = match ReadModelProjection::new(listener_pool).await {
+        let listener = match ReadModelProjection::new(listener_pool.clone()).await {
             Ok(listener) => listener,
             Err(e) => {
                 error!("Failed to create ReadModelProjection: {}", e);
@@ -122,11 +246,43 @@ async fn main() -> anyhow::Result<()> {
             }
         };
 
+        let migration_job = match EntityMigrationJob::new(
+            listener_pool,
+            listener_event_store.clone(),
+            listener_decision_maker,
+            listener_cipher,
+            listener_issuer_key,
+        )
+        .await
+        {
+            Ok(migration_job) => migration_job,
+            Err(e) => {
+                error!("Failed to create EntityMigrationJob: {}", e);
+                return;
+            }
+        };
+
         if let Err(e) = PgEventListener::builder(listener_event_store)
             .register_listener(
                 listener,
                 PgEventListenerConfig::poller(Duration::from_millis(5000)).with_notifier(),
             )
+            .register_listener(
+                migration_job,
+                PgEventListenerConfig::poller(Duration::from_millis(5000)).with_notifier(),
+            )
+            .register_listener(
+                auto_attestation_job,
+                PgEventListenerConfig::poller(Duration::from_millis(5000)).with_notifier(),
+            )
+            .register_listener(
+                credential_revocation_job,
+                PgEventListenerConfig::poller(Duration::from_millis(5000)).with_notifier(),
+            )
+            .register_listener(
+                notification_job,
+                PgEventListenerConfig::poller(Duration::from_millis(5000)).with_notifier(),
+            )
             .start_with_shutdown(definitions_read_model::shutdown())
             .await
         {
@@ -139,11 +295,25 @@ async fn main() -> anyhow::Result<()> {
         let decision_maker = Arc::clone(&decision_maker);
         let api = Arc::clone(&api);
         let client_origin_url = Arc::clone(&client_origin_url);
+        let cipher = cipher.clone();
+        let issuer_key = issuer_key.clone();
+        let issuer_identity = issuer_identity.clone();
+        let token_verifier = token_verifier.clone();
 
         move || {
             App::new()
                 .app_data(Data::new((*decision_maker).clone()))
                 .app_data(Data::new((*shared_pool_for_web).clone()))
+                .app_data(Data::new(cipher.clone()))
+                .app_data(Data::new(issuer_key.clone()))
+                .app_data(Data::new(issuer_identity.clone()))
+                .app_data(Data::new(DidResolver::new()))
+                .app_data(token_verifier.clone())
+                .app_data(Data::new(api_key_store.clone()))
+                .app_data(Data::new(registration_codes.clone()))
+                .app_data(Data::from(notifier.clone()))
+                .app_data(Data::new(notification_outbox.clone()))
+                .app_data(Data::from(blob_store.clone()))
                 .service(
                     SwaggerUi::new("/swagger-ui/{_:.*}")
                         .url("/api-docs/openapi.json", (*api).clone()),
@@ -153,6 +323,7 @@ async fn main() -> anyhow::Result<()> {
                 .service(api_routes::routes())
                 .service(
                     web::scope("")
+                        .service(did_routes::routes())
                         .service(health_check::routes())
                         .wrap(middleware::security_headers::security_headers())
                         .wrap(middleware::logger::logger())
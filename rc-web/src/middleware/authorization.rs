@@ -0,0 +1,183 @@
+//! Authorization of the schema, certificate template and entity routes.
+//!
+//! Definition commands, and storing the certificate templates definitions render, require the
+//! [`WRITE_DEFINITIONS`] permission, definitions themselves are readable by everyone. Entities are guarded by the `_osConfig` of their definition:
+//! - create: one of the `roles` or `inviteRoles`
+//! - read: one of the `roles`, or owning the entity when it is read by id
+//! - update: one of the `roles` or owning the entity, checked by the `ModifyEntityCmd`
+//!
+//! `anonymous` in a role list opens the operation to unauthenticated callers, definitions
+//! declaring no roles are open to every authenticated caller. [`ADMIN_PERMISSION`] grants every
+//! operation.
+use crate::middleware::claims::Claims;
+use definitions_core::ownership::{
+    declared_invite_roles, declared_roles, Principal, ANONYMOUS_ROLE,
+};
+use derive_more::Display;
+use serde_json::Value;
+use std::collections::HashSet;
+use thiserror::Error;
+
+/// Permission required by the definition commands
+pub const WRITE_DEFINITIONS: &str = "write:definitions";
+/// Permission granting every operation of the registry
+pub const ADMIN_PERMISSION: &str = "admin:registry";
+
+#[derive(Debug, Error, PartialEq, Eq)]
+pub enum AccessError {
+    #[error("Authentication is required to {0}")]
+    Unauthenticated(String),
+    #[error("`{0}` is not allowed to {1}")]
+    Forbidden(String, String),
+    #[error("Entity type `{0}` is not defined")]
+    UnknownEntityType(String),
+}
+
+#[derive(Debug, Display, Clone, Copy, PartialEq, Eq)]
+pub enum EntityOperation {
+    #[display("create")]
+    Create,
+    #[display("read")]
+    Read,
+}
+
+/// Checks that the caller holds `permission`, or the admin permission, to perform `action`
+pub fn require_permission(
+    claims: &Claims,
+    permission: &str,
+    action: &str,
+) -> Result<(), AccessError> {
+    let granted = [permission, ADMIN_PERMISSION]
+        .iter()
+        .any(|granting| claims.validate_permissions(&HashSet::from([granting.to_string()])));
+    if granted {
+        Ok(())
+    } else {
+        Err(AccessError::Forbidden(
+            claims.sub.clone().unwrap_or_default(),
+            action.to_string(),
+        ))
+    }
+}
+
+/// Roles of a definition allowed to perform `operation`
+fn operation_roles(schema: &Value, operation: EntityOperation) -> Vec<String> {
+    let mut roles = declared_roles(schema);
+    if operation == EntityOperation::Create {
+        roles.extend(declared_invite_roles(schema));
+    }
+    roles
+}
+
+/// Checks that the caller may perform `operation` on the entities of the definition `schema`,
+/// given by its role. Ownership of a single entity is checked by the caller of this function.
+pub fn authorize_entity(
+    principal: Option<&Principal>,
+    schema: &Value,
+    entity_type: &str,
+    operation: EntityOperation,
+) -> Result<(), AccessError> {
+    let roles = operation_roles(schema, operation);
+    let action = || format!("{} {} entities", operation, entity_type);
+    if roles.iter().any(|role| role == ANONYMOUS_ROLE) {
+        return Ok(());
+    }
+    let Some(principal) = principal else {
+        return Err(AccessError::Unauthenticated(action()));
+    };
+    if roles.is_empty()
+        || principal.has_any_role(&roles)
+        || principal
+            .permissions
+            .iter()
+            .any(|permission| permission == ADMIN_PERMISSION)
+    {
+        Ok(())
+    } else {
+        Err(AccessError::Forbidden(principal.subject.clone(), action()))
+    }
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn principal(roles: &[&str], permissions: &[&str]) -> Principal {
+        Principal {
+            subject: "user-1".to_string(),
+            roles: roles.iter().map(|role| role.to_string()).collect(),
+            permissions: permissions.iter().map(|p| p.to_string()).collect(),
+            ..Principal::default()
+        }
+    }
+
+    #[test]
+    fn test_entity_operations_follow_definition_roles() {
+        let schema = json!({
+            "title": "Insurance",
+            "_osConfig": { "roles": ["Official"], "inviteRoles": ["Agent"] }
+        });
+        let agent = principal(&["Agent"], &[]);
+        let official = principal(&["Official"], &[]);
+
+        assert!(
+            authorize_entity(Some(&agent), &schema, "Insurance", EntityOperation::Create).is_ok()
+        );
+        assert_eq!(
+            authorize_entity(Some(&agent), &schema, "Insurance", EntityOperation::Read),
+            Err(AccessError::Forbidden(
+                "user-1".to_string(),
+                "read Insurance entities".to_string()
+            ))
+        );
+        assert!(
+            authorize_entity(Some(&official), &schema, "Insurance", EntityOperation::Read).is_ok()
+        );
+        assert_eq!(
+            authorize_entity(None, &schema, "Insurance", EntityOperation::Read),
+            Err(AccessError::Unauthenticated(
+                "read Insurance entities".to_string()
+            ))
+        );
+        let admin = principal(&[], &[ADMIN_PERMISSION]);
+        assert!(
+            authorize_entity(Some(&admin), &schema, "Insurance", EntityOperation::Read).is_ok()
+        );
+    }
+
+    #[test]
+    fn test_only_anonymous_definitions_are_public() {
+        let public = json!({ "_osConfig": { "roles": ["anonymous"] } });
+        for operation in [EntityOperation::Create, EntityOperation::Read] {
+            assert!(authorize_entity(None, &public, "Teacher", operation).is_ok());
+        }
+
+        let unrestricted = json!({ "title": "Teacher" });
+        let caller = principal(&[], &[]);
+        for operation in [EntityOperation::Create, EntityOperation::Read] {
+            assert_eq!(
+                authorize_entity(None, &unrestricted, "Teacher", operation),
+                Err(AccessError::Unauthenticated(format!(
+                    "{} Teacher entities",
+                    operation
+                )))
+            );
+            assert!(authorize_entity(Some(&caller), &unrestricted, "Teacher", operation).is_ok());
+        }
+    }
+
+    #[test]
+    fn test_definition_commands_require_write_permission() {
+        let mut claims = Claims {
+            sub: Some("user-1".to_string()),
+            ..Claims::default()
+        };
+        assert!(require_permission(&claims, WRITE_DEFINITIONS, "create definitions").is_err());
+        claims.permissions = Some(HashSet::from([WRITE_DEFINITIONS.to_string()]));
+        assert!(require_permission(&claims, WRITE_DEFINITIONS, "create definitions").is_ok());
+        claims.permissions = Some(HashSet::from([ADMIN_PERMISSION.to_string()]));
+        assert!(require_permission(&claims, WRITE_DEFINITIONS, "create definitions").is_ok());
+    }
+}
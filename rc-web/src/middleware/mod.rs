@@ -1,3 +1,4 @@
+pub mod authorization;
 pub mod claims;
 pub mod cors;
 pub mod logger;
@@ -1,4 +1,6 @@
+use definitions_core::migration::MigrationSpec;
 use serde::{Deserialize, Serialize};
+use serde_json::Value;
 use utoipa::ToSchema;
 use validator::Validate;
 
@@ -13,3 +15,12 @@ pub struct ValidateDefRequest {
     ))]
     pub id: String,
 }
+
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct UpdateDefRequest {
+    /// The new version of the schema, the title must not change
+    #[schema(value_type = Object)]
+    pub json_schema: Value,
+    /// Migration applied to existing entities once the new version is activated
+    pub migration: Option<MigrationSpec>,
+}
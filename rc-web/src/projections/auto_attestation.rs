@@ -0,0 +1,76 @@
+use async_trait::async_trait;
+use definitions_core::audit::Actor;
+use definitions_core::auto_attestation::AutoAttestEntityCmd;
+use definitions_core::definitions_domain::DomainEvent;
+use definitions_core::encryption::FieldCipher;
+use definitions_core::verifiers::Verifiers;
+use disintegrate::{query, EventListener, PersistedEvent, StreamQuery};
+use disintegrate_postgres::PgEventId;
+use log::{debug, error};
+
+use crate::DecisionMaker;
+
+pub const AUTO_ATTESTED_BY: &str = "auto_attestation";
+
+/// Background job that runs the `autoAttestationPolicies` of a definition.
+///
+/// Every `EntityCreated` and `EntityUpdated` is followed by an `AutoAttestEntityCmd`, which
+/// verifies the policy values with the registered verifier plugins and emits one
+/// `EntityAutoAttested` event per attested property.
+pub struct AutoAttestationJob {
+    query: StreamQuery<PgEventId, DomainEvent>,
+    decision_maker: DecisionMaker,
+    cipher: FieldCipher,
+    verifiers: Verifiers,
+}
+
+impl AutoAttestationJob {
+    pub fn new(decision_maker: DecisionMaker, cipher: FieldCipher, verifiers: Verifiers) -> Self {
+        Self {
+            query: query!(DomainEvent),
+            decision_maker,
+            cipher,
+            verifiers,
+        }
+    }
+}
+
+#[async_trait]
+impl EventListener<i64, DomainEvent> for AutoAttestationJob {
+    type Error = sqlx::Error;
+    fn id(&self) -> &'static str {
+        "auto_attestation"
+    }
+
+    fn query(&self) -> &StreamQuery<PgEventId, DomainEvent> {
+        &self.query
+    }
+
+    async fn handle(&self, event: PersistedEvent<i64, DomainEvent>) -> Result<(), Self::Error> {
+        let (id, entity_type) = match event.into_inner() {
+            DomainEvent::EntityCreated {
+                id, entity_type, ..
+            }
+            | DomainEvent::EntityUpdated {
+                id, entity_type, ..
+            } => (id, entity_type),
+            _ => return Ok(()),
+        };
+        let auto_attest_cmd = AutoAttestEntityCmd {
+            id,
+            entity_type,
+            cipher: Some(self.cipher.clone()),
+            verifiers: self.verifiers.clone(),
+            attested_by: Actor::new(AUTO_ATTESTED_BY),
+        };
+        match self.decision_maker.make(auto_attest_cmd).await {
+            Ok(events) => debug!(
+                "Auto attestation of entity {} emitted {} events",
+                id,
+                events.len()
+            ),
+            Err(e) => error!("Auto attestation of entity {} failed: {}", id, e),
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,61 @@
+use async_trait::async_trait;
+use definitions_core::audit::Actor;
+use definitions_core::definitions_domain::DomainEvent;
+use definitions_core::status_list::RevokeEntityCredentialsCmd;
+use disintegrate::{query, EventListener, PersistedEvent, StreamQuery};
+use disintegrate_postgres::PgEventId;
+use log::{debug, error};
+
+use crate::DecisionMaker;
+
+pub const CREDENTIALS_REVOKED_BY: &str = "credential_revocation";
+
+/// Background job that revokes the credentials of deleted entities.
+///
+/// Every `EntityDeleted` is followed by a `RevokeEntityCredentialsCmd`, which sets the
+/// revocation bit of the credentials of the entity that are not revoked yet.
+pub struct CredentialRevocationJob {
+    query: StreamQuery<PgEventId, DomainEvent>,
+    decision_maker: DecisionMaker,
+}
+
+impl CredentialRevocationJob {
+    pub fn new(decision_maker: DecisionMaker) -> Self {
+        Self {
+            query: query!(DomainEvent),
+            decision_maker,
+        }
+    }
+}
+
+#[async_trait]
+impl EventListener<i64, DomainEvent> for CredentialRevocationJob {
+    type Error = sqlx::Error;
+    fn id(&self) -> &'static str {
+        "credential_revocation"
+    }
+
+    fn query(&self) -> &StreamQuery<PgEventId, DomainEvent> {
+        &self.query
+    }
+
+    async fn handle(&self, event: PersistedEvent<i64, DomainEvent>) -> Result<(), Self::Error> {
+        let DomainEvent::EntityDeleted { id, .. } = event.into_inner() else {
+            return Ok(());
+        };
+        let revoke_cmd = RevokeEntityCredentialsCmd {
+            id,
+            reason: Some("entity deleted".to_string()),
+            revoked_by: Actor::new(CREDENTIALS_REVOKED_BY),
+        };
+        match self.decision_maker.make(revoke_cmd).await {
+            Ok(events) => debug!(
+                "Revoked {} credentials of deleted entity {}",
+                events.len(),
+                id
+            ),
+            Err(e) => error!("Revoking the credentials of entity {} failed: {}", id, e),
+        }
+        Ok(())
+    }
+}
@@ -1,5 +1,13 @@
 use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use definitions_core::attestation::{ClaimId, ClaimStatus};
+use definitions_core::audit::AuditEntry;
+use definitions_core::consent::{ConsentId, ConsentStatus};
+use definitions_core::credentials::CredentialId;
 use definitions_core::definitions_domain::{DefRecordStatus, DomainEvent};
+use definitions_core::ownership::Owner;
+use definitions_core::references::EntityReference;
+use definitions_core::registry_domain::EntityId;
 use disintegrate::{query, EventListener, PersistedEvent, StreamQuery};
 use disintegrate_postgres::PgEventId;
 use log::debug;
@@ -9,7 +17,7 @@ use sqlx::PgPool;
 use tokio::signal;
 
 use crate::projections::schema_projection::{
-    generate_create_table_statement, generate_index_statements,
+    generate_add_columns_statements, generate_create_table_statement, generate_index_statements,
 };
 
 pub struct ReadModelProjection {
@@ -149,6 +157,190 @@ impl ReadModelProjection {
             .execute(&pool)
             .await?;
 
+        // Foreign keys between entities, see definitions_core::references
+        sqlx::query(
+            r#"
+            CREATE TABLE IF NOT EXISTS entity_references (
+                entity_id UUID NOT NULL,
+                path TEXT NOT NULL,
+                referenced_type TEXT NOT NULL,
+                referenced_id UUID NOT NULL,
+                PRIMARY KEY (entity_id, path)
+            );
+            "#,
+        )
+        .execute(&pool)
+        .await?;
+
+        sqlx::query("CREATE INDEX IF NOT EXISTS idx_entity_references_referenced_id ON entity_references (referenced_id);")
+            .execute(&pool)
+            .await?;
+
+        // Owners bound at creation time, see definitions_core::ownership
+        sqlx::query(
+            r#"
+            CREATE TABLE IF NOT EXISTS entity_owners (
+                entity_id UUID NOT NULL,
+                entity_type TEXT NOT NULL,
+                user_id TEXT,
+                email TEXT,
+                mobile TEXT
+            );
+            "#,
+        )
+        .execute(&pool)
+        .await?;
+
+        sqlx::query(
+            "CREATE INDEX IF NOT EXISTS idx_entity_owners_entity_id ON entity_owners (entity_id);",
+        )
+        .execute(&pool)
+        .await?;
+        sqlx::query(
+            "CREATE INDEX IF NOT EXISTS idx_entity_owners_user_id ON entity_owners (user_id);",
+        )
+        .execute(&pool)
+        .await?;
+        sqlx::query(
+            "CREATE INDEX IF NOT EXISTS idx_entity_owners_email ON entity_owners (lower(email));",
+        )
+        .execute(&pool)
+        .await?;
+
+        // Attestation claims, see definitions_core::attestation
+        sqlx::query(
+            r#"
+            CREATE TABLE IF NOT EXISTS claims (
+                id UUID PRIMARY KEY,
+                entity_id UUID NOT NULL,
+                entity_type TEXT NOT NULL,
+                policy_name TEXT NOT NULL,
+                attestor_entity TEXT NOT NULL,
+                property_data JSONB NOT NULL,
+                status TEXT NOT NULL,
+                raised_by TEXT NOT NULL,
+                raised_at TIMESTAMPTZ NOT NULL,
+                attestor_id UUID,
+                closed_by TEXT,
+                closed_at TIMESTAMPTZ,
+                notes TEXT
+            );
+            "#,
+        )
+        .execute(&pool)
+        .await?;
+
+        sqlx::query(
+            "CREATE INDEX IF NOT EXISTS idx_claims_attestor_entity ON claims (attestor_entity, status);",
+        )
+        .execute(&pool)
+        .await?;
+        sqlx::query("CREATE INDEX IF NOT EXISTS idx_claims_entity_id ON claims (entity_id);")
+            .execute(&pool)
+            .await?;
+
+        // Issued verifiable credentials, see definitions_core::credentials
+        sqlx::query(
+            r#"
+            CREATE TABLE IF NOT EXISTS credentials (
+                id UUID PRIMARY KEY,
+                entity_id UUID NOT NULL,
+                entity_type TEXT NOT NULL,
+                policy_name TEXT,
+                format TEXT NOT NULL,
+                credential TEXT NOT NULL,
+                encrypted BOOLEAN NOT NULL DEFAULT FALSE,
+                status_list_index BIGINT,
+                revoked_at TIMESTAMPTZ,
+                suspended BOOLEAN NOT NULL DEFAULT FALSE,
+                status_reason TEXT,
+                issued_by TEXT NOT NULL,
+                issued_at TIMESTAMPTZ NOT NULL
+            );
+            "#,
+        )
+        .execute(&pool)
+        .await?;
+        sqlx::query(
+            "CREATE INDEX IF NOT EXISTS idx_credentials_entity_id ON credentials (entity_id);",
+        )
+        .execute(&pool)
+        .await?;
+
+        // Consents to share entity fields and the accesses under them, see
+        // definitions_core::consent
+        sqlx::query(
+            r#"
+            CREATE TABLE IF NOT EXISTS consents (
+                id UUID PRIMARY KEY,
+                entity_id UUID NOT NULL,
+                entity_type TEXT NOT NULL,
+                fields JSONB NOT NULL,
+                purpose TEXT,
+                status TEXT NOT NULL,
+                requested_by TEXT NOT NULL,
+                requested_at TIMESTAMPTZ NOT NULL,
+                expires_at TIMESTAMPTZ,
+                decided_by TEXT,
+                decided_at TIMESTAMPTZ,
+                notes TEXT
+            );
+            "#,
+        )
+        .execute(&pool)
+        .await?;
+        sqlx::query("CREATE INDEX IF NOT EXISTS idx_consents_entity_id ON consents (entity_id);")
+            .execute(&pool)
+            .await?;
+        sqlx::query(
+            "CREATE INDEX IF NOT EXISTS idx_consents_requested_by ON consents (requested_by);",
+        )
+        .execute(&pool)
+        .await?;
+        sqlx::query(
+            r#"
+            CREATE TABLE IF NOT EXISTS consent_access_log (
+                event_id BIGINT PRIMARY KEY,
+                consent_id UUID NOT NULL,
+                entity_id UUID NOT NULL,
+                fields JSONB NOT NULL,
+                accessed_by TEXT NOT NULL,
+                accessed_at TIMESTAMPTZ NOT NULL
+            );
+            "#,
+        )
+        .execute(&pool)
+        .await?;
+        sqlx::query(
+            "CREATE INDEX IF NOT EXISTS idx_consent_access_log_consent_id ON consent_access_log (consent_id);",
+        )
+        .execute(&pool)
+        .await?;
+        sqlx::query("ALTER TABLE consent_access_log ADD COLUMN IF NOT EXISTS on_behalf_of TEXT;")
+            .execute(&pool)
+            .await?;
+
+        // Who did what to definitions and entities, see definitions_core::audit
+        sqlx::query(
+            r#"
+            CREATE TABLE IF NOT EXISTS audit_log (
+                event_id BIGINT PRIMARY KEY,
+                event_type TEXT NOT NULL,
+                subject_id UUID NOT NULL,
+                actor TEXT NOT NULL,
+                on_behalf_of TEXT,
+                occurred_at TIMESTAMPTZ NOT NULL
+            );
+            "#,
+        )
+        .execute(&pool)
+        .await?;
+        sqlx::query(
+            "CREATE INDEX IF NOT EXISTS idx_audit_log_subject_id ON audit_log (subject_id, event_id);",
+        )
+        .execute(&pool)
+        .await?;
+
         Ok(Self {
             query: query!(DomainEvent),
             pool,
@@ -168,6 +360,9 @@ impl EventListener<i64, DomainEvent> for ReadModelProjection {
     }
 
     async fn handle(&self, event: PersistedEvent<i64, DomainEvent>) -> Result<(), Self::Error> {
+        let event_id = event.id();
+        self.insert_audit_entry(event_id, &AuditEntry::of(&event))
+            .await?;
         match event.into_inner() {
             DomainEvent::DefCreated {
                 id,
@@ -198,6 +393,38 @@ impl EventListener<i64, DomainEvent> for ReadModelProjection {
                 }
                 result?;
             }
+            DomainEvent::DefUpdated {
+                id,
+                created_at,
+                updated_by,
+                json_schema_string,
+                ..
+            } => {
+                debug!(
+                    "DomainEvent::DefUpdated id {:#?} updated_by is {}",
+                    id, updated_by
+                );
+                let result = sqlx::query(
+                    "UPDATE definitions
+                            SET
+                                json_schema_string = $2::json,
+                                record_status = $3,
+                                updated_at = $4
+                            WHERE
+                                id = $1;
+                        ",
+                )
+                .bind(id)
+                .bind(json_schema_string)
+                .bind(DefRecordStatus::Draft.to_string())
+                .bind(created_at)
+                .execute(&self.pool)
+                .await;
+                if let Err(e) = &result {
+                    debug!("Failed to update definition: {:?}", e);
+                }
+                result?;
+            }
             DomainEvent::DefActivated {
                 id,
                 activated_by,
@@ -262,7 +489,10 @@ impl EventListener<i64, DomainEvent> for ReadModelProjection {
                 entity_type,
                 created_at,
                 created_by,
+                on_behalf_of: _,
                 version,
+                references,
+                owners,
             } => {
                 debug!(
                     "DomainEvent::EntityCreated id {:#?} entity_type '{}' created_by '{}' registry_def_id {:#?} version {}",
@@ -304,6 +534,335 @@ impl EventListener<i64, DomainEvent> for ReadModelProjection {
                 result?;
 
                 debug!("Successfully inserted entity data into '{}'", table_name);
+                self.replace_references(id, &references).await?;
+                self.insert_owners(id, &entity_type, &owners).await?;
+            }
+            // Entity updates (including migrations to a new definition version) replace the
+            // stored JSON data; generated columns are recomputed by PostgreSQL
+            DomainEvent::EntityUpdated {
+                id,
+                registry_def_id,
+                registry_def_version,
+                entity_body,
+                entity_type,
+                updated_by,
+                version,
+                references,
+                ..
+            } => {
+                debug!(
+                    "DomainEvent::EntityUpdated id {:#?} entity_type '{}' updated_by '{}' registry_def_version {} version {}",
+                    id, entity_type, updated_by, registry_def_version.get(), version.get()
+                );
+
+                let table_name = format!("{}_projection", entity_type.to_lowercase());
+                let update_sql = format!(
+                    "UPDATE {} SET registry_def_id = $2, registry_def_version = $3, version = $4, entity_data = $5::jsonb WHERE id = $1",
+                    table_name
+                );
+
+                let result = sqlx::query(&update_sql)
+                    .bind(id)
+                    .bind(registry_def_id)
+                    .bind(registry_def_version.get() as i32)
+                    .bind(version.get() as i32)
+                    .bind(entity_body)
+                    .execute(&self.pool)
+                    .await;
+
+                if let Err(e) = &result {
+                    debug!("Failed to update entity data in '{}': {:?}", table_name, e);
+                }
+                result?;
+                self.replace_references(id, &references).await?;
+            }
+            // The attested snapshot is part of the entity data, in `_osAttestedData`
+            DomainEvent::EntityAttested {
+                id,
+                entity_type,
+                entity_body,
+                claim_id,
+                ..
+            } => {
+                debug!(
+                    "DomainEvent::EntityAttested id {:#?} entity_type '{}' claim {:#?}",
+                    id, entity_type, claim_id
+                );
+                let table_name = format!("{}_projection", entity_type.to_lowercase());
+                let update_sql = format!(
+                    "UPDATE {} SET entity_data = $2::jsonb WHERE id = $1",
+                    table_name
+                );
+                sqlx::query(&update_sql)
+                    .bind(id)
+                    .bind(entity_body)
+                    .execute(&self.pool)
+                    .await?;
+            }
+            DomainEvent::EntityAutoAttested {
+                id,
+                entity_type,
+                entity_body,
+                property,
+                verifier,
+                verified,
+                ..
+            } => {
+                debug!(
+                    "DomainEvent::EntityAutoAttested id {:#?} entity_type '{}' property '{}' verifier '{}' verified {}",
+                    id, entity_type, property, verifier, verified
+                );
+                let table_name = format!("{}_projection", entity_type.to_lowercase());
+                let update_sql = format!(
+                    "UPDATE {} SET entity_data = $2::jsonb WHERE id = $1",
+                    table_name
+                );
+                sqlx::query(&update_sql)
+                    .bind(id)
+                    .bind(entity_body)
+                    .execute(&self.pool)
+                    .await?;
+            }
+            DomainEvent::CredentialIssued {
+                id,
+                credential_id,
+                entity_type,
+                policy_name,
+                format,
+                credential,
+                encrypted,
+                status_list_index,
+                issued_at,
+                issued_by,
+                on_behalf_of: _,
+            } => {
+                debug!(
+                    "DomainEvent::CredentialIssued id {:#?} entity {:#?} format '{}'",
+                    credential_id, id, format
+                );
+                sqlx::query(
+                    "INSERT INTO credentials (id, entity_id, entity_type, policy_name, format, credential, encrypted, status_list_index, issued_by, issued_at) VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10) ON CONFLICT DO NOTHING",
+                )
+                .bind(credential_id)
+                .bind(id)
+                .bind(entity_type)
+                .bind(policy_name)
+                .bind(format.to_string())
+                .bind(credential)
+                .bind(encrypted)
+                .bind(status_list_index.map(|index| index as i64))
+                .bind(issued_by)
+                .bind(issued_at)
+                .execute(&self.pool)
+                .await?;
+            }
+            DomainEvent::CredentialRevoked {
+                credential_id,
+                reason,
+                revoked_at,
+                ..
+            } => {
+                debug!("DomainEvent::CredentialRevoked id {:#?}", credential_id);
+                sqlx::query(
+                    "UPDATE credentials SET revoked_at = $2, status_reason = $3 WHERE id = $1",
+                )
+                .bind(credential_id)
+                .bind(revoked_at)
+                .bind(reason)
+                .execute(&self.pool)
+                .await?;
+            }
+            DomainEvent::CredentialSuspended {
+                credential_id,
+                reason,
+                ..
+            } => {
+                debug!("DomainEvent::CredentialSuspended id {:#?}", credential_id);
+                self.set_suspended(credential_id, true, reason).await?;
+            }
+            DomainEvent::CredentialReinstated {
+                credential_id,
+                reason,
+                ..
+            } => {
+                debug!("DomainEvent::CredentialReinstated id {:#?}", credential_id);
+                self.set_suspended(credential_id, false, reason).await?;
+            }
+            DomainEvent::ClaimRaised {
+                claim_id,
+                entity_id,
+                entity_type,
+                policy_name,
+                attestor_entity,
+                attestor_id,
+                property_data,
+                raised_at,
+                raised_by,
+                on_behalf_of: _,
+            } => {
+                debug!(
+                    "DomainEvent::ClaimRaised id {:#?} entity {:#?} policy '{}' attestor_entity '{}'",
+                    claim_id, entity_id, policy_name, attestor_entity
+                );
+                sqlx::query(
+                    "INSERT INTO claims (id, entity_id, entity_type, policy_name, attestor_entity, attestor_id, property_data, status, raised_by, raised_at) VALUES ($1, $2, $3, $4, $5, $6, $7::jsonb, $8, $9, $10) ON CONFLICT DO NOTHING",
+                )
+                .bind(claim_id)
+                .bind(entity_id)
+                .bind(entity_type)
+                .bind(policy_name)
+                .bind(attestor_entity)
+                .bind(attestor_id)
+                .bind(property_data)
+                .bind(ClaimStatus::Open.to_string())
+                .bind(raised_by)
+                .bind(raised_at)
+                .execute(&self.pool)
+                .await?;
+            }
+            DomainEvent::ClaimAttested {
+                claim_id,
+                attestor_id,
+                notes,
+                attested_at,
+                attested_by,
+                ..
+            } => {
+                self.close_claim(
+                    claim_id,
+                    ClaimStatus::Attested,
+                    attestor_id,
+                    &attested_by,
+                    attested_at,
+                    notes,
+                )
+                .await?;
+            }
+            DomainEvent::ClaimRejected {
+                claim_id,
+                attestor_id,
+                notes,
+                rejected_at,
+                rejected_by,
+                ..
+            } => {
+                self.close_claim(
+                    claim_id,
+                    ClaimStatus::Rejected,
+                    attestor_id,
+                    &rejected_by,
+                    rejected_at,
+                    notes,
+                )
+                .await?;
+            }
+            DomainEvent::ConsentRequested {
+                consent_id,
+                entity_id,
+                entity_type,
+                fields,
+                purpose,
+                requested_at,
+                requested_by,
+                on_behalf_of,
+            } => {
+                debug!(
+                    "DomainEvent::ConsentRequested id {:#?} entity {:#?} by '{}'",
+                    consent_id, entity_id, requested_by
+                );
+                sqlx::query(
+                    "INSERT INTO consents (id, entity_id, entity_type, fields, purpose, status, requested_by, requested_at) VALUES ($1, $2, $3, $4, $5, $6, $7, $8) ON CONFLICT DO NOTHING",
+                )
+                .bind(consent_id)
+                .bind(entity_id)
+                .bind(entity_type)
+                .bind(serde_json::json!(fields))
+                .bind(purpose)
+                .bind(ConsentStatus::Requested.to_string())
+                // The consented data is shared with the subject an impersonating actor acted for
+                .bind(on_behalf_of.unwrap_or(requested_by))
+                .bind(requested_at)
+                .execute(&self.pool)
+                .await?;
+            }
+            DomainEvent::ConsentGranted {
+                consent_id,
+                expires_at,
+                notes,
+                granted_at,
+                granted_by,
+                ..
+            } => {
+                sqlx::query("UPDATE consents SET expires_at = $2 WHERE id = $1")
+                    .bind(consent_id)
+                    .bind(expires_at)
+                    .execute(&self.pool)
+                    .await?;
+                self.decide_consent(
+                    consent_id,
+                    ConsentStatus::Granted,
+                    &granted_by,
+                    granted_at,
+                    notes,
+                )
+                .await?;
+            }
+            DomainEvent::ConsentDenied {
+                consent_id,
+                notes,
+                denied_at,
+                denied_by,
+                ..
+            } => {
+                self.decide_consent(
+                    consent_id,
+                    ConsentStatus::Denied,
+                    &denied_by,
+                    denied_at,
+                    notes,
+                )
+                .await?;
+            }
+            DomainEvent::ConsentRevoked {
+                consent_id,
+                notes,
+                revoked_at,
+                revoked_by,
+                ..
+            } => {
+                self.decide_consent(
+                    consent_id,
+                    ConsentStatus::Revoked,
+                    &revoked_by,
+                    revoked_at,
+                    notes,
+                )
+                .await?;
+            }
+            DomainEvent::ConsentedDataAccessed {
+                consent_id,
+                entity_id,
+                fields,
+                accessed_at,
+                accessed_by,
+                on_behalf_of,
+            } => {
+                debug!(
+                    "DomainEvent::ConsentedDataAccessed id {:#?} by '{}'",
+                    consent_id, accessed_by
+                );
+                sqlx::query(
+                    "INSERT INTO consent_access_log (event_id, consent_id, entity_id, fields, accessed_by, on_behalf_of, accessed_at) VALUES ($1, $2, $3, $4, $5, $6, $7) ON CONFLICT DO NOTHING",
+                )
+                .bind(event_id)
+                .bind(consent_id)
+                .bind(entity_id)
+                .bind(serde_json::json!(fields))
+                .bind(accessed_by)
+                .bind(on_behalf_of)
+                .bind(accessed_at)
+                .execute(&self.pool)
+                .await?;
             }
             _ => {}
         }
@@ -315,6 +874,9 @@ impl EventListener<i64, DomainEvent> for ReadModelProjection {
 impl ReadModelProjection {
     /// Creates projection table and indices for a given JSON schema
     ///
+    /// When the projection table already exists (a definition is re-activated after an update),
+    /// the generated columns of the new version are added to it instead.
+    ///
     /// This method generates a PostgreSQL projection table with:
     /// - An `entity_data JSONB NOT NULL` column to store the original JSON data
     /// - Generated columns for flattened schema attributes extracted from JSON paths
@@ -358,44 +920,54 @@ impl ReadModelProjection {
             .unwrap_or("unknown");
         debug!("Processing schema with title: '{}'", schema_title);
 
-        // Generate CREATE TABLE statement
-        let create_table_sql = match generate_create_table_statement(&schema) {
-            Ok(sql) => {
-                debug!(
-                    "Successfully generated CREATE TABLE statement for '{}'",
-                    schema_title
-                );
-                sql
-            }
-            Err(e) => {
+        let table_name = format!("{}_projection", schema_title.to_lowercase());
+        let table_exists: bool = sqlx::query_scalar("SELECT to_regclass($1) IS NOT NULL")
+            .bind(&table_name)
+            .fetch_one(&self.pool)
+            .await?;
+
+        if table_exists {
+            self.add_projection_columns(&schema, schema_title).await?;
+        } else {
+            // Generate CREATE TABLE statement
+            let create_table_sql = match generate_create_table_statement(&schema) {
+                Ok(sql) => {
+                    debug!(
+                        "Successfully generated CREATE TABLE statement for '{}'",
+                        schema_title
+                    );
+                    sql
+                }
+                Err(e) => {
+                    debug!(
+                        "Failed to generate CREATE TABLE statement for '{}': {}",
+                        schema_title, e
+                    );
+                    return Err(sqlx::Error::Protocol(format!(
+                        "Failed to generate CREATE TABLE: {}",
+                        e
+                    )));
+                }
+            };
+
+            // Execute CREATE TABLE statement
+            debug!(
+                "Executing CREATE TABLE statement for '{}_projection'",
+                schema_title.to_lowercase()
+            );
+            debug!("CREATE TABLE SQL:\n{}", create_table_sql);
+            if let Err(e) = sqlx::query(&create_table_sql).execute(&self.pool).await {
                 debug!(
-                    "Failed to generate CREATE TABLE statement for '{}': {}",
+                    "Failed to execute CREATE TABLE statement for '{}': {:?}",
                     schema_title, e
                 );
-                return Err(sqlx::Error::Protocol(format!(
-                    "Failed to generate CREATE TABLE: {}",
-                    e
-                )));
+                return Err(e);
             }
-        };
-
-        // Execute CREATE TABLE statement
-        debug!(
-            "Executing CREATE TABLE statement for '{}_projection'",
-            schema_title.to_lowercase()
-        );
-        debug!("CREATE TABLE SQL:\n{}", create_table_sql);
-        if let Err(e) = sqlx::query(&create_table_sql).execute(&self.pool).await {
             debug!(
-                "Failed to execute CREATE TABLE statement for '{}': {:?}",
-                schema_title, e
+                "Successfully created projection table '{}_projection'",
+                schema_title.to_lowercase()
             );
-            return Err(e);
         }
-        debug!(
-            "Successfully created projection table '{}_projection'",
-            schema_title.to_lowercase()
-        );
 
         // Generate CREATE INDEX statements
         let index_statements = match generate_index_statements(&schema) {
@@ -469,6 +1041,171 @@ impl ReadModelProjection {
     }
 }
 
+impl ReadModelProjection {
+    /// Records the actor of every event for the history routes
+    async fn insert_audit_entry(
+        &self,
+        event_id: i64,
+        entry: &AuditEntry,
+    ) -> Result<(), sqlx::Error> {
+        sqlx::query(
+            "INSERT INTO audit_log (event_id, event_type, subject_id, actor, on_behalf_of, occurred_at) VALUES ($1, $2, $3, $4, $5, $6) ON CONFLICT DO NOTHING",
+        )
+        .bind(event_id)
+        .bind(&entry.event_type)
+        .bind(entry.subject_id)
+        .bind(&entry.actor.id)
+        .bind(entry.actor.on_behalf_of.as_deref())
+        .bind(entry.occurred_at)
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    /// Replaces the stored references of an entity with the ones of its latest version
+    async fn replace_references(
+        &self,
+        entity_id: EntityId,
+        references: &[EntityReference],
+    ) -> Result<(), sqlx::Error> {
+        let mut tx = self.pool.begin().await?;
+        sqlx::query("DELETE FROM entity_references WHERE entity_id = $1")
+            .bind(entity_id)
+            .execute(&mut *tx)
+            .await?;
+        for reference in references {
+            sqlx::query(
+                "INSERT INTO entity_references (entity_id, path, referenced_type, referenced_id) VALUES ($1, $2, $3, $4)",
+            )
+            .bind(entity_id)
+            .bind(&reference.path)
+            .bind(&reference.entity_type)
+            .bind(reference.id)
+            .execute(&mut *tx)
+            .await?;
+        }
+        tx.commit().await
+    }
+
+    /// Stores the owners of a newly created entity
+    async fn insert_owners(
+        &self,
+        entity_id: EntityId,
+        entity_type: &str,
+        owners: &[Owner],
+    ) -> Result<(), sqlx::Error> {
+        let mut tx = self.pool.begin().await?;
+        // Events may be replayed, the owners of an entity never change after creation
+        sqlx::query("DELETE FROM entity_owners WHERE entity_id = $1")
+            .bind(entity_id)
+            .execute(&mut *tx)
+            .await?;
+        for owner in owners {
+            sqlx::query(
+                "INSERT INTO entity_owners (entity_id, entity_type, user_id, email, mobile) VALUES ($1, $2, $3, $4, $5)",
+            )
+            .bind(entity_id)
+            .bind(entity_type)
+            .bind(&owner.user_id)
+            .bind(&owner.email)
+            .bind(&owner.mobile)
+            .execute(&mut *tx)
+            .await?;
+        }
+        tx.commit().await
+    }
+
+    /// Records the decision of an attestor on a claim
+    async fn close_claim(
+        &self,
+        claim_id: ClaimId,
+        status: ClaimStatus,
+        attestor_id: EntityId,
+        closed_by: &str,
+        closed_at: DateTime<Utc>,
+        notes: Option<String>,
+    ) -> Result<(), sqlx::Error> {
+        debug!("Claim {:#?} is now {} by '{}'", claim_id, status, closed_by);
+        sqlx::query(
+            "UPDATE claims SET status = $2, attestor_id = $3, closed_by = $4, closed_at = $5, notes = $6 WHERE id = $1",
+        )
+        .bind(claim_id)
+        .bind(status.to_string())
+        .bind(attestor_id)
+        .bind(closed_by)
+        .bind(closed_at)
+        .bind(notes)
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    /// Records the decision of an owner on a consent
+    async fn decide_consent(
+        &self,
+        consent_id: ConsentId,
+        status: ConsentStatus,
+        decided_by: &str,
+        decided_at: DateTime<Utc>,
+        notes: Option<String>,
+    ) -> Result<(), sqlx::Error> {
+        debug!(
+            "Consent {:#?} is now {} by '{}'",
+            consent_id, status, decided_by
+        );
+        sqlx::query(
+            "UPDATE consents SET status = $2, decided_by = $3, decided_at = $4, notes = $5 WHERE id = $1",
+        )
+        .bind(consent_id)
+        .bind(status.to_string())
+        .bind(decided_by)
+        .bind(decided_at)
+        .bind(notes)
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    /// Records the suspension bit of a credential
+    async fn set_suspended(
+        &self,
+        credential_id: CredentialId,
+        suspended: bool,
+        reason: Option<String>,
+    ) -> Result<(), sqlx::Error> {
+        sqlx::query("UPDATE credentials SET suspended = $2, status_reason = $3 WHERE id = $1")
+            .bind(credential_id)
+            .bind(suspended)
+            .bind(reason)
+            .execute(&self.pool)
+            .await?;
+        Ok(())
+    }
+
+    /// Adds the generated columns of a new schema version to an existing projection table
+    async fn add_projection_columns(
+        &self,
+        schema: &serde_json::Value,
+        schema_title: &str,
+    ) -> Result<(), sqlx::Error> {
+        let alter_statements = generate_add_columns_statements(schema)
+            .map_err(|e| sqlx::Error::Protocol(format!("Failed to generate ALTER TABLE: {}", e)))?;
+
+        debug!(
+            "Projection table for '{}' exists, adding {} columns if missing",
+            schema_title,
+            alter_statements.len()
+        );
+        for alter_sql in &alter_statements {
+            if let Err(e) = sqlx::query(alter_sql).execute(&self.pool).await {
+                debug!("Failed SQL: {}", alter_sql);
+                return Err(e);
+            }
+        }
+        Ok(())
+    }
+}
+
 pub async fn shutdown() {
     signal::ctrl_c().await.expect("failed to listen for event");
 }
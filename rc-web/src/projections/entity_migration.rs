@@ -0,0 +1,231 @@
+use async_trait::async_trait;
+use chrono::Utc;
+use definitions_core::audit::Actor;
+use definitions_core::definitions_domain::{
+    read_title, DefId, DomainEvent, EntityLifecycleEvent, Version,
+};
+use definitions_core::encryption::FieldCipher;
+use definitions_core::registry_domain::{EntityId, MigrateEntityCmd};
+use definitions_core::signing::IssuerKey;
+use disintegrate::{query, EventListener, EventStore, PersistedEvent, StreamQuery};
+use disintegrate_postgres::{PgEventId, PgEventStore};
+use futures::TryStreamExt;
+use log::{debug, error};
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use crate::DecisionMaker;
+
+type DomainEventStore = PgEventStore<DomainEvent, disintegrate::serde::json::Json<DomainEvent>>;
+
+pub const MIGRATED_BY: &str = "entity_migration";
+
+/// Background job that brings existing entities up to date when a definition is re-activated.
+///
+/// For every `DefActivated` of a version greater than the first, the entities of the definition
+/// created before it, and not deleted, are migrated with `MigrateEntityCmd`, which applies the
+/// migration of every version activated since theirs and emits `EntityUpdated` events. The
+/// entities are read from the event store, the projection tables may lag behind the activation.
+/// The outcome of each entity is written to the `entity_migration_report` table.
+pub struct EntityMigrationJob {
+    query: StreamQuery<PgEventId, DomainEvent>,
+    pool: PgPool,
+    event_store: DomainEventStore,
+    decision_maker: DecisionMaker,
+    cipher: FieldCipher,
+    signer: IssuerKey,
+}
+
+impl EntityMigrationJob {
+    pub async fn new(
+        pool: PgPool,
+        event_store: DomainEventStore,
+        decision_maker: DecisionMaker,
+        cipher: FieldCipher,
+        signer: IssuerKey,
+    ) -> Result<Self, sqlx::Error> {
+        sqlx::query(
+            r#"
+            CREATE TABLE IF NOT EXISTS entity_migration_report (
+                definition_id UUID NOT NULL,
+                definition_version INTEGER NOT NULL,
+                entity_id UUID NOT NULL,
+                entity_type TEXT NOT NULL,
+                status TEXT NOT NULL,
+                error TEXT,
+                processed_at TIMESTAMPTZ NOT NULL,
+                PRIMARY KEY (definition_id, definition_version, entity_id)
+            );
+            "#,
+        )
+        .execute(&pool)
+        .await?;
+
+        Ok(Self {
+            query: query!(DomainEvent),
+            pool,
+            event_store,
+            decision_maker,
+            cipher,
+            signer,
+        })
+    }
+
+    /// The entities of a definition created, and not deleted, before the event `activated`
+    async fn entities_before(
+        &self,
+        definition_id: DefId,
+        activated: PgEventId,
+    ) -> Result<Vec<EntityId>, disintegrate_postgres::Error> {
+        let query = query!(EntityLifecycleEvent);
+        let mut events = self.event_store.stream(&query);
+        let mut entity_ids = Vec::new();
+        while let Some(event) = events.try_next().await? {
+            if event.id() >= activated {
+                break;
+            }
+            match event.into_inner() {
+                EntityLifecycleEvent::EntityCreated {
+                    id,
+                    registry_def_id,
+                    ..
+                } if registry_def_id == definition_id => entity_ids.push(id),
+                EntityLifecycleEvent::EntityDeleted { id, .. } => {
+                    entity_ids.retain(|entity_id| *entity_id != id)
+                }
+                _ => {}
+            }
+        }
+        Ok(entity_ids)
+    }
+
+    async fn migrate_entities(
+        &self,
+        definition_id: DefId,
+        version: Version,
+        entity_type: &str,
+        activated: PgEventId,
+    ) -> Result<(), disintegrate_postgres::Error> {
+        let entity_ids = self.entities_before(definition_id, activated).await?;
+
+        debug!(
+            "Migrating {} '{}' entities to definition version {}",
+            entity_ids.len(),
+            entity_type,
+            version.get()
+        );
+
+        for entity_id in entity_ids {
+            let migrate_cmd = MigrateEntityCmd {
+                id: entity_id,
+                entity_type: entity_type.to_string(),
+                migrated_by: Actor::new(MIGRATED_BY),
+                cipher: Some(self.cipher.clone()),
+                signer: Some(self.signer.clone()),
+            };
+            let (status, error) = match self.decision_maker.make(migrate_cmd).await {
+                Ok(events) if events.is_empty() => (MigrationStatus::Skipped, None),
+                Ok(_) => (MigrationStatus::Migrated, None),
+                Err(e) => {
+                    debug!("Migration of entity {} failed: {}", entity_id, e);
+                    (MigrationStatus::Failed, Some(e.to_string()))
+                }
+            };
+            self.record(
+                definition_id,
+                version,
+                entity_id,
+                entity_type,
+                status,
+                error,
+            )
+            .await?;
+        }
+        Ok(())
+    }
+
+    async fn record(
+        &self,
+        definition_id: DefId,
+        version: Version,
+        entity_id: Uuid,
+        entity_type: &str,
+        status: MigrationStatus,
+        error: Option<String>,
+    ) -> Result<(), sqlx::Error> {
+        sqlx::query(
+            "INSERT INTO entity_migration_report (definition_id, definition_version, entity_id, entity_type, status, error, processed_at)
+             VALUES ($1, $2, $3, $4, $5, $6, $7)
+             ON CONFLICT (definition_id, definition_version, entity_id)
+             DO UPDATE SET status = EXCLUDED.status, error = EXCLUDED.error, processed_at = EXCLUDED.processed_at",
+        )
+        .bind(definition_id)
+        .bind(version.get() as i32)
+        .bind(entity_id)
+        .bind(entity_type)
+        .bind(status.as_str())
+        .bind(error)
+        .bind(Utc::now())
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MigrationStatus {
+    /// An `EntityUpdated` event was emitted
+    Migrated,
+    /// The entity was already on the active version
+    Skipped,
+    /// The migration could not be applied or the result did not validate
+    Failed,
+}
+
+impl MigrationStatus {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            MigrationStatus::Migrated => "Migrated",
+            MigrationStatus::Skipped => "Skipped",
+            MigrationStatus::Failed => "Failed",
+        }
+    }
+}
+
+#[async_trait]
+impl EventListener<i64, DomainEvent> for EntityMigrationJob {
+    type Error = disintegrate_postgres::Error;
+    fn id(&self) -> &'static str {
+        "entity_migration"
+    }
+
+    fn query(&self) -> &StreamQuery<PgEventId, DomainEvent> {
+        &self.query
+    }
+
+    async fn handle(&self, event: PersistedEvent<i64, DomainEvent>) -> Result<(), Self::Error> {
+        let event_id = event.id();
+        if let DomainEvent::DefActivated {
+            id,
+            json_schema_string,
+            version,
+            ..
+        } = event.into_inner()
+        {
+            // The first activation has no entities to reconcile
+            if version == Version::default() {
+                return Ok(());
+            }
+            let entity_type = match read_title(&json_schema_string) {
+                Ok(title) => title,
+                Err(e) => {
+                    error!("Cannot migrate entities of definition {}: {}", id, e);
+                    return Ok(());
+                }
+            };
+            self.migrate_entities(id, version, &entity_type, event_id)
+                .await?;
+        }
+        Ok(())
+    }
+}
@@ -1,7 +1,11 @@
+pub mod auto_attestation;
+pub mod credential_revocation;
 pub mod definitions_read_model;
+pub mod entity_migration;
+pub mod notifications;
 pub mod schema_projection;
 
 pub use schema_projection::{
-    flatten_json_schema, generate_create_table_statement, generate_index_statements,
-    FlattenedAttribute,
+    flatten_json_schema, generate_add_columns_statements, generate_create_table_statement,
+    generate_index_statements, FlattenedAttribute,
 };
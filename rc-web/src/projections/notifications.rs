@@ -0,0 +1,394 @@
+use async_trait::async_trait;
+use definitions_core::definitions_domain::DomainEvent;
+use definitions_core::ownership::{owners_of, Owner};
+use definitions_core::registry_domain::EntityId;
+use disintegrate::{query, Event, EventListener, PersistedEvent, StreamQuery};
+use disintegrate_postgres::PgEventId;
+use log::{debug, warn};
+use serde_json::{json, Value};
+use sqlx::PgPool;
+use std::sync::Arc;
+use uuid::Uuid;
+
+use crate::base_url;
+use crate::services::notification_outbox::{NotificationOutbox, OutboundNotification};
+use crate::services::notification_templates::{
+    NotificationTemplates, ATTESTATION_REQUESTED, CONSENT_REQUESTED, CREDENTIAL_ISSUED,
+    ENTITY_CREATED, ENTITY_INVITED,
+};
+use crate::services::notifier::Channel;
+
+const LISTENER_ID: &str = "notifications";
+
+/// Who a notification is sent to
+#[derive(Debug, PartialEq)]
+enum Audience {
+    /// Owners carried by the event
+    Owners(Vec<Owner>),
+    /// Owners of an entity, as projected in `entity_owners`
+    OwnersOf(EntityId),
+    /// Owners resolved from the body of an entity and its definition
+    Body {
+        registry_def_id: Uuid,
+        entity_type: String,
+        entity_body: String,
+    },
+}
+
+/// A message to send for an event
+#[derive(Debug, PartialEq)]
+struct Notice {
+    template: &'static str,
+    entity_id: EntityId,
+    data: Value,
+    audience: Audience,
+}
+
+fn notice(event: &DomainEvent) -> Option<Notice> {
+    let registry_url = base_url();
+    match event {
+        DomainEvent::EntityCreated {
+            id,
+            entity_type,
+            owners,
+            ..
+        } => Some(Notice {
+            template: ENTITY_CREATED,
+            entity_id: *id,
+            data: json!({ "entityType": entity_type, "entityId": id, "registryUrl": registry_url }),
+            audience: Audience::Owners(owners.clone()),
+        }),
+        DomainEvent::EntityInvited {
+            id,
+            registry_def_id,
+            entity_type,
+            entity_body,
+            ..
+        } => Some(Notice {
+            template: ENTITY_INVITED,
+            entity_id: *id,
+            data: json!({ "entityType": entity_type, "entityId": id, "registryUrl": registry_url }),
+            audience: Audience::Body {
+                registry_def_id: *registry_def_id,
+                entity_type: entity_type.clone(),
+                entity_body: entity_body.clone(),
+            },
+        }),
+        DomainEvent::ClaimRaised {
+            claim_id,
+            entity_id,
+            entity_type,
+            policy_name,
+            attestor_entity,
+            attestor_id: Some(attestor_id),
+            ..
+        } => Some(Notice {
+            template: ATTESTATION_REQUESTED,
+            entity_id: *entity_id,
+            data: json!({
+                "entityType": entity_type,
+                "entityId": entity_id,
+                "policyName": policy_name,
+                "attestorEntity": attestor_entity,
+                "claimId": claim_id,
+                "registryUrl": registry_url,
+            }),
+            audience: Audience::OwnersOf(*attestor_id),
+        }),
+        DomainEvent::ConsentRequested {
+            consent_id,
+            entity_id,
+            entity_type,
+            fields,
+            purpose,
+            requested_by,
+            ..
+        } => Some(Notice {
+            template: CONSENT_REQUESTED,
+            entity_id: *entity_id,
+            data: json!({
+                "entityType": entity_type,
+                "entityId": entity_id,
+                "fields": fields,
+                "purpose": purpose,
+                "requestedBy": requested_by,
+                "consentId": consent_id,
+                "registryUrl": registry_url,
+            }),
+            audience: Audience::OwnersOf(*entity_id),
+        }),
+        DomainEvent::CredentialIssued {
+            id,
+            credential_id,
+            entity_type,
+            policy_name,
+            format,
+            ..
+        } => Some(Notice {
+            template: CREDENTIAL_ISSUED,
+            entity_id: *id,
+            data: json!({
+                "entityType": entity_type,
+                "entityId": id,
+                "credentialId": credential_id,
+                "policyName": policy_name,
+                "format": format,
+                "registryUrl": registry_url,
+            }),
+            audience: Audience::OwnersOf(*id),
+        }),
+        _ => None,
+    }
+}
+
+/// The email of each owner, or else its mobile, once per address
+fn recipients(owners: &[Owner]) -> Vec<(Channel, String)> {
+    let mut recipients = Vec::new();
+    for owner in owners {
+        let recipient = match (&owner.email, &owner.mobile) {
+            (Some(email), _) => (Channel::Email, email.clone()),
+            (None, Some(mobile)) => (Channel::Sms, mobile.clone()),
+            (None, None) => continue,
+        };
+        if !recipients.contains(&recipient) {
+            recipients.push(recipient);
+        }
+    }
+    recipients
+}
+
+/// Background job that notifies the people concerned by registry events.
+///
+/// Owners are told when their entity is created or invited, when consent to read it is requested
+/// and when a credential is issued for it. The owners of the attestor entity a claim is routed to
+/// are told when it awaits their attestation, claims routed to no attestor are not notified.
+/// Messages are rendered from [`NotificationTemplates`] and stored in the [`NotificationOutbox`],
+/// which sends them in the background, see [`NotificationOutbox::spawn_delivery`].
+///
+/// The events stored before the job first started are skipped, so that deploying it does not
+/// notify the whole history of the registry.
+pub struct NotificationJob {
+    query: StreamQuery<PgEventId, DomainEvent>,
+    pool: PgPool,
+    /// Id of the last event stored when the job first started
+    start_event_id: i64,
+    outbox: NotificationOutbox,
+    templates: Arc<NotificationTemplates>,
+}
+
+impl NotificationJob {
+    pub async fn new(
+        pool: PgPool,
+        outbox: NotificationOutbox,
+        templates: Arc<NotificationTemplates>,
+    ) -> Result<Self, sqlx::Error> {
+        sqlx::query(
+            r#"
+            CREATE TABLE IF NOT EXISTS listener_start (
+                id TEXT PRIMARY KEY,
+                start_event_id BIGINT NOT NULL,
+                started_at TIMESTAMPTZ NOT NULL DEFAULT now()
+            );
+            "#,
+        )
+        .execute(&pool)
+        .await?;
+        // Kept from the first start on, a restart must not skip the events it missed
+        sqlx::query(
+            "INSERT INTO listener_start (id, start_event_id) SELECT $1, COALESCE(MAX(event_id), 0) FROM event ON CONFLICT (id) DO NOTHING",
+        )
+        .bind(LISTENER_ID)
+        .execute(&pool)
+        .await?;
+        let start_event_id =
+            sqlx::query_scalar("SELECT start_event_id FROM listener_start WHERE id = $1")
+                .bind(LISTENER_ID)
+                .fetch_one(&pool)
+                .await?;
+
+        Ok(Self {
+            query: query!(DomainEvent),
+            pool,
+            start_event_id,
+            outbox,
+            templates,
+        })
+    }
+
+    async fn owners(&self, audience: Audience) -> Result<Vec<Owner>, sqlx::Error> {
+        let query = match audience {
+            Audience::Owners(owners) => return Ok(owners),
+            Audience::Body {
+                registry_def_id,
+                entity_type,
+                entity_body,
+            } => {
+                let schema = sqlx::query_scalar::<_, String>(
+                    "SELECT json_schema_string::TEXT FROM definitions WHERE id = $1",
+                )
+                .bind(registry_def_id)
+                .fetch_optional(&self.pool)
+                .await?;
+                return Ok(schema
+                    .and_then(|schema| owners_of(&schema, &entity_type, &entity_body).ok())
+                    .unwrap_or_default());
+            }
+            Audience::OwnersOf(entity_id) => {
+                sqlx::query_as::<_, (Option<String>, Option<String>, Option<String>)>(
+                    "SELECT user_id, email, mobile FROM entity_owners WHERE entity_id = $1",
+                )
+                .bind(entity_id)
+            }
+        };
+        Ok(query
+            .fetch_all(&self.pool)
+            .await?
+            .into_iter()
+            .map(|(user_id, email, mobile)| Owner {
+                user_id,
+                email,
+                mobile,
+            })
+            .collect())
+    }
+}
+
+#[async_trait]
+impl EventListener<i64, DomainEvent> for NotificationJob {
+    type Error = sqlx::Error;
+    fn id(&self) -> &'static str {
+        LISTENER_ID
+    }
+
+    fn query(&self) -> &StreamQuery<PgEventId, DomainEvent> {
+        &self.query
+    }
+
+    async fn handle(&self, event: PersistedEvent<i64, DomainEvent>) -> Result<(), Self::Error> {
+        let event_id = event.id();
+        if event_id <= self.start_event_id {
+            return Ok(());
+        }
+        let event = event.into_inner();
+        let Some(notice) = notice(&event) else {
+            return Ok(());
+        };
+        let owners = self.owners(notice.audience).await?;
+        let notifications: Vec<OutboundNotification> = recipients(&owners)
+            .into_iter()
+            .filter_map(|(channel, to)| {
+                match self
+                    .templates
+                    .render(notice.template, channel, &to, &notice.data)
+                {
+                    Ok(notification) => Some(OutboundNotification {
+                        event_id,
+                        event_type: event.name().to_string(),
+                        entity_id: notice.entity_id,
+                        template: notice.template.to_string(),
+                        notification,
+                    }),
+                    Err(e) => {
+                        warn!("Notification of event {} skipped: {}", event_id, e);
+                        None
+                    }
+                }
+            })
+            .collect();
+        if notifications.is_empty() {
+            return Ok(());
+        }
+        self.outbox.enqueue(&notifications).await?;
+        debug!(
+            "Queued {} notifications for event {}",
+            notifications.len(),
+            event_id
+        );
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used)]
+mod tests {
+    use super::*;
+    use chrono::Utc;
+
+    #[test]
+    fn test_owners_are_reached_by_email_else_by_sms_once() {
+        let owner = |email: Option<&str>, mobile: Option<&str>| Owner {
+            user_id: None,
+            email: email.map(str::to_string),
+            mobile: mobile.map(str::to_string),
+        };
+        let owners = vec![
+            owner(Some("asha@example.com"), Some("+91-9876543210")),
+            owner(None, Some("+91-9876543211")),
+            owner(None, None),
+            owner(Some("asha@example.com"), None),
+        ];
+
+        assert_eq!(
+            recipients(&owners),
+            vec![
+                (Channel::Email, "asha@example.com".to_string()),
+                (Channel::Sms, "+91-9876543211".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_consent_requests_notify_the_owners_of_the_entity() {
+        let entity_id = Uuid::from_u128(1);
+        let event = DomainEvent::ConsentRequested {
+            consent_id: Uuid::from_u128(2),
+            entity_id,
+            entity_type: "Insurance".to_string(),
+            fields: vec!["$.policyNumber".to_string()],
+            purpose: None,
+            requested_at: Utc::now(),
+            requested_by: "auth0|bank".to_string(),
+            on_behalf_of: None,
+        };
+
+        let notice = notice(&event).unwrap();
+        assert_eq!(notice.template, CONSENT_REQUESTED);
+        assert_eq!(notice.audience, Audience::OwnersOf(entity_id));
+        assert_eq!(notice.data["requestedBy"], "auth0|bank");
+
+        let templates = NotificationTemplates::new().unwrap();
+        let email = templates
+            .render(
+                notice.template,
+                Channel::Email,
+                "a@example.com",
+                &notice.data,
+            )
+            .unwrap();
+        assert!(email
+            .body
+            .starts_with("auth0|bank asks to read $.policyNumber from your Insurance record."));
+    }
+
+    #[test]
+    fn test_claims_notify_the_owners_of_their_attestor_only() {
+        let attestor_id = Uuid::from_u128(3);
+        let raised = |attestor_id| DomainEvent::ClaimRaised {
+            claim_id: Uuid::from_u128(2),
+            entity_id: Uuid::from_u128(1),
+            entity_type: "Insurance".to_string(),
+            policy_name: "policyApproval".to_string(),
+            attestor_entity: "Official".to_string(),
+            attestor_id,
+            property_data: "{}".to_string(),
+            raised_at: Utc::now(),
+            raised_by: "auth0|asha".to_string(),
+            on_behalf_of: None,
+        };
+
+        assert_eq!(notice(&raised(None)), None);
+        let notice = notice(&raised(Some(attestor_id))).unwrap();
+        assert_eq!(notice.template, ATTESTATION_REQUESTED);
+        assert_eq!(notice.audience, Audience::OwnersOf(attestor_id));
+    }
+}
@@ -9,6 +9,9 @@
 //! - `flatten_json_schema`: Converts JSON schema into flattened attributes with PostgreSQL types
 //! - `generate_create_table_statement`: Creates complete CREATE TABLE DDL from JSON schema
 //! - `generate_index_statements`: Creates CREATE INDEX statements based on _osConfig
+//! - `system_field_attributes`: Generated columns for the `_osConfig.systemFields` of an entity
+//! - `projected_attributes`: Flattened attributes without the encrypted `_osConfig.privateFields`
+//! - `internal_columns`: Generated columns holding the `_osConfig.internalFields` of an entity
 //!
 //! ## Usage Example
 //!
@@ -58,6 +61,10 @@
 //! - `array` → `JSONB`
 //! - `object` → `JSONB` (at max depth only)
 
+use definitions_core::encryption::private_fields;
+use definitions_core::json_path::{JsonPath, PathSegment};
+use definitions_core::system_fields::declared_system_fields;
+use definitions_core::visibility::declared_internal_fields;
 use serde_json::Value;
 
 /// Represents a flattened attribute from a JSON schema with PostgreSQL column information
@@ -417,8 +424,8 @@ pub fn generate_create_table_statement(schema: &Value) -> Result<String, String>
 
     let table_name = format!("{}_projection", title.to_lowercase());
 
-    // Get flattened attributes from the schema
-    let flattened_attributes = flatten_json_schema(schema)?;
+    // Get flattened attributes from the schema, encrypted fields are never projected
+    let flattened_attributes = projected_attributes(schema)?;
 
     // Start building the CREATE TABLE statement
     let mut create_table_sql = format!("CREATE TABLE {} (\n", table_name);
@@ -435,21 +442,14 @@ pub fn generate_create_table_statement(schema: &Value) -> Result<String, String>
     // Add the entity_data column to store JSON data
     create_table_sql.push_str("    entity_data JSONB NOT NULL");
 
-    // Add flattened columns as generated columns
-    for attribute in flattened_attributes {
+    // Add flattened columns and system field columns as generated columns
+    for attribute in flattened_attributes
+        .iter()
+        .chain(system_field_attributes(schema).iter())
+    {
         create_table_sql.push_str(",\n");
 
-        // Convert primitive types to TEXT for PostgreSQL immutability
-        // Generated columns must use immutable expressions, type casting is not immutable
-        let db_column_type = match attribute.column_type.as_str() {
-            "INTEGER" | "NUMERIC" | "BOOLEAN" | "DATE" | "TIMESTAMPTZ" => "TEXT",
-            _ => &attribute.column_type, // Keep TEXT and JSONB as-is
-        };
-
-        create_table_sql.push_str(&format!(
-            "    {} {} GENERATED ALWAYS AS ({}) STORED",
-            attribute.attribute_name, db_column_type, attribute.generated_column_pattern
-        ));
+        create_table_sql.push_str(&format!("    {}", generated_column_definition(attribute)));
     }
 
     // Close the CREATE TABLE statement
@@ -458,6 +458,149 @@ pub fn generate_create_table_statement(schema: &Value) -> Result<String, String>
     Ok(create_table_sql)
 }
 
+/// Generates ALTER TABLE statements that add the generated columns of a new schema version
+/// to an existing projection table
+///
+/// Used when a definition is re-activated: the projection table already exists and holds
+/// entities of the previous version. Columns are only added, never dropped, so data of
+/// entities that have not been migrated yet stays queryable.
+///
+/// # Arguments
+/// * `schema` - The JSON schema to process
+///
+/// # Returns
+/// * `Result<Vec<String>, String>` - Vector of ALTER TABLE statements or an error message
+pub fn generate_add_columns_statements(schema: &Value) -> Result<Vec<String>, String> {
+    let title = schema
+        .get("title")
+        .and_then(|t| t.as_str())
+        .ok_or("Schema must have a 'title' field")?;
+
+    let table_name = format!("{}_projection", title.to_lowercase());
+
+    Ok(projected_attributes(schema)?
+        .iter()
+        .chain(system_field_attributes(schema).iter())
+        .map(|attribute| {
+            format!(
+                "ALTER TABLE {} ADD COLUMN IF NOT EXISTS {};",
+                table_name,
+                generated_column_definition(attribute)
+            )
+        })
+        .collect())
+}
+
+/// Generated columns exposing the `_osConfig.systemFields` of the entity
+///
+/// System fields are injected by the registry at the top level of the entity node, e.g.
+/// `_osCreatedAt` of a Student is exposed as `os_created_at` computed from
+/// `entity_data -> 'Student' ->> '_osCreatedAt'`.
+pub fn system_field_attributes(schema: &Value) -> Vec<FlattenedAttribute> {
+    let title = schema.get("title").and_then(|t| t.as_str()).unwrap_or("");
+    let wrapped = is_wrapped(schema, title);
+
+    declared_system_fields(schema)
+        .iter()
+        .filter(|name| name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_'))
+        .map(|name| FlattenedAttribute {
+            attribute_name: to_snake_case(name.trim_start_matches('_')),
+            column_type: "TEXT".to_string(),
+            generated_column_pattern: if wrapped {
+                format!("entity_data -> '{}' ->> '{}'", title, name)
+            } else {
+                format!("entity_data ->> '{}'", name)
+            },
+        })
+        .collect()
+}
+
+/// Flattened attributes of the schema, without the paths listed in `_osConfig.privateFields`
+///
+/// Private fields are stored encrypted, a generated column or an index on them would either hold
+/// ciphertext or, before encryption, leak the plaintext. An attribute is excluded when it is the
+/// private path itself or one of its descendants.
+pub fn projected_attributes(schema: &Value) -> Result<Vec<FlattenedAttribute>, String> {
+    let excluded = column_prefixes(schema, &private_fields(schema));
+    Ok(flatten_json_schema(schema)?
+        .into_iter()
+        .filter(|attribute| !is_covered(&attribute.attribute_name, &excluded))
+        .collect())
+}
+
+/// Names of the projected columns holding the paths listed in `_osConfig.internalFields`
+///
+/// Read APIs must not let callers that cannot see internal fields filter on these columns.
+pub fn internal_columns(schema: &Value) -> Result<Vec<String>, String> {
+    let internal = column_prefixes(schema, &declared_internal_fields(schema));
+    Ok(projected_attributes(schema)?
+        .into_iter()
+        .map(|attribute| attribute.attribute_name)
+        .filter(|name| is_covered(name, &internal))
+        .collect())
+}
+
+/// Flattened attribute names of the given JSON paths, relative to the entity node
+fn column_prefixes(schema: &Value, paths: &[String]) -> Vec<String> {
+    let title = schema.get("title").and_then(|t| t.as_str()).unwrap_or("");
+    let wrapped = is_wrapped(schema, title);
+    paths
+        .iter()
+        .filter_map(|path| JsonPath::parse(path).ok())
+        .map(|path| {
+            // Arrays are projected as a single JSONB column
+            let keys = path.segments().iter().map_while(|segment| match segment {
+                PathSegment::Key(key) => Some(key.to_lowercase()),
+                PathSegment::Index(_) => None,
+            });
+            let prefix = wrapped.then(|| title.to_lowercase());
+            prefix.into_iter().chain(keys).collect::<Vec<_>>().join("_")
+        })
+        .filter(|name| !name.is_empty())
+        .collect()
+}
+
+/// Returns true when the attribute is one of `prefixes` or one of their descendants
+fn is_covered(attribute_name: &str, prefixes: &[String]) -> bool {
+    prefixes.iter().any(|prefix| {
+        attribute_name == prefix || attribute_name.starts_with(&format!("{}_", prefix))
+    })
+}
+
+/// Documents are wrapped in their entity type when the schema has a single title property
+fn is_wrapped(schema: &Value, title: &str) -> bool {
+    schema
+        .get("properties")
+        .and_then(|p| p.as_object())
+        .is_some_and(|p| p.len() == 1 && p.contains_key(title))
+}
+
+fn to_snake_case(name: &str) -> String {
+    let mut snake = String::new();
+    for c in name.chars() {
+        if c.is_ascii_uppercase() {
+            snake.push('_');
+        }
+        snake.push(c.to_ascii_lowercase());
+    }
+    snake
+}
+
+/// Column definition of a flattened attribute as a stored generated column
+fn generated_column_definition(attribute: &FlattenedAttribute) -> String {
+    // Convert primitive types to TEXT for PostgreSQL immutability
+    // Generated columns must use immutable expressions, type casting is not immutable
+    let db_column_type = match attribute.column_type.as_str() {
+        "INTEGER" | "NUMERIC" | "BOOLEAN" | "DATE" | "TIMESTAMPTZ" => "TEXT",
+        _ => &attribute.column_type, // Keep TEXT and JSONB as-is
+    };
+
+    format!(
+        "{} {} GENERATED ALWAYS AS ({}) STORED",
+        attribute.attribute_name, db_column_type, attribute.generated_column_pattern
+    )
+}
+
 /// Generates CREATE INDEX statements from a JSON schema using flattened attributes and _osConfig
 ///
 /// # Arguments
@@ -495,7 +638,7 @@ pub fn generate_index_statements(schema: &Value) -> Result<Vec<String>, String>
     let table_name = format!("{}_projection", title.to_lowercase());
 
     // Get flattened attributes from the schema to map field names to column names
-    let flattened_attributes = flatten_json_schema(schema)?;
+    let flattened_attributes = projected_attributes(schema)?;
 
     let mut index_statements = Vec::new();
 
@@ -584,6 +727,7 @@ fn find_matching_columns(
 }
 
 #[cfg(test)]
+#[allow(clippy::unwrap_used)]
 mod tests {
     use super::*;
     use serde_json::json;
@@ -673,4 +817,122 @@ mod tests {
             .iter()
             .any(|stmt| stmt.contains("CREATE INDEX idx_user_projection_entity_data_gin")));
     }
+
+    #[test]
+    fn test_generate_add_columns_statements() {
+        let schema = json!({
+            "title": "User",
+            "type": "object",
+            "properties": {
+                "name": { "type": "string" },
+                "fullName": { "type": "string" }
+            }
+        });
+
+        let result = generate_add_columns_statements(&schema).unwrap();
+
+        assert_eq!(result.len(), 2);
+        assert!(result.iter().any(|stmt| stmt.starts_with(
+            "ALTER TABLE user_projection ADD COLUMN IF NOT EXISTS name TEXT GENERATED ALWAYS AS"
+        )));
+        assert!(result.iter().all(|stmt| stmt.ends_with("STORED;")));
+    }
+
+    #[test]
+    fn test_system_fields_are_exposed_as_columns() {
+        let schema = json!({
+            "title": "Student",
+            "type": "object",
+            "properties": {
+                "Student": {
+                    "type": "object",
+                    "properties": { "name": { "type": "string" } }
+                }
+            },
+            "_osConfig": {
+                "systemFields": ["_osCreatedAt", "osUpdatedBy"]
+            }
+        });
+
+        let create_table = generate_create_table_statement(&schema).unwrap();
+
+        assert!(create_table.contains(
+            "os_created_at TEXT GENERATED ALWAYS AS (entity_data -> 'Student' ->> '_osCreatedAt') STORED"
+        ));
+        assert!(create_table.contains(
+            "os_updated_by TEXT GENERATED ALWAYS AS (entity_data -> 'Student' ->> 'osUpdatedBy') STORED"
+        ));
+    }
+
+    #[test]
+    fn test_private_fields_are_not_projected() {
+        let schema = json!({
+            "title": "Student",
+            "type": "object",
+            "properties": {
+                "Student": {
+                    "type": "object",
+                    "properties": {
+                        "name": { "type": "string" },
+                        "identityDetails": {
+                            "type": "object",
+                            "properties": {
+                                "dob": { "type": "string", "format": "date" },
+                                "gender": { "type": "string" }
+                            }
+                        }
+                    }
+                }
+            },
+            "_osConfig": {
+                "privateFields": ["$.identityDetails.dob"],
+                "indexFields": ["dob", "name"]
+            }
+        });
+
+        let create_table = generate_create_table_statement(&schema).unwrap();
+        let add_columns = generate_add_columns_statements(&schema).unwrap();
+        let indexes = generate_index_statements(&schema).unwrap();
+
+        assert!(create_table.contains("student_identitydetails_gender TEXT"));
+        assert!(!create_table.contains("student_identitydetails_dob"));
+        assert!(add_columns.iter().all(|stmt| !stmt.contains("dob")));
+        assert!(indexes.iter().all(|stmt| !stmt.contains("dob")));
+        assert!(indexes.iter().any(|stmt| stmt.contains("student_name")));
+    }
+
+    #[test]
+    fn test_internal_columns() {
+        let schema = json!({
+            "title": "Student",
+            "type": "object",
+            "properties": {
+                "Student": {
+                    "type": "object",
+                    "properties": {
+                        "name": { "type": "string" },
+                        "contactDetails": {
+                            "type": "object",
+                            "properties": {
+                                "email": { "type": "string" },
+                                "mobile": { "type": "string" },
+                                "address": { "type": "string" }
+                            }
+                        }
+                    }
+                }
+            },
+            "_osConfig": {
+                "internalFields": ["$.contactDetails.email", "$.contactDetails.mobile"]
+            }
+        });
+
+        assert_eq!(
+            internal_columns(&schema).unwrap(),
+            vec![
+                "student_contactdetails_email".to_string(),
+                "student_contactdetails_mobile".to_string()
+            ]
+        );
+    }
 }
@@ -1,8 +1,25 @@
-use crate::routes::{definition_routes, entity_routes};
+use crate::routes::{
+    certificate_routes, claim_routes, consent_routes, credential_routes, definition_routes,
+    entity_routes, notification_routes, registration_routes, service_account_routes,
+    signature_routes, verification_routes, wallet_routes,
+};
 use actix_web::{web, Scope};
 
 pub fn routes() -> Scope {
     web::scope("/api")
         .service(web::scope("/v1/entity").service(entity_routes::routes()))
+        .service(web::scope("/v1/me").service(entity_routes::me_routes()))
         .service(web::scope("/v1/schema").service(definition_routes::routes()))
+        .service(web::scope("/v1/signatures").service(signature_routes::routes()))
+        .service(web::scope("/v1/claims").service(claim_routes::routes()))
+        .service(web::scope("/v1/consents").service(consent_routes::routes()))
+        .service(web::scope("/v1/credentials").service(credential_routes::status_routes()))
+        .service(web::scope("/v1/verify").service(verification_routes::routes()))
+        .service(web::scope("/v1/wallet").service(wallet_routes::routes()))
+        .service(web::scope("/v1/registrations").service(registration_routes::routes()))
+        .service(web::scope("/v1/notifications").service(notification_routes::routes()))
+        .service(web::scope("/v1/service-accounts").service(service_account_routes::routes()))
+        .service(
+            web::scope("/v1/certificate-templates").service(certificate_routes::template_routes()),
+        )
 }
@@ -0,0 +1,315 @@
+use crate::middleware::authorization::{require_permission, WRITE_DEFINITIONS};
+use crate::middleware::claims::Claims;
+use crate::routes::credential_routes::load_credentials;
+use crate::routes::ErrorResponse;
+use crate::services::blob_store::{BlobStore, BlobStoreError};
+use crate::{base_url, DError, SuccessResponse};
+use crate::{API_PREFIX, COMMANDS, DEFINITIONS, ENTITY, QUERY};
+use actix_web::web::{Bytes, Data};
+use actix_web::{get, put, web, HttpResponse, Scope};
+use definitions_core::certificates::{
+    certificate_media_type, certificate_template_uri, qr_payload, render_certificate, QrContent,
+};
+use definitions_core::encryption::FieldCipher;
+use definitions_core::json_path::entity_node;
+use definitions_core::registry_domain::EntityError;
+use serde::Deserialize;
+#[allow(unused_imports)]
+use serde_json::{json, Value};
+use sqlx::PgPool;
+use utoipa::IntoParams;
+use uuid::Uuid;
+
+/// Mounted inside [`crate::routes::entity_routes::routes`], ahead of the credential routes
+pub fn routes() -> Scope {
+    web::scope("/{entity_type}/{id}/certificate").service(get_certificate)
+}
+
+/// Certificate templates, mounted under `/api/v1/certificate-templates`
+pub fn template_routes() -> Scope {
+    web::scope("").service(put_certificate_template)
+}
+
+#[derive(Debug, Deserialize, IntoParams)]
+pub struct CertificateQuery {
+    /// Name of the template in `_osConfig.certificateTemplates`
+    pub template: String,
+    /// What the QR code carries, the credential when it fits or else the verification URL by
+    /// default
+    pub qr: Option<QrContent>,
+}
+
+/// URL verifying a credential, carried by the QR code of certificates
+pub(crate) fn credential_verification_url(credential_id: Uuid) -> String {
+    format!("{}{API_PREFIX}/verify/{}", base_url(), credential_id)
+}
+
+fn domain_error(e: EntityError) -> DError {
+    DError::from(disintegrate::DecisionError::Domain(e))
+}
+
+fn database_error(e: sqlx::Error) -> HttpResponse {
+    log::error!("Failed to render a certificate: {}", e);
+    HttpResponse::InternalServerError().json(ErrorResponse {
+        error: Some("DATABASE_ERROR".to_string()),
+        error_description: Some(format!("Database error: {}", e)),
+        message: "Failed to render the certificate".to_string(),
+    })
+}
+
+/// Get the certificate of an entity
+///
+/// Renders a template of the definition `certificateTemplates` with the entity data, as HTML or
+/// SVG after the template file extension. The embedded QR code carries the most recent valid
+/// credential of the entity, or the URL verifying it, so a credential must have been issued
+/// first. Only the owners of the entity, or holders of one of the definition `roles`, may render
+/// it.
+#[utoipa::path(
+    get,
+    path = "/api/v1/entity/{entity_type}/{id}/certificate",
+    tags= [ENTITY, QUERY],
+    params(
+        ("entity_type" = String, Path, description = "Entity type", example = "Insurance"),
+        ("id" = String, Path, description = "Entity ID (UUID format)"),
+        CertificateQuery
+    ),
+    responses(
+        (status = 200, description = "Rendered certificate, HTML or SVG", content_type = "text/html", body = String),
+        (status = 400, description = "The template cannot be rendered", body = String),
+        (status = 401, description = "Missing or invalid access token", body = ErrorResponse),
+        (status = 403, description = "Caller neither owns the entity nor holds a definition role", body = String),
+        (status = 404, description = "Entity, template or stored template not found", body = ErrorResponse),
+        (status = 409, description = "No valid credential has been issued for the entity", body = String),
+        (status = 500, description = "Internal server error", body = ErrorResponse),
+    )
+)]
+#[get("")]
+async fn get_certificate(
+    db_pool: Data<PgPool>,
+    cipher: Data<FieldCipher>,
+    blob_store: Data<dyn BlobStore>,
+    path: web::Path<(String, Uuid)>,
+    query: web::Query<CertificateQuery>,
+    claims: Claims,
+) -> Result<HttpResponse, DError> {
+    let (entity_type, entity_id) = path.into_inner();
+    let schema = match sqlx::query_scalar::<_, Value>(
+        "SELECT json_schema_string FROM definitions WHERE title = $1",
+    )
+    .bind(&entity_type)
+    .fetch_optional(db_pool.get_ref())
+    .await
+    {
+        Ok(Some(schema)) => schema,
+        Ok(None) => {
+            return Ok(HttpResponse::NotFound().json(ErrorResponse {
+                error: Some("INVALID_ENTITY_TYPE".to_string()),
+                error_description: Some(format!(
+                    "Entity type '{}' not found in definitions",
+                    entity_type
+                )),
+                message: "Entity type invalid".to_string(),
+            }))
+        }
+        Err(e) => return Ok(database_error(e)),
+    };
+    let template_uri = certificate_template_uri(&schema.to_string(), &entity_type, &query.template)
+        .map_err(domain_error)?;
+
+    let credentials = match load_credentials(
+        db_pool.get_ref(),
+        &claims.principal(),
+        &entity_type,
+        entity_id,
+    )
+    .await
+    {
+        Ok(credentials) => credentials.map_err(domain_error)?,
+        Err(e) => return Ok(database_error(e)),
+    };
+    // The definition credential is preferred over the credentials of attested policies
+    let mut valid = credentials
+        .into_iter()
+        .map(|row| row.into_record(&cipher))
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(domain_error)?
+        .into_iter()
+        .filter(|record| record.revoked_at.is_none() && !record.suspended)
+        .collect::<Vec<_>>();
+    valid.sort_by_key(|record| record.policy_name.is_some());
+    let Some(credential) = valid.into_iter().next() else {
+        return Err(domain_error(EntityError::CertificateNotAvailable(
+            "no valid credential has been issued for the entity".to_string(),
+        )));
+    };
+
+    let mut entity_data = match sqlx::query_scalar::<_, Value>(&format!(
+        "SELECT entity_data FROM {}_projection WHERE id = $1",
+        entity_type.to_lowercase()
+    ))
+    .bind(entity_id)
+    .fetch_optional(db_pool.get_ref())
+    .await
+    {
+        Ok(Some(entity_data)) => entity_data,
+        Ok(None) => return Err(domain_error(EntityError::EntityNotFound(entity_id))),
+        Err(e) => return Ok(database_error(e)),
+    };
+    cipher
+        .decrypt_all(&mut entity_data)
+        .map_err(|e| domain_error(EntityError::Encryption(e.to_string())))?;
+
+    let template = match blob_store.get(&template_uri).await {
+        Ok(Some(template)) => String::from_utf8_lossy(&template).into_owned(),
+        Ok(None) => {
+            return Ok(HttpResponse::NotFound().json(ErrorResponse {
+                error: Some("TEMPLATE_NOT_FOUND".to_string()),
+                error_description: Some(format!("Nothing is stored under {}", template_uri)),
+                message: "Certificate template not found".to_string(),
+            }))
+        }
+        Err(e) => {
+            log::error!("Failed to read template {}: {}", template_uri, e);
+            return Ok(HttpResponse::InternalServerError().json(ErrorResponse {
+                error: Some("STORAGE_ERROR".to_string()),
+                error_description: Some(e.to_string()),
+                message: "Failed to read the certificate template".to_string(),
+            }));
+        }
+    };
+
+    let signed = match &credential.credential {
+        Value::String(jwt) => jwt.clone(),
+        document => document.to_string(),
+    };
+    let verification_url = credential_verification_url(credential.id);
+    let payload = qr_payload(&signed, &verification_url, query.qr).map_err(domain_error)?;
+    let certificate = render_certificate(
+        &template,
+        entity_node(&entity_data, &entity_type),
+        entity_id,
+        payload,
+    )
+    .map_err(domain_error)?;
+    Ok(HttpResponse::Ok()
+        .content_type(certificate_media_type(&template_uri))
+        .body(certificate))
+}
+
+/// Store a certificate template
+///
+/// Stores an HTML or SVG template in the blob store, which requires the `write:definitions`
+/// permission. The path is the template URI without its
+/// scheme: a definition declaring `"first": "minio://Insurance/template.html"` in its
+/// `certificateTemplates` renders what is stored at `/api/v1/certificate-templates/Insurance/template.html`.
+#[utoipa::path(
+    put,
+    path = "/api/v1/certificate-templates/{key}",
+    tags= [DEFINITIONS, COMMANDS],
+    params(
+        ("key" = String, Path, description = "Template URI without its scheme", example = "Insurance/template.html")
+    ),
+    request_body(
+        content = String,
+        content_type = "text/html",
+        example = json!("<html><body><h1>{{fullName}}</h1>{{{qrCode}}}</body></html>")
+    ),
+    responses(
+        (status = 201, description = "Template stored", body = String),
+        (status = 400, description = "Invalid key", body = ErrorResponse),
+        (status = 401, description = "Missing or invalid access token", body = ErrorResponse),
+        (status = 403, description = "Caller lacks the `write:definitions` permission", body = String),
+        (status = 500, description = "Internal server error", body = ErrorResponse),
+    ),
+    security(
+        ("bearer_auth" = ["write:definitions"])
+    )
+)]
+#[put("/{key:.*}")]
+async fn put_certificate_template(
+    blob_store: Data<dyn BlobStore>,
+    key: web::Path<String>,
+    claims: Claims,
+    body: Bytes,
+) -> Result<HttpResponse, DError> {
+    require_permission(&claims, WRITE_DEFINITIONS, "store certificate templates")?;
+    let key = key.into_inner();
+    Ok(match blob_store.put(&key, &body).await {
+        Ok(()) => HttpResponse::Created().json(SuccessResponse {
+            id: key,
+            message: "Certificate template stored".to_string(),
+        }),
+        Err(e @ BlobStoreError::InvalidKey(_)) => HttpResponse::BadRequest().json(ErrorResponse {
+            error: Some("INVALID_KEY".to_string()),
+            error_description: Some(e.to_string()),
+            message: "Failed to store the certificate template".to_string(),
+        }),
+        Err(e) => {
+            log::error!("Failed to store template {}: {}", key, e);
+            HttpResponse::InternalServerError().json(ErrorResponse {
+                error: Some("STORAGE_ERROR".to_string()),
+                error_description: Some(e.to_string()),
+                message: "Failed to store the certificate template".to_string(),
+            })
+        }
+    })
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used)]
+mod tests {
+    use super::*;
+    use crate::services::blob_store::FileBlobStore;
+    use crate::services::token_verifier::{KeySource, TokenVerifier, TrustedIssuer};
+    use actix_web::http::{header, StatusCode};
+    use actix_web::{test, App};
+    use jsonwebtoken::{encode, Algorithm, EncodingKey, Header};
+    use std::sync::Arc;
+
+    fn token(permissions: &[&str]) -> String {
+        encode(
+            &Header::new(Algorithm::HS256),
+            &json!({
+                "iss": "http://localhost:8000",
+                "exp": chrono::Utc::now().timestamp() + 300,
+                "sub": "user-1",
+                "permissions": permissions
+            }),
+            &EncodingKey::from_secret(b"test-secret"),
+        )
+        .unwrap()
+    }
+
+    #[actix_web::test]
+    async fn test_storing_templates_requires_write_definitions() {
+        let root = std::env::temp_dir().join(format!("rc-templates-{}", Uuid::now_v7()));
+        let blob_store: Arc<dyn BlobStore> = Arc::new(FileBlobStore::new(&root));
+        let verifier = TokenVerifier::new(vec![TrustedIssuer::new(
+            "http://localhost:8000",
+            KeySource::Secret(b"test-secret".to_vec()),
+        )
+        .with_any_audience()]);
+        let app = test::init_service(
+            App::new()
+                .app_data(Data::new(verifier))
+                .app_data(Data::from(blob_store))
+                .service(template_routes()),
+        )
+        .await;
+        let put = |token: String| {
+            test::TestRequest::put()
+                .uri("/Insurance/template.html")
+                .insert_header((header::AUTHORIZATION, format!("Bearer {}", token)))
+                .set_payload("<html>{{fullName}}</html>")
+                .to_request()
+        };
+
+        let denied = test::call_service(&app, put(token(&["read:entities"]))).await;
+        assert_eq!(denied.status(), StatusCode::FORBIDDEN);
+        assert!(!root.join("Insurance/template.html").exists());
+
+        let stored = test::call_service(&app, put(token(&[WRITE_DEFINITIONS]))).await;
+        assert_eq!(stored.status(), StatusCode::CREATED);
+        assert!(root.join("Insurance/template.html").exists());
+    }
+}
@@ -0,0 +1,295 @@
+use crate::middleware::claims::Claims;
+use crate::routes::ErrorResponse;
+use crate::{DError, DecisionMaker, SuccessResponse};
+use crate::{COMMANDS, ENTITY, QUERY};
+use actix_web::web::{Data, Json};
+use actix_web::{get, post, web, HttpResponse, Scope};
+use chrono::{DateTime, Utc};
+use definitions_core::attestation::{
+    AttestClaimCmd, AttestationAction, ClaimStatus, RaiseClaimCmd,
+};
+use definitions_core::registry_domain::{EntityError, EntityId};
+use serde::{Deserialize, Serialize};
+#[allow(unused_imports)]
+use serde_json::{json, Value};
+use sqlx::{FromRow, PgPool};
+use utoipa::ToSchema;
+use uuid::Uuid;
+
+pub fn routes() -> Scope {
+    web::scope("")
+        .service(raise_claim)
+        .service(get_claims)
+        .service(attest_claim)
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct RaiseClaimRequest {
+    pub entity_type: String,
+    pub entity_id: Uuid,
+    /// Name of the attestation policy, as declared in `attestationPolicies`
+    pub policy_name: String,
+    /// The attestor entity to route the claim to, whose owners are notified
+    pub attestor_id: Option<Uuid>,
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct AttestClaimRequest {
+    pub action: AttestationAction,
+    /// The attestor record of the caller, defaults to the one the caller owns
+    pub attestor_id: Option<Uuid>,
+    pub notes: Option<String>,
+}
+
+#[derive(Debug, Serialize, FromRow, ToSchema)]
+pub struct ClaimRecord {
+    pub id: Uuid,
+    pub entity_id: Uuid,
+    pub entity_type: String,
+    pub policy_name: String,
+    pub attestor_entity: String,
+    /// Snapshot of the properties to attest
+    #[schema(value_type = Object)]
+    pub property_data: Value,
+    pub status: String,
+    pub raised_by: String,
+    pub raised_at: DateTime<Utc>,
+    pub attestor_id: Option<Uuid>,
+    pub closed_by: Option<String>,
+    pub closed_at: Option<DateTime<Utc>>,
+    pub notes: Option<String>,
+}
+
+/// Raise a claim
+///
+/// Asks the attestors of an attestation policy to attest the current values of its properties.
+/// Only the owners of the entity, or holders of one of the definition `roles`, may raise a claim.
+/// A claim routed to an `attestor_id` is notified to its owners and may only be decided by them.
+#[utoipa::path(
+    post,
+    path = "/api/v1/claims",
+    tags= [ENTITY, COMMANDS],
+    request_body(
+        content = RaiseClaimRequest,
+        content_type = "application/json",
+        examples(
+            ("Insurance" = (value = json!({"entity_type": "Insurance", "entity_id": "0196d2b4-3c2a-7d4e-8f00-000000000a01", "policy_name": "cropApprovalPolicy", "attestor_id": "0196d2b4-3c2a-7d4e-8f00-000000000a02"}), description = "Claim on an insurance policy")),
+        )
+    ),
+    responses(
+        (status = 201, description = "Claim raised", body = String),
+        (status = 400, description = "Bad request", body = ErrorResponse),
+        (status = 401, description = "Missing or invalid access token", body = ErrorResponse),
+        (status = 403, description = "Caller neither owns the entity nor holds a definition role, or the attestor is not one of the policy", body = String),
+        (status = 404, description = "Entity or attestation policy not found", body = String),
+    )
+)]
+#[post("")]
+async fn raise_claim(
+    decision_maker: Data<DecisionMaker>,
+    claims: Claims,
+    request: Json<RaiseClaimRequest>,
+) -> Result<HttpResponse, DError> {
+    let request = request.into_inner();
+    let claim_id = Uuid::now_v7();
+    decision_maker
+        .make(RaiseClaimCmd {
+            claim_id,
+            entity_id: request.entity_id,
+            entity_type: request.entity_type,
+            policy_name: request.policy_name.clone(),
+            attestor_id: request.attestor_id,
+            principal: claims.principal(),
+        })
+        .await?;
+
+    Ok(HttpResponse::Created().json(SuccessResponse {
+        id: claim_id.to_string(),
+        message: format!("Claim raised on policy {}", request.policy_name),
+    }))
+}
+
+/// Get my claims
+///
+/// Lists the claims raised by the caller and the claims routed to the caller, i.e. raised on a
+/// policy whose `attestorEntity` has an entity owned by the caller, or routed to such an entity.
+#[utoipa::path(
+    get,
+    path = "/api/v1/claims",
+    tags= [ENTITY, QUERY],
+    params(
+        ("status" = Option<String>, Query, description = "Filter by status: Open, Attested or Rejected", example = "Open")
+    ),
+    responses(
+        (status = 200, description = "Claims of the caller", body = Vec<ClaimRecord>),
+        (status = 401, description = "Missing or invalid access token", body = ErrorResponse),
+        (status = 500, description = "Internal server error", body = ErrorResponse),
+    )
+)]
+#[get("")]
+async fn get_claims(
+    db_pool: Data<PgPool>,
+    claims: Claims,
+    query: web::Query<ClaimsQuery>,
+) -> HttpResponse {
+    let principal = claims.principal();
+    let result = sqlx::query_as::<_, ClaimRecord>(
+        r#"
+        SELECT c.id, c.entity_id, c.entity_type, c.policy_name, c.attestor_entity, c.property_data, c.status,
+               c.raised_by, c.raised_at, c.attestor_id, c.closed_by, c.closed_at, c.notes
+        FROM claims c
+        WHERE ($3::TEXT IS NULL OR c.status = $3)
+          AND ((c.raised_by <> '' AND c.raised_by = $1)
+               OR EXISTS (
+                   SELECT 1 FROM entity_owners o
+                   WHERE o.entity_type = c.attestor_entity
+                     AND (c.attestor_id IS NULL OR o.entity_id = c.attestor_id)
+                     AND (($1 <> '' AND o.user_id = $1) OR lower(o.email) = lower($2))
+               ))
+        ORDER BY c.raised_at DESC
+        "#,
+    )
+    .bind(&principal.subject)
+    .bind(principal.email.as_deref())
+    .bind(query.status.as_deref())
+    .fetch_all(db_pool.get_ref())
+    .await;
+
+    match result {
+        Ok(records) => HttpResponse::Ok().json(records),
+        Err(e) => {
+            log::error!("Failed to fetch claims: {}", e);
+            HttpResponse::InternalServerError().json(ErrorResponse {
+                error: Some("DATABASE_ERROR".to_string()),
+                error_description: Some(format!("Database error: {}", e)),
+                message: "Failed to fetch claims".to_string(),
+            })
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ClaimsQuery {
+    pub status: Option<String>,
+}
+
+/// Attest a claim
+///
+/// Grants or rejects an open claim. The caller must own an entity of the `attestorEntity` of
+/// the claimed policy, the one the claim was routed to if any. A granted claim stores the attested snapshot in `_osAttestedData`.
+#[utoipa::path(
+    post,
+    path = "/api/v1/claims/{claim_id}/attest",
+    tags= [ENTITY, COMMANDS],
+    request_body(
+        content = AttestClaimRequest,
+        content_type = "application/json",
+        examples(
+            ("Grant" = (value = json!({"action": "GRANT_CLAIM", "notes": "Verified against the policy register"}), description = "Grant the claim")),
+            ("Reject" = (value = json!({"action": "REJECT_CLAIM", "notes": "Policy number does not match"}), description = "Reject the claim")),
+        )
+    ),
+    params(
+        ("claim_id" = String, Path, description = "Claim ID (UUID format)")
+    ),
+    responses(
+        (status = 200, description = "Claim attested or rejected", body = String),
+        (status = 401, description = "Missing or invalid access token", body = ErrorResponse),
+        (status = 403, description = "Caller is not an attestor of the claim", body = String),
+        (status = 404, description = "Claim not found", body = ErrorResponse),
+        (status = 409, description = "Claim already attested or rejected", body = String),
+    )
+)]
+#[post("/{claim_id}/attest")]
+async fn attest_claim(
+    decision_maker: Data<DecisionMaker>,
+    db_pool: Data<PgPool>,
+    claim_id: web::Path<Uuid>,
+    claims: Claims,
+    request: Json<AttestClaimRequest>,
+) -> Result<HttpResponse, DError> {
+    let claim_id = claim_id.into_inner();
+    let request = request.into_inner();
+    let principal = claims.principal();
+
+    let claim: Option<(Uuid, String, String, Option<Uuid>)> = match sqlx::query_as(
+        "SELECT entity_id, entity_type, attestor_entity, attestor_id FROM claims WHERE id = $1",
+    )
+    .bind(claim_id)
+    .fetch_optional(db_pool.get_ref())
+    .await
+    {
+        Ok(claim) => claim,
+        Err(e) => {
+            log::error!("Failed to read claim {}: {}", claim_id, e);
+            return Ok(HttpResponse::InternalServerError().json(ErrorResponse {
+                error: Some("DATABASE_ERROR".to_string()),
+                error_description: Some(format!("Database error: {}", e)),
+                message: "Failed to attest claim".to_string(),
+            }));
+        }
+    };
+    let Some((entity_id, entity_type, attestor_entity, routed_to)) = claim else {
+        return Err(DError::from(disintegrate::DecisionError::Domain(
+            EntityError::ClaimNotFound(claim_id),
+        )));
+    };
+
+    let attestor_id = match request.attestor_id.or(routed_to) {
+        Some(attestor_id) => Some(attestor_id),
+        None => find_attestor_id(
+            db_pool.get_ref(),
+            &attestor_entity,
+            &principal.subject,
+            principal.email.as_deref(),
+        )
+        .await
+        .unwrap_or_else(|e| {
+            log::error!("Failed to find the attestor record of the caller: {}", e);
+            None
+        }),
+    };
+    let Some(attestor_id) = attestor_id else {
+        return Err(DError::from(disintegrate::DecisionError::Domain(
+            EntityError::NotAuthorized(principal.subject, entity_id),
+        )));
+    };
+
+    decision_maker
+        .make(AttestClaimCmd {
+            claim_id,
+            entity_id,
+            entity_type,
+            attestor_id,
+            action: request.action,
+            notes: request.notes,
+            principal,
+        })
+        .await?;
+
+    let status = match request.action {
+        AttestationAction::GrantClaim => ClaimStatus::Attested,
+        AttestationAction::RejectClaim => ClaimStatus::Rejected,
+    };
+    Ok(HttpResponse::Ok().json(SuccessResponse {
+        id: claim_id.to_string(),
+        message: format!("Claim {}", status),
+    }))
+}
+
+/// The first entity of `attestor_entity` owned by the caller
+async fn find_attestor_id(
+    db_pool: &PgPool,
+    attestor_entity: &str,
+    subject: &str,
+    email: Option<&str>,
+) -> Result<Option<EntityId>, sqlx::Error> {
+    sqlx::query_scalar(
+        "SELECT entity_id FROM entity_owners WHERE entity_type = $1 AND (($2 <> '' AND user_id = $2) OR lower(email) = lower($3)) ORDER BY entity_id LIMIT 1",
+    )
+    .bind(attestor_entity)
+    .bind(subject)
+    .bind(email)
+    .fetch_optional(db_pool)
+    .await
+}
@@ -0,0 +1,462 @@
+use crate::middleware::claims::Claims;
+use crate::routes::ErrorResponse;
+use crate::{DError, DecisionMaker, SuccessResponse};
+use crate::{COMMANDS, ENTITY, QUERY};
+use actix_web::web::{Data, Json};
+use actix_web::{get, post, web, HttpResponse, Scope};
+use chrono::{DateTime, Utc};
+use definitions_core::consent::{
+    consented_data, is_requester, AccessConsentedDataCmd, ConsentAction, ConsentStatus,
+    DecideConsentCmd, RequestConsentCmd,
+};
+use definitions_core::encryption::FieldCipher;
+use definitions_core::ownership::{Owner, Principal};
+use definitions_core::registry_domain::EntityError;
+use serde::{Deserialize, Serialize};
+#[allow(unused_imports)]
+use serde_json::{json, Value};
+use sqlx::{FromRow, PgPool};
+use utoipa::ToSchema;
+use uuid::Uuid;
+
+pub fn routes() -> Scope {
+    web::scope("")
+        .service(request_consent)
+        .service(get_consents)
+        .service(decide_consent)
+        .service(get_consented_data)
+        .service(get_consent_access_log)
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct RequestConsentRequest {
+    pub entity_type: String,
+    pub entity_id: Uuid,
+    /// Paths of the requested fields, e.g. `$.policyNumber`
+    pub fields: Vec<String>,
+    pub purpose: Option<String>,
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct DecideConsentRequest {
+    pub action: ConsentAction,
+    /// End of the consent, required to grant it
+    pub expires_at: Option<DateTime<Utc>>,
+    pub notes: Option<String>,
+}
+
+#[derive(Debug, Serialize, FromRow, ToSchema)]
+pub struct ConsentRecord {
+    pub id: Uuid,
+    pub entity_id: Uuid,
+    pub entity_type: String,
+    /// Paths of the requested fields
+    #[schema(value_type = Vec<String>)]
+    pub fields: Value,
+    pub purpose: Option<String>,
+    pub status: String,
+    pub requested_by: String,
+    pub requested_at: DateTime<Utc>,
+    pub expires_at: Option<DateTime<Utc>>,
+    pub decided_by: Option<String>,
+    pub decided_at: Option<DateTime<Utc>>,
+    pub notes: Option<String>,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct ConsentedData {
+    pub consent_id: Uuid,
+    pub entity_id: Uuid,
+    pub entity_type: String,
+    pub expires_at: Option<DateTime<Utc>>,
+    /// The consented fields of the entity
+    #[schema(value_type = Object)]
+    pub data: Value,
+}
+
+#[derive(Debug, Serialize, FromRow, ToSchema)]
+pub struct ConsentAccess {
+    pub consent_id: Uuid,
+    pub entity_id: Uuid,
+    /// Paths of the fields read
+    #[schema(value_type = Vec<String>)]
+    pub fields: Value,
+    pub accessed_by: String,
+    /// The requester an impersonating actor read the fields for
+    pub on_behalf_of: Option<String>,
+    pub accessed_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ConsentsQuery {
+    pub status: Option<String>,
+}
+
+fn database_error(e: sqlx::Error, message: &str) -> HttpResponse {
+    log::error!("{}: {}", message, e);
+    HttpResponse::InternalServerError().json(ErrorResponse {
+        error: Some("DATABASE_ERROR".to_string()),
+        error_description: Some(format!("Database error: {}", e)),
+        message: message.to_string(),
+    })
+}
+
+fn consent_not_found(consent_id: Uuid) -> DError {
+    DError::from(disintegrate::DecisionError::Domain(
+        EntityError::ConsentNotFound(consent_id),
+    ))
+}
+
+async fn load_consent(
+    db_pool: &PgPool,
+    consent_id: Uuid,
+) -> Result<Option<ConsentRecord>, sqlx::Error> {
+    sqlx::query_as::<_, ConsentRecord>(
+        r#"
+        SELECT id, entity_id, entity_type, fields, purpose, status, requested_by, requested_at,
+               expires_at, decided_by, decided_at, notes
+        FROM consents WHERE id = $1
+        "#,
+    )
+    .bind(consent_id)
+    .fetch_optional(db_pool)
+    .await
+}
+
+/// The owners of an entity, as projected in `entity_owners`
+pub(crate) async fn entity_owners(
+    db_pool: &PgPool,
+    entity_id: Uuid,
+) -> Result<Vec<Owner>, sqlx::Error> {
+    Ok(
+        sqlx::query_as::<_, (Option<String>, Option<String>, Option<String>)>(
+            "SELECT user_id, email, mobile FROM entity_owners WHERE entity_id = $1",
+        )
+        .bind(entity_id)
+        .fetch_all(db_pool)
+        .await?
+        .into_iter()
+        .map(|(user_id, email, mobile)| Owner {
+            user_id,
+            email,
+            mobile,
+        })
+        .collect(),
+    )
+}
+
+/// Returns true when the principal is one of the owners of an entity
+pub(crate) async fn owns_entity(
+    db_pool: &PgPool,
+    entity_id: Uuid,
+    principal: &Principal,
+) -> Result<bool, sqlx::Error> {
+    sqlx::query_scalar(
+        "SELECT EXISTS (SELECT 1 FROM entity_owners WHERE entity_id = $1 AND (($2 <> '' AND user_id = $2) OR lower(email) = lower($3)))",
+    )
+    .bind(entity_id)
+    .bind(&principal.subject)
+    .bind(principal.email.as_deref())
+    .fetch_one(db_pool)
+    .await
+}
+
+/// Request consent
+///
+/// Asks the owners of an entity for some of its fields. Once one of them grants the consent, the
+/// caller reads the consented fields at `/api/v1/consents/{consent_id}/data` until it expires.
+#[utoipa::path(
+    post,
+    path = "/api/v1/consents",
+    tags= [ENTITY, COMMANDS],
+    request_body(
+        content = RequestConsentRequest,
+        content_type = "application/json",
+        examples(
+            ("Insurance" = (value = json!({"entity_type": "Insurance", "entity_id": "0196d2b4-3c2a-7d4e-8f00-000000000a01", "fields": ["$.policyNumber", "$.fullName"], "purpose": "Loan KYC"}), description = "Consent to read an insurance policy")),
+        )
+    ),
+    responses(
+        (status = 201, description = "Consent requested", body = String),
+        (status = 400, description = "Invalid fields", body = ErrorResponse),
+        (status = 401, description = "Missing or invalid access token", body = ErrorResponse),
+        (status = 404, description = "Entity not found", body = String),
+    )
+)]
+#[post("")]
+async fn request_consent(
+    decision_maker: Data<DecisionMaker>,
+    claims: Claims,
+    request: Json<RequestConsentRequest>,
+) -> Result<HttpResponse, DError> {
+    let request = request.into_inner();
+    let consent_id = Uuid::now_v7();
+    decision_maker
+        .make(RequestConsentCmd {
+            consent_id,
+            entity_id: request.entity_id,
+            entity_type: request.entity_type,
+            fields: request.fields,
+            purpose: request.purpose,
+            principal: claims.principal(),
+        })
+        .await?;
+
+    Ok(HttpResponse::Created().json(SuccessResponse {
+        id: consent_id.to_string(),
+        message: format!("Consent requested on entity {}", request.entity_id),
+    }))
+}
+
+/// Get my consents
+///
+/// Lists the consents requested by the caller and the consents requested on entities the caller
+/// owns.
+#[utoipa::path(
+    get,
+    path = "/api/v1/consents",
+    tags= [ENTITY, QUERY],
+    params(
+        ("status" = Option<String>, Query, description = "Filter by status: Requested, Granted, Denied or Revoked", example = "Requested")
+    ),
+    responses(
+        (status = 200, description = "Consents of the caller", body = Vec<ConsentRecord>),
+        (status = 401, description = "Missing or invalid access token", body = ErrorResponse),
+        (status = 500, description = "Internal server error", body = ErrorResponse),
+    )
+)]
+#[get("")]
+async fn get_consents(
+    db_pool: Data<PgPool>,
+    claims: Claims,
+    query: web::Query<ConsentsQuery>,
+) -> HttpResponse {
+    let principal = claims.principal();
+    let result = sqlx::query_as::<_, ConsentRecord>(
+        r#"
+        SELECT c.id, c.entity_id, c.entity_type, c.fields, c.purpose, c.status, c.requested_by,
+               c.requested_at, c.expires_at, c.decided_by, c.decided_at, c.notes
+        FROM consents c
+        WHERE ($3::TEXT IS NULL OR c.status = $3)
+          AND ((c.requested_by <> '' AND c.requested_by = $1)
+               OR EXISTS (
+                   SELECT 1 FROM entity_owners o
+                   WHERE o.entity_id = c.entity_id
+                     AND (($1 <> '' AND o.user_id = $1) OR lower(o.email) = lower($2))
+               ))
+        ORDER BY c.requested_at DESC
+        "#,
+    )
+    .bind(&principal.subject)
+    .bind(principal.email.as_deref())
+    .bind(query.status.as_deref())
+    .fetch_all(db_pool.get_ref())
+    .await;
+
+    match result {
+        Ok(records) => HttpResponse::Ok().json(records),
+        Err(e) => database_error(e, "Failed to fetch consents"),
+    }
+}
+
+/// Decide on a consent
+///
+/// Grants or denies a requested consent, or revokes a granted one. Only the owners of the entity
+/// may decide. A granted consent must expire.
+#[utoipa::path(
+    post,
+    path = "/api/v1/consents/{consent_id}/decide",
+    tags= [ENTITY, COMMANDS],
+    request_body(
+        content = DecideConsentRequest,
+        content_type = "application/json",
+        examples(
+            ("Grant" = (value = json!({"action": "GRANT_CONSENT", "expires_at": "2030-01-01T00:00:00Z"}), description = "Grant the consent until 2030")),
+            ("Deny" = (value = json!({"action": "DENY_CONSENT", "notes": "Not needed for the loan"}), description = "Deny the consent")),
+            ("Revoke" = (value = json!({"action": "REVOKE_CONSENT"}), description = "Revoke a granted consent")),
+        )
+    ),
+    params(
+        ("consent_id" = String, Path, description = "Consent ID (UUID format)")
+    ),
+    responses(
+        (status = 200, description = "Consent granted, denied or revoked", body = String),
+        (status = 400, description = "Missing or past expiry", body = ErrorResponse),
+        (status = 401, description = "Missing or invalid access token", body = ErrorResponse),
+        (status = 403, description = "Caller does not own the entity", body = String),
+        (status = 404, description = "Consent not found", body = ErrorResponse),
+        (status = 409, description = "The consent cannot take this decision in its status", body = String),
+    )
+)]
+#[post("/{consent_id}/decide")]
+async fn decide_consent(
+    decision_maker: Data<DecisionMaker>,
+    db_pool: Data<PgPool>,
+    consent_id: web::Path<Uuid>,
+    claims: Claims,
+    request: Json<DecideConsentRequest>,
+) -> Result<HttpResponse, DError> {
+    let consent_id = consent_id.into_inner();
+    let request = request.into_inner();
+    let consent = match load_consent(db_pool.get_ref(), consent_id).await {
+        Ok(consent) => consent,
+        Err(e) => return Ok(database_error(e, "Failed to decide on the consent")),
+    };
+    let Some(consent) = consent else {
+        return Err(consent_not_found(consent_id));
+    };
+
+    decision_maker
+        .make(DecideConsentCmd {
+            consent_id,
+            entity_id: consent.entity_id,
+            action: request.action,
+            expires_at: request.expires_at,
+            notes: request.notes,
+            principal: claims.principal(),
+        })
+        .await?;
+
+    let status = match request.action {
+        ConsentAction::GrantConsent => ConsentStatus::Granted,
+        ConsentAction::DenyConsent => ConsentStatus::Denied,
+        ConsentAction::RevokeConsent => ConsentStatus::Revoked,
+    };
+    Ok(HttpResponse::Ok().json(SuccessResponse {
+        id: consent_id.to_string(),
+        message: format!("Consent {}", status),
+    }))
+}
+
+/// Read consented data
+///
+/// Returns the consented fields of the entity to the requester of a granted consent that has not
+/// expired. Every read is recorded in the access log of the consent.
+#[utoipa::path(
+    get,
+    path = "/api/v1/consents/{consent_id}/data",
+    tags= [ENTITY, QUERY],
+    params(
+        ("consent_id" = String, Path, description = "Consent ID (UUID format)")
+    ),
+    responses(
+        (status = 200, description = "Consented fields of the entity", body = ConsentedData),
+        (status = 401, description = "Missing or invalid access token", body = ErrorResponse),
+        (status = 403, description = "Caller is not the requester, or the consent is not granted or expired", body = String),
+        (status = 404, description = "Consent or entity not found", body = ErrorResponse),
+        (status = 500, description = "Internal server error", body = ErrorResponse),
+    )
+)]
+#[get("/{consent_id}/data")]
+async fn get_consented_data(
+    decision_maker: Data<DecisionMaker>,
+    db_pool: Data<PgPool>,
+    cipher: Data<FieldCipher>,
+    consent_id: web::Path<Uuid>,
+    claims: Claims,
+) -> Result<HttpResponse, DError> {
+    let consent_id = consent_id.into_inner();
+    decision_maker
+        .make(AccessConsentedDataCmd {
+            consent_id,
+            principal: claims.principal(),
+        })
+        .await?;
+
+    let consent = match load_consent(db_pool.get_ref(), consent_id).await {
+        Ok(consent) => consent,
+        Err(e) => return Ok(database_error(e, "Failed to read consented data")),
+    };
+    let Some(consent) = consent else {
+        return Err(consent_not_found(consent_id));
+    };
+    let entity_data = match sqlx::query_scalar::<_, Value>(&format!(
+        "SELECT entity_data FROM {}_projection WHERE id = $1",
+        consent.entity_type.to_lowercase()
+    ))
+    .bind(consent.entity_id)
+    .fetch_optional(db_pool.get_ref())
+    .await
+    {
+        Ok(entity_data) => entity_data,
+        Err(e) => return Ok(database_error(e, "Failed to read consented data")),
+    };
+    let Some(mut entity_data) = entity_data else {
+        return Err(DError::from(disintegrate::DecisionError::Domain(
+            EntityError::EntityNotFound(consent.entity_id),
+        )));
+    };
+    cipher.decrypt_all(&mut entity_data).map_err(|e| {
+        DError::from(disintegrate::DecisionError::Domain(
+            EntityError::Encryption(e.to_string()),
+        ))
+    })?;
+    let fields: Vec<String> = serde_json::from_value(consent.fields).unwrap_or_default();
+
+    Ok(HttpResponse::Ok().json(ConsentedData {
+        consent_id,
+        entity_id: consent.entity_id,
+        data: consented_data(&entity_data, &consent.entity_type, &fields),
+        entity_type: consent.entity_type,
+        expires_at: consent.expires_at,
+    }))
+}
+
+/// Get the access log of a consent
+///
+/// Lists the reads of the consented data, most recent first. Only the requester and the owners
+/// of the entity may read the log.
+#[utoipa::path(
+    get,
+    path = "/api/v1/consents/{consent_id}/access-log",
+    tags= [ENTITY, QUERY],
+    params(
+        ("consent_id" = String, Path, description = "Consent ID (UUID format)")
+    ),
+    responses(
+        (status = 200, description = "Accesses under the consent", body = Vec<ConsentAccess>),
+        (status = 401, description = "Missing or invalid access token", body = ErrorResponse),
+        (status = 403, description = "Caller is neither the requester nor an owner", body = String),
+        (status = 404, description = "Consent not found", body = ErrorResponse),
+        (status = 500, description = "Internal server error", body = ErrorResponse),
+    )
+)]
+#[get("/{consent_id}/access-log")]
+async fn get_consent_access_log(
+    db_pool: Data<PgPool>,
+    consent_id: web::Path<Uuid>,
+    claims: Claims,
+) -> Result<HttpResponse, DError> {
+    let consent_id = consent_id.into_inner();
+    let principal = claims.principal();
+    let consent = match load_consent(db_pool.get_ref(), consent_id).await {
+        Ok(consent) => consent,
+        Err(e) => return Ok(database_error(e, "Failed to fetch the access log")),
+    };
+    let Some(consent) = consent else {
+        return Err(consent_not_found(consent_id));
+    };
+    // The requester and the owners, as the consent commands check them
+    if !is_requester(&principal, &consent.requested_by) {
+        match entity_owners(db_pool.get_ref(), consent.entity_id).await {
+            Ok(owners) if principal.owns(&owners) => {}
+            Ok(_) => {
+                return Err(DError::from(disintegrate::DecisionError::Domain(
+                    EntityError::NotAuthorized(principal.subject, consent.entity_id),
+                )))
+            }
+            Err(e) => return Ok(database_error(e, "Failed to fetch the access log")),
+        }
+    }
+
+    match sqlx::query_as::<_, ConsentAccess>(
+        "SELECT consent_id, entity_id, fields, accessed_by, on_behalf_of, accessed_at FROM consent_access_log WHERE consent_id = $1 ORDER BY event_id DESC",
+    )
+    .bind(consent_id)
+    .fetch_all(db_pool.get_ref())
+    .await
+    {
+        Ok(accesses) => Ok(HttpResponse::Ok().json(accesses)),
+        Err(e) => Ok(database_error(e, "Failed to fetch the access log")),
+    }
+}
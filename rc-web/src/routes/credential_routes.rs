@@ -0,0 +1,476 @@
+use crate::middleware::claims::Claims;
+use crate::routes::ErrorResponse;
+use crate::{base_url, DError, SuccessResponse};
+use crate::{API_PREFIX, COMMANDS, ENTITY, QUERY};
+use actix_web::web::{Data, Json};
+use actix_web::{get, post, web, HttpResponse, Scope};
+use chrono::{DateTime, Utc};
+use definitions_core::credentials::{open_credential, CredentialFormat, IssueCredentialCmd};
+use definitions_core::definitions_domain::DomainEvent;
+use definitions_core::did::IssuerIdentity;
+use definitions_core::encryption::FieldCipher;
+use definitions_core::ownership::{declared_roles, Principal};
+use definitions_core::registry_domain::{EntityError, EntityId};
+use definitions_core::status_list::{
+    status_list_credential, ChangeCredentialStatusCmd, CredentialStatusAction, StatusList,
+    StatusPurpose,
+};
+use serde::{Deserialize, Serialize};
+#[allow(unused_imports)]
+use serde_json::{json, Value};
+use sqlx::{FromRow, PgPool};
+use utoipa::ToSchema;
+use uuid::Uuid;
+
+use crate::routes::consent_routes::entity_owners;
+use crate::DecisionMaker;
+
+/// Mounted inside [`crate::routes::entity_routes::routes`], the credentials of an entity live
+/// under `/api/v1/entity/{entity_type}/{id}/credential`
+pub fn routes() -> Scope {
+    web::scope("")
+        .service(issue_credential)
+        .service(get_credentials)
+}
+
+/// Status lists and status changes, mounted under `/api/v1/credentials`
+pub fn status_routes() -> Scope {
+    web::scope("")
+        .service(get_status_list)
+        .service(change_credential_status)
+}
+
+/// Base URL of the status list credentials, embedded in every issued credential
+pub(crate) fn status_lists_url() -> String {
+    format!("{}{API_PREFIX}/credentials/status", base_url())
+}
+
+/// Builds the status list of a purpose from the credentials read model
+pub(crate) async fn load_status_list(
+    db_pool: &PgPool,
+    purpose: StatusPurpose,
+) -> Result<StatusList, sqlx::Error> {
+    let condition = match purpose {
+        StatusPurpose::Revocation => "revoked_at IS NOT NULL",
+        StatusPurpose::Suspension => "suspended",
+    };
+    let indices = sqlx::query_scalar::<_, i64>(&format!(
+        "SELECT status_list_index FROM credentials WHERE status_list_index IS NOT NULL AND {}",
+        condition
+    ))
+    .fetch_all(db_pool)
+    .await?;
+    Ok(StatusList::with_set(
+        indices.into_iter().map(|index| index as u64),
+    ))
+}
+
+#[derive(Debug, Default, Deserialize, ToSchema)]
+pub struct IssueCredentialRequest {
+    /// `ldp_vc` (default), `jwt_vc` or `vc+sd-jwt`
+    #[serde(default)]
+    pub format: CredentialFormat,
+    /// Issues the credential of an attested policy instead of the definition credential
+    pub policy_name: Option<String>,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct CredentialRecord {
+    pub id: Uuid,
+    pub entity_id: Uuid,
+    pub entity_type: String,
+    pub policy_name: Option<String>,
+    pub format: String,
+    /// The signed credential: a JSON-LD document for `ldp_vc`, a compact JWT or SD-JWT otherwise
+    #[schema(value_type = Object)]
+    pub credential: Value,
+    /// Position of the credential in the status lists
+    pub status_list_index: Option<i64>,
+    pub revoked_at: Option<DateTime<Utc>>,
+    pub suspended: bool,
+    pub issued_by: String,
+    pub issued_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct CredentialStatusRequest {
+    pub action: CredentialStatusAction,
+    pub reason: Option<String>,
+}
+
+#[derive(Debug, FromRow)]
+pub(crate) struct CredentialRow {
+    id: Uuid,
+    entity_id: Uuid,
+    entity_type: String,
+    policy_name: Option<String>,
+    format: String,
+    credential: String,
+    encrypted: bool,
+    status_list_index: Option<i64>,
+    revoked_at: Option<DateTime<Utc>>,
+    suspended: bool,
+    issued_by: String,
+    issued_at: DateTime<Utc>,
+}
+
+impl CredentialRow {
+    /// Opens a sealed credential and parses JSON-LD credentials
+    pub(crate) fn into_record(self, cipher: &FieldCipher) -> Result<CredentialRecord, EntityError> {
+        let credential = if self.encrypted {
+            open_credential(cipher, &self.credential)?
+        } else {
+            self.credential
+        };
+        let credential = match serde_json::from_str::<Value>(&credential) {
+            Ok(document) if document.is_object() => document,
+            _ => Value::String(credential),
+        };
+        Ok(CredentialRecord {
+            id: self.id,
+            entity_id: self.entity_id,
+            entity_type: self.entity_type,
+            policy_name: self.policy_name,
+            format: self.format,
+            credential,
+            status_list_index: self.status_list_index,
+            revoked_at: self.revoked_at,
+            suspended: self.suspended,
+            issued_by: self.issued_by,
+            issued_at: self.issued_at,
+        })
+    }
+}
+
+/// Issue a credential
+///
+/// Renders the `credentialTemplate` of the definition, or of an attested policy, with the entity
+/// data and signs it with the registry issuer key. Only the owners of the entity, or holders of
+/// one of the definition `roles`, may issue its credentials.
+#[utoipa::path(
+    post,
+    path = "/api/v1/entity/{entity_type}/{id}/credential",
+    tags= [ENTITY, COMMANDS],
+    request_body(
+        content = IssueCredentialRequest,
+        content_type = "application/json",
+        examples(
+            ("Data Integrity" = (value = json!({"format": "ldp_vc"}), description = "JSON-LD credential with an Ed25519 proof")),
+            ("VC-JWT" = (value = json!({"format": "jwt_vc", "policy_name": "cropApprovalPolicy"}), description = "Attested policy credential as a JWT")),
+        )
+    ),
+    params(
+        ("entity_type" = String, Path, description = "Entity type", example = "Insurance"),
+        ("id" = String, Path, description = "Entity ID (UUID format)")
+    ),
+    responses(
+        (status = 201, description = "Credential issued", body = CredentialRecord),
+        (status = 401, description = "Missing or invalid access token", body = ErrorResponse),
+        (status = 403, description = "Caller neither owns the entity nor holds a definition role", body = String),
+        (status = 404, description = "Entity or credential template not found", body = String),
+        (status = 409, description = "The policy has not been attested", body = String),
+    )
+)]
+#[post("/{entity_type}/{id}/credential")]
+async fn issue_credential(
+    decision_maker: Data<DecisionMaker>,
+    cipher: Data<FieldCipher>,
+    issuer_identity: Data<IssuerIdentity>,
+    path: web::Path<(String, Uuid)>,
+    claims: Claims,
+    request: Option<Json<IssueCredentialRequest>>,
+) -> Result<HttpResponse, DError> {
+    let (entity_type, entity_id) = path.into_inner();
+    let request = request.map(Json::into_inner).unwrap_or_default();
+    let events = decision_maker
+        .make(IssueCredentialCmd {
+            credential_id: Uuid::now_v7(),
+            entity_id,
+            entity_type,
+            policy_name: request.policy_name,
+            format: request.format,
+            issuer: issuer_identity.key().clone(),
+            issuer_did: Some(issuer_identity.did().to_string()),
+            status_list_url: status_lists_url(),
+            cipher: Some(cipher.get_ref().clone()),
+            principal: claims.principal(),
+        })
+        .await?;
+
+    let issued = events
+        .into_iter()
+        .find_map(|event| match event.into_inner() {
+            DomainEvent::CredentialIssued {
+                id,
+                credential_id,
+                entity_type,
+                policy_name,
+                format,
+                credential,
+                encrypted,
+                status_list_index,
+                issued_at,
+                issued_by,
+                on_behalf_of: _,
+            } => Some(CredentialRow {
+                id: credential_id,
+                entity_id: id,
+                entity_type,
+                policy_name,
+                format: format.to_string(),
+                credential,
+                encrypted,
+                status_list_index: status_list_index.map(|index| index as i64),
+                revoked_at: None,
+                suspended: false,
+                issued_by,
+                issued_at,
+            }),
+            _ => None,
+        });
+    match issued.map(|row| row.into_record(&cipher)) {
+        Some(Ok(record)) => Ok(HttpResponse::Created().json(record)),
+        Some(Err(e)) => Err(DError::from(disintegrate::DecisionError::Domain(e))),
+        None => Ok(HttpResponse::InternalServerError().json(ErrorResponse {
+            error: Some("INTERNAL_ERROR".to_string()),
+            error_description: None,
+            message: "No credential was issued".to_string(),
+        })),
+    }
+}
+
+/// Get the credentials of an entity
+///
+/// Lists the credentials issued for an entity, most recent first. Only the owners of the entity,
+/// or holders of one of the definition `roles`, may read them.
+#[utoipa::path(
+    get,
+    path = "/api/v1/entity/{entity_type}/{id}/credential",
+    tags= [ENTITY, QUERY],
+    params(
+        ("entity_type" = String, Path, description = "Entity type", example = "Insurance"),
+        ("id" = String, Path, description = "Entity ID (UUID format)")
+    ),
+    responses(
+        (status = 200, description = "Credentials of the entity", body = Vec<CredentialRecord>),
+        (status = 401, description = "Missing or invalid access token", body = ErrorResponse),
+        (status = 403, description = "Caller neither owns the entity nor holds a definition role", body = String),
+        (status = 500, description = "Internal server error", body = ErrorResponse),
+    )
+)]
+#[get("/{entity_type}/{id}/credential")]
+async fn get_credentials(
+    db_pool: Data<PgPool>,
+    cipher: Data<FieldCipher>,
+    path: web::Path<(String, Uuid)>,
+    claims: Claims,
+) -> Result<HttpResponse, DError> {
+    let (entity_type, entity_id) = path.into_inner();
+    let principal = claims.principal();
+    let rows = match load_credentials(db_pool.get_ref(), &principal, &entity_type, entity_id).await
+    {
+        Ok(rows) => rows,
+        Err(e) => {
+            log::error!("Failed to fetch credentials of {}: {}", entity_id, e);
+            return Ok(HttpResponse::InternalServerError().json(ErrorResponse {
+                error: Some("DATABASE_ERROR".to_string()),
+                error_description: Some(format!("Database error: {}", e)),
+                message: "Failed to fetch credentials".to_string(),
+            }));
+        }
+    };
+    let rows = rows.map_err(|e| DError::from(disintegrate::DecisionError::Domain(e)))?;
+    let records = rows
+        .into_iter()
+        .map(|row| row.into_record(&cipher))
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| DError::from(disintegrate::DecisionError::Domain(e)))?;
+    Ok(HttpResponse::Ok().json(records))
+}
+
+/// Loads the credentials of an entity once the caller is authorized to read them
+pub(crate) async fn load_credentials(
+    db_pool: &PgPool,
+    principal: &Principal,
+    entity_type: &str,
+    entity_id: EntityId,
+) -> Result<Result<Vec<CredentialRow>, EntityError>, sqlx::Error> {
+    let owners = entity_owners(db_pool, entity_id).await?;
+    let roles = sqlx::query_scalar::<_, Value>(
+        "SELECT json_schema_string FROM definitions WHERE title = $1",
+    )
+    .bind(entity_type)
+    .fetch_optional(db_pool)
+    .await?
+    .map(|schema| declared_roles(&schema))
+    .unwrap_or_default();
+    if let Err(e) = principal.authorize(entity_id, &owners, &roles) {
+        return Ok(Err(e));
+    }
+
+    sqlx::query_as::<_, CredentialRow>(
+        r#"
+        SELECT id, entity_id, entity_type, policy_name, format, credential, encrypted, status_list_index,
+               revoked_at, suspended, issued_by, issued_at
+        FROM credentials
+        WHERE entity_id = $1 AND entity_type = $2
+        ORDER BY issued_at DESC
+        "#,
+    )
+    .bind(entity_id)
+    .bind(entity_type)
+    .fetch_all(db_pool)
+    .await
+    .map(Ok)
+}
+
+/// Loads a credential by id
+pub(crate) async fn load_credential(
+    db_pool: &PgPool,
+    credential_id: Uuid,
+) -> Result<Option<CredentialRow>, sqlx::Error> {
+    sqlx::query_as::<_, CredentialRow>(
+        r#"
+        SELECT id, entity_id, entity_type, policy_name, format, credential, encrypted, status_list_index,
+               revoked_at, suspended, issued_by, issued_at
+        FROM credentials
+        WHERE id = $1
+        "#,
+    )
+    .bind(credential_id)
+    .fetch_optional(db_pool)
+    .await
+}
+
+/// Get a status list
+///
+/// Returns the signed StatusList2021 credential of a purpose, `revocation` or `suspension`. The
+/// bit at the `statusListIndex` of a credential is set when the credential is revoked, or
+/// suspended. This endpoint is public so that any verifier can check credentials.
+#[utoipa::path(
+    get,
+    path = "/api/v1/credentials/status/{purpose}",
+    tags= [ENTITY, QUERY],
+    params(
+        ("purpose" = StatusPurpose, Path, description = "Status purpose", example = "revocation")
+    ),
+    responses(
+        (status = 200, description = "Signed status list credential", body = Object),
+        (status = 404, description = "Unknown status purpose", body = ErrorResponse),
+        (status = 500, description = "Internal server error", body = ErrorResponse),
+    )
+)]
+#[get("/status/{purpose}")]
+async fn get_status_list(
+    db_pool: Data<PgPool>,
+    issuer_identity: Data<IssuerIdentity>,
+    purpose: web::Path<String>,
+) -> Result<HttpResponse, DError> {
+    let Ok(purpose) = purpose.parse::<StatusPurpose>() else {
+        return Ok(HttpResponse::NotFound().json(ErrorResponse {
+            error: Some("NOT_FOUND".to_string()),
+            error_description: Some(format!("Unknown status purpose: {}", purpose)),
+            message: "Status list not found".to_string(),
+        }));
+    };
+    let list = match load_status_list(db_pool.get_ref(), purpose).await {
+        Ok(list) => list,
+        Err(e) => {
+            log::error!("Failed to read the {} status list: {}", purpose, e);
+            return Ok(HttpResponse::InternalServerError().json(ErrorResponse {
+                error: Some("DATABASE_ERROR".to_string()),
+                error_description: Some(format!("Database error: {}", e)),
+                message: "Failed to fetch the status list".to_string(),
+            }));
+        }
+    };
+    let credential = status_list_credential(
+        &status_lists_url(),
+        purpose,
+        &list,
+        issuer_identity.did(),
+        issuer_identity.key(),
+        Utc::now(),
+    )
+    .map_err(|e| DError::from(disintegrate::DecisionError::Domain(e)))?;
+    Ok(HttpResponse::Ok().json(credential))
+}
+
+/// Change the status of a credential
+///
+/// Revokes, suspends or reinstates an issued credential by setting or clearing its bit in the
+/// status lists. Revocation is permanent. Only the owners of the entity, or holders of one of the
+/// definition `roles`, may change the status of its credentials.
+#[utoipa::path(
+    post,
+    path = "/api/v1/credentials/{credential_id}/status",
+    tags= [ENTITY, COMMANDS],
+    request_body(
+        content = CredentialStatusRequest,
+        content_type = "application/json",
+        examples(
+            ("Revoke" = (value = json!({"action": "REVOKE", "reason": "Policy expired"}), description = "Revoke the credential")),
+            ("Suspend" = (value = json!({"action": "SUSPEND", "reason": "Premium overdue"}), description = "Suspend the credential")),
+            ("Reinstate" = (value = json!({"action": "REINSTATE"}), description = "Lift the suspension")),
+        )
+    ),
+    params(
+        ("credential_id" = String, Path, description = "Credential ID (UUID format)")
+    ),
+    responses(
+        (status = 200, description = "Status changed", body = String),
+        (status = 401, description = "Missing or invalid access token", body = ErrorResponse),
+        (status = 403, description = "Caller neither owns the entity nor holds a definition role", body = String),
+        (status = 404, description = "Credential not found", body = String),
+        (status = 409, description = "The credential is revoked or already in that status", body = String),
+    )
+)]
+#[post("/{credential_id}/status")]
+async fn change_credential_status(
+    decision_maker: Data<DecisionMaker>,
+    db_pool: Data<PgPool>,
+    credential_id: web::Path<Uuid>,
+    claims: Claims,
+    request: Json<CredentialStatusRequest>,
+) -> Result<HttpResponse, DError> {
+    let credential_id = credential_id.into_inner();
+    let request = request.into_inner();
+
+    let credential: Option<(Uuid, String)> =
+        match sqlx::query_as("SELECT entity_id, entity_type FROM credentials WHERE id = $1")
+            .bind(credential_id)
+            .fetch_optional(db_pool.get_ref())
+            .await
+        {
+            Ok(credential) => credential,
+            Err(e) => {
+                log::error!("Failed to read credential {}: {}", credential_id, e);
+                return Ok(HttpResponse::InternalServerError().json(ErrorResponse {
+                    error: Some("DATABASE_ERROR".to_string()),
+                    error_description: Some(format!("Database error: {}", e)),
+                    message: "Failed to change the credential status".to_string(),
+                }));
+            }
+        };
+    let Some((entity_id, entity_type)) = credential else {
+        return Err(DError::from(disintegrate::DecisionError::Domain(
+            EntityError::CredentialNotFound(credential_id),
+        )));
+    };
+
+    decision_maker
+        .make(ChangeCredentialStatusCmd {
+            credential_id,
+            entity_id,
+            entity_type,
+            action: request.action,
+            reason: request.reason,
+            principal: claims.principal(),
+        })
+        .await?;
+
+    Ok(HttpResponse::Ok().json(SuccessResponse {
+        id: credential_id.to_string(),
+        message: format!("Credential status changed: {}", request.action),
+    }))
+}
@@ -1,9 +1,15 @@
-use crate::models::ValidateDefRequest;
+use crate::middleware::authorization::{require_permission, WRITE_DEFINITIONS};
+use crate::middleware::claims::Claims;
+use crate::models::{UpdateDefRequest, ValidateDefRequest};
 // use rc_web::{DError, DecisionMaker};
 use crate::routes::{
     ErrorResponse, CLIENT_EXAMPLE, CONSULTANT_EXAMPLE, INSURANCE_EXAMPLE,
     INSURANCE_OFFICIAL_EXAMPLE, STUDENT_EXAMPLE, TEACHER_EXAMPLE,
 };
+use crate::services::history::{history, HistoryEntry};
+use crate::services::impact_analysis::{
+    analyse, comparable, ImpactAnalysisReport, DEFAULT_SAMPLE_SIZE,
+};
 use crate::{
     base_url, DError, DecisionMaker, SuccessResponse, API_PREFIX, COMMANDS, DEFINITIONS, QUERY,
 };
@@ -12,8 +18,11 @@ use actix_web::{get, post, web, HttpResponse, Responder, Scope};
 use chrono::{DateTime, Utc};
 use definitions_core::definitions_domain::{
     generate_id_from_title, read_title, ActivateDefinitionCmd, CreateDefinitionCmd, DefError,
-    DefRecordStatus, DomainEvent, ValidateDefinitionCmd,
+    DefRecordStatus, DomainEvent, UpdateDefinitionCmd, ValidateDefinitionCmd,
 };
+use definitions_core::encryption::FieldCipher;
+use definitions_core::registry_domain::EntitySchemaValidator;
+use definitions_core::system_fields::SystemFields;
 use disintegrate::PersistedEvent;
 use disintegrate_postgres::PgEventId;
 use log::{debug, error};
@@ -69,7 +78,11 @@ pub fn routes() -> Scope {
         .service(activate_def)
         .service(validate_def)
         .service(create_def)
+        .service(update_def)
+        .service(impact_analysis)
         .service(get_definitions)
+        .service(get_migration_report)
+        .service(get_definition_history)
         .service(get_definitions_by_id)
 }
 
@@ -78,8 +91,13 @@ pub fn routes() -> Scope {
     post,
     path = "/api/v1/schema/activate_def",
     tags= [DEFINITIONS, COMMANDS],
+    security(
+        ("bearer_auth" = ["write:definitions"])
+    ),
     responses(
-        (status = 200, description = "Activation successful", body = String)
+        (status = 200, description = "Activation successful", body = String),
+        (status = 401, description = "Missing or invalid access token", body = ErrorResponse),
+        (status = 403, description = "Caller lacks the `write:definitions` permission", body = String),
     ),
      request_body(
         content_type = "application/json",
@@ -94,14 +112,16 @@ pub fn routes() -> Scope {
 #[post("/activate_def")]
 async fn activate_def(
     decision_maker: Data<DecisionMaker>,
+    claims: Claims,
     web_cmd: web::Json<ValidateDefRequest>,
 ) -> Result<HttpResponse, DError> {
+    require_permission(&claims, WRITE_DEFINITIONS, "activate definitions")?;
     let identifier = validate_id(&web_cmd)?;
     debug!("Activating def with id: {}", identifier);
     let activate_def_command = ActivateDefinitionCmd {
         id: identifier,
         activated_at: Utc::now(),
-        activated_by: "test_activated_by".to_string(),
+        activated_by: claims.actor(),
     };
 
     let _exec_results: Vec<PersistedEvent<PgEventId, DomainEvent>> =
@@ -138,21 +158,28 @@ fn validate_id(web_cmd: &Json<ValidateDefRequest>) -> Result<Uuid, DError> {
     post,
     path = "/api/v1/schema/validate_def",
     tags= [DEFINITIONS, COMMANDS],
+    security(
+        ("bearer_auth" = ["write:definitions"])
+    ),
     responses(
-        (status = 200, description = "Validation successful", body = String)
+        (status = 200, description = "Validation successful", body = String),
+        (status = 401, description = "Missing or invalid access token", body = ErrorResponse),
+        (status = 403, description = "Caller lacks the `write:definitions` permission", body = String),
     )
 )]
 #[post("/validate_def")]
 async fn validate_def(
     decision_maker: Data<DecisionMaker>,
+    claims: Claims,
     web_cmd: web::Json<ValidateDefRequest>,
 ) -> Result<HttpResponse, DError> {
+    require_permission(&claims, WRITE_DEFINITIONS, "validate definitions")?;
     let identifier = validate_id(&web_cmd)?;
     debug!("Validating def with id: {}", identifier);
     let validate_def_cmd = ValidateDefinitionCmd {
         id: identifier,
         validated_at: Utc::now(),
-        validated_by: "test_validated_by".to_string(),
+        validated_by: claims.actor(),
     };
 
     let exec_results: Vec<PersistedEvent<PgEventId, DomainEvent>> =
@@ -206,6 +233,9 @@ async fn validate_def(
     post,
     path = "/api/v1/schema/create_def",
     tags= [DEFINITIONS, COMMANDS],
+    security(
+        ("bearer_auth" = ["write:definitions"])
+    ),
      request_body(
         content = String,
         content_type = "application/json",
@@ -221,21 +251,25 @@ async fn validate_def(
     responses(
         (status = 200, description = "Definition created", body = String),
         (status = 400, description = "Invalid Schema", body = String),
+        (status = 401, description = "Missing or invalid access token", body = ErrorResponse),
+        (status = 403, description = "Caller lacks the `write:definitions` permission", body = String),
         (status = 409, description = "Definition Already Exists", body = String),
     )
 )]
 #[post("/create_def")]
 async fn create_def(
     decision_maker: Data<DecisionMaker>,
+    claims: Claims,
     web_cmd: String,
 ) -> Result<HttpResponse, DError> {
+    require_permission(&claims, WRITE_DEFINITIONS, "create definitions")?;
     let title =
         read_title(&web_cmd).map_err(|e| DError::from(disintegrate::DecisionError::Domain(e)))?;
     let create_def_cmd = CreateDefinitionCmd {
         id: generate_id_from_title(&title),
         title,
         definitions: vec!["test_def".to_string()],
-        created_by: "test_created_by".to_string(),
+        created_by: claims.actor(),
         json_schema_string: web_cmd,
     };
 
@@ -270,6 +304,86 @@ async fn create_def(
         }))
 }
 
+/// Update a schema definition
+///
+/// The definition goes back to Draft and must be validated and activated again. When it is
+/// activated, existing entities are migrated to the new version using the optional migration.
+#[utoipa::path(
+    post,
+    path = "/api/v1/schema/update_def",
+    tags= [DEFINITIONS, COMMANDS],
+    security(
+        ("bearer_auth" = ["write:definitions"])
+    ),
+    request_body(
+        content = UpdateDefRequest,
+        content_type = "application/json",
+        example = json!({
+            "json_schema": {"title": "Student", "type": "object", "properties": {"fullName": {"type": "string"}}},
+            "migration": {"operations": [{"op": "rename_field", "path": "$.name", "to": "fullName"}]}
+        })
+    ),
+    responses(
+        (status = 200, description = "Definition updated", body = String),
+        (status = 400, description = "Invalid Schema or Migration", body = String),
+        (status = 401, description = "Missing or invalid access token", body = ErrorResponse),
+        (status = 403, description = "Caller lacks the `write:definitions` permission", body = String),
+    )
+)]
+#[post("/update_def")]
+async fn update_def(
+    decision_maker: Data<DecisionMaker>,
+    claims: Claims,
+    web_cmd: web::Json<UpdateDefRequest>,
+) -> Result<HttpResponse, DError> {
+    require_permission(&claims, WRITE_DEFINITIONS, "update definitions")?;
+    let UpdateDefRequest {
+        json_schema,
+        migration,
+    } = web_cmd.into_inner();
+    let json_schema_string = json_schema.to_string();
+    let title = read_title(&json_schema_string)
+        .map_err(|e| DError::from(disintegrate::DecisionError::Domain(e)))?;
+    let update_def_cmd = UpdateDefinitionCmd {
+        id: generate_id_from_title(&title),
+        definitions: vec!["test_def".to_string()],
+        created_at: Utc::now(),
+        updated_by: claims.actor(),
+        json_schema_string,
+        migration,
+    };
+
+    let exec_results: Vec<PersistedEvent<PgEventId, DomainEvent>> =
+        decision_maker.make(update_def_cmd).await?;
+    let updated_defid = exec_results
+        .iter()
+        .find_map(|ev| match ev.deref() {
+            DomainEvent::DefUpdated { id, .. } => Some(*id),
+            _ => None,
+        })
+        .ok_or_else(|| {
+            DError::from(disintegrate::DecisionError::Domain(
+                DefError::EventNotFound("DefUpdated".to_string()),
+            ))
+        })?;
+
+    let response_message = format!(
+        "Definition updated with Id: {} for Title:  {}",
+        updated_defid, title
+    );
+    debug!("{}", response_message.clone());
+    Ok(HttpResponse::Ok()
+        .append_header((
+            "Location",
+            format!("{}{API_PREFIX}/schema/{}", base_url(), updated_defid),
+        ))
+        .append_header(("message", response_message.clone()))
+        .json(SuccessResponse {
+            id: updated_defid.to_string(),
+            message: response_message,
+        }))
+}
+
 #[derive(Debug, Serialize, FromRow)]
 struct Definition {
     id: Uuid,
@@ -305,6 +419,9 @@ pub struct DefinitionQuery {
     get,
     path = "/api/v1/schema",
     tags= [DEFINITIONS, QUERY],
+    security(
+        ()
+    ),
     params(
         DefinitionQuery
     ),
@@ -366,6 +483,9 @@ async fn get_definitions(db_pool: Data<PgPool>, query: Query<DefinitionQuery>) -
     get,
     path = "/api/v1/schema/{id}",
     tags= [DEFINITIONS, QUERY],
+    security(
+        ()
+    ),
     params(
         ("id" = Uuid, Path, description = "ID of the definition to fetch",example = "1bd23c91-3379-b65b-11cc-64984050e35c")
     ),
@@ -400,3 +520,217 @@ async fn get_definitions_by_id(db_pool: Data<PgPool>, path: web::Path<Uuid>) ->
         }
     }
 }
+
+#[derive(Debug, Serialize, FromRow, ToSchema)]
+pub struct MigrationReportEntry {
+    pub definition_version: i32,
+    pub entity_id: Uuid,
+    pub entity_type: String,
+    /// Migrated, Skipped or Failed
+    pub status: String,
+    pub error: Option<String>,
+    pub processed_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Deserialize, IntoParams, Default)]
+#[into_params(parameter_in = Query)]
+pub struct MigrationReportQuery {
+    /// Only report entities that failed to migrate
+    #[param(example = true)]
+    pub failed_only: Option<bool>,
+}
+
+/// Get the entity migration report of a definition
+#[utoipa::path(
+    get,
+    path = "/api/v1/schema/{id}/migration_report",
+    tags= [DEFINITIONS, QUERY],
+    security(
+        ("bearer_auth" = ["write:definitions"])
+    ),
+    params(
+        ("id" = Uuid, Path, description = "ID of the definition",example = "1bd23c91-3379-b65b-11cc-64984050e35c"),
+        MigrationReportQuery
+    ),
+    responses(
+     (status = 200, body = [MigrationReportEntry]),
+     (status = 401, description = "Missing or invalid access token", body = ErrorResponse),
+     (status = 403, description = "Caller lacks the `write:definitions` permission", body = String),
+     (status = 500, description = "Failed to fetch the report", body = ErrorResponse)
+    )
+)]
+#[get("/{id}/migration_report")]
+async fn get_migration_report(
+    db_pool: Data<PgPool>,
+    claims: Claims,
+    path: web::Path<Uuid>,
+    query: Query<MigrationReportQuery>,
+) -> Result<HttpResponse, DError> {
+    require_permission(&claims, WRITE_DEFINITIONS, "read migration reports")?;
+    let id = path.into_inner();
+    let failed_only = query.failed_only.unwrap_or(false);
+    match sqlx::query_as::<_, MigrationReportEntry>(
+        r#"
+        SELECT definition_version, entity_id, entity_type, status, error, processed_at
+        FROM entity_migration_report
+        WHERE definition_id = $1 AND (NOT $2 OR status = 'Failed')
+        ORDER BY definition_version DESC, processed_at
+        "#,
+    )
+    .bind(id)
+    .bind(failed_only)
+    .fetch_all(db_pool.get_ref())
+    .await
+    {
+        Ok(entries) => Ok(HttpResponse::Ok().json(entries)),
+        Err(e) => {
+            error!("Database query failed: {}", e);
+            Ok(HttpResponse::InternalServerError().json(ErrorResponse {
+                error: Some("Failed to fetch migration report".into()),
+                error_description: Some(format!("Error {} while fetching id {}", e, id)),
+                message: format!("Error {} while fetching id {}", e, id),
+            }))
+        }
+    }
+}
+
+/// Get the history of a definition
+///
+/// Every command applied to the definition with the actor that issued it, oldest first.
+#[utoipa::path(
+    get,
+    path = "/api/v1/schema/{id}/history",
+    tags= [DEFINITIONS, QUERY],
+    security(
+        ("bearer_auth" = ["write:definitions"])
+    ),
+    params(
+        ("id" = Uuid, Path, description = "ID of the definition",example = "1bd23c91-3379-b65b-11cc-64984050e35c")
+    ),
+    responses(
+     (status = 200, body = [HistoryEntry]),
+     (status = 401, description = "Missing or invalid access token", body = ErrorResponse),
+     (status = 403, description = "Caller lacks the `write:definitions` permission", body = String),
+     (status = 500, description = "Failed to fetch the history", body = ErrorResponse)
+    )
+)]
+#[get("/{id}/history")]
+async fn get_definition_history(
+    db_pool: Data<PgPool>,
+    claims: Claims,
+    path: web::Path<Uuid>,
+) -> Result<HttpResponse, DError> {
+    require_permission(&claims, WRITE_DEFINITIONS, "read definition history")?;
+    let id = path.into_inner();
+    match history(db_pool.get_ref(), id).await {
+        Ok(entries) => Ok(HttpResponse::Ok().json(entries)),
+        Err(e) => {
+            error!("Database query failed: {}", e);
+            Ok(HttpResponse::InternalServerError().json(ErrorResponse {
+                error: Some("Failed to fetch definition history".into()),
+                error_description: Some(format!("Error {} while fetching id {}", e, id)),
+                message: format!("Error {} while fetching id {}", e, id),
+            }))
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, IntoParams, Default)]
+#[into_params(parameter_in = Query)]
+pub struct ImpactAnalysisQuery {
+    /// Maximum number of sample entity ids and messages per error kind
+    #[param(example = 5)]
+    pub sample_size: Option<usize>,
+}
+
+/// Dry-run a candidate schema against the existing entities
+///
+/// Every entity of the type given by the schema title is validated against the candidate schema,
+/// with its private fields decrypted and without the system fields. Entities that cannot be
+/// decrypted are left out of the report. Nothing is persisted.
+#[utoipa::path(
+    post,
+    path = "/api/v1/schema/impact_analysis",
+    tags= [DEFINITIONS, QUERY],
+    security(
+        ("bearer_auth" = ["write:definitions"])
+    ),
+    params(
+        ImpactAnalysisQuery
+    ),
+    request_body(
+        content = String,
+        content_type = "application/json",
+        examples(
+            ("Student" = (value = json!(serde_json::from_str::<Value>(STUDENT_EXAMPLE).expect("Failed to parse STUDENT_EXAMPLE JSON")), description = "Student in Education domain")),
+        )
+    ),
+    responses(
+        (status = 200, description = "Impact of the candidate schema", body = ImpactAnalysisReport),
+        (status = 400, description = "Invalid Schema", body = String),
+        (status = 401, description = "Missing or invalid access token", body = ErrorResponse),
+        (status = 403, description = "Caller lacks the `write:definitions` permission", body = String),
+        (status = 500, description = "Failed to read entities", body = ErrorResponse),
+    )
+)]
+#[post("/impact_analysis")]
+async fn impact_analysis(
+    db_pool: Data<PgPool>,
+    cipher: Data<FieldCipher>,
+    claims: Claims,
+    query: Query<ImpactAnalysisQuery>,
+    web_cmd: String,
+) -> Result<HttpResponse, DError> {
+    require_permission(&claims, WRITE_DEFINITIONS, "analyse definitions")?;
+    let entity_type =
+        read_title(&web_cmd).map_err(|e| DError::from(disintegrate::DecisionError::Domain(e)))?;
+    let validator = EntitySchemaValidator::new(&entity_type, &web_cmd)
+        .map_err(|e| DError::from(disintegrate::DecisionError::Domain(e)))?;
+    let system_fields = SystemFields::from_schema(&web_cmd, &entity_type)
+        .map_err(|e| DError::from(disintegrate::DecisionError::Domain(e)))?;
+    let sample_size = query.sample_size.unwrap_or(DEFAULT_SAMPLE_SIZE);
+
+    match fetch_entity_data(db_pool.get_ref(), &entity_type).await {
+        Ok(entities) => {
+            let entities = entities.into_iter().filter_map(|(id, entity_data)| {
+                match comparable(&entity_type, &cipher, &system_fields, entity_data) {
+                    Ok(document) => Some((id, document)),
+                    Err(e) => {
+                        error!("Entity {} left out of the impact analysis: {}", id, e);
+                        None
+                    }
+                }
+            });
+            Ok(HttpResponse::Ok().json(analyse(&entity_type, &validator, entities, sample_size)))
+        }
+        Err(e) => {
+            error!("Database query failed: {}", e);
+            Ok(HttpResponse::InternalServerError().json(ErrorResponse {
+                error: Some("Failed to fetch entities".into()),
+                error_description: Some(format!("Error {} while fetching {}", e, entity_type)),
+                message: format!("Error {} while fetching {}", e, entity_type),
+            }))
+        }
+    }
+}
+
+/// Reads the current documents of an entity type, a missing projection table means no entities
+async fn fetch_entity_data(
+    db_pool: &PgPool,
+    entity_type: &str,
+) -> Result<Vec<(Uuid, Value)>, sqlx::Error> {
+    let table_name = format!("{}_projection", entity_type.to_lowercase());
+    let table_exists: bool = sqlx::query_scalar("SELECT to_regclass($1) IS NOT NULL")
+        .bind(&table_name)
+        .fetch_one(db_pool)
+        .await?;
+    if !table_exists {
+        return Ok(vec![]);
+    }
+    sqlx::query_as::<_, (Uuid, Value)>(&format!(
+        "SELECT id, entity_data FROM {} ORDER BY created_at",
+        table_name
+    ))
+    .fetch_all(db_pool)
+    .await
+}
@@ -0,0 +1,28 @@
+use crate::QUERY;
+use actix_web::web::Data;
+use actix_web::{get, web, HttpResponse, Scope};
+use definitions_core::did::IssuerIdentity;
+
+/// Served at the root of the registry, `did:web` resolution looks for `/.well-known/did.json`
+pub fn routes() -> Scope {
+    web::scope("/.well-known").service(get_did_document)
+}
+
+/// Get the DID document of the registry
+///
+/// The `did:web` document of the registry, listing the current and retired issuer keys. Any
+/// credential or signature of the registry verifies against one of its verification methods.
+#[utoipa::path(
+    get,
+    path = "/.well-known/did.json",
+    tag = QUERY,
+    responses(
+        (status = 200, description = "DID document of the registry", body = Object, content_type = "application/did+json"),
+    )
+)]
+#[get("/did.json")]
+async fn get_did_document(identity: Data<IssuerIdentity>) -> HttpResponse {
+    HttpResponse::Ok()
+        .content_type("application/did+json")
+        .json(identity.document())
+}
@@ -1,20 +1,31 @@
+use crate::middleware::authorization::{authorize_entity, AccessError, EntityOperation};
+use crate::middleware::claims::{Claims, OptionalClaims};
+use crate::projections::schema_projection::internal_columns;
+use crate::routes::consent_routes::owns_entity;
 use crate::routes::{
-    ErrorResponse, CLIENT_JOHN_EXAMPLE, CONSULTANT_SARAH_EXAMPLE, STUDENT_JOHN_EXAMPLE,
-    TEACHER_SMITH_EXAMPLE,
+    certificate_routes, credential_routes, ErrorResponse, CLIENT_JOHN_EXAMPLE,
+    CONSULTANT_SARAH_EXAMPLE, STUDENT_JOHN_EXAMPLE, TEACHER_SMITH_EXAMPLE,
 };
+use crate::services::history::{history, HistoryEntry};
 use crate::{base_url, DError, DecisionMaker, SuccessResponse};
 use crate::{API_PREFIX, COMMANDS, ENTITY, QUERY};
 use actix_web::web::Data;
-use actix_web::{get, post, web, HttpResponse, Responder, Scope};
+use actix_web::{get, post, put, web, HttpResponse, Responder, Scope};
 use chrono::{DateTime, Utc};
 use definitions_core::definitions_domain::DomainEvent;
-use definitions_core::registry_domain::{CreateEntityCmd, EntityError};
+use definitions_core::encryption::{contains_encrypted, FieldCipher};
+use definitions_core::ownership::{declared_roles, Owner, Principal};
+use definitions_core::references::{collect_references, referenced_ids};
+use definitions_core::registry_domain::{CreateEntityCmd, EntityError, EntityId, ModifyEntityCmd};
+use definitions_core::signing::IssuerKey;
+use definitions_core::visibility::InternalFields;
 use disintegrate::PersistedEvent;
 use disintegrate_postgres::PgEventId;
 use serde::Serialize;
 #[allow(unused_imports)]
 use serde_json::{json, Value};
 use sqlx::{FromRow, PgPool};
+use std::collections::HashMap;
 use std::ops::Deref;
 use utoipa::ToSchema;
 use uuid::Uuid;
@@ -313,15 +324,281 @@ struct Entity {
     registry_def_id: Uuid,
     /// Version of the registry definition used
     registry_def_version: i32,
+    /// Referenced entities the caller may read, keyed by the JSON pointer of the referencing field,
+    /// only with `embed=true`
+    #[sqlx(skip)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[schema(value_type = Option<Object>)]
+    embedded: Option<serde_json::Map<String, Value>>,
+}
+
+/// Query parameter that embeds referenced entities in read responses
+const EMBED_PARAM: &str = "embed";
+
+fn embed_requested(query: &HashMap<String, String>) -> bool {
+    query
+        .get(EMBED_PARAM)
+        .is_some_and(|value| value.eq_ignore_ascii_case("true"))
+}
+
+/// Computes the ids of the entities referenced by an entity body
+///
+/// The references are derived from the `x-ref` annotations of the definition in the read model.
+/// `CreateEntityCmd` derives them again from the definition state and rejects any reference
+/// that was not declared here.
+pub(crate) async fn find_referenced_ids(
+    db_pool: &PgPool,
+    entity_type: &str,
+    entity_body: &Value,
+) -> Result<Vec<EntityId>, DError> {
+    let schema = match sqlx::query_scalar::<_, Value>(
+        "SELECT json_schema_string FROM definitions WHERE title = $1",
+    )
+    .bind(entity_type)
+    .fetch_optional(db_pool)
+    .await
+    {
+        Ok(Some(schema)) => schema,
+        Ok(None) => return Ok(vec![]),
+        Err(e) => {
+            log::error!("Failed to read definition of '{}': {}", entity_type, e);
+            return Ok(vec![]);
+        }
+    };
+    let references = collect_references(&schema, entity_body)
+        .map_err(|e| DError::from(disintegrate::DecisionError::Domain(e)))?;
+    Ok(referenced_ids(&references))
+}
+
+/// Adds the entity data of every referenced entity the caller may read to `embedded`
+///
+/// Referenced entities are authorized and decrypted like the entity itself, their internal fields
+/// are then masked by [`mask_internal_fields`]. References the caller may not read are left out.
+async fn embed_references(
+    db_pool: &PgPool,
+    cipher: &FieldCipher,
+    principal: Option<&Principal>,
+    entities: &mut [Entity],
+) -> Result<(), sqlx::Error> {
+    let ids: Vec<Uuid> = entities.iter().map(|entity| entity.id).collect();
+    let references: Vec<(Uuid, String, String, Uuid)> = sqlx::query_as(
+        "SELECT entity_id, path, referenced_type, referenced_id FROM entity_references WHERE entity_id = ANY($1)",
+    )
+    .bind(&ids)
+    .fetch_all(db_pool)
+    .await?;
+
+    let mut readable: Vec<Uuid> = Vec::new();
+    let mut referenced: Vec<Entity> = Vec::new();
+    for (_, _, referenced_type, referenced_id) in &references {
+        if readable.contains(referenced_id) {
+            continue;
+        }
+        let Some(type_name) = sanitize_column_name(referenced_type) else {
+            continue;
+        };
+        if authorize_read(db_pool, principal, referenced_type, *referenced_id)
+            .await?
+            .is_err()
+        {
+            continue;
+        }
+        readable.push(*referenced_id);
+        let sql = format!(
+            "SELECT id, entity_data, entity_type, created_by, created_at, registry_def_id, registry_def_version FROM {}_projection WHERE id = $1",
+            type_name
+        );
+        match sqlx::query_as::<_, Entity>(&sql)
+            .bind(referenced_id)
+            .fetch_optional(db_pool)
+            .await
+        {
+            Ok(entity) => referenced.extend(entity),
+            Err(e) if is_table_not_found_error(&e) => {}
+            Err(e) => return Err(e),
+        }
+    }
+    decrypt_authorized(db_pool, cipher, principal, &mut referenced).await?;
+    let loaded: HashMap<Uuid, Value> = referenced
+        .into_iter()
+        .map(|entity| (entity.id, entity.entity_data))
+        .collect();
+
+    for entity in entities.iter_mut() {
+        let mut embedded = serde_json::Map::new();
+        for (_, path, referenced_type, referenced_id) in
+            references
+                .iter()
+                .filter(|(entity_id, _, _, referenced_id)| {
+                    *entity_id == entity.id && readable.contains(referenced_id)
+                })
+        {
+            embedded.insert(
+                path.clone(),
+                json!({
+                    "id": referenced_id,
+                    "entity_type": referenced_type,
+                    "entity_data": loaded.get(referenced_id).unwrap_or(&Value::Null),
+                }),
+            );
+        }
+        entity.embedded = Some(embedded);
+    }
+    Ok(())
+}
+
+/// Loads the JSON schema of the definition with the given title from the read model
+async fn load_definition_schema(
+    db_pool: &PgPool,
+    entity_type: &str,
+) -> Result<Option<Value>, sqlx::Error> {
+    sqlx::query_scalar::<_, Value>("SELECT json_schema_string FROM definitions WHERE title = $1")
+        .bind(entity_type)
+        .fetch_optional(db_pool)
+        .await
+}
+
+/// Checks that the caller may read an entity by id: by a definition role, or as its owner. An
+/// entity of an unknown type is never readable.
+async fn authorize_read(
+    pool: &PgPool,
+    principal: Option<&Principal>,
+    entity_type: &str,
+    entity_id: EntityId,
+) -> Result<Result<(), AccessError>, sqlx::Error> {
+    let Some(schema) = load_definition_schema(pool, entity_type).await? else {
+        return Ok(Err(AccessError::UnknownEntityType(entity_type.to_string())));
+    };
+    let by_role = authorize_entity(principal, &schema, entity_type, EntityOperation::Read);
+    match (&by_role, principal) {
+        (Err(_), Some(principal)) if owns_entity(pool, entity_id, principal).await? => Ok(Ok(())),
+        _ => Ok(by_role),
+    }
+}
+
+/// Removes the `internalFields` the caller may not read from the entities and from the
+/// referenced entities embedded in them
+async fn mask_internal_fields(
+    db_pool: &PgPool,
+    principal: Option<&Principal>,
+    entities: &mut [Entity],
+) -> Result<(), sqlx::Error> {
+    let mut visibility: HashMap<String, InternalFields> = HashMap::new();
+    for entity in entities.iter_mut() {
+        let embedded = entity.embedded.iter_mut().flat_map(|embedded| {
+            embedded.values_mut().filter_map(|reference| {
+                let entity_type = reference.get("entity_type")?.as_str()?.to_string();
+                Some((entity_type, reference.get_mut("entity_data")?))
+            })
+        });
+        let documents =
+            std::iter::once((entity.entity_type.clone(), &mut entity.entity_data)).chain(embedded);
+        for (entity_type, document) in documents {
+            if !visibility.contains_key(&entity_type) {
+                let internal = load_definition_schema(db_pool, &entity_type)
+                    .await?
+                    .and_then(|schema| InternalFields::from_schema(&schema, &entity_type).ok())
+                    .unwrap_or_default();
+                visibility.insert(entity_type.clone(), internal);
+            }
+            if let Some(internal) = visibility.get(&entity_type) {
+                internal.filter(document, principal);
+            }
+        }
+    }
+    Ok(())
+}
+
+/// `(entity_id, user_id, email, mobile)` row of the `entity_owners` table
+type OwnerRow = (Uuid, Option<String>, Option<String>, Option<String>);
+
+/// Returns true when the principal may read the `privateFields` of an entity in clear: it owns
+/// the entity or holds one of its definition `roles` other than `anonymous`
+fn may_decrypt(principal: &Principal, owners: &[Owner], roles: &[String]) -> bool {
+    principal.owns(owners) || principal.has_any_role(roles)
+}
+
+/// Decrypts the `privateFields` of the entities the caller may read in clear
+///
+/// Owners of an entity and holders of one of its definition `roles` see the plaintext values,
+/// anonymous and other callers keep the encrypted envelopes, even when the definition is open to
+/// `anonymous`.
+async fn decrypt_authorized(
+    db_pool: &PgPool,
+    cipher: &FieldCipher,
+    principal: Option<&Principal>,
+    entities: &mut [Entity],
+) -> Result<(), sqlx::Error> {
+    let Some(principal) = principal else {
+        return Ok(());
+    };
+    let encrypted: Vec<Uuid> = entities
+        .iter()
+        .filter(|entity| contains_encrypted(&entity.entity_data))
+        .map(|entity| entity.id)
+        .collect();
+    if encrypted.is_empty() {
+        return Ok(());
+    }
+
+    let owners: Vec<OwnerRow> = sqlx::query_as(
+        "SELECT entity_id, user_id, email, mobile FROM entity_owners WHERE entity_id = ANY($1)",
+    )
+    .bind(&encrypted)
+    .fetch_all(db_pool)
+    .await?;
+
+    let mut roles: HashMap<String, Vec<String>> = HashMap::new();
+    for entity in entities
+        .iter_mut()
+        .filter(|entity| encrypted.contains(&entity.id))
+    {
+        if !roles.contains_key(&entity.entity_type) {
+            let schema = load_definition_schema(db_pool, &entity.entity_type).await?;
+            roles.insert(
+                entity.entity_type.clone(),
+                schema
+                    .map(|schema| declared_roles(&schema))
+                    .unwrap_or_default(),
+            );
+        }
+        let entity_owners: Vec<Owner> = owners
+            .iter()
+            .filter(|(entity_id, ..)| *entity_id == entity.id)
+            .map(|(_, user_id, email, mobile)| Owner {
+                user_id: user_id.clone(),
+                email: email.clone(),
+                mobile: mobile.clone(),
+            })
+            .collect();
+        let entity_roles = roles.get(&entity.entity_type).cloned().unwrap_or_default();
+        if !may_decrypt(principal, &entity_owners, &entity_roles) {
+            continue;
+        }
+        if let Err(e) = cipher.decrypt_all(&mut entity.entity_data) {
+            log::error!("Failed to decrypt entity {}: {}", entity.id, e);
+        }
+    }
+    Ok(())
 }
 
 pub fn routes() -> Scope {
     web::scope("")
         // .service(handlers::admin)
         .service(create_entity)
+        .service(update_entity)
         .service(get_entities)
         .service(get_entity_by_id)
+        .service(get_entity_history)
         .service(hello)
+        .service(certificate_routes::routes())
+        // Registered last: an empty scope does not fall through to the routes after it
+        .service(credential_routes::routes())
+}
+
+/// Routes acting on the caller's own records, mounted under `/api/v1/me`
+pub fn me_routes() -> Scope {
+    web::scope("").service(get_my_entities)
 }
 
 /// Respond with "Hello world!"
@@ -332,11 +609,16 @@ async fn hello() -> impl Responder {
 
 /// Create an entity
 ///
-/// Creates an entity for a given entity type.
+/// Creates an entity for a given entity type. The caller must hold one of the `roles` or
+/// `inviteRoles` of the definition, no token is needed when they include `anonymous`.
 #[utoipa::path(
     post,
     path = "/api/v1/entity/{entity_type}",
     tags= [ENTITY, COMMANDS],
+    security(
+        (),
+        ("bearer_auth" = [])
+    ),
     request_body(
         content = String,
         content_type = "application/json",
@@ -353,20 +635,50 @@ async fn hello() -> impl Responder {
     responses(
         (status = 200, description = "Entity created", body = String),
         (status = 400, description = "Bad request", body = ErrorResponse),
+        (status = 401, description = "The definition roles require an access token", body = ErrorResponse),
+        (status = 403, description = "Caller holds none of the definition roles", body = String),
         (status = 409, description = "Entity Already Exists", body = String),
     )
 )]
 #[post("/{entity_type}")]
 async fn create_entity(
     decision_maker: Data<DecisionMaker>,
+    db_pool: Data<PgPool>,
+    cipher: Data<FieldCipher>,
+    issuer_key: Data<IssuerKey>,
+    claims: OptionalClaims,
     entity_type: web::Path<String>,
     web_cmd: web::Json<serde_json::Value>,
 ) -> Result<HttpResponse, DError> {
+    let entity_type = entity_type.into_inner();
+    let principal = claims.principal();
+    match load_definition_schema(db_pool.get_ref(), &entity_type).await {
+        Ok(Some(schema)) => authorize_entity(
+            principal.as_ref(),
+            &schema,
+            &entity_type,
+            EntityOperation::Create,
+        )?,
+        // The command reports the unknown entity type
+        Ok(None) => {}
+        Err(e) => {
+            log::error!("Failed to read definition of '{}': {}", entity_type, e);
+            return Ok(HttpResponse::InternalServerError().json(ErrorResponse {
+                error: Some("DATABASE_ERROR".to_string()),
+                error_description: Some(format!("Database error: {}", e)),
+                message: "Failed to create entity".to_string(),
+            }));
+        }
+    }
+    let referenced_ids = find_referenced_ids(db_pool.get_ref(), &entity_type, &web_cmd).await?;
     let create_entity_cmd = CreateEntityCmd {
         id: Uuid::now_v7(),
         entity_body: web_cmd.to_string(),
-        entity_type: entity_type.into_inner(),
-        created_by: "demo".to_string(),
+        entity_type,
+        created_by: principal.unwrap_or_default().actor(),
+        referenced_ids,
+        cipher: Some(cipher.get_ref().clone()),
+        signer: Some(issuer_key.get_ref().clone()),
     };
 
     let exec_results: Vec<PersistedEvent<PgEventId, DomainEvent>> =
@@ -410,6 +722,159 @@ async fn create_entity(
         }))
 }
 
+/// Update an entity
+///
+/// Replaces the body of an entity. Only the owners of the entity, as bound from the
+/// `ownershipAttributes` of its definition, or holders of one of the definition `roles` may
+/// update it.
+#[utoipa::path(
+    put,
+    path = "/api/v1/entity/{entity_type}/{id}",
+    tags= [ENTITY, COMMANDS],
+    security(
+        ("bearer_auth" = [])
+    ),
+    request_body(
+        content = String,
+        content_type = "application/json",
+        examples(
+            ("Teacher_smith" = (value = json!(serde_json::from_str::<Value>(TEACHER_SMITH_EXAMPLE).expect("Failed to parse TEACHER_SMITH_EXAMPLE JSON")), description = "Teacher in Education domain")),
+        )
+    ),
+    params(
+        ("entity_type" = String, Path, description = "Entity type", example = "Teacher"),
+        ("id" = String, Path, description = "Entity ID (UUID format)")
+    ),
+    responses(
+        (status = 200, description = "Entity updated", body = String),
+        (status = 400, description = "Bad request", body = ErrorResponse),
+        (status = 401, description = "Missing or invalid access token", body = ErrorResponse),
+        (status = 403, description = "Caller neither owns the entity nor holds a definition role", body = String),
+    )
+)]
+#[put("/{entity_type}/{id}")]
+async fn update_entity(
+    decision_maker: Data<DecisionMaker>,
+    db_pool: Data<PgPool>,
+    cipher: Data<FieldCipher>,
+    issuer_key: Data<IssuerKey>,
+    path: web::Path<(String, Uuid)>,
+    claims: Claims,
+    web_cmd: web::Json<serde_json::Value>,
+) -> Result<HttpResponse, DError> {
+    let (entity_type, id) = path.into_inner();
+    let referenced_ids = find_referenced_ids(db_pool.get_ref(), &entity_type, &web_cmd).await?;
+    let modify_entity_cmd = ModifyEntityCmd {
+        id,
+        entity_body: web_cmd.to_string(),
+        entity_type: entity_type.clone(),
+        principal: claims.principal(),
+        referenced_ids,
+        cipher: Some(cipher.get_ref().clone()),
+        signer: Some(issuer_key.get_ref().clone()),
+    };
+
+    decision_maker.make(modify_entity_cmd).await?;
+
+    Ok(HttpResponse::Ok()
+        .append_header((
+            "Location",
+            format!("{}{API_PREFIX}/entity/{}", base_url(), id),
+        ))
+        .json(SuccessResponse {
+            id: id.to_string(),
+            message: format!("Entity updated for Entity type: {}", entity_type),
+        }))
+}
+
+/// Get my entities
+///
+/// Lists the entities owned by the caller, across all entity types. An entity is owned when one
+/// of its owners has the caller's JWT subject as user id or the caller's email.
+#[utoipa::path(
+    get,
+    path = "/api/v1/me/entities",
+    tags= [ENTITY, QUERY],
+    responses(
+        (status = 200, description = "Entities owned by the caller", body = Vec<Entity>),
+        (status = 401, description = "Missing or invalid access token", body = ErrorResponse),
+        (status = 500, description = "Internal server error", body = ErrorResponse),
+    )
+)]
+#[get("/entities")]
+async fn get_my_entities(
+    db_pool: Data<PgPool>,
+    cipher: Data<FieldCipher>,
+    claims: Claims,
+) -> HttpResponse {
+    let principal = claims.principal();
+    let entities = async {
+        let mut entities = find_owned_entities(
+            db_pool.get_ref(),
+            &principal.subject,
+            principal.email.as_deref(),
+        )
+        .await?;
+        decrypt_authorized(db_pool.get_ref(), &cipher, Some(&principal), &mut entities).await?;
+        mask_internal_fields(db_pool.get_ref(), Some(&principal), &mut entities).await?;
+        Ok::<_, sqlx::Error>(entities)
+    }
+    .await;
+    match entities {
+        Ok(entities) => HttpResponse::Ok().json(entities),
+        Err(e) => {
+            log::error!("Failed to fetch owned entities: {}", e);
+            HttpResponse::InternalServerError().json(ErrorResponse {
+                error: Some("DATABASE_ERROR".to_string()),
+                error_description: Some(format!("Database error: {}", e)),
+                message: "Failed to fetch entities".to_string(),
+            })
+        }
+    }
+}
+
+/// Loads the entities whose owners match `subject` or `email` from their projection tables
+async fn find_owned_entities(
+    db_pool: &PgPool,
+    subject: &str,
+    email: Option<&str>,
+) -> Result<Vec<Entity>, sqlx::Error> {
+    let owned: Vec<(Uuid, String)> = sqlx::query_as(
+        "SELECT DISTINCT entity_id, entity_type FROM entity_owners WHERE ($1 <> '' AND user_id = $1) OR lower(email) = lower($2)",
+    )
+    .bind(subject)
+    .bind(email)
+    .fetch_all(db_pool)
+    .await?;
+
+    let mut by_type: HashMap<String, Vec<Uuid>> = HashMap::new();
+    for (entity_id, entity_type) in owned {
+        by_type.entry(entity_type).or_default().push(entity_id);
+    }
+
+    let mut entities = Vec::new();
+    for (entity_type, ids) in by_type {
+        let Some(type_name) = sanitize_column_name(&entity_type) else {
+            continue;
+        };
+        let sql = format!(
+            "SELECT id, entity_data, entity_type, created_by, created_at, registry_def_id, registry_def_version FROM {}_projection WHERE id = ANY($1)",
+            type_name.to_lowercase()
+        );
+        match sqlx::query_as::<_, Entity>(&sql)
+            .bind(&ids)
+            .fetch_all(db_pool)
+            .await
+        {
+            Ok(found) => entities.extend(found),
+            Err(e) if is_table_not_found_error(&e) => continue,
+            Err(e) => return Err(e),
+        }
+    }
+    entities.sort_by(|a, b| b.created_at.cmp(&a.created_at));
+    Ok(entities)
+}
+
 /// Get entities
 ///
 /// This endpoint retrieves entities from the projection table for a given entity type.
@@ -432,6 +897,7 @@ async fn create_entity(
 /// - `/api/v1/entity/Student?created_by=demo&registry_def_version=1&location_city=Seattle'` - Mixed types with special characters (sanitized automatically)
 /// - `/api/v1/entity/Student?created_by=demo&registry_def_version=1&active=true` - Multiple filters with different types
 /// - `/api/v1/entity/Student?name=John O'Connor` - String with special characters (automatically escaped)
+/// - `/api/v1/entity/Student?embed=true` - Embed the entities referenced through `x-ref` fields
 ///
 /// # Security
 /// - Entity types are validated against the definitions table
@@ -440,12 +906,23 @@ async fn create_entity(
 /// - SQL injection patterns are detected and blocked
 /// - String values are properly escaped with multiple escape mechanisms
 /// - Input length limits prevent buffer overflow attacks
+/// - `privateFields` are returned encrypted unless the bearer token belongs to an owner of the
+///   entity or to a holder of one of the definition `roles`
+/// - `internalFields` are removed, and filters on their columns are blocked, unless the bearer
+///   token has the `read:internal_fields` permission or one of the definition `roles`
+/// - Listing requires one of the definition `roles`, unless they include `anonymous`. Owners list
+///   their own entities with `/api/v1/me/entities`
 #[utoipa::path(
     get,
     path = "/api/v1/entity/{entity_type}",
     tags= [ENTITY, QUERY],
+    security(
+        (),
+        ("bearer_auth" = [])
+    ),
     params(
-        ("entity_type" = String, Path, description = "The type of entity to retrieve (e.g., Student, Teacher, Client)", example = "Consultant")
+        ("entity_type" = String, Path, description = "The type of entity to retrieve (e.g., Student, Teacher, Client)", example = "Consultant"),
+        ("embed" = Option<bool>, Query, description = "Embed the entities referenced through `x-ref` fields")
     ),
     responses(
         (status = 200,
@@ -510,6 +987,8 @@ async fn create_entity(
              ))
          )
         ),
+        (status = 401, description = "The definition roles require an access token", body = ErrorResponse),
+        (status = 403, description = "Caller holds none of the definition roles", body = String),
         (status = 404,
          description = "Entity type not found",
          body = ErrorResponse,
@@ -554,10 +1033,14 @@ async fn create_entity(
 #[get("/{entity_type}")]
 async fn get_entities(
     db_pool: Data<PgPool>,
+    cipher: Data<FieldCipher>,
+    claims: OptionalClaims,
     entity_type: web::Path<String>,
-    query: web::Query<std::collections::HashMap<String, String>>,
+    query: web::Query<HashMap<String, String>>,
 ) -> Result<HttpResponse, DError> {
     let entity_type_str = entity_type.into_inner();
+    let embed = embed_requested(&query);
+    let principal = claims.principal();
 
     // Validate that the entity type exists in definitions table
     match validate_entity_type(db_pool.get_ref(), &entity_type_str).await {
@@ -583,6 +1066,34 @@ async fn get_entities(
         }
     }
 
+    // Callers that cannot read the internal fields cannot filter on their columns either
+    let hidden_columns = match load_definition_schema(db_pool.get_ref(), &entity_type_str).await {
+        Ok(Some(schema)) => {
+            authorize_entity(
+                principal.as_ref(),
+                &schema,
+                &entity_type_str,
+                EntityOperation::Read,
+            )?;
+            let readable = InternalFields::from_schema(&schema, &entity_type_str)
+                .is_ok_and(|internal| internal.readable_by(principal.as_ref()));
+            if readable {
+                vec![]
+            } else {
+                internal_columns(&schema).unwrap_or_default()
+            }
+        }
+        Ok(None) => vec![],
+        Err(e) => {
+            log::error!("Failed to read definition of '{}': {}", entity_type_str, e);
+            return Ok(HttpResponse::InternalServerError().json(ErrorResponse {
+                error: Some("DATABASE_ERROR".to_string()),
+                error_description: Some(format!("Database error: {}", e)),
+                message: "Failed to fetch entities".to_string(),
+            }));
+        }
+    };
+
     let table_name = format!("{}_projection", entity_type_str.to_lowercase());
 
     let mut sql = format!(
@@ -596,9 +1107,18 @@ async fn get_entities(
     let mut blocked_filters = 0;
     let mut applied_filters = 0;
 
-    for (key, value) in query.iter() {
+    for (key, value) in query.iter().filter(|(key, _)| key.as_str() != EMBED_PARAM) {
         // Sanitize column name
         if let Some(sanitized_column) = sanitize_column_name(key) {
+            if hidden_columns.contains(&sanitized_column) {
+                blocked_filters += 1;
+                log_security_event(
+                    "COLUMN_NOT_VISIBLE",
+                    "Filter on an internal field without permission",
+                    key,
+                );
+                continue;
+            }
             // Sanitize value
             if let Some(sanitized_value) = sanitize_sql_value(value) {
                 conditions.push(format!("{} = {}", sanitized_column, sanitized_value));
@@ -642,13 +1162,55 @@ async fn get_entities(
         .fetch_all(db_pool.get_ref())
         .await
     {
-        Ok(entities) => {
+        Ok(mut entities) => {
             log::info!(
                 "Successfully retrieved {} entities of type '{}' with {} filters",
                 entities.len(),
                 entity_type_str,
                 applied_filters
             );
+            if let Err(e) = decrypt_authorized(
+                db_pool.get_ref(),
+                &cipher,
+                principal.as_ref(),
+                &mut entities,
+            )
+            .await
+            {
+                log::error!("Failed to authorize decryption: {}", e);
+                return Ok(HttpResponse::InternalServerError().json(ErrorResponse {
+                    error: Some("DATABASE_ERROR".to_string()),
+                    error_description: Some(format!("Database error: {}", e)),
+                    message: "Failed to fetch entities".to_string(),
+                }));
+            }
+            if embed {
+                if let Err(e) = embed_references(
+                    db_pool.get_ref(),
+                    &cipher,
+                    principal.as_ref(),
+                    &mut entities,
+                )
+                .await
+                {
+                    log::error!("Failed to embed references: {}", e);
+                    return Ok(HttpResponse::InternalServerError().json(ErrorResponse {
+                        error: Some("DATABASE_ERROR".to_string()),
+                        error_description: Some(format!("Database error: {}", e)),
+                        message: "Failed to embed referenced entities".to_string(),
+                    }));
+                }
+            }
+            if let Err(e) =
+                mask_internal_fields(db_pool.get_ref(), principal.as_ref(), &mut entities).await
+            {
+                log::error!("Failed to mask internal fields: {}", e);
+                return Ok(HttpResponse::InternalServerError().json(ErrorResponse {
+                    error: Some("DATABASE_ERROR".to_string()),
+                    error_description: Some(format!("Database error: {}", e)),
+                    message: "Failed to fetch entities".to_string(),
+                }));
+            }
             Ok(HttpResponse::Ok().json(entities))
         }
         Err(e) => {
@@ -689,11 +1251,17 @@ async fn get_entities(
 ///
 /// This endpoint retrieves a specific entity by its ID from the projection table
 /// for a given entity type. The entity ID must be a valid UUID.
+/// `privateFields` are decrypted only for the owners of the entity and the holders of one of the
+/// definition `roles`, other callers receive the encrypted envelopes. `internalFields` are removed
+/// unless the caller has the `read:internal_fields` permission or one of the definition `roles`.
+/// Reading requires one of the definition `roles`, unless they include `anonymous`, or owning the
+/// entity.
 ///
 /// # Examples
 /// - `/api/v1/entity/Student/123e4567-e89b-12d3-a456-426614174000` - Get specific student
 /// - `/api/v1/entity/Teacher/456e7890-e12b-34d5-a678-901234567890` - Get specific teacher
 /// - `/api/v1/entity/Client/789abcdef-0123-4567-8901-23456789abcd` - Get specific client
+/// - `/api/v1/entity/Student/123e4567-e89b-12d3-a456-426614174000?embed=true` - Embed referenced entities
 ///
 /// # Error Scenarios
 /// - Returns 404 if the entity type is not defined in the definitions table
@@ -705,10 +1273,15 @@ async fn get_entities(
     path = "/api/v1/entity/{entity_type}/{id}",
     tags= [ENTITY, QUERY],
     summary = "Get entity by ID",
+    security(
+        (),
+        ("bearer_auth" = [])
+    ),
     description = "Retrieves a specific entity by its ID from the projection table for a given entity type.",
     params(
         ("entity_type" = String, Path, description = "The type of entity (e.g., Student, Teacher, Client)", example = "Student"),
-        ("id" = String, Path, description = "The unique identifier of the entity (UUID format)", example = "123e4567-e89b-12d3-a456-426614174000")
+        ("id" = String, Path, description = "The unique identifier of the entity (UUID format)", example = "123e4567-e89b-12d3-a456-426614174000"),
+        ("embed" = Option<bool>, Query, description = "Embed the entities referenced through `x-ref` fields")
     ),
     responses(
         (status = 200,
@@ -752,6 +1325,8 @@ async fn get_entities(
              ))
          )
         ),
+        (status = 401, description = "The definition roles require an access token", body = ErrorResponse),
+        (status = 403, description = "Caller neither owns the entity nor holds a definition role", body = String),
         (status = 404,
          description = "Entity not found or entity type does not exist",
          body = ErrorResponse,
@@ -805,9 +1380,14 @@ async fn get_entities(
 #[get("/{entity_type}/{id}")]
 async fn get_entity_by_id(
     db_pool: Data<PgPool>,
+    cipher: Data<FieldCipher>,
+    claims: OptionalClaims,
     path: web::Path<(String, Uuid)>,
+    query: web::Query<HashMap<String, String>>,
 ) -> Result<HttpResponse, DError> {
     let (entity_type_str, entity_id) = path.into_inner();
+    let embed = embed_requested(&query);
+    let principal = claims.principal();
 
     // Validate that the entity type exists in definitions table
     match validate_entity_type(db_pool.get_ref(), &entity_type_str).await {
@@ -833,6 +1413,25 @@ async fn get_entity_by_id(
         }
     }
 
+    let authorized = authorize_read(
+        db_pool.get_ref(),
+        principal.as_ref(),
+        &entity_type_str,
+        entity_id,
+    )
+    .await;
+    match authorized {
+        Ok(by_role) => by_role?,
+        Err(e) => {
+            log::error!("Failed to authorize reading entity {}: {}", entity_id, e);
+            return Ok(HttpResponse::InternalServerError().json(ErrorResponse {
+                error: Some("DATABASE_ERROR".to_string()),
+                error_description: Some(format!("Database error: {}", e)),
+                message: "Failed to fetch entity".to_string(),
+            }));
+        }
+    }
+
     let table_name = format!("{}_projection", entity_type_str.to_lowercase());
 
     let sql = format!(
@@ -845,7 +1444,53 @@ async fn get_entity_by_id(
         .fetch_optional(db_pool.get_ref())
         .await
     {
-        Ok(Some(entity)) => Ok(HttpResponse::Ok().json(entity)),
+        Ok(Some(entity)) => {
+            let mut entities = [entity];
+            if let Err(e) = decrypt_authorized(
+                db_pool.get_ref(),
+                &cipher,
+                principal.as_ref(),
+                &mut entities,
+            )
+            .await
+            {
+                log::error!("Failed to authorize decryption: {}", e);
+                return Ok(HttpResponse::InternalServerError().json(ErrorResponse {
+                    error: Some("DATABASE_ERROR".to_string()),
+                    error_description: Some(format!("Database error: {}", e)),
+                    message: "Failed to fetch entity".to_string(),
+                }));
+            }
+            if embed {
+                if let Err(e) = embed_references(
+                    db_pool.get_ref(),
+                    &cipher,
+                    principal.as_ref(),
+                    &mut entities,
+                )
+                .await
+                {
+                    log::error!("Failed to embed references: {}", e);
+                    return Ok(HttpResponse::InternalServerError().json(ErrorResponse {
+                        error: Some("DATABASE_ERROR".to_string()),
+                        error_description: Some(format!("Database error: {}", e)),
+                        message: "Failed to embed referenced entities".to_string(),
+                    }));
+                }
+            }
+            if let Err(e) =
+                mask_internal_fields(db_pool.get_ref(), principal.as_ref(), &mut entities).await
+            {
+                log::error!("Failed to mask internal fields: {}", e);
+                return Ok(HttpResponse::InternalServerError().json(ErrorResponse {
+                    error: Some("DATABASE_ERROR".to_string()),
+                    error_description: Some(format!("Database error: {}", e)),
+                    message: "Failed to fetch entity".to_string(),
+                }));
+            }
+            let [entity] = entities;
+            Ok(HttpResponse::Ok().json(entity))
+        }
         Ok(None) => Ok(HttpResponse::NotFound().json(ErrorResponse {
             error: Some("NOT_FOUND".to_string()),
             error_description: Some("Entity not found".to_string()),
@@ -874,3 +1519,137 @@ async fn get_entity_by_id(
         }
     }
 }
+
+/// Get the history of an entity
+///
+/// Every command applied to the entity, its credentials, claims and consents, with the actor
+/// that issued it and the subject it acted for, oldest first. Readable by the holders of a
+/// definition role and by the owners of the entity.
+#[utoipa::path(
+    get,
+    path = "/api/v1/entity/{entity_type}/{id}/history",
+    tags= [ENTITY, QUERY],
+    security(
+        (),
+        ("bearer_auth" = [])
+    ),
+    params(
+        ("entity_type" = String, Path, description = "Entity type", example = "Student"),
+        ("id" = Uuid, Path, description = "ID of the entity", example = "123e4567-e89b-12d3-a456-426614174000")
+    ),
+    responses(
+        (status = 200, body = [HistoryEntry]),
+        (status = 401, description = "The definition roles require an access token", body = ErrorResponse),
+        (status = 403, description = "Caller neither owns the entity nor holds a definition role", body = String),
+        (status = 404, description = "Entity not found", body = ErrorResponse),
+        (status = 500, description = "Failed to fetch the history", body = ErrorResponse),
+    )
+)]
+#[get("/{entity_type}/{id}/history")]
+async fn get_entity_history(
+    db_pool: Data<PgPool>,
+    claims: OptionalClaims,
+    path: web::Path<(String, Uuid)>,
+) -> Result<HttpResponse, DError> {
+    let (entity_type, entity_id) = path.into_inner();
+    let principal = claims.principal();
+    let database_error = |e: sqlx::Error| {
+        log::error!("Failed to fetch the history of entity {}: {}", entity_id, e);
+        HttpResponse::InternalServerError().json(ErrorResponse {
+            error: Some("DATABASE_ERROR".to_string()),
+            error_description: Some(format!("Database error: {}", e)),
+            message: "Failed to fetch entity history".to_string(),
+        })
+    };
+
+    // The role check only holds for entities of the requested type
+    let sql = format!(
+        "SELECT EXISTS (SELECT 1 FROM {}_projection WHERE id = $1)",
+        entity_type.to_lowercase()
+    );
+    let exists = match validate_entity_type(db_pool.get_ref(), &entity_type).await {
+        Ok(true) => sqlx::query_scalar::<_, bool>(&sql)
+            .bind(entity_id)
+            .fetch_one(db_pool.get_ref())
+            .await
+            .or_else(|e| {
+                if is_table_not_found_error(&e) {
+                    Ok(false)
+                } else {
+                    Err(e)
+                }
+            }),
+        result => result,
+    };
+    match exists {
+        Ok(true) => {}
+        Ok(false) => {
+            return Ok(HttpResponse::NotFound().json(ErrorResponse {
+                error: Some("NOT_FOUND".to_string()),
+                error_description: Some("Entity not found".to_string()),
+                message: format!(
+                    "Entity with ID {} not found for type: {}",
+                    entity_id, entity_type
+                ),
+            }))
+        }
+        Err(e) => return Ok(database_error(e)),
+    }
+    match authorize_read(
+        db_pool.get_ref(),
+        principal.as_ref(),
+        &entity_type,
+        entity_id,
+    )
+    .await
+    {
+        Ok(authorized) => authorized?,
+        Err(e) => return Ok(database_error(e)),
+    }
+
+    match history(db_pool.get_ref(), entity_id).await {
+        Ok(entries) => Ok(HttpResponse::Ok().json(entries)),
+        Err(e) => Ok(database_error(e)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use definitions_core::ownership::ANONYMOUS_ROLE;
+
+    fn principal(subject: &str, roles: &[&str]) -> Principal {
+        Principal {
+            subject: subject.to_string(),
+            roles: roles.iter().map(|role| role.to_string()).collect(),
+            ..Principal::default()
+        }
+    }
+
+    #[test]
+    fn test_only_owners_and_role_holders_read_private_fields() {
+        let owners = vec![Owner {
+            user_id: Some("auth0|john".to_string()),
+            email: None,
+            mobile: None,
+        }];
+        let roles = vec![ANONYMOUS_ROLE.to_string(), "registrar".to_string()];
+
+        assert!(may_decrypt(&principal("auth0|john", &[]), &owners, &roles));
+        assert!(may_decrypt(
+            &principal("auth0|clerk", &["registrar"]),
+            &owners,
+            &roles
+        ));
+        assert!(!may_decrypt(
+            &principal("auth0|stranger", &[ANONYMOUS_ROLE]),
+            &owners,
+            &roles
+        ));
+        assert!(!may_decrypt(
+            &principal("auth0|stranger", &[]),
+            &[],
+            &[ANONYMOUS_ROLE.to_string()]
+        ));
+    }
+}
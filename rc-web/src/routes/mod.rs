@@ -2,10 +2,21 @@ use serde::Serialize;
 use utoipa::ToSchema;
 
 pub mod api_routes;
+pub mod certificate_routes;
+pub mod claim_routes;
+pub mod consent_routes;
+pub mod credential_routes;
 pub mod definition_routes;
+pub mod did_routes;
 pub mod entity_routes;
 pub mod health_check;
+pub mod notification_routes;
+pub mod registration_routes;
+pub mod service_account_routes;
+pub mod signature_routes;
 pub mod user;
+pub mod verification_routes;
+pub mod wallet_routes;
 
 pub const INSURANCE_EXAMPLE: &str = r###"{
   "$schema": "http://json-schema.org/draft-07/schema",
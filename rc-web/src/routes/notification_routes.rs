@@ -0,0 +1,70 @@
+use crate::middleware::authorization::{require_permission, ADMIN_PERMISSION};
+use crate::middleware::claims::Claims;
+use crate::routes::ErrorResponse;
+use crate::services::notification_outbox::{
+    DeliveryStatus, NotificationOutbox, NotificationRecord,
+};
+use crate::{DError, QUERY};
+use actix_web::web::Data;
+use actix_web::{get, web, HttpResponse, Scope};
+use serde::Deserialize;
+use utoipa::IntoParams;
+use uuid::Uuid;
+
+const DEFAULT_LIMIT: i64 = 100;
+const MAX_LIMIT: i64 = 1000;
+
+pub fn routes() -> Scope {
+    web::scope("").service(get_notifications)
+}
+
+#[derive(Debug, Deserialize, IntoParams)]
+pub struct NotificationsQuery {
+    /// Only the notifications with this delivery status
+    pub status: Option<DeliveryStatus>,
+    /// Only the notifications about this entity
+    pub entity_id: Option<Uuid>,
+    /// Maximum number of notifications, 100 by default and at most 1000
+    pub limit: Option<i64>,
+}
+
+/// List the notifications and their delivery status
+///
+/// Newest first, without the message bodies.
+#[utoipa::path(
+    get,
+    path = "/api/v1/notifications",
+    tags= [QUERY],
+    security(
+        ("bearer_auth" = ["admin:registry"])
+    ),
+    params(NotificationsQuery),
+    responses(
+        (status = 200, body = [NotificationRecord]),
+        (status = 401, description = "Missing or invalid credentials", body = ErrorResponse),
+        (status = 403, description = "Caller lacks the `admin:registry` permission", body = String),
+        (status = 500, description = "Internal server error", body = ErrorResponse)
+    )
+)]
+#[get("")]
+async fn get_notifications(
+    outbox: Data<NotificationOutbox>,
+    claims: Claims,
+    query: web::Query<NotificationsQuery>,
+) -> Result<HttpResponse, DError> {
+    require_permission(&claims, ADMIN_PERMISSION, "read notifications")?;
+    let limit = query.limit.unwrap_or(DEFAULT_LIMIT).clamp(1, MAX_LIMIT);
+    Ok(
+        match outbox.list(query.status, query.entity_id, limit).await {
+            Ok(notifications) => HttpResponse::Ok().json(notifications),
+            Err(e) => {
+                log::error!("Failed to list notifications: {}", e);
+                HttpResponse::InternalServerError().json(ErrorResponse {
+                    error: Some("DATABASE_ERROR".to_string()),
+                    error_description: Some(format!("Database error: {}", e)),
+                    message: "Failed to list notifications".to_string(),
+                })
+            }
+        },
+    )
+}
@@ -0,0 +1,211 @@
+use crate::middleware::claims::OptionalClaims;
+use crate::routes::entity_routes::find_referenced_ids;
+use crate::routes::{ErrorResponse, STUDENT_JOHN_EXAMPLE};
+use crate::services::notifier::Notifier;
+use crate::services::registration_codes::{RegistrationCodes, SentCode};
+use crate::{base_url, DError, DecisionMaker, SuccessResponse};
+use crate::{API_PREFIX, COMMANDS, ENTITY};
+use actix_web::web::{Data, Json};
+use actix_web::{post, web, HttpResponse, Scope};
+use definitions_core::definitions_domain::DomainEvent;
+use definitions_core::encryption::FieldCipher;
+use definitions_core::registration::{
+    verification_contact, ConfirmRegistrationCmd, RegisterEntityCmd,
+};
+use definitions_core::registry_domain::EntityError;
+use definitions_core::signing::IssuerKey;
+use serde::{Deserialize, Serialize};
+#[allow(unused_imports)]
+use serde_json::{json, Value};
+use std::ops::Deref;
+use utoipa::ToSchema;
+use uuid::Uuid;
+
+pub fn routes() -> Scope {
+    web::scope("")
+        .service(register_entity)
+        .service(confirm_registration)
+        .service(resend_registration_code)
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct PendingRegistration {
+    /// Id of the registered entity, to confirm with the code
+    pub id: Uuid,
+    pub entity_type: String,
+    #[serde(flatten)]
+    pub code: SentCode,
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct ConfirmRegistrationRequest {
+    /// The one-time code sent to the owner
+    pub code: String,
+}
+
+/// Register an entity
+///
+/// Signs up an entity of a type whose `roles` or `inviteRoles` include `anonymous`, without an
+/// access token. The entity stays pending, and is not listed, until the one-time code sent to
+/// the email, or else the mobile, of its `ownershipAttributes` is confirmed.
+#[utoipa::path(
+    post,
+    path = "/api/v1/registrations/{entity_type}",
+    tags= [ENTITY, COMMANDS],
+    security(
+        (),
+        ("bearer_auth" = [])
+    ),
+    request_body(
+        content = String,
+        content_type = "application/json",
+        examples(
+            ("Student_john" = (value = json!(serde_json::from_str::<Value>(STUDENT_JOHN_EXAMPLE).expect("Failed to parse STUDENT_JOHN_EXAMPLE JSON")), description = "Student in Education domain")),
+        )
+    ),
+    params(
+        ("entity_type" = String, Path, description = "Entity type", example = "Student")
+    ),
+    responses(
+        (status = 202, description = "Registration pending, a code was sent", body = PendingRegistration),
+        (status = 400, description = "Invalid entity or no email or mobile owner", body = ErrorResponse),
+        (status = 403, description = "The entity type is not open to self-registration", body = String),
+        (status = 502, description = "The code could not be sent", body = String),
+    )
+)]
+#[post("/{entity_type}")]
+#[allow(clippy::too_many_arguments)]
+async fn register_entity(
+    decision_maker: Data<DecisionMaker>,
+    db_pool: Data<sqlx::PgPool>,
+    cipher: Data<FieldCipher>,
+    issuer_key: Data<IssuerKey>,
+    codes: Data<RegistrationCodes>,
+    notifier: Data<dyn Notifier>,
+    claims: OptionalClaims,
+    entity_type: web::Path<String>,
+    web_cmd: Json<Value>,
+) -> Result<HttpResponse, DError> {
+    let entity_type = entity_type.into_inner();
+    let referenced_ids = find_referenced_ids(db_pool.get_ref(), &entity_type, &web_cmd).await?;
+    let register_cmd = RegisterEntityCmd {
+        id: Uuid::now_v7(),
+        entity_body: web_cmd.to_string(),
+        entity_type: entity_type.clone(),
+        registered_by: claims.principal().unwrap_or_default().actor(),
+        referenced_ids,
+        cipher: Some(cipher.get_ref().clone()),
+        signer: Some(issuer_key.get_ref().clone()),
+    };
+
+    let events = decision_maker.make(register_cmd).await?;
+    let (id, contact) = events
+        .iter()
+        .find_map(|event| match event.deref() {
+            DomainEvent::EntityRegistered { id, owners, .. } => {
+                Some((*id, verification_contact(owners)?))
+            }
+            _ => None,
+        })
+        .ok_or_else(|| {
+            DError::from(disintegrate::DecisionError::Domain(
+                EntityError::EventNotFound("EntityRegistered".to_string()),
+            ))
+        })?;
+    let code = codes
+        .send(notifier.get_ref(), id, &entity_type, &contact)
+        .await?;
+
+    Ok(HttpResponse::Accepted().json(PendingRegistration {
+        id,
+        entity_type,
+        code,
+    }))
+}
+
+/// Confirm a registration
+///
+/// Creates the registered entity when the code sent to its owner matches.
+#[utoipa::path(
+    post,
+    path = "/api/v1/registrations/{entity_type}/{id}/confirm",
+    tags= [ENTITY, COMMANDS],
+    security(
+        (),
+        ("bearer_auth" = [])
+    ),
+    request_body = ConfirmRegistrationRequest,
+    params(
+        ("entity_type" = String, Path, description = "Entity type", example = "Student"),
+        ("id" = Uuid, Path, description = "Id of the registered entity")
+    ),
+    responses(
+        (status = 200, description = "Entity created", body = String),
+        (status = 400, description = "Wrong or expired code", body = String),
+        (status = 404, description = "No pending registration", body = String),
+        (status = 409, description = "The registration is already confirmed", body = String),
+        (status = 429, description = "Too many wrong codes, a new code must be requested", body = String),
+    )
+)]
+#[post("/{entity_type}/{id}/confirm")]
+async fn confirm_registration(
+    decision_maker: Data<DecisionMaker>,
+    codes: Data<RegistrationCodes>,
+    claims: OptionalClaims,
+    path: web::Path<(String, Uuid)>,
+    request: Json<ConfirmRegistrationRequest>,
+) -> Result<HttpResponse, DError> {
+    let (entity_type, id) = path.into_inner();
+    codes.verify(id, &entity_type, &request.code).await?;
+    decision_maker
+        .make(ConfirmRegistrationCmd {
+            id,
+            entity_type: entity_type.clone(),
+            confirmed_by: claims.principal().unwrap_or_default().actor(),
+        })
+        .await?;
+    codes.remove(id).await?;
+
+    Ok(HttpResponse::Ok()
+        .append_header((
+            "Location",
+            format!("{}{API_PREFIX}/entity/{}", base_url(), id),
+        ))
+        .json(SuccessResponse {
+            id: id.to_string(),
+            message: format!("Entity created for Entity type: {}", entity_type),
+        }))
+}
+
+/// Send a new registration code
+///
+/// Replaces the code of a pending registration, at most once a minute.
+#[utoipa::path(
+    post,
+    path = "/api/v1/registrations/{entity_type}/{id}/resend",
+    tags= [ENTITY, COMMANDS],
+    security(
+        (),
+        ("bearer_auth" = [])
+    ),
+    params(
+        ("entity_type" = String, Path, description = "Entity type", example = "Student"),
+        ("id" = Uuid, Path, description = "Id of the registered entity")
+    ),
+    responses(
+        (status = 202, description = "A new code was sent", body = SentCode),
+        (status = 404, description = "No pending registration", body = String),
+        (status = 429, description = "A code was sent less than a minute ago", body = String),
+        (status = 502, description = "The code could not be sent", body = String),
+    )
+)]
+#[post("/{entity_type}/{id}/resend")]
+async fn resend_registration_code(
+    codes: Data<RegistrationCodes>,
+    notifier: Data<dyn Notifier>,
+    path: web::Path<(String, Uuid)>,
+) -> Result<HttpResponse, DError> {
+    let (entity_type, id) = path.into_inner();
+    let code = codes.resend(notifier.get_ref(), id, &entity_type).await?;
+    Ok(HttpResponse::Accepted().json(code))
+}
@@ -0,0 +1,197 @@
+use crate::middleware::authorization::{require_permission, ADMIN_PERMISSION};
+use crate::middleware::claims::Claims;
+use crate::routes::ErrorResponse;
+use crate::services::api_keys::{
+    ApiKeyInfo, ApiKeyStore, IssuedApiKey, KeyRotation, NewServiceAccount, ServiceAccount,
+};
+use crate::{DError, COMMANDS, QUERY};
+use actix_web::web::{Data, Json};
+use actix_web::{delete, get, post, web, HttpResponse, Scope};
+use serde::Serialize;
+use utoipa::ToSchema;
+use uuid::Uuid;
+
+pub fn routes() -> Scope {
+    web::scope("")
+        .service(create_service_account)
+        .service(get_service_accounts)
+        .service(rotate_api_key)
+        .service(revoke_api_key)
+        .service(disable_service_account)
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct CreatedServiceAccount {
+    pub account: ServiceAccount,
+    /// The first key of the account, its secret is not shown again
+    pub key: IssuedApiKey,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct ServiceAccountKeys {
+    pub account: ServiceAccount,
+    /// Keys of the account without their secrets, newest first
+    pub keys: Vec<ApiKeyInfo>,
+}
+
+/// Create a service account
+///
+/// The account is granted its `scopes` as permissions and its `roles` as roles. The response
+/// carries its first API key, to send in the `X-API-Key` header.
+#[utoipa::path(
+    post,
+    path = "/api/v1/service-accounts",
+    tags= [COMMANDS],
+    security(
+        ("bearer_auth" = ["admin:registry"])
+    ),
+    request_body = NewServiceAccount,
+    responses(
+        (status = 201, body = CreatedServiceAccount),
+        (status = 400, description = "The key expiry is out of range", body = String),
+        (status = 401, description = "Missing or invalid credentials", body = ErrorResponse),
+        (status = 403, description = "Caller lacks the `admin:registry` permission", body = String),
+        (status = 409, description = "A service account with this name exists", body = String)
+    )
+)]
+#[post("")]
+async fn create_service_account(
+    store: Data<ApiKeyStore>,
+    claims: Claims,
+    account: Json<NewServiceAccount>,
+) -> Result<HttpResponse, DError> {
+    require_permission(&claims, ADMIN_PERMISSION, "create service accounts")?;
+    let (account, key) = store
+        .create_account(account.into_inner(), &claims.actor())
+        .await?;
+    Ok(HttpResponse::Created().json(CreatedServiceAccount { account, key }))
+}
+
+/// List the service accounts and their keys
+#[utoipa::path(
+    get,
+    path = "/api/v1/service-accounts",
+    tags= [QUERY],
+    security(
+        ("bearer_auth" = ["admin:registry"])
+    ),
+    responses(
+        (status = 200, body = [ServiceAccountKeys]),
+        (status = 401, description = "Missing or invalid credentials", body = ErrorResponse),
+        (status = 403, description = "Caller lacks the `admin:registry` permission", body = String)
+    )
+)]
+#[get("")]
+async fn get_service_accounts(
+    store: Data<ApiKeyStore>,
+    claims: Claims,
+) -> Result<HttpResponse, DError> {
+    require_permission(&claims, ADMIN_PERMISSION, "read service accounts")?;
+    let mut accounts = Vec::new();
+    for account in store.accounts().await? {
+        let keys = store.keys(account.id).await?;
+        accounts.push(ServiceAccountKeys { account, keys });
+    }
+    Ok(HttpResponse::Ok().json(accounts))
+}
+
+/// Issue a new API key for a service account
+///
+/// The other keys of the account stay valid unless `previous_keys_grace_secs` is set, in which
+/// case they expire after that many seconds.
+#[utoipa::path(
+    post,
+    path = "/api/v1/service-accounts/{id}/keys",
+    tags= [COMMANDS],
+    security(
+        ("bearer_auth" = ["admin:registry"])
+    ),
+    params(
+        ("id" = Uuid, Path, description = "ID of the service account")
+    ),
+    request_body = KeyRotation,
+    responses(
+        (status = 201, body = IssuedApiKey),
+        (status = 400, description = "The key expiry is out of range", body = String),
+        (status = 401, description = "Missing or invalid credentials", body = ErrorResponse),
+        (status = 403, description = "Caller lacks the `admin:registry` permission", body = String),
+        (status = 404, description = "Service account not found", body = String),
+        (status = 409, description = "The service account is disabled", body = String)
+    )
+)]
+#[post("/{id}/keys")]
+async fn rotate_api_key(
+    store: Data<ApiKeyStore>,
+    claims: Claims,
+    path: web::Path<Uuid>,
+    rotation: Option<Json<KeyRotation>>,
+) -> Result<HttpResponse, DError> {
+    require_permission(&claims, ADMIN_PERMISSION, "issue API keys")?;
+    let rotation = rotation.map(Json::into_inner).unwrap_or_default();
+    let key = store
+        .rotate_key(path.into_inner(), rotation, &claims.actor())
+        .await?;
+    Ok(HttpResponse::Created().json(key))
+}
+
+/// Revoke an API key
+#[utoipa::path(
+    delete,
+    path = "/api/v1/service-accounts/{id}/keys/{key_id}",
+    tags= [COMMANDS],
+    security(
+        ("bearer_auth" = ["admin:registry"])
+    ),
+    params(
+        ("id" = Uuid, Path, description = "ID of the service account"),
+        ("key_id" = Uuid, Path, description = "ID of the key")
+    ),
+    responses(
+        (status = 204, description = "The key is revoked"),
+        (status = 401, description = "Missing or invalid credentials", body = ErrorResponse),
+        (status = 403, description = "Caller lacks the `admin:registry` permission", body = String),
+        (status = 404, description = "API key not found", body = String)
+    )
+)]
+#[delete("/{id}/keys/{key_id}")]
+async fn revoke_api_key(
+    store: Data<ApiKeyStore>,
+    claims: Claims,
+    path: web::Path<(Uuid, Uuid)>,
+) -> Result<HttpResponse, DError> {
+    require_permission(&claims, ADMIN_PERMISSION, "revoke API keys")?;
+    let (id, key_id) = path.into_inner();
+    store.revoke_key(id, key_id).await?;
+    Ok(HttpResponse::NoContent().finish())
+}
+
+/// Disable a service account
+///
+/// None of the keys of a disabled account is accepted.
+#[utoipa::path(
+    delete,
+    path = "/api/v1/service-accounts/{id}",
+    tags= [COMMANDS],
+    security(
+        ("bearer_auth" = ["admin:registry"])
+    ),
+    params(
+        ("id" = Uuid, Path, description = "ID of the service account")
+    ),
+    responses(
+        (status = 204, description = "The service account is disabled"),
+        (status = 401, description = "Missing or invalid credentials", body = ErrorResponse),
+        (status = 403, description = "Caller lacks the `admin:registry` permission", body = String),
+        (status = 404, description = "Service account not found", body = String)
+    )
+)]
+#[delete("/{id}")]
+async fn disable_service_account(
+    store: Data<ApiKeyStore>,
+    claims: Claims,
+    path: web::Path<Uuid>,
+) -> Result<HttpResponse, DError> {
+    require_permission(&claims, ADMIN_PERMISSION, "disable service accounts")?;
+    store.disable_account(path.into_inner()).await?;
+    Ok(HttpResponse::NoContent().finish())
+}
@@ -0,0 +1,150 @@
+use crate::routes::ErrorResponse;
+use crate::{ENTITY, QUERY};
+use actix_web::web::{Data, Json};
+use actix_web::{get, post, web, HttpResponse, Scope};
+use definitions_core::did::IssuerIdentity;
+use definitions_core::signing::{PublicKey, SignedFields};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use sqlx::PgPool;
+use utoipa::ToSchema;
+
+pub fn routes() -> Scope {
+    web::scope("")
+        .service(verify_signature)
+        .service(get_signing_keys)
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct VerifySignatureRequest {
+    /// Entity type whose `signedFields` apply
+    pub entity_type: String,
+    /// The entity data, as stored in the registry or exported from it
+    #[schema(value_type = Object)]
+    pub entity: Value,
+    /// A detached signature, `_osSignedData` of the entity is used when missing
+    pub signature: Option<String>,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct VerifySignatureResponse {
+    pub valid: bool,
+    /// Id of the key that produced the signature
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub kid: Option<String>,
+    /// Why the verification failed
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+/// Verify a signed entity
+///
+/// Checks that the signature was produced by the registry issuer key and that it covers the
+/// `signedFields` of the presented entity, or the whole entity when the definition declares none.
+/// `privateFields` must be presented decrypted.
+#[utoipa::path(
+    post,
+    path = "/api/v1/signatures/verify",
+    tags= [ENTITY, QUERY],
+    request_body(
+        content = VerifySignatureRequest,
+        content_type = "application/json",
+        examples(
+            ("Insurance" = (value = json!({"entity_type": "Insurance", "entity": {"Insurance": {"policyNumber": "P-1", "_osSignedData": "eyJhbGciOiJFZERTQSIsImtpZCI6Ii4uLiJ9..."}}}), description = "Entity carrying its signature in _osSignedData")),
+        )
+    ),
+    responses(
+        (status = 200, description = "Verification result", body = VerifySignatureResponse),
+        (status = 400, description = "The definition declares invalid signedFields", body = ErrorResponse),
+        (status = 404, description = "Entity type not found in definitions", body = ErrorResponse),
+        (status = 500, description = "Internal server error", body = ErrorResponse),
+    )
+)]
+#[post("/verify")]
+async fn verify_signature(
+    db_pool: Data<PgPool>,
+    issuer_identity: Data<IssuerIdentity>,
+    request: Json<VerifySignatureRequest>,
+) -> HttpResponse {
+    let schema = match sqlx::query_scalar::<_, Value>(
+        "SELECT json_schema_string FROM definitions WHERE title = $1",
+    )
+    .bind(&request.entity_type)
+    .fetch_optional(db_pool.get_ref())
+    .await
+    {
+        Ok(Some(schema)) => schema,
+        Ok(None) => {
+            return HttpResponse::NotFound().json(ErrorResponse {
+                error: Some("INVALID_ENTITY_TYPE".to_string()),
+                error_description: Some(format!(
+                    "Entity type '{}' not found in definitions",
+                    request.entity_type
+                )),
+                message: "Entity type invalid".to_string(),
+            })
+        }
+        Err(e) => {
+            log::error!(
+                "Failed to read definition of '{}': {}",
+                request.entity_type,
+                e
+            );
+            return HttpResponse::InternalServerError().json(ErrorResponse {
+                error: Some("DATABASE_ERROR".to_string()),
+                error_description: Some(format!("Database error: {}", e)),
+                message: "Failed to verify signature".to_string(),
+            });
+        }
+    };
+    let signed_fields = match SignedFields::from_schema(&schema.to_string(), &request.entity_type) {
+        Ok(signed_fields) => signed_fields,
+        Err(e) => {
+            return HttpResponse::BadRequest().json(ErrorResponse {
+                error: Some("INVALID_DEFINITION".to_string()),
+                error_description: Some(e.to_string()),
+                message: "Failed to verify signature".to_string(),
+            })
+        }
+    };
+
+    let response = match signed_fields.verify(
+        &request.entity.to_string(),
+        request.signature.as_deref(),
+        &issuer_identity.public_keys(),
+    ) {
+        Ok(kid) => VerifySignatureResponse {
+            valid: true,
+            kid: Some(kid),
+            error: None,
+        },
+        Err(e) => VerifySignatureResponse {
+            valid: false,
+            kid: None,
+            error: Some(e.to_string()),
+        },
+    };
+    HttpResponse::Ok().json(response)
+}
+
+/// Get the signing keys
+///
+/// The public keys of the registry issuer as a JSON Web Key Set, for offline verification of
+/// `_osSignedData`. The current key comes first, retired keys follow.
+#[utoipa::path(
+    get,
+    path = "/api/v1/signatures/keys",
+    tags= [ENTITY, QUERY],
+    responses(
+        (status = 200, description = "JSON Web Key Set of the issuer", body = Object),
+    )
+)]
+#[get("/keys")]
+async fn get_signing_keys(issuer_identity: Data<IssuerIdentity>) -> HttpResponse {
+    let keys: Vec<Value> = issuer_identity
+        .public_keys()
+        .iter()
+        .map(PublicKey::to_jwk)
+        .collect();
+    HttpResponse::Ok().json(json!({ "keys": keys }))
+}
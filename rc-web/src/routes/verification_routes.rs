@@ -0,0 +1,181 @@
+use crate::routes::credential_routes::{load_credential, load_status_list, status_lists_url};
+use crate::routes::ErrorResponse;
+use crate::services::did_resolver::DidResolver;
+use crate::{ENTITY, QUERY};
+use actix_web::web::{Bytes, Data};
+use actix_web::{get, post, web, HttpResponse, Scope};
+use definitions_core::did::{verification_keys, IssuerIdentity};
+use definitions_core::encryption::FieldCipher;
+use definitions_core::status_list::{status_list_url, StatusPurpose};
+use definitions_core::verification::{holder_did, CredentialVerifier, Verdict};
+#[allow(unused_imports)]
+use serde_json::{json, Value};
+use sqlx::PgPool;
+use uuid::Uuid;
+
+pub fn routes() -> Scope {
+    web::scope("")
+        .service(verify)
+        .service(verify_issued_credential)
+}
+
+/// Verify a credential or presentation
+///
+/// Checks a Verifiable Credential or Verifiable Presentation, sent as a JSON-LD document or as a
+/// compact JWT, against the registry issuer keys, its validity period, the registry status lists
+/// and the active definitions. The proof of a presentation is checked against the DID document
+/// of its `did:key` or `did:web` holder. The verdict lists the outcome of every check;
+/// `verified` is true when none failed.
+#[utoipa::path(
+    post,
+    path = "/api/v1/verify",
+    tags= [ENTITY, QUERY],
+    request_body(
+        content = Object,
+        description = "A JSON-LD credential or presentation, or a JWT as a JSON string or plain text",
+        content_type = "application/json",
+        examples(
+            ("JSON-LD" = (value = json!({"@context": ["https://www.w3.org/2018/credentials/v1"], "type": ["VerifiableCredential", "InsuranceCredential"], "credentialSubject": {"policyNumber": "P-1"}, "proof": {"type": "DataIntegrityProof", "cryptosuite": "eddsa-jcs-2022"}}), description = "Credential with a Data Integrity proof")),
+            ("JWT" = (value = json!("eyJhbGciOiJFZERTQSIsImtpZCI6InJlZ2lzdHJ5LTEiLCJ0eXAiOiJKV1QifQ..."), description = "VC-JWT or VP-JWT")),
+        )
+    ),
+    responses(
+        (status = 200, description = "Verification verdict", body = Verdict),
+        (status = 400, description = "The body is neither JSON nor a JWT", body = ErrorResponse),
+        (status = 500, description = "Internal server error", body = ErrorResponse),
+    )
+)]
+#[post("")]
+async fn verify(
+    db_pool: Data<PgPool>,
+    issuer_identity: Data<IssuerIdentity>,
+    resolver: Data<DidResolver>,
+    body: Bytes,
+) -> HttpResponse {
+    let text = String::from_utf8_lossy(&body);
+    let document = match serde_json::from_str::<Value>(text.trim()) {
+        Ok(document) => document,
+        // A compact JWT sent as plain text
+        Err(_) if text.trim().split('.').count() == 3 => Value::String(text.trim().to_string()),
+        Err(e) => {
+            return HttpResponse::BadRequest().json(ErrorResponse {
+                error: Some("INVALID_DOCUMENT".to_string()),
+                error_description: Some(e.to_string()),
+                message: "Expected a JSON-LD document or a JWT".to_string(),
+            })
+        }
+    };
+
+    let verifier = match load_verifier(db_pool.get_ref(), &issuer_identity).await {
+        Ok(verifier) => verifier,
+        Err(e) => {
+            log::error!("Failed to load the verification material: {}", e);
+            return HttpResponse::InternalServerError().json(ErrorResponse {
+                error: Some("DATABASE_ERROR".to_string()),
+                error_description: Some(format!("Database error: {}", e)),
+                message: "Failed to verify the document".to_string(),
+            });
+        }
+    };
+    let verifier = match holder_did(&document) {
+        Some(did) => match resolver.resolve(&did).await {
+            Ok(did_document) => verifier.with_holder_keys(verification_keys(&did_document)),
+            Err(e) => {
+                log::warn!("Failed to resolve the holder {}: {}", did, e);
+                verifier
+            }
+        },
+        None => verifier,
+    };
+    HttpResponse::Ok().json(verifier.verify(&document))
+}
+
+/// Verify an issued credential
+///
+/// Verifies a credential issued by the registry, by its id. This is the URL carried by the QR
+/// code of certificates whose credential is too long to fit: the verdict tells whether the
+/// credential holds without disclosing its subject.
+#[utoipa::path(
+    get,
+    path = "/api/v1/verify/{credential_id}",
+    tags= [ENTITY, QUERY],
+    params(
+        ("credential_id" = String, Path, description = "Credential ID (UUID format)")
+    ),
+    responses(
+        (status = 200, description = "Verification verdict", body = Verdict),
+        (status = 404, description = "Credential not found", body = ErrorResponse),
+        (status = 500, description = "Internal server error", body = ErrorResponse),
+    )
+)]
+#[get("/{credential_id}")]
+async fn verify_issued_credential(
+    db_pool: Data<PgPool>,
+    cipher: Data<FieldCipher>,
+    issuer_identity: Data<IssuerIdentity>,
+    credential_id: web::Path<Uuid>,
+) -> HttpResponse {
+    let credential_id = credential_id.into_inner();
+    let loaded = match load_credential(db_pool.get_ref(), credential_id).await {
+        Ok(row) => match load_verifier(db_pool.get_ref(), &issuer_identity).await {
+            Ok(verifier) => Ok((row, verifier)),
+            Err(e) => Err(e),
+        },
+        Err(e) => Err(e),
+    };
+    let (row, verifier) = match loaded {
+        Ok(loaded) => loaded,
+        Err(e) => {
+            log::error!("Failed to verify credential {}: {}", credential_id, e);
+            return HttpResponse::InternalServerError().json(ErrorResponse {
+                error: Some("DATABASE_ERROR".to_string()),
+                error_description: Some(format!("Database error: {}", e)),
+                message: "Failed to verify the credential".to_string(),
+            });
+        }
+    };
+    let Some(row) = row else {
+        return HttpResponse::NotFound().json(ErrorResponse {
+            error: Some("NOT_FOUND".to_string()),
+            error_description: Some(format!("Credential not found: {}", credential_id)),
+            message: "Credential not found".to_string(),
+        });
+    };
+    match row.into_record(&cipher) {
+        Ok(record) => {
+            let mut verdict = verifier.verify(&record.credential);
+            verdict.id = Some(format!("urn:uuid:{}", record.id));
+            HttpResponse::Ok().json(verdict)
+        }
+        Err(e) => {
+            log::error!("Failed to open credential {}: {}", credential_id, e);
+            HttpResponse::InternalServerError().json(ErrorResponse {
+                error: Some("ENCRYPTION_ERROR".to_string()),
+                error_description: Some(e.to_string()),
+                message: "Failed to verify the credential".to_string(),
+            })
+        }
+    }
+}
+
+/// A verifier with the issuer keys, the status lists and the active definitions of the registry
+async fn load_verifier(
+    db_pool: &PgPool,
+    issuer_identity: &IssuerIdentity,
+) -> Result<CredentialVerifier, sqlx::Error> {
+    let mut verifier = CredentialVerifier::new(issuer_identity.public_keys());
+    for purpose in StatusPurpose::ALL {
+        verifier = verifier.with_status_list(
+            status_list_url(&status_lists_url(), purpose),
+            load_status_list(db_pool, purpose).await?,
+        );
+    }
+    let titles = sqlx::query_scalar::<_, String>(
+        "SELECT title FROM definitions WHERE record_status = 'Active' AND title IS NOT NULL",
+    )
+    .fetch_all(db_pool)
+    .await?;
+    Ok(titles.into_iter().fold(verifier, |verifier, title| {
+        verifier.with_active_definition(title)
+    }))
+}
@@ -0,0 +1,223 @@
+use crate::middleware::claims::Claims;
+use crate::routes::consent_routes::owns_entity;
+use crate::routes::credential_routes::{load_credential, CredentialRecord, CredentialRow};
+use crate::routes::ErrorResponse;
+use crate::DError;
+use crate::{COMMANDS, ENTITY, QUERY};
+use actix_web::web::{Data, Json};
+use actix_web::{get, post, web, HttpResponse, Scope};
+use definitions_core::credentials::CredentialFormat;
+use definitions_core::encryption::FieldCipher;
+use definitions_core::registry_domain::EntityError;
+use definitions_core::sd_jwt::SdJwt;
+use serde::{Deserialize, Serialize};
+#[allow(unused_imports)]
+use serde_json::{json, Value};
+use sqlx::PgPool;
+use utoipa::ToSchema;
+use uuid::Uuid;
+
+/// The holder wallet, mounted under `/api/v1/wallet`. Holders are the owners of the entities the
+/// credentials were issued for.
+pub fn routes() -> Scope {
+    web::scope("")
+        .service(get_wallet_credentials)
+        .service(present_credential)
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct WalletCredential {
+    #[serde(flatten)]
+    pub record: CredentialRecord,
+    /// Claims the holder may disclose selectively, for `vc+sd-jwt` credentials
+    pub disclosable: Vec<String>,
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct PresentCredentialRequest {
+    /// Names of the `credentialSubject` claims to disclose, the others stay concealed
+    pub disclose: Vec<String>,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct Presentation {
+    pub credential_id: Uuid,
+    pub format: CredentialFormat,
+    /// The SD-JWT to hand to the verifier, with the disclosures of the chosen claims only
+    pub presentation: String,
+    pub disclosed: Vec<String>,
+}
+
+fn database_error(e: sqlx::Error, message: &str) -> HttpResponse {
+    log::error!("{}: {}", message, e);
+    HttpResponse::InternalServerError().json(ErrorResponse {
+        error: Some("DATABASE_ERROR".to_string()),
+        error_description: Some(format!("Database error: {}", e)),
+        message: message.to_string(),
+    })
+}
+
+fn domain_error(e: EntityError) -> DError {
+    DError::from(disintegrate::DecisionError::Domain(e))
+}
+
+/// The SD-JWT of a stored credential, `None` for other formats
+fn sd_jwt(record: &CredentialRecord) -> Option<SdJwt> {
+    if record.format != CredentialFormat::SdJwtVc.to_string() {
+        return None;
+    }
+    record
+        .credential
+        .as_str()
+        .and_then(|c| SdJwt::parse(c).ok())
+}
+
+/// List my credentials
+///
+/// Returns the credentials issued for the entities the caller owns, most recent first, with the
+/// claims of `vc+sd-jwt` credentials that can be disclosed selectively.
+#[utoipa::path(
+    get,
+    path = "/api/v1/wallet/credentials",
+    tags= [ENTITY, QUERY],
+    responses(
+        (status = 200, description = "Credentials of the caller", body = Vec<WalletCredential>),
+        (status = 401, description = "Missing or invalid access token", body = ErrorResponse),
+        (status = 500, description = "Internal server error", body = ErrorResponse),
+    )
+)]
+#[get("/credentials")]
+async fn get_wallet_credentials(
+    db_pool: Data<PgPool>,
+    cipher: Data<FieldCipher>,
+    claims: Claims,
+) -> Result<HttpResponse, DError> {
+    let principal = claims.principal();
+    let rows = match sqlx::query_as::<_, CredentialRow>(
+        r#"
+        SELECT c.id, c.entity_id, c.entity_type, c.policy_name, c.format, c.credential, c.encrypted,
+               c.status_list_index, c.revoked_at, c.suspended, c.issued_by, c.issued_at
+        FROM credentials c
+        WHERE EXISTS (
+            SELECT 1 FROM entity_owners o
+            WHERE o.entity_id = c.entity_id
+              AND (($1 <> '' AND o.user_id = $1) OR lower(o.email) = lower($2))
+        )
+        ORDER BY c.issued_at DESC
+        "#,
+    )
+    .bind(&principal.subject)
+    .bind(principal.email.as_deref())
+    .fetch_all(db_pool.get_ref())
+    .await
+    {
+        Ok(rows) => rows,
+        Err(e) => return Ok(database_error(e, "Failed to fetch wallet credentials")),
+    };
+
+    let credentials = rows
+        .into_iter()
+        .map(|row| {
+            let record = row.into_record(&cipher)?;
+            let disclosable = sd_jwt(&record)
+                .map(|sd_jwt| sd_jwt.claim_names().into_iter().map(String::from).collect())
+                .unwrap_or_default();
+            Ok(WalletCredential {
+                record,
+                disclosable,
+            })
+        })
+        .collect::<Result<Vec<_>, EntityError>>()
+        .map_err(domain_error)?;
+    Ok(HttpResponse::Ok().json(credentials))
+}
+
+/// Present a credential
+///
+/// Builds a presentation of a `vc+sd-jwt` credential disclosing the named claims only, for the
+/// holder to hand to a verifier, who checks it at `/api/v1/verify`. The issuer signature covers
+/// the concealed claims, so the presentation stays verifiable. Only the owners of the entity may
+/// present its credentials, and revoked credentials cannot be presented.
+#[utoipa::path(
+    post,
+    path = "/api/v1/wallet/credentials/{credential_id}/present",
+    tags= [ENTITY, COMMANDS],
+    request_body(
+        content = PresentCredentialRequest,
+        content_type = "application/json",
+        examples(
+            ("Policy number only" = (value = json!({"disclose": ["policyNumber"]}), description = "Disclose the policy number, conceal the other claims")),
+        )
+    ),
+    params(
+        ("credential_id" = String, Path, description = "Credential ID (UUID format)")
+    ),
+    responses(
+        (status = 200, description = "Presentation for the verifier", body = Presentation),
+        (status = 400, description = "The credential has no such claim", body = ErrorResponse),
+        (status = 401, description = "Missing or invalid access token", body = ErrorResponse),
+        (status = 403, description = "Caller does not own the entity", body = String),
+        (status = 404, description = "Credential not found", body = String),
+        (status = 409, description = "The credential is revoked or does not support selective disclosure", body = String),
+        (status = 500, description = "Internal server error", body = ErrorResponse),
+    )
+)]
+#[post("/credentials/{credential_id}/present")]
+async fn present_credential(
+    db_pool: Data<PgPool>,
+    cipher: Data<FieldCipher>,
+    credential_id: web::Path<Uuid>,
+    claims: Claims,
+    request: Json<PresentCredentialRequest>,
+) -> Result<HttpResponse, DError> {
+    let credential_id = credential_id.into_inner();
+    let principal = claims.principal();
+    let row = match load_credential(db_pool.get_ref(), credential_id).await {
+        Ok(row) => row,
+        Err(e) => return Ok(database_error(e, "Failed to present the credential")),
+    };
+    let Some(row) = row else {
+        return Err(domain_error(EntityError::CredentialNotFound(credential_id)));
+    };
+    let record = row.into_record(&cipher).map_err(domain_error)?;
+    match owns_entity(db_pool.get_ref(), record.entity_id, &principal).await {
+        Ok(true) => {}
+        Ok(false) => {
+            return Err(domain_error(EntityError::NotAuthorized(
+                principal.subject,
+                record.entity_id,
+            )))
+        }
+        Err(e) => return Ok(database_error(e, "Failed to present the credential")),
+    }
+    if record.revoked_at.is_some() {
+        return Err(domain_error(EntityError::CredentialNotPresentable(
+            credential_id,
+            "the credential is revoked".to_string(),
+        )));
+    }
+    let Some(issued) = sd_jwt(&record) else {
+        return Err(domain_error(EntityError::CredentialNotPresentable(
+            credential_id,
+            format!(
+                "`{}` credentials do not support selective disclosure, issue a `{}` one",
+                record.format,
+                CredentialFormat::SdJwtVc
+            ),
+        )));
+    };
+    let presented = issued
+        .present(&request.disclose)
+        .map_err(|e| domain_error(EntityError::InvalidDisclosure(e.to_string())))?;
+
+    Ok(HttpResponse::Ok().json(Presentation {
+        credential_id,
+        format: CredentialFormat::SdJwtVc,
+        presentation: presented.to_string(),
+        disclosed: presented
+            .claim_names()
+            .into_iter()
+            .map(String::from)
+            .collect(),
+    }))
+}
@@ -0,0 +1,498 @@
+//! Service accounts and their API keys, for machine clients that cannot do interactive OAuth.
+//!
+//! A service account holds `scopes` and `roles` that become the `permissions` and `roles` of its
+//! [`Claims`], so service accounts are authorized exactly like bearer tokens. An API key has the
+//! form `rk_<key id>_<secret>`: only the SHA-256 of the secret is stored, and the key is shown
+//! once when issued. Keys expire after `API_KEY_TTL_DAYS` unless another expiry is requested,
+//! several keys may be active at once so that clients can rotate without downtime, and the
+//! last use of every key is recorded.
+use crate::middleware::claims::Claims;
+use base64::engine::general_purpose::URL_SAFE_NO_PAD as BASE64URL;
+use base64::Engine;
+use chrono::{DateTime, Duration, Utc};
+use definitions_core::audit::Actor;
+use definitions_core::encryption::generate_key;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use sqlx::{FromRow, PgPool};
+use std::collections::HashSet;
+use std::env;
+use thiserror::Error;
+use utoipa::ToSchema;
+use uuid::Uuid;
+
+const KEY_PREFIX: &str = "rk";
+/// Subject of the claims of a service account, followed by the account name
+pub const SERVICE_ACCOUNT_SUBJECT: &str = "service-account|";
+const DEFAULT_TTL_DAYS: i64 = 90;
+/// The last use of a key is recorded at most once per interval
+const LAST_USED_RESOLUTION_SECS: i64 = 60;
+
+#[derive(Debug, Error)]
+pub enum ApiKeyError {
+    #[error("The API key is malformed")]
+    Malformed,
+    #[error("The API key is not valid")]
+    Invalid,
+    #[error("The API key `{0}` has expired")]
+    Expired(Uuid),
+    #[error("The API key `{0}` has been revoked")]
+    Revoked(Uuid),
+    #[error("The service account `{0}` is disabled")]
+    AccountDisabled(String),
+    #[error("Service account `{0}` not found")]
+    AccountNotFound(Uuid),
+    #[error("API key `{0}` not found")]
+    KeyNotFound(Uuid),
+    #[error("A service account named `{0}` already exists")]
+    NameTaken(String),
+    #[error("An API key cannot expire in {0} days")]
+    InvalidExpiry(i64),
+    #[error(transparent)]
+    Database(#[from] sqlx::Error),
+}
+
+impl ApiKeyError {
+    /// True when the error rejects the presented key, false for a failure of the registry
+    pub fn is_authentication(&self) -> bool {
+        matches!(
+            self,
+            Self::Malformed
+                | Self::Invalid
+                | Self::Expired(_)
+                | Self::Revoked(_)
+                | Self::AccountDisabled(_)
+        )
+    }
+}
+
+#[derive(Debug, Clone, Serialize, FromRow, ToSchema)]
+pub struct ServiceAccount {
+    pub id: Uuid,
+    pub name: String,
+    pub description: Option<String>,
+    /// Granted as the `permissions` of the account, e.g. `write:definitions`
+    pub scopes: Vec<String>,
+    /// Granted as the `roles` of the account, matched against the definition roles
+    pub roles: Vec<String>,
+    pub created_by: String,
+    pub created_at: DateTime<Utc>,
+    pub disabled_at: Option<DateTime<Utc>>,
+}
+
+impl ServiceAccount {
+    /// The claims a request authenticated with a key of this account carries
+    pub fn claims(&self) -> Claims {
+        Claims {
+            sub: Some(format!("{}{}", SERVICE_ACCOUNT_SUBJECT, self.name)),
+            client_id: Some(self.id.to_string()),
+            roles: Some(self.roles.iter().cloned().collect()),
+            permissions: Some(self.scopes.iter().cloned().collect::<HashSet<_>>()),
+            ..Claims::default()
+        }
+    }
+}
+
+/// An API key without its secret
+#[derive(Debug, Clone, Serialize, FromRow, ToSchema)]
+pub struct ApiKeyInfo {
+    pub id: Uuid,
+    pub account_id: Uuid,
+    pub created_by: String,
+    pub created_at: DateTime<Utc>,
+    pub expires_at: Option<DateTime<Utc>>,
+    pub last_used_at: Option<DateTime<Utc>>,
+    pub revoked_at: Option<DateTime<Utc>>,
+}
+
+/// A newly issued API key, the only time its secret is returned
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct IssuedApiKey {
+    pub key_id: Uuid,
+    /// The key to send in the `X-API-Key` header
+    pub api_key: String,
+    pub expires_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Clone, Deserialize, ToSchema)]
+pub struct NewServiceAccount {
+    pub name: String,
+    pub description: Option<String>,
+    #[serde(default)]
+    pub scopes: Vec<String>,
+    #[serde(default)]
+    pub roles: Vec<String>,
+    /// Lifetime of the first key, `API_KEY_TTL_DAYS` by default
+    pub key_expires_in_days: Option<u32>,
+}
+
+#[derive(Debug, Clone, Default, Deserialize, ToSchema)]
+pub struct KeyRotation {
+    /// Lifetime of the new key, `API_KEY_TTL_DAYS` by default
+    pub expires_in_days: Option<u32>,
+    /// Expire the other keys of the account after this many seconds, they are kept otherwise
+    pub previous_keys_grace_secs: Option<u32>,
+}
+
+/// An API key split into its id and the SHA-256 of its secret
+#[derive(Debug, PartialEq, Eq)]
+struct ParsedKey {
+    id: Uuid,
+    hash: String,
+}
+
+fn hash_secret(secret: &str) -> String {
+    BASE64URL.encode(Sha256::digest(secret.as_bytes()))
+}
+
+/// Generates a key with a 256 bit secret and the hash to store
+fn generate_api_key(id: Uuid) -> (String, String) {
+    let secret = BASE64URL.encode(generate_key());
+    let hash = hash_secret(&secret);
+    (format!("{}_{}_{}", KEY_PREFIX, id.simple(), secret), hash)
+}
+
+fn parse_api_key(key: &str) -> Result<ParsedKey, ApiKeyError> {
+    let mut parts = key.trim().splitn(3, '_');
+    let (Some(KEY_PREFIX), Some(id), Some(secret)) = (parts.next(), parts.next(), parts.next())
+    else {
+        return Err(ApiKeyError::Malformed);
+    };
+    let id = Uuid::try_parse(id).map_err(|_| ApiKeyError::Malformed)?;
+    if secret.is_empty() {
+        return Err(ApiKeyError::Malformed);
+    }
+    Ok(ParsedKey {
+        id,
+        hash: hash_secret(secret),
+    })
+}
+
+/// Compares two hashes in constant time
+pub(crate) fn same_hash(a: &str, b: &str) -> bool {
+    a.len() == b.len()
+        && a.bytes()
+            .zip(b.bytes())
+            .fold(0u8, |diff, (x, y)| diff | (x ^ y))
+            == 0
+}
+
+#[derive(FromRow)]
+struct StoredKey {
+    key_hash: String,
+    expires_at: Option<DateTime<Utc>>,
+    revoked_at: Option<DateTime<Utc>>,
+    account_id: Uuid,
+}
+
+/// Service accounts and API keys, stored in the registry database
+#[derive(Clone)]
+pub struct ApiKeyStore {
+    pool: PgPool,
+    default_ttl_days: i64,
+}
+
+impl ApiKeyStore {
+    pub async fn new(pool: PgPool) -> Result<Self, sqlx::Error> {
+        sqlx::query(
+            r#"
+            CREATE TABLE IF NOT EXISTS service_accounts (
+                id UUID PRIMARY KEY,
+                name TEXT NOT NULL UNIQUE,
+                description TEXT,
+                scopes TEXT[] NOT NULL,
+                roles TEXT[] NOT NULL,
+                created_by TEXT NOT NULL,
+                created_at TIMESTAMPTZ NOT NULL,
+                disabled_at TIMESTAMPTZ
+            );
+            "#,
+        )
+        .execute(&pool)
+        .await?;
+        sqlx::query(
+            r#"
+            CREATE TABLE IF NOT EXISTS api_keys (
+                id UUID PRIMARY KEY,
+                account_id UUID NOT NULL REFERENCES service_accounts (id),
+                key_hash TEXT NOT NULL,
+                created_by TEXT NOT NULL,
+                created_at TIMESTAMPTZ NOT NULL,
+                expires_at TIMESTAMPTZ,
+                last_used_at TIMESTAMPTZ,
+                revoked_at TIMESTAMPTZ
+            );
+            "#,
+        )
+        .execute(&pool)
+        .await?;
+        sqlx::query("CREATE INDEX IF NOT EXISTS idx_api_keys_account_id ON api_keys (account_id);")
+            .execute(&pool)
+            .await?;
+        let ttl_days = env::var("API_KEY_TTL_DAYS")
+            .ok()
+            .and_then(|days| days.parse().ok())
+            .unwrap_or(DEFAULT_TTL_DAYS);
+        Ok(Self {
+            pool,
+            default_ttl_days: ttl_days,
+        })
+    }
+
+    fn expiry(&self, days: Option<u32>, now: DateTime<Utc>) -> Result<DateTime<Utc>, ApiKeyError> {
+        expiry(days.map_or(self.default_ttl_days, i64::from), now)
+    }
+
+    /// Creates a service account with its first key
+    pub async fn create_account(
+        &self,
+        account: NewServiceAccount,
+        created_by: &Actor,
+    ) -> Result<(ServiceAccount, IssuedApiKey), ApiKeyError> {
+        let now = Utc::now();
+        let expires_at = self.expiry(account.key_expires_in_days, now)?;
+        let created = sqlx::query_as::<_, ServiceAccount>(
+            r#"
+            INSERT INTO service_accounts (id, name, description, scopes, roles, created_by, created_at)
+            VALUES ($1, $2, $3, $4, $5, $6, $7)
+            ON CONFLICT (name) DO NOTHING
+            RETURNING id, name, description, scopes, roles, created_by, created_at, disabled_at
+            "#,
+        )
+        .bind(Uuid::now_v7())
+        .bind(&account.name)
+        .bind(&account.description)
+        .bind(&account.scopes)
+        .bind(&account.roles)
+        .bind(created_by.to_string())
+        .bind(now)
+        .fetch_optional(&self.pool)
+        .await?
+        .ok_or_else(|| ApiKeyError::NameTaken(account.name.clone()))?;
+        let key = self
+            .insert_key(created.id, expires_at, created_by, now)
+            .await?;
+        Ok((created, key))
+    }
+
+    pub async fn accounts(&self) -> Result<Vec<ServiceAccount>, ApiKeyError> {
+        Ok(sqlx::query_as::<_, ServiceAccount>(
+            "SELECT id, name, description, scopes, roles, created_by, created_at, disabled_at FROM service_accounts ORDER BY name",
+        )
+        .fetch_all(&self.pool)
+        .await?)
+    }
+
+    pub async fn account(&self, id: Uuid) -> Result<ServiceAccount, ApiKeyError> {
+        sqlx::query_as::<_, ServiceAccount>(
+            "SELECT id, name, description, scopes, roles, created_by, created_at, disabled_at FROM service_accounts WHERE id = $1",
+        )
+        .bind(id)
+        .fetch_optional(&self.pool)
+        .await?
+        .ok_or(ApiKeyError::AccountNotFound(id))
+    }
+
+    /// Keys of an account, newest first
+    pub async fn keys(&self, account_id: Uuid) -> Result<Vec<ApiKeyInfo>, ApiKeyError> {
+        Ok(sqlx::query_as::<_, ApiKeyInfo>(
+            "SELECT id, account_id, created_by, created_at, expires_at, last_used_at, revoked_at FROM api_keys WHERE account_id = $1 ORDER BY created_at DESC",
+        )
+        .bind(account_id)
+        .fetch_all(&self.pool)
+        .await?)
+    }
+
+    /// Issues a new key for an account, expiring its other keys after the requested grace period
+    pub async fn rotate_key(
+        &self,
+        account_id: Uuid,
+        rotation: KeyRotation,
+        created_by: &Actor,
+    ) -> Result<IssuedApiKey, ApiKeyError> {
+        let account = self.account(account_id).await?;
+        if account.disabled_at.is_some() {
+            return Err(ApiKeyError::AccountDisabled(account.name));
+        }
+        let now = Utc::now();
+        let expires_at = self.expiry(rotation.expires_in_days, now)?;
+        if let Some(grace) = rotation.previous_keys_grace_secs {
+            sqlx::query(
+                "UPDATE api_keys SET expires_at = $2 WHERE account_id = $1 AND revoked_at IS NULL AND (expires_at IS NULL OR expires_at > $2)",
+            )
+            .bind(account_id)
+            .bind(now + Duration::seconds(grace.into()))
+            .execute(&self.pool)
+            .await?;
+        }
+        self.insert_key(account_id, expires_at, created_by, now)
+            .await
+    }
+
+    async fn insert_key(
+        &self,
+        account_id: Uuid,
+        expires_at: DateTime<Utc>,
+        created_by: &Actor,
+        now: DateTime<Utc>,
+    ) -> Result<IssuedApiKey, ApiKeyError> {
+        let key_id = Uuid::now_v7();
+        let (api_key, hash) = generate_api_key(key_id);
+        sqlx::query(
+            "INSERT INTO api_keys (id, account_id, key_hash, created_by, created_at, expires_at) VALUES ($1, $2, $3, $4, $5, $6)",
+        )
+        .bind(key_id)
+        .bind(account_id)
+        .bind(hash)
+        .bind(created_by.to_string())
+        .bind(now)
+        .bind(expires_at)
+        .execute(&self.pool)
+        .await?;
+        Ok(IssuedApiKey {
+            key_id,
+            api_key,
+            expires_at: Some(expires_at),
+        })
+    }
+
+    pub async fn revoke_key(&self, account_id: Uuid, key_id: Uuid) -> Result<(), ApiKeyError> {
+        let revoked = sqlx::query(
+            "UPDATE api_keys SET revoked_at = COALESCE(revoked_at, now()) WHERE id = $1 AND account_id = $2",
+        )
+        .bind(key_id)
+        .bind(account_id)
+        .execute(&self.pool)
+        .await?;
+        if revoked.rows_affected() == 0 {
+            return Err(ApiKeyError::KeyNotFound(key_id));
+        }
+        Ok(())
+    }
+
+    /// Disables an account, none of its keys is accepted afterwards
+    pub async fn disable_account(&self, id: Uuid) -> Result<(), ApiKeyError> {
+        let disabled = sqlx::query(
+            "UPDATE service_accounts SET disabled_at = COALESCE(disabled_at, now()) WHERE id = $1",
+        )
+        .bind(id)
+        .execute(&self.pool)
+        .await?;
+        if disabled.rows_affected() == 0 {
+            return Err(ApiKeyError::AccountNotFound(id));
+        }
+        Ok(())
+    }
+
+    /// The service account of a valid key, recording the use of the key
+    pub async fn authenticate(&self, api_key: &str) -> Result<ServiceAccount, ApiKeyError> {
+        let parsed = parse_api_key(api_key)?;
+        let stored = sqlx::query_as::<_, StoredKey>(
+            "SELECT key_hash, expires_at, revoked_at, account_id FROM api_keys WHERE id = $1",
+        )
+        .bind(parsed.id)
+        .fetch_optional(&self.pool)
+        .await?
+        .ok_or(ApiKeyError::Invalid)?;
+        if !same_hash(&stored.key_hash, &parsed.hash) {
+            return Err(ApiKeyError::Invalid);
+        }
+        if stored.revoked_at.is_some() {
+            return Err(ApiKeyError::Revoked(parsed.id));
+        }
+        let now = Utc::now();
+        if stored
+            .expires_at
+            .is_some_and(|expires_at| expires_at <= now)
+        {
+            return Err(ApiKeyError::Expired(parsed.id));
+        }
+        let account = self.account(stored.account_id).await?;
+        if account.disabled_at.is_some() {
+            return Err(ApiKeyError::AccountDisabled(account.name));
+        }
+        sqlx::query(
+            "UPDATE api_keys SET last_used_at = $2 WHERE id = $1 AND (last_used_at IS NULL OR last_used_at < $3)",
+        )
+        .bind(parsed.id)
+        .bind(now)
+        .bind(now - Duration::seconds(LAST_USED_RESOLUTION_SECS))
+        .execute(&self.pool)
+        .await?;
+        Ok(account)
+    }
+}
+
+/// The expiry of a key issued at `now` for `days`, rejecting days out of the range of dates
+fn expiry(days: i64, now: DateTime<Utc>) -> Result<DateTime<Utc>, ApiKeyError> {
+    Duration::try_days(days)
+        .and_then(|ttl| now.checked_add_signed(ttl))
+        .ok_or(ApiKeyError::InvalidExpiry(days))
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_expiry_out_of_range_is_rejected() {
+        let now = Utc::now();
+        assert_eq!(expiry(30, now).unwrap(), now + Duration::days(30));
+        for days in [u32::MAX.into(), i64::MAX] {
+            assert!(matches!(
+                expiry(days, now),
+                Err(ApiKeyError::InvalidExpiry(rejected)) if rejected == days
+            ));
+        }
+    }
+
+    #[test]
+    fn test_generated_keys_parse_to_their_hash() {
+        let id = Uuid::now_v7();
+        let (api_key, hash) = generate_api_key(id);
+
+        assert!(api_key.starts_with("rk_"));
+        assert_eq!(parse_api_key(&api_key).unwrap(), ParsedKey { id, hash });
+        assert_ne!(generate_api_key(id).0, api_key);
+        for malformed in [
+            "",
+            "rk_",
+            "sk_0196d2b43c2a7d4e8f000000000000bb_secret",
+            "rk_nope_secret",
+        ] {
+            assert!(matches!(
+                parse_api_key(malformed),
+                Err(ApiKeyError::Malformed)
+            ));
+        }
+    }
+
+    #[test]
+    fn test_hashes_compare_exactly() {
+        let hash = hash_secret("secret");
+        assert!(same_hash(&hash, &hash_secret("secret")));
+        assert!(!same_hash(&hash, &hash_secret("secreT")));
+        assert!(!same_hash(&hash, &hash[1..]));
+    }
+
+    #[test]
+    fn test_service_account_claims() {
+        let account = ServiceAccount {
+            id: Uuid::nil(),
+            name: "nightly-import".to_string(),
+            description: None,
+            scopes: vec!["write:definitions".to_string()],
+            roles: vec!["Registrar".to_string()],
+            created_by: "admin".to_string(),
+            created_at: Utc::now(),
+            disabled_at: None,
+        };
+
+        let claims = account.claims();
+
+        assert!(claims.validate_permissions(&HashSet::from(["write:definitions".to_string()])));
+        assert_eq!(claims.principal().roles, vec!["Registrar"]);
+        assert_eq!(claims.actor(), Actor::new("service-account|nightly-import"));
+    }
+}
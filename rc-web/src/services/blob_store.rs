@@ -0,0 +1,123 @@
+//! Storage of documents such as certificate templates.
+//!
+//! Documents are addressed by URI, e.g. `minio://Insurance/1-68619c95/template.html`. The scheme
+//! names the backend the definition was written for and is not interpreted, the rest of the URI
+//! is the key of the document. [`FileBlobStore`] keeps each document in a file under its root.
+use async_trait::async_trait;
+use std::path::{Component, Path, PathBuf};
+use thiserror::Error;
+
+pub const BLOB_STORE_DIR: &str = "BLOB_STORE_DIR";
+pub const DEFAULT_BLOB_STORE_DIR: &str = "./blobs";
+
+#[derive(Debug, Error, PartialEq, Eq)]
+pub enum BlobStoreError {
+    #[error("Invalid blob key `{0}`")]
+    InvalidKey(String),
+    #[error("Blob store error: {0}")]
+    Storage(String),
+}
+
+#[async_trait]
+pub trait BlobStore: Send + Sync {
+    /// The content stored under `uri`, `None` when there is none
+    async fn get(&self, uri: &str) -> Result<Option<Vec<u8>>, BlobStoreError>;
+    async fn put(&self, uri: &str, content: &[u8]) -> Result<(), BlobStoreError>;
+}
+
+/// The key of a URI: the URI without its scheme. Keys are relative paths without `..`.
+pub fn blob_key(uri: &str) -> Result<&str, BlobStoreError> {
+    let key = uri
+        .split_once("://")
+        .map_or(uri, |(_, key)| key)
+        .trim_start_matches('/');
+    let valid = !key.is_empty()
+        && Path::new(key)
+            .components()
+            .all(|component| matches!(component, Component::Normal(_)));
+    if valid {
+        Ok(key)
+    } else {
+        Err(BlobStoreError::InvalidKey(uri.to_string()))
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct FileBlobStore {
+    root: PathBuf,
+}
+
+impl FileBlobStore {
+    pub fn new(root: impl Into<PathBuf>) -> Self {
+        Self { root: root.into() }
+    }
+
+    /// Stores documents in the directory named by `BLOB_STORE_DIR`, `./blobs` by default
+    pub fn from_env() -> Self {
+        Self::new(
+            std::env::var(BLOB_STORE_DIR).unwrap_or_else(|_| DEFAULT_BLOB_STORE_DIR.to_string()),
+        )
+    }
+
+    fn path(&self, uri: &str) -> Result<PathBuf, BlobStoreError> {
+        Ok(self.root.join(blob_key(uri)?))
+    }
+}
+
+#[async_trait]
+impl BlobStore for FileBlobStore {
+    async fn get(&self, uri: &str) -> Result<Option<Vec<u8>>, BlobStoreError> {
+        match tokio::fs::read(self.path(uri)?).await {
+            Ok(content) => Ok(Some(content)),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(BlobStoreError::Storage(e.to_string())),
+        }
+    }
+
+    async fn put(&self, uri: &str, content: &[u8]) -> Result<(), BlobStoreError> {
+        let path = self.path(uri)?;
+        if let Some(parent) = path.parent() {
+            tokio::fs::create_dir_all(parent)
+                .await
+                .map_err(|e| BlobStoreError::Storage(e.to_string()))?;
+        }
+        tokio::fs::write(&path, content)
+            .await
+            .map_err(|e| BlobStoreError::Storage(e.to_string()))
+    }
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used)]
+mod tests {
+    use super::*;
+    use uuid::Uuid;
+
+    #[test]
+    fn test_keys_drop_the_scheme_and_stay_under_the_root() {
+        assert_eq!(
+            blob_key("minio://Insurance/1/template.html"),
+            Ok("Insurance/1/template.html")
+        );
+        assert_eq!(blob_key("Insurance/t.svg"), Ok("Insurance/t.svg"));
+        assert!(blob_key("minio://Insurance/../../etc/passwd").is_err());
+        assert!(blob_key("minio://").is_err());
+    }
+
+    #[actix_web::test]
+    async fn test_file_store_round_trip() {
+        let root = std::env::temp_dir().join(format!("rc-blobs-{}", Uuid::now_v7()));
+        let store = FileBlobStore::new(&root);
+
+        assert_eq!(store.get("minio://Insurance/t.html").await, Ok(None));
+        store
+            .put("minio://Insurance/t.html", b"<html></html>")
+            .await
+            .unwrap();
+        assert_eq!(
+            store.get("s3://Insurance/t.html").await,
+            Ok(Some(b"<html></html>".to_vec()))
+        );
+        std::fs::remove_dir_all(root).unwrap();
+    }
+}
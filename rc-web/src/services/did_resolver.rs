@@ -0,0 +1,120 @@
+//! Resolution of the DIDs of holders presenting credentials.
+//!
+//! A `did:key` is resolved locally, a `did:web` by fetching its DID document over HTTPS, or
+//! plain HTTP for loopback hosts (see [`did_web_url`]).
+use definitions_core::did::{did_key_document, did_web_url, DidError};
+use serde_json::Value;
+use std::time::Duration;
+
+const FETCH_TIMEOUT: Duration = Duration::from_secs(5);
+
+#[derive(Debug, Clone, Default)]
+pub struct DidResolver {
+    client: reqwest::Client,
+}
+
+impl DidResolver {
+    pub fn new() -> Self {
+        Self {
+            client: reqwest::Client::builder()
+                .timeout(FETCH_TIMEOUT)
+                .build()
+                .unwrap_or_default(),
+        }
+    }
+
+    /// The DID document of a `did:key` or a `did:web`
+    pub async fn resolve(&self, did: &str) -> Result<Value, DidError> {
+        let did = did.split_once('#').map_or(did, |(did, _)| did);
+        if did.starts_with("did:key:") {
+            return did_key_document(did);
+        }
+        let url = did_web_url(did)?;
+        let resolution_error =
+            |e: reqwest::Error| DidError::Resolution(did.to_string(), e.to_string());
+        let document: Value = self
+            .client
+            .get(&url)
+            .send()
+            .await
+            .and_then(reqwest::Response::error_for_status)
+            .map_err(resolution_error)?
+            .json()
+            .await
+            .map_err(resolution_error)?;
+        if document.get("id").and_then(Value::as_str) != Some(did) {
+            return Err(DidError::Resolution(
+                did.to_string(),
+                format!("the document of {} has another id", url),
+            ));
+        }
+        Ok(document)
+    }
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used)]
+mod tests {
+    use super::*;
+    use actix_web::{web, App, HttpResponse, HttpServer};
+    use definitions_core::did::{verification_keys, IssuerIdentity};
+    use definitions_core::signing::IssuerKey;
+
+    /// Serves `document` as the `/.well-known/did.json` of a local server, returning its port
+    fn did_web_stand_in(document: impl Fn(u16) -> Value + Send + Clone + 'static) -> u16 {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let server = HttpServer::new(move || {
+            let document = document(port);
+            App::new().route(
+                "/.well-known/did.json",
+                web::get().to(move || {
+                    let document = document.clone();
+                    async move { HttpResponse::Ok().json(document) }
+                }),
+            )
+        })
+        .workers(1)
+        .listen(listener)
+        .unwrap()
+        .run();
+        actix_web::rt::spawn(server);
+        port
+    }
+
+    #[actix_web::test]
+    async fn test_resolve_did_web() {
+        let key = IssuerKey::from_seed("key-1", [8; 32]);
+        let served_key = key.clone();
+        let port = did_web_stand_in(move |port| {
+            IssuerIdentity::new(
+                format!("did:web:127.0.0.1%3A{port}"),
+                served_key.clone(),
+                vec![],
+            )
+            .document()
+        });
+        let did = format!("did:web:127.0.0.1%3A{port}");
+
+        let document = DidResolver::new().resolve(&did).await.unwrap();
+
+        assert_eq!(
+            verification_keys(&document)
+                .into_iter()
+                .map(|k| (k.kid, k.x))
+                .collect::<Vec<_>>(),
+            vec![(format!("{did}#key-1"), key.public_key().x)]
+        );
+    }
+
+    #[actix_web::test]
+    async fn test_documents_must_match_the_did() {
+        let port = did_web_stand_in(|_| serde_json::json!({ "id": "did:web:elsewhere.example" }));
+
+        let resolved = DidResolver::new()
+            .resolve(&format!("did:web:127.0.0.1%3A{port}"))
+            .await;
+
+        assert!(matches!(resolved, Err(DidError::Resolution(..))));
+    }
+}
@@ -0,0 +1,32 @@
+//! History of definitions and entities, read from the `audit_log` kept by the read model.
+//!
+//! Each entry is an event with the actor that caused it and the subject it acted for, see
+//! [`definitions_core::audit`].
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use sqlx::{FromRow, PgPool};
+use utoipa::ToSchema;
+use uuid::Uuid;
+
+#[derive(Debug, Serialize, FromRow, ToSchema)]
+pub struct HistoryEntry {
+    /// Position of the event in the event store
+    pub event_id: i64,
+    /// The event, e.g. `DefActivated` or `EntityUpdated`
+    pub event_type: String,
+    /// Who performed the command
+    pub actor: String,
+    /// The subject an impersonating or delegated actor acted for
+    pub on_behalf_of: Option<String>,
+    pub occurred_at: DateTime<Utc>,
+}
+
+/// Events of the definition or entity `subject_id`, oldest first
+pub async fn history(pool: &PgPool, subject_id: Uuid) -> Result<Vec<HistoryEntry>, sqlx::Error> {
+    sqlx::query_as::<_, HistoryEntry>(
+        "SELECT event_id, event_type, actor, on_behalf_of, occurred_at FROM audit_log WHERE subject_id = $1 ORDER BY event_id",
+    )
+    .bind(subject_id)
+    .fetch_all(pool)
+    .await
+}
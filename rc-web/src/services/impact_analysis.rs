@@ -0,0 +1,224 @@
+//! Dry-run validation of a candidate schema against the entities already in the registry.
+//!
+//! Nothing is persisted: entity documents are read from the `{type}_projection` table and checked
+//! with the same [`EntitySchemaValidator`] used by `CreateEntityCmd`. As in `MigrateEntityCmd`, the
+//! private fields of each document are decrypted and the system fields of the candidate schema
+//! removed before validation, see [`comparable`].
+use definitions_core::encryption::FieldCipher;
+use definitions_core::registry_domain::{
+    decrypt_stored, EntityError, EntityId, EntitySchemaValidator,
+};
+use definitions_core::system_fields::SystemFields;
+use serde::Serialize;
+use serde_json::Value;
+use std::collections::BTreeMap;
+use utoipa::ToSchema;
+
+pub const DEFAULT_SAMPLE_SIZE: usize = 5;
+
+#[derive(Debug, Serialize, ToSchema, PartialEq, Eq)]
+pub struct ImpactAnalysisReport {
+    pub entity_type: String,
+    /// Number of entities checked
+    pub total_entities: usize,
+    /// Number of entities that would become invalid with the candidate schema
+    pub invalid_entities: usize,
+    /// Invalid entities grouped by the failing schema keyword
+    pub issues: Vec<IssueSummary>,
+}
+
+#[derive(Debug, Serialize, ToSchema, PartialEq, Eq)]
+pub struct IssueSummary {
+    /// The JSON schema keyword that failed, e.g. `required`, `enum` or `format`
+    pub kind: String,
+    /// Number of entities with at least one issue of this kind
+    pub entity_count: usize,
+    /// Up to `sample_size` ids of affected entities
+    #[schema(value_type = Vec<String>)]
+    pub sample_entity_ids: Vec<EntityId>,
+    /// Up to `sample_size` distinct messages reported for this kind
+    pub sample_messages: Vec<String>,
+}
+
+/// The stored document of an entity as the candidate schema sees it: with its private fields
+/// decrypted and without the system fields
+pub fn comparable(
+    entity_type: &str,
+    cipher: &FieldCipher,
+    system_fields: &SystemFields,
+    mut entity_data: Value,
+) -> Result<Value, EntityError> {
+    decrypt_stored(&mut entity_data, Some(cipher), entity_type)?;
+    let stripped = system_fields.strip(&entity_data.to_string())?;
+    serde_json::from_str(&stripped).map_err(|e| EntityError::InvalidJson(e.to_string()))
+}
+
+/// Validates each `(id, entity_data)` pair and aggregates the issues per kind
+pub fn analyse<I>(
+    entity_type: &str,
+    validator: &EntitySchemaValidator,
+    entities: I,
+    sample_size: usize,
+) -> ImpactAnalysisReport
+where
+    I: IntoIterator<Item = (EntityId, Value)>,
+{
+    let mut total_entities = 0;
+    let mut invalid_entities = 0;
+    let mut by_kind: BTreeMap<String, IssueSummary> = BTreeMap::new();
+
+    for (id, entity_data) in entities {
+        total_entities += 1;
+        let issues = validator.issues(&entity_data);
+        if issues.is_empty() {
+            continue;
+        }
+        invalid_entities += 1;
+
+        let mut counted_kinds: Vec<&str> = Vec::new();
+        for issue in &issues {
+            let summary = by_kind
+                .entry(issue.kind.clone())
+                .or_insert_with(|| IssueSummary {
+                    kind: issue.kind.clone(),
+                    entity_count: 0,
+                    sample_entity_ids: Vec::new(),
+                    sample_messages: Vec::new(),
+                });
+            if !counted_kinds.contains(&issue.kind.as_str()) {
+                counted_kinds.push(&issue.kind);
+                summary.entity_count += 1;
+                if summary.sample_entity_ids.len() < sample_size {
+                    summary.sample_entity_ids.push(id);
+                }
+            }
+            if summary.sample_messages.len() < sample_size
+                && !summary.sample_messages.contains(&issue.message)
+            {
+                summary.sample_messages.push(issue.message.clone());
+            }
+        }
+    }
+
+    let mut issues: Vec<IssueSummary> = by_kind.into_values().collect();
+    issues.sort_by(|a, b| b.entity_count.cmp(&a.entity_count));
+
+    ImpactAnalysisReport {
+        entity_type: entity_type.to_string(),
+        total_entities,
+        invalid_entities,
+        issues,
+    }
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used)]
+mod tests {
+    use super::*;
+    use crate::services::keystore::FileKeyStore;
+    use serde_json::json;
+    use std::sync::Arc;
+    use uuid::Uuid;
+
+    fn candidate_schema() -> String {
+        json!({
+            "title": "Student",
+            "type": "object",
+            "properties": {
+                "Student": {
+                    "type": "object",
+                    "properties": {
+                        "name": { "type": "string" },
+                        "gender": { "type": "string", "enum": ["Male", "Female"] }
+                    },
+                    "required": ["name"]
+                }
+            }
+        })
+        .to_string()
+    }
+
+    #[test]
+    fn test_analyse_groups_issues_by_kind() {
+        let validator = EntitySchemaValidator::new("Student", &candidate_schema()).unwrap();
+        let valid = Uuid::now_v7();
+        let missing_name = Uuid::now_v7();
+        let missing_name_and_bad_gender = Uuid::now_v7();
+        let entities = vec![
+            (valid, json!({"Student": {"name": "A", "gender": "Male"}})),
+            (missing_name, json!({"Student": {"gender": "Male"}})),
+            (
+                missing_name_and_bad_gender,
+                json!({"Student": {"gender": "Other"}}),
+            ),
+        ];
+
+        let report = analyse("Student", &validator, entities, 1);
+
+        assert_eq!(report.total_entities, 3);
+        assert_eq!(report.invalid_entities, 2);
+        assert_eq!(report.issues[0].kind, "required");
+        assert_eq!(report.issues[0].entity_count, 2);
+        assert_eq!(report.issues[0].sample_entity_ids, vec![missing_name]);
+        assert_eq!(report.issues[1].kind, "enum");
+        assert_eq!(report.issues[1].entity_count, 1);
+        assert_eq!(
+            report.issues[1].sample_entity_ids,
+            vec![missing_name_and_bad_gender]
+        );
+    }
+
+    #[test]
+    fn test_stored_documents_are_decrypted_and_stripped_before_validation() {
+        let schema = json!({
+            "title": "Student",
+            "type": "object",
+            "properties": {
+                "Student": {
+                    "type": "object",
+                    "additionalProperties": false,
+                    "properties": {
+                        "name": { "type": "string" },
+                        "dob": { "type": "string", "format": "date" }
+                    },
+                    "required": ["name"]
+                }
+            },
+            "_osConfig": {
+                "privateFields": ["$.dob"],
+                "systemFields": ["_osCreatedAt", "_osCreatedBy"]
+            }
+        })
+        .to_string();
+        let dir = std::env::temp_dir().join(format!("rc-impact-{}", Uuid::now_v7()));
+        let cipher = FieldCipher::new(Arc::new(FileKeyStore::open(&dir).unwrap()));
+        let mut stored = json!({"Student": {
+            "name": "A",
+            "dob": "2001-02-03",
+            "_osCreatedAt": "2024-01-01T00:00:00Z",
+            "_osCreatedBy": "auth0|a"
+        }});
+        cipher
+            .encrypt_paths(&mut stored, "Student", &["$.dob".to_string()])
+            .unwrap();
+        let validator = EntitySchemaValidator::new("Student", &schema).unwrap();
+        let system_fields = SystemFields::from_schema(&schema, "Student").unwrap();
+
+        let raw = analyse(
+            "Student",
+            &validator,
+            vec![(Uuid::now_v7(), stored.clone())],
+            1,
+        );
+        assert_eq!(raw.invalid_entities, 1);
+
+        let document = comparable("Student", &cipher, &system_fields, stored).unwrap();
+        assert_eq!(
+            document,
+            json!({"Student": {"name": "A", "dob": "2001-02-03"}})
+        );
+        let report = analyse("Student", &validator, vec![(Uuid::now_v7(), document)], 1);
+        assert_eq!(report.invalid_entities, 0);
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,500 @@
+//! Shared cache of the JWKS of the token issuers.
+//!
+//! A JWKS is kept for the `max-age` of its `Cache-Control` header, clamped between
+//! [`JwksCacheConfig::min_ttl`] and [`JwksCacheConfig::max_ttl`], or for
+//! [`JwksCacheConfig::default_ttl`] when the IdP sends none. Tokens signed by a key the cached
+//! JWKS lacks trigger a re-fetch, at most once per [`JwksCacheConfig::refetch_interval`], so that
+//! rotated keys are picked up without letting forged `kid`s hammer the IdP.
+//!
+//! An expired JWKS keeps being served for [`JwksCacheConfig::max_stale`] while it is re-fetched
+//! in the background, at most once per [`JwksCacheConfig::refetch_interval`], so that requests do
+//! not wait on the IdP nor fail while it is down. [`JwksCache::spawn_refresh`] renews the JWKS
+//! about to expire ahead of time. Concurrent fetches of the same JWKS share a single request.
+use jsonwebtoken::jwk::JwkSet;
+use log::{debug, warn};
+use reqwest::header::CACHE_CONTROL;
+use std::collections::HashMap;
+use std::env;
+use std::sync::{Arc, Mutex, PoisonError, RwLock};
+use std::time::{Duration, Instant};
+use thiserror::Error;
+use tokio::sync::OnceCell;
+
+pub const JWKS_CACHE_TTL_SECS: &str = "JWKS_CACHE_TTL_SECS";
+pub const JWKS_MAX_STALE_SECS: &str = "JWKS_MAX_STALE_SECS";
+const FETCH_TIMEOUT: Duration = Duration::from_secs(5);
+
+#[derive(Debug, Error, Clone, PartialEq, Eq)]
+pub enum JwksError {
+    #[error("Failed to fetch {0}: {1}")]
+    Fetch(String, String),
+}
+
+#[derive(Debug, Clone)]
+pub struct JwksCacheConfig {
+    /// How long a JWKS is kept when the IdP sends no `max-age`
+    pub default_ttl: Duration,
+    pub min_ttl: Duration,
+    pub max_ttl: Duration,
+    /// Minimum delay between two fetches triggered by unknown `kid`s or an expired JWKS
+    pub refetch_interval: Duration,
+    /// How long an expired JWKS is served while it is re-fetched or the IdP is down
+    pub max_stale: Duration,
+}
+
+impl Default for JwksCacheConfig {
+    fn default() -> Self {
+        Self {
+            default_ttl: Duration::from_secs(600),
+            min_ttl: Duration::from_secs(30),
+            max_ttl: Duration::from_secs(86_400),
+            refetch_interval: Duration::from_secs(30),
+            max_stale: Duration::from_secs(86_400),
+        }
+    }
+}
+
+impl JwksCacheConfig {
+    /// The defaults, with `JWKS_CACHE_TTL_SECS` and `JWKS_MAX_STALE_SECS` when set
+    pub fn from_env() -> Self {
+        let seconds = |name: &str| {
+            env::var(name)
+                .ok()
+                .and_then(|value| value.parse::<u64>().ok())
+                .map(Duration::from_secs)
+        };
+        let default = Self::default();
+        Self {
+            default_ttl: seconds(JWKS_CACHE_TTL_SECS).unwrap_or(default.default_ttl),
+            max_stale: seconds(JWKS_MAX_STALE_SECS).unwrap_or(default.max_stale),
+            ..default
+        }
+    }
+
+    /// The lifetime of a JWKS from the `Cache-Control` header of its response
+    fn ttl(&self, cache_control: Option<&str>) -> Duration {
+        let directives: Vec<&str> = cache_control
+            .unwrap_or_default()
+            .split(',')
+            .map(str::trim)
+            .collect();
+        if directives
+            .iter()
+            .any(|directive| matches!(*directive, "no-cache" | "no-store"))
+        {
+            return self.min_ttl;
+        }
+        directives
+            .iter()
+            .find_map(|directive| directive.strip_prefix("max-age="))
+            .and_then(|max_age| max_age.parse::<u64>().ok())
+            .map(|max_age| Duration::from_secs(max_age).clamp(self.min_ttl, self.max_ttl))
+            .unwrap_or(self.default_ttl)
+    }
+}
+
+#[derive(Debug, Clone)]
+struct CachedJwks {
+    jwks: JwkSet,
+    expires_at: Instant,
+    /// When the last fetch started, successful or not
+    fetched_at: Instant,
+}
+
+impl CachedJwks {
+    fn has_key(&self, kid: Option<&str>) -> bool {
+        kid.map_or(true, |kid| self.jwks.find(kid).is_some())
+    }
+}
+
+type SharedFetch = Arc<OnceCell<Result<JwkSet, JwksError>>>;
+
+pub struct JwksCache {
+    client: reqwest::Client,
+    config: JwksCacheConfig,
+    entries: RwLock<HashMap<String, CachedJwks>>,
+    /// The fetch in flight of each JWKS, awaited by every caller that needs it
+    in_flight: Mutex<HashMap<String, SharedFetch>>,
+}
+
+impl JwksCache {
+    pub fn new(config: JwksCacheConfig) -> Self {
+        Self {
+            client: reqwest::Client::builder()
+                .timeout(FETCH_TIMEOUT)
+                .build()
+                .unwrap_or_default(),
+            config,
+            entries: RwLock::new(HashMap::new()),
+            in_flight: Mutex::new(HashMap::new()),
+        }
+    }
+
+    fn cached(&self, jwks_uri: &str) -> Option<CachedJwks> {
+        self.entries
+            .read()
+            .ok()
+            .and_then(|entries| entries.get(jwks_uri).cloned())
+    }
+
+    /// Records that `jwks_uri` is fetched from `now` on, unless it was less than
+    /// `refetch_interval` ago
+    fn start_refetch(&self, jwks_uri: &str, now: Instant) -> bool {
+        let Ok(mut entries) = self.entries.write() else {
+            return false;
+        };
+        match entries.get_mut(jwks_uri) {
+            Some(cached)
+                if now.duration_since(cached.fetched_at) < self.config.refetch_interval =>
+            {
+                false
+            }
+            Some(cached) => {
+                cached.fetched_at = now;
+                true
+            }
+            None => true,
+        }
+    }
+
+    /// The JWKS at `jwks_uri`, re-fetched when it lacks the key `kid` or is too stale.
+    ///
+    /// An expired JWKS within `max_stale` is returned right away, and re-fetched in the
+    /// background.
+    pub async fn jwks(
+        self: &Arc<Self>,
+        jwks_uri: &str,
+        kid: Option<&str>,
+    ) -> Result<JwkSet, JwksError> {
+        let now = Instant::now();
+        let Some(cached) = self.cached(jwks_uri) else {
+            return self.refresh(jwks_uri).await;
+        };
+        let expired = now >= cached.expires_at;
+        if expired && now.duration_since(cached.expires_at) > self.config.max_stale {
+            return self.refresh(jwks_uri).await;
+        }
+        if cached.has_key(kid) {
+            if expired && self.start_refetch(jwks_uri, now) {
+                self.refresh_in_background(jwks_uri);
+            }
+            return Ok(cached.jwks);
+        }
+        if self.start_refetch(jwks_uri, now) {
+            self.refresh(jwks_uri).await
+        } else {
+            // The caller reports the unknown key, the IdP was asked for it a moment ago
+            Ok(cached.jwks)
+        }
+    }
+
+    fn refresh_in_background(self: &Arc<Self>, jwks_uri: &str) {
+        let cache = Arc::clone(self);
+        let jwks_uri = jwks_uri.to_string();
+        tokio::spawn(async move {
+            if let Err(e) = cache.refresh(&jwks_uri).await {
+                warn!("Background JWKS refresh failed: {}", e);
+            }
+        });
+    }
+
+    /// Fetches the JWKS at `jwks_uri`, or falls back on the cached one if it is not too stale.
+    ///
+    /// Callers arriving while a fetch of the same JWKS is in flight share its result.
+    pub async fn refresh(&self, jwks_uri: &str) -> Result<JwkSet, JwksError> {
+        let fetch = self
+            .in_flight
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .entry(jwks_uri.to_string())
+            .or_default()
+            .clone();
+        let result = fetch
+            .get_or_init(|| self.fetch_and_cache(jwks_uri))
+            .await
+            .clone();
+        let mut in_flight = self
+            .in_flight
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner);
+        if in_flight
+            .get(jwks_uri)
+            .is_some_and(|current| Arc::ptr_eq(current, &fetch))
+        {
+            in_flight.remove(jwks_uri);
+        }
+        result
+    }
+
+    async fn fetch_and_cache(&self, jwks_uri: &str) -> Result<JwkSet, JwksError> {
+        let started = Instant::now();
+        if let Ok(mut entries) = self.entries.write() {
+            if let Some(cached) = entries.get_mut(jwks_uri) {
+                cached.fetched_at = started;
+            }
+        }
+        match self.fetch(jwks_uri).await {
+            Ok((jwks, ttl)) => {
+                debug!("Fetched the JWKS at {}, cached for {:?}", jwks_uri, ttl);
+                if let Ok(mut entries) = self.entries.write() {
+                    entries.insert(
+                        jwks_uri.to_string(),
+                        CachedJwks {
+                            jwks: jwks.clone(),
+                            expires_at: started + ttl,
+                            fetched_at: started,
+                        },
+                    );
+                }
+                Ok(jwks)
+            }
+            Err(e) => match self.cached(jwks_uri) {
+                Some(cached)
+                    if started.saturating_duration_since(cached.expires_at)
+                        <= self.config.max_stale =>
+                {
+                    warn!("{}, serving the cached JWKS", e);
+                    Ok(cached.jwks)
+                }
+                _ => Err(e),
+            },
+        }
+    }
+
+    async fn fetch(&self, jwks_uri: &str) -> Result<(JwkSet, Duration), JwksError> {
+        let fetch_error = |e: reqwest::Error| JwksError::Fetch(jwks_uri.to_string(), e.to_string());
+        let response = self
+            .client
+            .get(jwks_uri)
+            .send()
+            .await
+            .and_then(reqwest::Response::error_for_status)
+            .map_err(fetch_error)?;
+        let ttl = self.config.ttl(
+            response
+                .headers()
+                .get(CACHE_CONTROL)
+                .and_then(|value| value.to_str().ok()),
+        );
+        let jwks = response.json().await.map_err(fetch_error)?;
+        Ok((jwks, ttl))
+    }
+
+    /// Renews, every `period`, the cached JWKS that expire before the next round
+    pub fn spawn_refresh(self: &Arc<Self>, period: Duration) -> tokio::task::JoinHandle<()> {
+        let cache = Arc::clone(self);
+        tokio::spawn(async move {
+            loop {
+                tokio::time::sleep(period).await;
+                let horizon = Instant::now() + period;
+                let expiring: Vec<String> = cache
+                    .entries
+                    .read()
+                    .map(|entries| {
+                        entries
+                            .iter()
+                            .filter(|(_, cached)| cached.expires_at <= horizon)
+                            .map(|(jwks_uri, _)| jwks_uri.clone())
+                            .collect()
+                    })
+                    .unwrap_or_default();
+                for jwks_uri in expiring {
+                    if let Err(e) = cache.refresh(&jwks_uri).await {
+                        warn!("Background JWKS refresh failed: {}", e);
+                    }
+                }
+            }
+        })
+    }
+}
+
+impl Default for JwksCache {
+    fn default() -> Self {
+        Self::new(JwksCacheConfig::default())
+    }
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used)]
+mod tests {
+    use super::*;
+    use actix_web::{web, App, HttpResponse, HttpServer};
+    use definitions_core::signing::IssuerKey;
+    use serde_json::json;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Mutex;
+
+    /// A local JWKS endpoint whose keys and availability the test controls
+    struct IdpStandIn {
+        jwks_uri: String,
+        kids: Arc<Mutex<Vec<&'static str>>>,
+        up: Arc<Mutex<bool>>,
+        fetches: Arc<AtomicUsize>,
+    }
+
+    impl IdpStandIn {
+        fn start(cache_control: &'static str) -> Self {
+            let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+            let jwks_uri = format!("http://{}/jwks.json", listener.local_addr().unwrap());
+            let kids = Arc::new(Mutex::new(vec!["key-1"]));
+            let up = Arc::new(Mutex::new(true));
+            let fetches = Arc::new(AtomicUsize::new(0));
+            let state = (kids.clone(), up.clone(), fetches.clone());
+            let server = HttpServer::new(move || {
+                let (kids, up, fetches) = state.clone();
+                App::new().route(
+                    "/jwks.json",
+                    web::get().to(move || {
+                        let (kids, up, fetches) = (kids.clone(), up.clone(), fetches.clone());
+                        async move {
+                            fetches.fetch_add(1, Ordering::SeqCst);
+                            if !*up.lock().unwrap() {
+                                return HttpResponse::ServiceUnavailable().finish();
+                            }
+                            let keys: Vec<_> = kids
+                                .lock()
+                                .unwrap()
+                                .iter()
+                                .map(|kid| IssuerKey::from_seed(kid, [1; 32]).public_key().to_jwk())
+                                .collect();
+                            HttpResponse::Ok()
+                                .insert_header(("Cache-Control", cache_control))
+                                .json(json!({ "keys": keys }))
+                        }
+                    }),
+                )
+            })
+            .workers(1)
+            .listen(listener)
+            .unwrap()
+            .run();
+            tokio::spawn(server);
+            Self {
+                jwks_uri,
+                kids,
+                up,
+                fetches,
+            }
+        }
+
+        fn fetches(&self) -> usize {
+            self.fetches.load(Ordering::SeqCst)
+        }
+    }
+
+    fn config() -> JwksCacheConfig {
+        JwksCacheConfig {
+            min_ttl: Duration::ZERO,
+            refetch_interval: Duration::from_millis(200),
+            ..JwksCacheConfig::default()
+        }
+    }
+
+    #[test]
+    fn test_ttl_from_cache_control() {
+        let config = JwksCacheConfig::default();
+        assert_eq!(
+            config.ttl(Some("public, max-age=3600")),
+            Duration::from_secs(3600)
+        );
+        assert_eq!(config.ttl(Some("max-age=1")), config.min_ttl);
+        assert_eq!(config.ttl(Some("no-store")), config.min_ttl);
+        assert_eq!(config.ttl(None), config.default_ttl);
+    }
+
+    #[actix_web::test]
+    async fn test_unknown_kid_refetches_at_a_limited_rate() {
+        let idp = IdpStandIn::start("max-age=3600");
+        let cache = Arc::new(JwksCache::new(config()));
+
+        cache.jwks(&idp.jwks_uri, Some("key-1")).await.unwrap();
+        cache.jwks(&idp.jwks_uri, Some("key-1")).await.unwrap();
+        assert_eq!(idp.fetches(), 1);
+
+        idp.kids.lock().unwrap().push("key-2");
+        tokio::time::sleep(Duration::from_millis(250)).await;
+        let jwks = cache.jwks(&idp.jwks_uri, Some("key-2")).await.unwrap();
+        assert!(jwks.find("key-2").is_some());
+        assert_eq!(idp.fetches(), 2);
+
+        cache.jwks(&idp.jwks_uri, Some("forged")).await.unwrap();
+        cache.jwks(&idp.jwks_uri, Some("forged")).await.unwrap();
+        assert_eq!(idp.fetches(), 2);
+        tokio::time::sleep(Duration::from_millis(250)).await;
+        cache.jwks(&idp.jwks_uri, Some("forged")).await.unwrap();
+        assert_eq!(idp.fetches(), 3);
+    }
+
+    #[actix_web::test]
+    async fn test_stale_keys_are_served_while_the_idp_is_down() {
+        let idp = IdpStandIn::start("max-age=0");
+        let cache = Arc::new(JwksCache::new(JwksCacheConfig {
+            max_stale: Duration::from_millis(300),
+            ..config()
+        }));
+        cache.jwks(&idp.jwks_uri, None).await.unwrap();
+
+        *idp.up.lock().unwrap() = false;
+        tokio::time::sleep(Duration::from_millis(250)).await;
+        let jwks = cache.jwks(&idp.jwks_uri, Some("key-1")).await.unwrap();
+        assert!(jwks.find("key-1").is_some());
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        assert_eq!(idp.fetches(), 2);
+
+        tokio::time::sleep(Duration::from_millis(350)).await;
+        assert!(matches!(
+            cache.jwks(&idp.jwks_uri, Some("key-1")).await,
+            Err(JwksError::Fetch(..))
+        ));
+    }
+
+    #[actix_web::test]
+    async fn test_expired_keys_are_served_while_refetched_once() {
+        let idp = IdpStandIn::start("max-age=0");
+        let cache = Arc::new(JwksCache::new(config()));
+        cache.jwks(&idp.jwks_uri, None).await.unwrap();
+        *idp.kids.lock().unwrap() = vec!["key-1", "key-2"];
+
+        tokio::time::sleep(Duration::from_millis(250)).await;
+        for _ in 0..5 {
+            let jwks = cache.jwks(&idp.jwks_uri, Some("key-1")).await.unwrap();
+            assert!(jwks.find("key-2").is_none());
+        }
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        assert_eq!(idp.fetches(), 2);
+        let cached = cache.cached(&idp.jwks_uri).unwrap();
+        assert!(cached.jwks.find("key-2").is_some());
+    }
+
+    #[actix_web::test]
+    async fn test_concurrent_fetches_share_one_request() {
+        let idp = IdpStandIn::start("max-age=3600");
+        let cache = Arc::new(JwksCache::new(config()));
+
+        let (first, second, third) = tokio::join!(
+            cache.jwks(&idp.jwks_uri, Some("key-1")),
+            cache.jwks(&idp.jwks_uri, Some("key-1")),
+            cache.refresh(&idp.jwks_uri),
+        );
+        for jwks in [first, second, third] {
+            assert!(jwks.unwrap().find("key-1").is_some());
+        }
+        assert_eq!(idp.fetches(), 1);
+        assert!(cache.in_flight.lock().unwrap().is_empty());
+    }
+
+    #[actix_web::test]
+    async fn test_background_refresh_picks_up_rotated_keys() {
+        let idp = IdpStandIn::start("max-age=0");
+        let cache = Arc::new(JwksCache::new(config()));
+        cache.jwks(&idp.jwks_uri, None).await.unwrap();
+        *idp.kids.lock().unwrap() = vec!["key-2"];
+
+        let refresh = cache.spawn_refresh(Duration::from_millis(50));
+        tokio::time::sleep(Duration::from_millis(200)).await;
+        refresh.abort();
+
+        let cached = cache.cached(&idp.jwks_uri).unwrap();
+        assert!(cached.jwks.find("key-2").is_some());
+    }
+}
@@ -0,0 +1,538 @@
+//! File backed [`KeyStore`] holding the key encryption keys of field level encryption.
+//!
+//! Each key is stored in its own `<kid>.key` file, the `current` file names the key used to wrap
+//! new data keys. An empty directory is initialised with a freshly generated key. Keys are never
+//! deleted, retired keys are still needed to read old envelopes. Every file is written with mode
+//! `0600` to a temporary file that is then renamed into place.
+//!
+//! The same directory holds the Ed25519 [`IssuerKey`]s signing entity data and credentials, in
+//! `issuer.json`. Rotating the issuer key retires the current one: it no longer signs but stays
+//! listed in the DID document of the registry, so that what it signed keeps verifying.
+//!
+//! When `KEYSTORE_PASSPHRASE` is set, key material is encrypted at rest with AES-256-GCM under a
+//! key derived from the passphrase with PBKDF2-HMAC-SHA256 and the salt in the `salt` file.
+//! Sealed values are prefixed with `enc:`, plain ones are the base64 of the key. Plain keys found
+//! in a keystore opened with a passphrase are encrypted in place.
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+use chrono::{DateTime, Duration, Utc};
+use definitions_core::encryption::{
+    generate_key, open_with_key, seal_with_key, EncryptionError, KeyStore,
+};
+use definitions_core::signing::{IssuerKey, PublicKey};
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+use std::collections::HashMap;
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use uuid::Uuid;
+
+pub const KEYSTORE_DIR: &str = "KEYSTORE_DIR";
+pub const KEYSTORE_PASSPHRASE: &str = "KEYSTORE_PASSPHRASE";
+pub const DEFAULT_KEYSTORE_DIR: &str = "./keystore";
+const CURRENT_FILE: &str = "current";
+const KEY_EXTENSION: &str = "key";
+const ISSUER_KEY_FILE: &str = "issuer.json";
+const SALT_FILE: &str = "salt";
+const SEALED_PREFIX: &str = "enc:";
+const PBKDF2_ROUNDS: u32 = if cfg!(test) { 1_000 } else { 600_000 };
+
+/// An issuer key of `issuer.json`
+#[derive(Serialize, Deserialize)]
+struct StoredIssuerKey {
+    kid: String,
+    /// The Ed25519 secret key, base64 or sealed
+    seed: String,
+    /// Missing for keys written before rotation was supported
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    created_at: Option<DateTime<Utc>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    retired_at: Option<DateTime<Utc>>,
+}
+
+/// Content of `issuer.json`
+#[derive(Serialize, Deserialize)]
+struct IssuerKeyRing {
+    current: String,
+    keys: Vec<StoredIssuerKey>,
+}
+
+/// `issuer.json` used to hold a single key
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum StoredIssuerKeys {
+    Ring(IssuerKeyRing),
+    Single(StoredIssuerKey),
+}
+
+pub struct FileKeyStore {
+    dir: PathBuf,
+    current: String,
+    keys: HashMap<String, [u8; 32]>,
+    /// Derived from `KEYSTORE_PASSPHRASE`, encrypts the key files when set
+    master_key: Option<[u8; 32]>,
+}
+
+impl std::fmt::Debug for FileKeyStore {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("FileKeyStore")
+            .field("dir", &self.dir)
+            .field("current", &self.current)
+            .field("encrypted", &self.master_key.is_some())
+            .finish_non_exhaustive()
+    }
+}
+
+impl FileKeyStore {
+    /// Opens the keystore in the directory named by `KEYSTORE_DIR`, `./keystore` by default,
+    /// encrypted with `KEYSTORE_PASSPHRASE` when set
+    pub fn from_env() -> Result<Self, EncryptionError> {
+        let dir = std::env::var(KEYSTORE_DIR).unwrap_or_else(|_| DEFAULT_KEYSTORE_DIR.to_string());
+        let passphrase = std::env::var(KEYSTORE_PASSPHRASE)
+            .ok()
+            .filter(|p| !p.is_empty());
+        if passphrase.is_none() {
+            warn!(
+                "{} is not set, the keys in {} are stored unencrypted",
+                KEYSTORE_PASSPHRASE, dir
+            );
+        }
+        Self::open_with_passphrase(dir, passphrase.as_deref())
+    }
+
+    /// Loads all keys of `dir`, creating the directory and a first key when needed
+    pub fn open(dir: impl AsRef<Path>) -> Result<Self, EncryptionError> {
+        Self::open_with_passphrase(dir, None)
+    }
+
+    /// Loads all keys of `dir`, decrypting them with `passphrase`
+    pub fn open_with_passphrase(
+        dir: impl AsRef<Path>,
+        passphrase: Option<&str>,
+    ) -> Result<Self, EncryptionError> {
+        let dir = dir.as_ref().to_path_buf();
+        fs::create_dir_all(&dir).map_err(io_error)?;
+        let master_key = passphrase
+            .map(|passphrase| derive_master_key(&dir, passphrase))
+            .transpose()?;
+
+        let mut keystore = Self {
+            dir,
+            current: String::new(),
+            keys: HashMap::new(),
+            master_key,
+        };
+        for entry in fs::read_dir(&keystore.dir).map_err(io_error)? {
+            let path = entry.map_err(io_error)?.path();
+            if path.extension().and_then(|ext| ext.to_str()) != Some(KEY_EXTENSION) {
+                continue;
+            }
+            let Some(kid) = path.file_stem().and_then(|stem| stem.to_str()) else {
+                continue;
+            };
+            let stored = fs::read_to_string(&path).map_err(io_error)?;
+            let key = keystore.unseal(stored.trim(), &path)?;
+            if keystore.master_key.is_some() && !stored.starts_with(SEALED_PREFIX) {
+                write_private(&path, keystore.seal(&key)?)?;
+                info!("Encrypted key {}", path.display());
+            }
+            keystore.keys.insert(kid.to_string(), key);
+        }
+
+        let current_path = keystore.dir.join(CURRENT_FILE);
+        if current_path.exists() {
+            let current = fs::read_to_string(&current_path).map_err(io_error)?;
+            let current = current.trim().to_string();
+            if !keystore.keys.contains_key(&current) {
+                return Err(EncryptionError::KeyNotFound(current));
+            }
+            keystore.current = current;
+        } else {
+            keystore.rotate()?;
+        }
+        Ok(keystore)
+    }
+
+    /// Generates a new key and makes it the current one, returning its id
+    pub fn rotate(&mut self) -> Result<String, EncryptionError> {
+        let kid = Uuid::now_v7().to_string();
+        let key = generate_key();
+        write_private(
+            &self.dir.join(format!("{kid}.{KEY_EXTENSION}")),
+            self.seal(&key)?,
+        )?;
+        write_private(&self.dir.join(CURRENT_FILE), &kid)?;
+        info!("Keystore {} now uses key {}", self.dir.display(), kid);
+        self.keys.insert(kid.clone(), key);
+        self.current = kid.clone();
+        Ok(kid)
+    }
+
+    /// The issuer key of the registry, generated on first use
+    pub fn issuer_key(&self) -> Result<IssuerKey, EncryptionError> {
+        let ring = match self.load_issuer_keys()? {
+            Some(ring) => ring,
+            None => {
+                let ring = IssuerKeyRing {
+                    current: String::new(),
+                    keys: vec![],
+                };
+                let issuer_key = self.add_issuer_key(ring)?;
+                info!("Generated issuer key {}", issuer_key.kid());
+                return Ok(issuer_key);
+            }
+        };
+        let stored = ring
+            .keys
+            .iter()
+            .find(|key| key.kid == ring.current)
+            .ok_or_else(|| EncryptionError::KeyNotFound(ring.current.clone()))?;
+        self.issuer_key_of(stored)
+    }
+
+    /// The public keys of the retired issuer keys, most recent first
+    pub fn retired_issuer_keys(&self) -> Result<Vec<PublicKey>, EncryptionError> {
+        let Some(ring) = self.load_issuer_keys()? else {
+            return Ok(vec![]);
+        };
+        ring.keys
+            .iter()
+            .rev()
+            .filter(|key| key.kid != ring.current)
+            .map(|key| self.issuer_key_of(key).map(|key| key.public_key()))
+            .collect()
+    }
+
+    /// Generates a new issuer key and retires the current one, returning the new key
+    pub fn rotate_issuer_key(&self) -> Result<IssuerKey, EncryptionError> {
+        // Makes sure a key exists before retiring it
+        self.issuer_key()?;
+        let ring = self
+            .load_issuer_keys()?
+            .ok_or_else(|| EncryptionError::KeyStore("no issuer key to rotate".to_string()))?;
+        let issuer_key = self.add_issuer_key(ring)?;
+        info!("Rotated the issuer key, {} now signs", issuer_key.kid());
+        Ok(issuer_key)
+    }
+
+    /// Rotates the issuer key when the current one was created more than `max_age` ago. Keys
+    /// without a creation date are dated the first time they are checked.
+    pub fn rotate_issuer_key_older_than(
+        &self,
+        max_age: Duration,
+    ) -> Result<Option<IssuerKey>, EncryptionError> {
+        self.issuer_key()?;
+        let Some(mut ring) = self.load_issuer_keys()? else {
+            return Ok(None);
+        };
+        let current = ring.current.clone();
+        let Some(stored) = ring.keys.iter_mut().find(|key| key.kid == current) else {
+            return Err(EncryptionError::KeyNotFound(current));
+        };
+        match stored.created_at {
+            Some(created_at) if Utc::now() - created_at > max_age => {
+                self.rotate_issuer_key().map(Some)
+            }
+            Some(_) => Ok(None),
+            None => {
+                stored.created_at = Some(Utc::now());
+                self.save_issuer_keys(&ring)?;
+                Ok(None)
+            }
+        }
+    }
+
+    /// Appends a fresh issuer key to `ring` and makes it the current one
+    fn add_issuer_key(&self, mut ring: IssuerKeyRing) -> Result<IssuerKey, EncryptionError> {
+        let now = Utc::now();
+        if let Some(current) = ring.keys.iter_mut().find(|key| key.kid == ring.current) {
+            current.retired_at = Some(now);
+        }
+        let issuer_key = IssuerKey::generate(&Uuid::now_v7().to_string());
+        ring.keys.push(StoredIssuerKey {
+            kid: issuer_key.kid().to_string(),
+            seed: self.seal(&issuer_key.seed())?,
+            created_at: Some(now),
+            retired_at: None,
+        });
+        ring.current = issuer_key.kid().to_string();
+        self.save_issuer_keys(&ring)?;
+        Ok(issuer_key)
+    }
+
+    /// Reads `issuer.json`, upgrading single key files and encrypting plain seeds in place
+    fn load_issuer_keys(&self) -> Result<Option<IssuerKeyRing>, EncryptionError> {
+        let path = self.dir.join(ISSUER_KEY_FILE);
+        if !path.exists() {
+            return Ok(None);
+        }
+        let stored: StoredIssuerKeys =
+            serde_json::from_str(&fs::read_to_string(&path).map_err(io_error)?)
+                .map_err(|e| EncryptionError::KeyStore(e.to_string()))?;
+        let (mut ring, mut upgraded) = match stored {
+            StoredIssuerKeys::Ring(ring) => (ring, false),
+            StoredIssuerKeys::Single(key) => (
+                IssuerKeyRing {
+                    current: key.kid.clone(),
+                    keys: vec![key],
+                },
+                true,
+            ),
+        };
+        if self.master_key.is_some() {
+            for key in ring
+                .keys
+                .iter_mut()
+                .filter(|key| !key.seed.starts_with(SEALED_PREFIX))
+            {
+                key.seed = self.seal(&self.unseal(&key.seed, &path)?)?;
+                upgraded = true;
+            }
+        }
+        if upgraded {
+            self.save_issuer_keys(&ring)?;
+            info!("Upgraded {}", path.display());
+        }
+        Ok(Some(ring))
+    }
+
+    fn save_issuer_keys(&self, ring: &IssuerKeyRing) -> Result<(), EncryptionError> {
+        write_private(
+            &self.dir.join(ISSUER_KEY_FILE),
+            serde_json::to_string_pretty(ring)
+                .map_err(|e| EncryptionError::KeyStore(e.to_string()))?,
+        )
+    }
+
+    fn issuer_key_of(&self, stored: &StoredIssuerKey) -> Result<IssuerKey, EncryptionError> {
+        let seed = self.unseal(&stored.seed, &self.dir.join(ISSUER_KEY_FILE))?;
+        Ok(IssuerKey::from_seed(&stored.kid, seed))
+    }
+
+    /// Encodes a key for storage, encrypted when the keystore has a passphrase
+    fn seal(&self, key: &[u8; 32]) -> Result<String, EncryptionError> {
+        match &self.master_key {
+            Some(master_key) => Ok(format!(
+                "{SEALED_PREFIX}{}",
+                seal_with_key(master_key, key)?
+            )),
+            None => Ok(BASE64.encode(key)),
+        }
+    }
+
+    /// Decodes a stored key, `path` names its file in errors
+    fn unseal(&self, stored: &str, path: &Path) -> Result<[u8; 32], EncryptionError> {
+        let bytes = match stored.strip_prefix(SEALED_PREFIX) {
+            Some(sealed) => {
+                let master_key = self.master_key.as_ref().ok_or_else(|| {
+                    EncryptionError::KeyStore(format!(
+                        "{} is encrypted but {} is not set",
+                        path.display(),
+                        KEYSTORE_PASSPHRASE
+                    ))
+                })?;
+                open_with_key(master_key, sealed).map_err(|_| {
+                    EncryptionError::KeyStore(format!(
+                        "{} could not be decrypted, check {}",
+                        path.display(),
+                        KEYSTORE_PASSPHRASE
+                    ))
+                })?
+            }
+            None => BASE64
+                .decode(stored)
+                .map_err(|e| EncryptionError::KeyStore(e.to_string()))?,
+        };
+        bytes.try_into().map_err(|_| {
+            EncryptionError::KeyStore(format!("{} is not a 256 bit key", path.display()))
+        })
+    }
+}
+
+impl KeyStore for FileKeyStore {
+    fn current_key_id(&self) -> Result<String, EncryptionError> {
+        Ok(self.current.clone())
+    }
+
+    fn key(&self, key_id: &str) -> Result<[u8; 32], EncryptionError> {
+        self.keys
+            .get(key_id)
+            .copied()
+            .ok_or_else(|| EncryptionError::KeyNotFound(key_id.to_string()))
+    }
+}
+
+/// Derives the key encrypting the key files from `passphrase` and the salt of `dir`, generating
+/// the salt on first use
+fn derive_master_key(dir: &Path, passphrase: &str) -> Result<[u8; 32], EncryptionError> {
+    let salt_path = dir.join(SALT_FILE);
+    let salt = if salt_path.exists() {
+        BASE64
+            .decode(fs::read_to_string(&salt_path).map_err(io_error)?.trim())
+            .map_err(|e| EncryptionError::KeyStore(e.to_string()))?
+    } else {
+        let salt = generate_key()[..16].to_vec();
+        write_private(&salt_path, BASE64.encode(&salt))?;
+        salt
+    };
+    let mut master_key = [0; 32];
+    pbkdf2::pbkdf2_hmac::<Sha256>(passphrase.as_bytes(), &salt, PBKDF2_ROUNDS, &mut master_key);
+    Ok(master_key)
+}
+
+/// Writes a keystore file readable by its owner only.
+///
+/// The contents go to a temporary file of the same directory first, renamed over `path` once
+/// synced, so that a crash never leaves a truncated key behind.
+fn write_private(path: &Path, contents: impl AsRef<[u8]>) -> Result<(), EncryptionError> {
+    let file_name = path
+        .file_name()
+        .and_then(|name| name.to_str())
+        .ok_or_else(|| EncryptionError::KeyStore(format!("invalid path {}", path.display())))?;
+    let temp_path = path.with_file_name(format!(".{}.{}.tmp", file_name, Uuid::now_v7()));
+    let mut options = fs::OpenOptions::new();
+    options.write(true).create_new(true);
+    #[cfg(unix)]
+    std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+    let written = options.open(&temp_path).and_then(|mut file| {
+        file.write_all(contents.as_ref())?;
+        file.sync_all()
+    });
+    if let Err(e) = written.and_then(|()| fs::rename(&temp_path, path)) {
+        let _ = fs::remove_file(&temp_path);
+        return Err(io_error(e));
+    }
+    Ok(())
+}
+
+fn io_error(e: std::io::Error) -> EncryptionError {
+    EncryptionError::KeyStore(e.to_string())
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used)]
+mod tests {
+    use super::*;
+
+    fn temp_dir() -> PathBuf {
+        std::env::temp_dir().join(format!("rc-keystore-{}", Uuid::now_v7()))
+    }
+
+    #[test]
+    fn test_open_initialises_and_reloads_keys() {
+        let dir = temp_dir();
+        let mut keystore = FileKeyStore::open(&dir).unwrap();
+        let first = keystore.current_key_id().unwrap();
+        let second = keystore.rotate().unwrap();
+
+        let reopened = FileKeyStore::open(&dir).unwrap();
+
+        assert_eq!(reopened.current_key_id().unwrap(), second);
+        assert_eq!(reopened.key(&first).unwrap(), keystore.key(&first).unwrap());
+        assert_eq!(
+            reopened.key(&second).unwrap(),
+            keystore.key(&second).unwrap()
+        );
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_key_files_are_private_to_their_owner() {
+        use std::os::unix::fs::PermissionsExt;
+        let dir = temp_dir();
+        let mut keystore = FileKeyStore::open_with_passphrase(&dir, Some("secret")).unwrap();
+        keystore.rotate().unwrap();
+        keystore.issuer_key().unwrap();
+
+        let files: Vec<PathBuf> = fs::read_dir(&dir)
+            .unwrap()
+            .map(|entry| entry.unwrap().path())
+            .collect();
+        assert_eq!(files.len(), 5);
+        for path in files {
+            assert!(!path.to_string_lossy().ends_with(".tmp"));
+            let mode = fs::metadata(&path).unwrap().permissions().mode();
+            assert_eq!(mode & 0o777, 0o600, "{}", path.display());
+        }
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_issuer_key_is_persisted() {
+        let dir = temp_dir();
+        let keystore = FileKeyStore::open(&dir).unwrap();
+
+        let issuer_key = keystore.issuer_key().unwrap();
+
+        assert_eq!(keystore.issuer_key().unwrap(), issuer_key);
+        assert_eq!(
+            FileKeyStore::open(&dir).unwrap().issuer_key().unwrap(),
+            issuer_key
+        );
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_unknown_key_is_reported() {
+        let dir = temp_dir();
+        let keystore = FileKeyStore::open(&dir).unwrap();
+
+        assert_eq!(
+            keystore.key("missing"),
+            Err(EncryptionError::KeyNotFound("missing".to_string()))
+        );
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_rotated_issuer_keys_are_retired() {
+        let dir = temp_dir();
+        let keystore = FileKeyStore::open(&dir).unwrap();
+        let first = keystore.issuer_key().unwrap();
+
+        let second = keystore.rotate_issuer_key().unwrap();
+
+        assert_ne!(first, second);
+        assert_eq!(keystore.issuer_key().unwrap(), second);
+        assert_eq!(
+            keystore.retired_issuer_keys().unwrap(),
+            vec![first.public_key()]
+        );
+        assert_eq!(
+            keystore
+                .rotate_issuer_key_older_than(Duration::days(1))
+                .unwrap(),
+            None
+        );
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_passphrase_encrypts_existing_keys() {
+        let dir = temp_dir();
+        let keystore = FileKeyStore::open(&dir).unwrap();
+        let kek = keystore.current_key_id().unwrap();
+        let issuer_key = keystore.issuer_key().unwrap();
+        let seed = BASE64.encode(issuer_key.seed());
+        assert!(fs::read_to_string(dir.join(ISSUER_KEY_FILE))
+            .unwrap()
+            .contains(&seed));
+
+        let encrypted = FileKeyStore::open_with_passphrase(&dir, Some("secret")).unwrap();
+        assert_eq!(encrypted.issuer_key().unwrap(), issuer_key);
+        assert_eq!(encrypted.key(&kek).unwrap(), keystore.key(&kek).unwrap());
+        assert!(!fs::read_to_string(dir.join(ISSUER_KEY_FILE))
+            .unwrap()
+            .contains(&seed));
+        assert!(fs::read_to_string(dir.join(format!("{kek}.key")))
+            .unwrap()
+            .starts_with(SEALED_PREFIX));
+
+        assert!(FileKeyStore::open(&dir).is_err());
+        assert!(FileKeyStore::open_with_passphrase(&dir, Some("wrong")).is_err());
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
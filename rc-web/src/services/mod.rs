@@ -1 +1,14 @@
+pub mod api_keys;
+pub mod blob_store;
+pub mod did_resolver;
+pub mod history;
+pub mod impact_analysis;
+pub mod jwks_cache;
+pub mod keystore;
+pub mod notification_outbox;
+pub mod notification_templates;
+pub mod notifier;
+pub mod registration_codes;
+pub mod smtp;
+pub mod token_verifier;
 mod user_service;
@@ -0,0 +1,297 @@
+//! Delivery status of the notifications sent for registry events.
+//!
+//! A notification is stored before it is sent, once per event, template, channel and recipient,
+//! so that replaying an event does not send it again. Failed deliveries are retried with an
+//! exponential backoff, from 30 seconds up to an hour, and given up after
+//! `NOTIFICATION_MAX_ATTEMPTS` attempts (five by default).
+use crate::services::notifier::{Notification, Notifier};
+use chrono::{DateTime, Duration, Utc};
+use derive_more::Display;
+use serde::{Deserialize, Serialize};
+use sqlx::{FromRow, PgPool};
+use std::env;
+use std::sync::Arc;
+use utoipa::ToSchema;
+use uuid::Uuid;
+
+const DEFAULT_MAX_ATTEMPTS: i32 = 5;
+const FIRST_RETRY_SECS: i64 = 30;
+const MAX_RETRY_SECS: i64 = 3600;
+/// A claimed notification is not claimed again before the lease ends, even if its sender died
+const LEASE_SECS: i64 = 300;
+const BATCH_SIZE: i64 = 50;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Display, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum DeliveryStatus {
+    /// Not attempted yet
+    #[display("pending")]
+    Pending,
+    #[display("sent")]
+    Sent,
+    /// Failed, attempted again at `next_attempt_at`
+    #[display("retrying")]
+    Retrying,
+    /// Failed `NOTIFICATION_MAX_ATTEMPTS` times
+    #[display("failed")]
+    Failed,
+}
+
+/// A notification to store, for one recipient of an event
+#[derive(Debug, Clone)]
+pub struct OutboundNotification {
+    pub event_id: i64,
+    pub event_type: String,
+    pub entity_id: Uuid,
+    /// Name of the template of the message, see [`crate::services::notification_templates`]
+    pub template: String,
+    pub notification: Notification,
+}
+
+#[derive(Debug, Clone, Serialize, FromRow, ToSchema)]
+pub struct NotificationRecord {
+    pub id: Uuid,
+    /// Id of the event the notification was sent for
+    pub event_id: i64,
+    pub event_type: String,
+    pub entity_id: Uuid,
+    pub template: String,
+    /// `email` or `sms`
+    pub channel: String,
+    pub recipient: String,
+    pub subject: Option<String>,
+    /// `pending`, `sent`, `retrying` or `failed`
+    pub status: String,
+    pub attempts: i32,
+    pub last_error: Option<String>,
+    pub next_attempt_at: DateTime<Utc>,
+    pub created_at: DateTime<Utc>,
+    pub sent_at: Option<DateTime<Utc>>,
+}
+
+#[derive(FromRow)]
+struct ClaimedNotification {
+    id: Uuid,
+    channel: String,
+    recipient: String,
+    subject: Option<String>,
+    body: String,
+    attempts: i32,
+}
+
+/// Delay before the attempt following `attempts` failed ones
+fn retry_delay(attempts: i32) -> Duration {
+    let exponent = attempts.clamp(1, 16) - 1;
+    Duration::seconds((FIRST_RETRY_SECS << exponent).min(MAX_RETRY_SECS))
+}
+
+fn max_attempts() -> i32 {
+    env::var("NOTIFICATION_MAX_ATTEMPTS")
+        .ok()
+        .and_then(|attempts| attempts.parse().ok())
+        .filter(|attempts| *attempts > 0)
+        .unwrap_or(DEFAULT_MAX_ATTEMPTS)
+}
+
+/// Notifications and their delivery status, stored in the registry database
+#[derive(Clone)]
+pub struct NotificationOutbox {
+    pool: PgPool,
+}
+
+impl NotificationOutbox {
+    pub async fn new(pool: PgPool) -> Result<Self, sqlx::Error> {
+        sqlx::query(
+            r#"
+            CREATE TABLE IF NOT EXISTS notifications (
+                id UUID PRIMARY KEY,
+                event_id BIGINT NOT NULL,
+                event_type TEXT NOT NULL,
+                entity_id UUID NOT NULL,
+                template TEXT NOT NULL,
+                channel TEXT NOT NULL,
+                recipient TEXT NOT NULL,
+                subject TEXT,
+                body TEXT NOT NULL,
+                status TEXT NOT NULL,
+                attempts INTEGER NOT NULL DEFAULT 0,
+                last_error TEXT,
+                next_attempt_at TIMESTAMPTZ NOT NULL,
+                created_at TIMESTAMPTZ NOT NULL,
+                sent_at TIMESTAMPTZ,
+                UNIQUE (event_id, template, channel, recipient)
+            );
+            "#,
+        )
+        .execute(&pool)
+        .await?;
+        sqlx::query(
+            "CREATE INDEX IF NOT EXISTS idx_notifications_due ON notifications (next_attempt_at) WHERE status IN ('pending', 'retrying');",
+        )
+        .execute(&pool)
+        .await?;
+        sqlx::query(
+            "CREATE INDEX IF NOT EXISTS idx_notifications_entity_id ON notifications (entity_id);",
+        )
+        .execute(&pool)
+        .await?;
+        Ok(Self { pool })
+    }
+
+    /// Stores notifications to send, ignoring those already stored for the same event
+    pub async fn enqueue(&self, notifications: &[OutboundNotification]) -> Result<(), sqlx::Error> {
+        let now = Utc::now();
+        for outbound in notifications {
+            sqlx::query(
+                r#"
+                INSERT INTO notifications (id, event_id, event_type, entity_id, template, channel, recipient, subject, body, status, attempts, next_attempt_at, created_at)
+                VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, 0, $11, $11)
+                ON CONFLICT (event_id, template, channel, recipient) DO NOTHING
+                "#,
+            )
+            .bind(Uuid::now_v7())
+            .bind(outbound.event_id)
+            .bind(&outbound.event_type)
+            .bind(outbound.entity_id)
+            .bind(&outbound.template)
+            .bind(outbound.notification.channel.to_string())
+            .bind(&outbound.notification.to)
+            .bind(&outbound.notification.subject)
+            .bind(&outbound.notification.body)
+            .bind(DeliveryStatus::Pending.to_string())
+            .bind(now)
+            .execute(&self.pool)
+            .await?;
+        }
+        Ok(())
+    }
+
+    /// Sends the notifications that are due, returning how many were sent
+    pub async fn deliver_due(&self, notifier: &dyn Notifier) -> Result<usize, sqlx::Error> {
+        let now = Utc::now();
+        // Claiming moves the next attempt past the lease, so that concurrent senders skip it
+        let claimed = sqlx::query_as::<_, ClaimedNotification>(
+            r#"
+            UPDATE notifications SET next_attempt_at = $2
+            WHERE id IN (
+                SELECT id FROM notifications
+                WHERE status IN ('pending', 'retrying') AND next_attempt_at <= $1
+                ORDER BY next_attempt_at
+                LIMIT $3
+                FOR UPDATE SKIP LOCKED
+            )
+            RETURNING id, channel, recipient, subject, body, attempts
+            "#,
+        )
+        .bind(now)
+        .bind(now + Duration::seconds(LEASE_SECS))
+        .bind(BATCH_SIZE)
+        .fetch_all(&self.pool)
+        .await?;
+
+        let max_attempts = max_attempts();
+        let mut sent = 0;
+        for claimed in claimed {
+            let Ok(channel) = serde_json::from_value(serde_json::Value::String(claimed.channel))
+            else {
+                continue;
+            };
+            let notification = Notification {
+                channel,
+                to: claimed.recipient,
+                subject: claimed.subject,
+                body: claimed.body,
+            };
+            let attempts = claimed.attempts + 1;
+            match notifier.send(&notification).await {
+                Ok(()) => {
+                    sqlx::query(
+                        "UPDATE notifications SET status = $2, attempts = $3, last_error = NULL, sent_at = $4 WHERE id = $1",
+                    )
+                    .bind(claimed.id)
+                    .bind(DeliveryStatus::Sent.to_string())
+                    .bind(attempts)
+                    .bind(Utc::now())
+                    .execute(&self.pool)
+                    .await?;
+                    sent += 1;
+                }
+                Err(e) => {
+                    let status = if attempts >= max_attempts {
+                        DeliveryStatus::Failed
+                    } else {
+                        DeliveryStatus::Retrying
+                    };
+                    log::warn!(
+                        "Notification {} failed after {} attempts: {}",
+                        claimed.id,
+                        attempts,
+                        e
+                    );
+                    sqlx::query(
+                        "UPDATE notifications SET status = $2, attempts = $3, last_error = $4, next_attempt_at = $5 WHERE id = $1",
+                    )
+                    .bind(claimed.id)
+                    .bind(status.to_string())
+                    .bind(attempts)
+                    .bind(e.to_string())
+                    .bind(Utc::now() + retry_delay(attempts))
+                    .execute(&self.pool)
+                    .await?;
+                }
+            }
+        }
+        Ok(sent)
+    }
+
+    /// Sends the due notifications every `interval`, the new ones and those to retry
+    pub fn spawn_delivery(&self, notifier: Arc<dyn Notifier>, interval: std::time::Duration) {
+        let outbox = self.clone();
+        tokio::spawn(async move {
+            let mut ticker = tokio::time::interval(interval);
+            loop {
+                ticker.tick().await;
+                if let Err(e) = outbox.deliver_due(notifier.as_ref()).await {
+                    log::error!("Delivering notifications failed: {}", e);
+                }
+            }
+        });
+    }
+
+    /// The latest notifications, optionally of one status or entity
+    pub async fn list(
+        &self,
+        status: Option<DeliveryStatus>,
+        entity_id: Option<Uuid>,
+        limit: i64,
+    ) -> Result<Vec<NotificationRecord>, sqlx::Error> {
+        sqlx::query_as::<_, NotificationRecord>(
+            r#"
+            SELECT id, event_id, event_type, entity_id, template, channel, recipient, subject, status, attempts, last_error, next_attempt_at, created_at, sent_at
+            FROM notifications
+            WHERE ($1::TEXT IS NULL OR status = $1) AND ($2::UUID IS NULL OR entity_id = $2)
+            ORDER BY created_at DESC
+            LIMIT $3
+            "#,
+        )
+        .bind(status.map(|status| status.to_string()))
+        .bind(entity_id)
+        .bind(limit)
+        .fetch_all(&self.pool)
+        .await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_retries_back_off_exponentially_up_to_an_hour() {
+        assert_eq!(retry_delay(1), Duration::seconds(30));
+        assert_eq!(retry_delay(2), Duration::seconds(60));
+        assert_eq!(retry_delay(4), Duration::seconds(240));
+        assert_eq!(retry_delay(8), Duration::seconds(3600));
+        assert_eq!(retry_delay(100), Duration::seconds(3600));
+    }
+}
@@ -0,0 +1,229 @@
+//! Templates of the email and SMS messages sent by the registry.
+//!
+//! Each message has a name, e.g. `consent_requested`, and is rendered with Handlebars from the
+//! data of the event that caused it, e.g. `{{entityType}}` or `{{#if purpose}}`. Every message has
+//! a built-in template. A file in `NOTIFICATION_TEMPLATE_DIR` named `<name>.subject.hbs`,
+//! `<name>.email.hbs` or `<name>.sms.hbs` replaces the email subject, the email body or the SMS
+//! text of a message.
+use crate::services::notifier::{Channel, Notification};
+use handlebars::{no_escape, Handlebars};
+use serde_json::Value;
+use std::path::Path;
+use thiserror::Error;
+
+pub const NOTIFICATION_TEMPLATE_DIR: &str = "NOTIFICATION_TEMPLATE_DIR";
+
+pub const REGISTRATION_CODE: &str = "registration_code";
+pub const ENTITY_CREATED: &str = "entity_created";
+pub const ENTITY_INVITED: &str = "entity_invited";
+pub const ATTESTATION_REQUESTED: &str = "attestation_requested";
+pub const CONSENT_REQUESTED: &str = "consent_requested";
+pub const CREDENTIAL_ISSUED: &str = "credential_issued";
+
+/// Name, email subject, email body and SMS text of the built-in templates
+const BUILT_IN: &[(&str, &str, &str, &str)] = &[
+    (
+        REGISTRATION_CODE,
+        "Confirm your {{entityType}} registration",
+        "Your {{entityType}} registration code is {{code}}.\n\nIt expires in {{expiresInMinutes}} minutes. If you did not register, ignore this message.",
+        "Your {{entityType}} registration code is {{code}}. It expires in {{expiresInMinutes}} minutes.",
+    ),
+    (
+        ENTITY_CREATED,
+        "Your {{entityType}} record was created",
+        "A {{entityType}} record with id {{entityId}} was created for you.\n\nYou can sign in to {{registryUrl}} to view it.",
+        "A {{entityType}} record with id {{entityId}} was created for you at {{registryUrl}}.",
+    ),
+    (
+        ENTITY_INVITED,
+        "You are invited as {{entityType}}",
+        "You were invited to the registry as {{entityType}}.\n\nSign in to {{registryUrl}} to complete your record {{entityId}}.",
+        "You were invited as {{entityType}}. Complete your record at {{registryUrl}}.",
+    ),
+    (
+        ATTESTATION_REQUESTED,
+        "A {{entityType}} claim awaits your attestation",
+        "A {{entityType}} claim under the policy {{policyName}} was raised and awaits attestation by a {{attestorEntity}}.\n\nClaim id: {{claimId}}",
+        "A {{entityType}} claim ({{policyName}}) awaits your attestation. Claim {{claimId}}.",
+    ),
+    (
+        CONSENT_REQUESTED,
+        "Consent requested for your {{entityType}} record",
+        "{{requestedBy}} asks to read {{#each fields}}{{#if @index}}, {{/if}}{{this}}{{/each}} from your {{entityType}} record{{#if purpose}} for: {{purpose}}{{/if}}.\n\nYou can grant or deny the request {{consentId}} at {{registryUrl}}.",
+        "{{requestedBy}} requests access to your {{entityType}} record. Decide on consent {{consentId}} at {{registryUrl}}.",
+    ),
+    (
+        CREDENTIAL_ISSUED,
+        "Your {{entityType}} credential was issued",
+        "A {{format}} credential {{credentialId}} was issued for your {{entityType}} record {{entityId}}.",
+        "Your {{entityType}} credential {{credentialId}} was issued.",
+    ),
+];
+
+#[derive(Debug, Error)]
+pub enum TemplateError {
+    #[error("Invalid notification template `{0}`: {1}")]
+    Invalid(String, String),
+    #[error("Notification template `{0}` could not be read: {1}")]
+    Io(String, String),
+    #[error("Notification template `{0}` could not be rendered: {1}")]
+    Render(String, String),
+    #[error("No notification template `{0}`")]
+    NotFound(String),
+}
+
+#[derive(Debug)]
+pub struct NotificationTemplates {
+    registry: Handlebars<'static>,
+}
+
+fn template_key(name: &str, part: &str) -> String {
+    format!("{}.{}", name, part)
+}
+
+impl NotificationTemplates {
+    /// The built-in templates
+    pub fn new() -> Result<Self, TemplateError> {
+        let mut registry = Handlebars::new();
+        // Messages are plain text
+        registry.register_escape_fn(no_escape);
+        for (name, subject, email, sms) in BUILT_IN {
+            for (part, template) in [("subject", subject), ("email", email), ("sms", sms)] {
+                let key = template_key(name, part);
+                registry
+                    .register_template_string(&key, template)
+                    .map_err(|e| TemplateError::Invalid(key.clone(), e.to_string()))?;
+            }
+        }
+        Ok(Self { registry })
+    }
+
+    /// The built-in templates, replaced by the files found in `dir`
+    pub fn with_overrides(dir: &Path) -> Result<Self, TemplateError> {
+        let mut templates = Self::new()?;
+        for (name, ..) in BUILT_IN {
+            for part in ["subject", "email", "sms"] {
+                let key = template_key(name, part);
+                let path = dir.join(format!("{}.hbs", key));
+                match std::fs::read_to_string(&path) {
+                    Ok(template) => templates
+                        .registry
+                        .register_template_string(&key, template.trim_end())
+                        .map_err(|e| TemplateError::Invalid(key.clone(), e.to_string()))?,
+                    Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+                    Err(e) => return Err(TemplateError::Io(key, e.to_string())),
+                }
+            }
+        }
+        Ok(templates)
+    }
+
+    /// The templates of `NOTIFICATION_TEMPLATE_DIR`, the built-in ones when it is not set
+    pub fn from_env() -> Result<Self, TemplateError> {
+        match std::env::var(NOTIFICATION_TEMPLATE_DIR) {
+            Ok(dir) => Self::with_overrides(Path::new(&dir)),
+            Err(_) => Self::new(),
+        }
+    }
+
+    /// Renders the message `name` for one recipient
+    pub fn render(
+        &self,
+        name: &str,
+        channel: Channel,
+        to: &str,
+        data: &Value,
+    ) -> Result<Notification, TemplateError> {
+        let render = |part: &str| {
+            let key = template_key(name, part);
+            if !self.registry.has_template(&key) {
+                return Err(TemplateError::NotFound(key));
+            }
+            self.registry
+                .render(&key, data)
+                .map_err(|e| TemplateError::Render(key, e.to_string()))
+        };
+        Ok(match channel {
+            Channel::Email => Notification {
+                channel,
+                to: to.to_string(),
+                subject: Some(render("subject")?),
+                body: render("email")?,
+            },
+            Channel::Sms => Notification {
+                channel,
+                to: to.to_string(),
+                subject: None,
+                body: render("sms")?,
+            },
+        })
+    }
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+    use uuid::Uuid;
+
+    #[test]
+    fn test_built_in_templates_render_plain_text() {
+        let templates = NotificationTemplates::new().unwrap();
+        let data = json!({
+            "entityType": "Insurance",
+            "requestedBy": "auth0|bank<kyc>",
+            "fields": ["$.policyNumber", "$.fullName"],
+            "purpose": "KYC",
+            "consentId": "c-1",
+            "registryUrl": "http://localhost:8000",
+        });
+
+        let email = templates
+            .render(CONSENT_REQUESTED, Channel::Email, "asha@example.com", &data)
+            .unwrap();
+        assert_eq!(
+            email.subject.as_deref(),
+            Some("Consent requested for your Insurance record")
+        );
+        assert!(email
+            .body
+            .starts_with("auth0|bank<kyc> asks to read $.policyNumber, $.fullName from your Insurance record for: KYC."));
+
+        let sms = templates
+            .render(CONSENT_REQUESTED, Channel::Sms, "+91-9876543210", &data)
+            .unwrap();
+        assert_eq!(sms.subject, None);
+        assert!(sms.body.contains("consent c-1"));
+        assert!(matches!(
+            templates.render("unknown", Channel::Sms, "+1", &data),
+            Err(TemplateError::NotFound(_))
+        ));
+    }
+
+    #[test]
+    fn test_files_replace_built_in_templates() {
+        let dir = std::env::temp_dir().join(format!("rc-templates-{}", Uuid::now_v7()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(
+            dir.join("credential_issued.sms.hbs"),
+            "{{entityType}} ready\n",
+        )
+        .unwrap();
+
+        let templates = NotificationTemplates::with_overrides(&dir).unwrap();
+        let data = json!({ "entityType": "Insurance", "credentialId": "1" });
+
+        let sms = templates
+            .render(CREDENTIAL_ISSUED, Channel::Sms, "+1", &data)
+            .unwrap();
+        assert_eq!(sms.body, "Insurance ready");
+        let email = templates
+            .render(CREDENTIAL_ISSUED, Channel::Email, "a@example.com", &data)
+            .unwrap();
+        assert_eq!(
+            email.subject.as_deref(),
+            Some("Your Insurance credential was issued")
+        );
+    }
+}
@@ -0,0 +1,204 @@
+//! Outbound messages to people, by email or SMS.
+//!
+//! [`Notifier`] hides the delivery backend. `NOTIFIER` selects it: `log` (the default) writes the
+//! messages to the application log and `file` appends them as JSON lines to `NOTIFIER_FILE`, so
+//! that tests and local setups can read the messages back without any external service.
+//! `NOTIFIER_EMAIL` and `NOTIFIER_SMS` override it per channel, and emails can also be sent with
+//! `smtp`, see [`crate::services::smtp`].
+use crate::services::smtp::{SmtpConfig, SmtpNotifier};
+use async_trait::async_trait;
+use derive_more::Display;
+use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
+use std::sync::Arc;
+use thiserror::Error;
+use tokio::io::AsyncWriteExt;
+use tokio::sync::Mutex;
+use utoipa::ToSchema;
+
+pub const NOTIFIER: &str = "NOTIFIER";
+pub const NOTIFIER_EMAIL: &str = "NOTIFIER_EMAIL";
+pub const NOTIFIER_SMS: &str = "NOTIFIER_SMS";
+pub const NOTIFIER_FILE: &str = "NOTIFIER_FILE";
+pub const DEFAULT_NOTIFIER_FILE: &str = "./notifications.jsonl";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Display, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum Channel {
+    #[display("email")]
+    Email,
+    #[display("sms")]
+    Sms,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Notification {
+    pub channel: Channel,
+    /// Email address or phone number of the recipient
+    pub to: String,
+    /// Subject of an email, not used for SMS
+    pub subject: Option<String>,
+    pub body: String,
+}
+
+#[derive(Debug, Error)]
+pub enum NotifierError {
+    #[error("Notification to `{0}` could not be delivered: {1}")]
+    Delivery(String, String),
+    #[error("Invalid notifier configuration: {0}")]
+    Configuration(String),
+}
+
+#[async_trait]
+pub trait Notifier: Send + Sync {
+    async fn send(&self, notification: &Notification) -> Result<(), NotifierError>;
+}
+
+/// Writes the messages to the application log
+#[derive(Debug, Clone, Default)]
+pub struct LogNotifier;
+
+#[async_trait]
+impl Notifier for LogNotifier {
+    async fn send(&self, notification: &Notification) -> Result<(), NotifierError> {
+        log::info!(
+            "Notification by {} to {}: {}{}",
+            notification.channel,
+            notification.to,
+            notification
+                .subject
+                .as_ref()
+                .map(|subject| format!("[{}] ", subject))
+                .unwrap_or_default(),
+            notification.body
+        );
+        Ok(())
+    }
+}
+
+/// Appends the messages to a file, one JSON object per line
+#[derive(Debug)]
+pub struct FileNotifier {
+    path: PathBuf,
+    lock: Mutex<()>,
+}
+
+impl FileNotifier {
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self {
+            path: path.into(),
+            lock: Mutex::new(()),
+        }
+    }
+}
+
+#[async_trait]
+impl Notifier for FileNotifier {
+    async fn send(&self, notification: &Notification) -> Result<(), NotifierError> {
+        let delivery_error = |e: String| NotifierError::Delivery(notification.to.clone(), e);
+        let mut line =
+            serde_json::to_vec(notification).map_err(|e| delivery_error(e.to_string()))?;
+        line.push(b'\n');
+        let _guard = self.lock.lock().await;
+        let mut file = tokio::fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)
+            .await
+            .map_err(|e| delivery_error(e.to_string()))?;
+        file.write_all(&line)
+            .await
+            .map_err(|e| delivery_error(e.to_string()))?;
+        // A tokio file completes its writes in the background until flushed
+        file.flush()
+            .await
+            .map_err(|e| delivery_error(e.to_string()))
+    }
+}
+
+/// Sends each message with the notifier of its channel
+pub struct ChannelNotifier {
+    email: Arc<dyn Notifier>,
+    sms: Arc<dyn Notifier>,
+}
+
+impl ChannelNotifier {
+    pub fn new(email: Arc<dyn Notifier>, sms: Arc<dyn Notifier>) -> Self {
+        Self { email, sms }
+    }
+}
+
+#[async_trait]
+impl Notifier for ChannelNotifier {
+    async fn send(&self, notification: &Notification) -> Result<(), NotifierError> {
+        match notification.channel {
+            Channel::Email => self.email.send(notification).await,
+            Channel::Sms => self.sms.send(notification).await,
+        }
+    }
+}
+
+fn backend(channel: Channel, name: &str) -> Result<Arc<dyn Notifier>, NotifierError> {
+    Ok(match name {
+        "log" => Arc::new(LogNotifier),
+        "file" => Arc::new(FileNotifier::new(
+            std::env::var(NOTIFIER_FILE).unwrap_or_else(|_| DEFAULT_NOTIFIER_FILE.to_string()),
+        )),
+        "smtp" if channel == Channel::Email => Arc::new(SmtpNotifier::new(SmtpConfig::from_env()?)),
+        other => {
+            return Err(NotifierError::Configuration(format!(
+                "Unknown {} notifier `{}`",
+                channel, other
+            )))
+        }
+    })
+}
+
+/// The notifiers named by `NOTIFIER_EMAIL` and `NOTIFIER_SMS`, both `NOTIFIER` by default, which
+/// is [`LogNotifier`] by default
+pub fn notifier_from_env() -> Result<Arc<dyn Notifier>, NotifierError> {
+    let default = std::env::var(NOTIFIER).unwrap_or_else(|_| "log".to_string());
+    let named = |channel: Channel, var: &str| {
+        let name = std::env::var(var).unwrap_or_else(|_| default.clone());
+        backend(channel, &name)
+    };
+    Ok(Arc::new(ChannelNotifier::new(
+        named(Channel::Email, NOTIFIER_EMAIL)?,
+        named(Channel::Sms, NOTIFIER_SMS)?,
+    )))
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used)]
+mod tests {
+    use super::*;
+    use uuid::Uuid;
+
+    #[actix_web::test]
+    async fn test_file_notifier_appends_json_lines() {
+        let path = std::env::temp_dir().join(format!("rc-notifications-{}.jsonl", Uuid::now_v7()));
+        let notifier = FileNotifier::new(&path);
+        let email = Notification {
+            channel: Channel::Email,
+            to: "john@example.com".to_string(),
+            subject: Some("Welcome".to_string()),
+            body: "Hello".to_string(),
+        };
+        let sms = Notification {
+            channel: Channel::Sms,
+            to: "+91-9876543210".to_string(),
+            subject: None,
+            body: "Hello".to_string(),
+        };
+
+        notifier.send(&email).await.unwrap();
+        notifier.send(&sms).await.unwrap();
+
+        let written: Vec<Notification> = std::fs::read_to_string(&path)
+            .unwrap()
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        assert_eq!(written, vec![email, sms]);
+    }
+}
@@ -0,0 +1,290 @@
+//! One-time codes confirming self-registrations, see [`definitions_core::registration`].
+//!
+//! A six digit code is sent to the email or mobile of the owner of a registered entity. Only a
+//! hash of the code is stored, it expires after `REGISTRATION_CODE_TTL_SECS` (ten minutes by
+//! default) and is rejected after [`MAX_ATTEMPTS`] wrong guesses, when a new code must be sent.
+//! The message is the `registration_code` template of [`NotificationTemplates`].
+use crate::services::api_keys::same_hash;
+use crate::services::notification_templates::{
+    NotificationTemplates, TemplateError, REGISTRATION_CODE,
+};
+use crate::services::notifier::{Channel, Notifier, NotifierError};
+use base64::engine::general_purpose::URL_SAFE_NO_PAD as BASE64URL;
+use base64::Engine;
+use chrono::{DateTime, Duration, Utc};
+use definitions_core::encryption::generate_key;
+use definitions_core::registration::VerificationContact;
+use definitions_core::registry_domain::EntityId;
+use serde::Serialize;
+use serde_json::json;
+use sha2::{Digest, Sha256};
+use sqlx::{FromRow, PgPool};
+use std::env;
+use std::sync::Arc;
+use thiserror::Error;
+use utoipa::ToSchema;
+
+pub const MAX_ATTEMPTS: i32 = 5;
+const DEFAULT_TTL_SECS: i64 = 600;
+/// A new code is sent at most once per interval
+const RESEND_INTERVAL_SECS: i64 = 60;
+
+#[derive(Debug, Error)]
+pub enum RegistrationError {
+    #[error("No pending registration for entity {0}")]
+    NotFound(EntityId),
+    #[error("The code of registration {0} has expired, request a new one")]
+    Expired(EntityId),
+    #[error("Too many wrong codes for registration {0}, request a new one")]
+    TooManyAttempts(EntityId),
+    #[error("A code was sent for registration {0} less than a minute ago")]
+    ResendTooSoon(EntityId),
+    #[error("The code is not valid")]
+    InvalidCode,
+    #[error(transparent)]
+    Notification(#[from] NotifierError),
+    #[error(transparent)]
+    Template(#[from] TemplateError),
+    #[error(transparent)]
+    Database(#[from] sqlx::Error),
+}
+
+/// Where a code was sent, without the code
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct SentCode {
+    pub channel: Channel,
+    /// The masked email or mobile the code was sent to
+    pub sent_to: String,
+    pub expires_at: DateTime<Utc>,
+}
+
+#[derive(FromRow)]
+struct StoredCode {
+    entity_type: String,
+    channel: String,
+    destination: String,
+    issued_at: DateTime<Utc>,
+}
+
+/// A random six digit code
+fn generate_code() -> String {
+    let [a, b, c, d, ..] = generate_key();
+    format!("{:06}", u32::from_le_bytes([a, b, c, d]) % 1_000_000)
+}
+
+fn hash_code(entity_id: EntityId, code: &str) -> String {
+    BASE64URL.encode(Sha256::digest(format!("{}:{}", entity_id, code.trim())))
+}
+
+/// Hides most of an email or phone number, e.g. `j***@example.com` or `*******3210`
+fn mask(destination: &str) -> String {
+    match destination.split_once('@') {
+        Some((local, domain)) => {
+            let first: String = local.chars().take(1).collect();
+            format!("{}***@{}", first, domain)
+        }
+        None => {
+            let count = destination.chars().count();
+            destination
+                .chars()
+                .enumerate()
+                .map(|(i, c)| if i + 4 < count { '*' } else { c })
+                .collect()
+        }
+    }
+}
+
+fn code_ttl() -> Duration {
+    Duration::seconds(
+        env::var("REGISTRATION_CODE_TTL_SECS")
+            .ok()
+            .and_then(|secs| secs.parse().ok())
+            .unwrap_or(DEFAULT_TTL_SECS),
+    )
+}
+
+/// Pending registration codes, stored in the registry database
+#[derive(Clone)]
+pub struct RegistrationCodes {
+    pool: PgPool,
+    templates: Arc<NotificationTemplates>,
+}
+
+impl RegistrationCodes {
+    pub async fn new(
+        pool: PgPool,
+        templates: Arc<NotificationTemplates>,
+    ) -> Result<Self, sqlx::Error> {
+        sqlx::query(
+            r#"
+            CREATE TABLE IF NOT EXISTS registration_codes (
+                entity_id UUID PRIMARY KEY,
+                entity_type TEXT NOT NULL,
+                channel TEXT NOT NULL,
+                destination TEXT NOT NULL,
+                code_hash TEXT NOT NULL,
+                expires_at TIMESTAMPTZ NOT NULL,
+                attempts INTEGER NOT NULL DEFAULT 0,
+                issued_at TIMESTAMPTZ NOT NULL
+            );
+            "#,
+        )
+        .execute(&pool)
+        .await?;
+        Ok(Self { pool, templates })
+    }
+
+    /// Sends a new code for a registration to its contact
+    pub async fn send(
+        &self,
+        notifier: &dyn Notifier,
+        entity_id: EntityId,
+        entity_type: &str,
+        contact: &VerificationContact,
+    ) -> Result<SentCode, RegistrationError> {
+        let (channel, destination) = match contact {
+            VerificationContact::Email(email) => (Channel::Email, email),
+            VerificationContact::Mobile(mobile) => (Channel::Sms, mobile),
+        };
+        let code = generate_code();
+        let now = Utc::now();
+        let ttl = code_ttl();
+        let expires_at = now + ttl;
+        let notification = self.templates.render(
+            REGISTRATION_CODE,
+            channel,
+            destination,
+            &json!({
+                "entityType": entity_type,
+                "entityId": entity_id,
+                "code": code,
+                "expiresInMinutes": ttl.num_minutes(),
+            }),
+        )?;
+        sqlx::query(
+            r#"
+            INSERT INTO registration_codes (entity_id, entity_type, channel, destination, code_hash, expires_at, attempts, issued_at)
+            VALUES ($1, $2, $3, $4, $5, $6, 0, $7)
+            ON CONFLICT (entity_id) DO UPDATE
+            SET code_hash = EXCLUDED.code_hash, expires_at = EXCLUDED.expires_at, attempts = 0, issued_at = EXCLUDED.issued_at
+            "#,
+        )
+        .bind(entity_id)
+        .bind(entity_type)
+        .bind(channel.to_string())
+        .bind(destination)
+        .bind(hash_code(entity_id, &code))
+        .bind(expires_at)
+        .bind(now)
+        .execute(&self.pool)
+        .await?;
+        notifier.send(&notification).await?;
+        Ok(SentCode {
+            channel,
+            sent_to: mask(destination),
+            expires_at,
+        })
+    }
+
+    /// Sends a new code to the contact of a pending registration
+    pub async fn resend(
+        &self,
+        notifier: &dyn Notifier,
+        entity_id: EntityId,
+        entity_type: &str,
+    ) -> Result<SentCode, RegistrationError> {
+        let stored = self.stored(entity_id, entity_type).await?;
+        if stored.issued_at > Utc::now() - Duration::seconds(RESEND_INTERVAL_SECS) {
+            return Err(RegistrationError::ResendTooSoon(entity_id));
+        }
+        let contact = if stored.channel == Channel::Email.to_string() {
+            VerificationContact::Email(stored.destination)
+        } else {
+            VerificationContact::Mobile(stored.destination)
+        };
+        self.send(notifier, entity_id, entity_type, &contact).await
+    }
+
+    /// Checks a code, counting every guess.
+    ///
+    /// The attempt is counted in the same statement that checks the limit, so concurrent guesses
+    /// cannot exceed `MAX_ATTEMPTS` between them.
+    pub async fn verify(
+        &self,
+        entity_id: EntityId,
+        entity_type: &str,
+        code: &str,
+    ) -> Result<(), RegistrationError> {
+        let counted = sqlx::query_as::<_, (String, DateTime<Utc>)>(
+            r#"
+            UPDATE registration_codes SET attempts = attempts + 1
+            WHERE entity_id = $1 AND entity_type = $2 AND attempts < $3
+            RETURNING code_hash, expires_at
+            "#,
+        )
+        .bind(entity_id)
+        .bind(entity_type)
+        .bind(MAX_ATTEMPTS)
+        .fetch_optional(&self.pool)
+        .await?;
+        let Some((code_hash, expires_at)) = counted else {
+            // Either there is no code or its attempts are used up
+            self.stored(entity_id, entity_type).await?;
+            return Err(RegistrationError::TooManyAttempts(entity_id));
+        };
+        if expires_at <= Utc::now() {
+            return Err(RegistrationError::Expired(entity_id));
+        }
+        if !same_hash(&code_hash, &hash_code(entity_id, code)) {
+            return Err(RegistrationError::InvalidCode);
+        }
+        Ok(())
+    }
+
+    /// Forgets the code of a confirmed registration
+    pub async fn remove(&self, entity_id: EntityId) -> Result<(), RegistrationError> {
+        sqlx::query("DELETE FROM registration_codes WHERE entity_id = $1")
+            .bind(entity_id)
+            .execute(&self.pool)
+            .await?;
+        Ok(())
+    }
+
+    async fn stored(
+        &self,
+        entity_id: EntityId,
+        entity_type: &str,
+    ) -> Result<StoredCode, RegistrationError> {
+        sqlx::query_as::<_, StoredCode>(
+            "SELECT entity_type, channel, destination, issued_at FROM registration_codes WHERE entity_id = $1",
+        )
+        .bind(entity_id)
+        .fetch_optional(&self.pool)
+        .await?
+        .filter(|stored| stored.entity_type == entity_type)
+        .ok_or(RegistrationError::NotFound(entity_id))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use uuid::Uuid;
+
+    #[test]
+    fn test_codes_are_six_digits_hashed_per_entity() {
+        let code = generate_code();
+        assert_eq!(code.len(), 6);
+        assert!(code.chars().all(|c| c.is_ascii_digit()));
+
+        let id = Uuid::from_u128(1);
+        assert_eq!(hash_code(id, &code), hash_code(id, &format!(" {} ", code)));
+        assert_ne!(hash_code(id, &code), hash_code(Uuid::from_u128(2), &code));
+    }
+
+    #[test]
+    fn test_destinations_are_masked() {
+        assert_eq!(mask("john@example.com"), "j***@example.com");
+        assert_eq!(mask("+91-9876543210"), "**********3210");
+    }
+}
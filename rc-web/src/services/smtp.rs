@@ -0,0 +1,366 @@
+//! Delivery of email notifications to an SMTP server.
+//!
+//! The server is configured with `SMTP_HOST`, `SMTP_PORT`, `SMTP_FROM` and, when it requires
+//! authentication, `SMTP_USERNAME` and `SMTP_PASSWORD`. `SMTP_TLS` is `starttls` (the default,
+//! port 587), `tls` for implicit TLS (port 465) or `none` for a trusted local relay (port 25).
+//! Credentials are only sent over TLS. Each message opens its own connection.
+use crate::services::notifier::{Channel, Notification, Notifier, NotifierError};
+use async_trait::async_trait;
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+use chrono::Utc;
+use std::time::Duration;
+use tokio::io::{AsyncBufReadExt, AsyncRead, AsyncWrite, AsyncWriteExt, BufReader};
+use tokio::net::TcpStream;
+use tokio_native_tls::{native_tls, TlsConnector};
+use uuid::Uuid;
+
+const TIMEOUT: Duration = Duration::from_secs(30);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SmtpTls {
+    /// Upgrades the connection with `STARTTLS`
+    StartTls,
+    /// Connects with TLS
+    Implicit,
+    /// Plain text, for a local relay
+    None,
+}
+
+#[derive(Debug, Clone)]
+pub struct SmtpConfig {
+    pub host: String,
+    pub port: u16,
+    pub tls: SmtpTls,
+    /// Username and password for `AUTH PLAIN`
+    pub credentials: Option<(String, String)>,
+    /// Sender address of the messages
+    pub from: String,
+    /// Name announced with `EHLO`
+    pub helo: String,
+}
+
+impl SmtpConfig {
+    pub fn from_env() -> Result<Self, NotifierError> {
+        let var = |name: &str| std::env::var(name).ok().filter(|value| !value.is_empty());
+        let missing = |name: &str| {
+            NotifierError::Configuration(format!("{} must be set for the SMTP notifier", name))
+        };
+        let tls = match var("SMTP_TLS").as_deref() {
+            None | Some("starttls") => SmtpTls::StartTls,
+            Some("tls") => SmtpTls::Implicit,
+            Some("none") => SmtpTls::None,
+            Some(other) => {
+                return Err(NotifierError::Configuration(format!(
+                    "SMTP_TLS must be starttls, tls or none, not `{}`",
+                    other
+                )))
+            }
+        };
+        let port = match var("SMTP_PORT") {
+            Some(port) => port.parse().map_err(|_| {
+                NotifierError::Configuration(format!("Invalid SMTP_PORT `{}`", port))
+            })?,
+            None => match tls {
+                SmtpTls::StartTls => 587,
+                SmtpTls::Implicit => 465,
+                SmtpTls::None => 25,
+            },
+        };
+        let credentials = match (var("SMTP_USERNAME"), var("SMTP_PASSWORD")) {
+            (Some(username), Some(password)) => Some((username, password)),
+            (None, None) => None,
+            _ => {
+                return Err(NotifierError::Configuration(
+                    "SMTP_USERNAME and SMTP_PASSWORD must be set together".to_string(),
+                ))
+            }
+        };
+        if credentials.is_some() && tls == SmtpTls::None {
+            return Err(NotifierError::Configuration(
+                "SMTP credentials require SMTP_TLS starttls or tls".to_string(),
+            ));
+        }
+        Ok(Self {
+            host: var("SMTP_HOST").ok_or_else(|| missing("SMTP_HOST"))?,
+            port,
+            tls,
+            credentials,
+            from: var("SMTP_FROM").ok_or_else(|| missing("SMTP_FROM"))?,
+            helo: var("SMTP_HELO").unwrap_or_else(|| "localhost".to_string()),
+        })
+    }
+}
+
+trait Stream: AsyncRead + AsyncWrite + Unpin + Send {}
+impl<S: AsyncRead + AsyncWrite + Unpin + Send> Stream for S {}
+
+/// An SMTP session, failing with the description of the first unexpected reply
+struct Session {
+    stream: BufReader<Box<dyn Stream>>,
+}
+
+impl Session {
+    /// Reads a possibly multi-line reply and checks its code
+    async fn expect(&mut self, code: u16) -> Result<String, String> {
+        let mut reply = String::new();
+        loop {
+            let mut line = String::new();
+            if self
+                .stream
+                .read_line(&mut line)
+                .await
+                .map_err(|e| e.to_string())?
+                == 0
+            {
+                return Err("connection closed by the server".to_string());
+            }
+            reply.push_str(&line);
+            // `250-...` continues a reply, `250 ...` ends it
+            if line.as_bytes().get(3) != Some(&b'-') {
+                break;
+            }
+        }
+        match reply.get(..3).and_then(|found| found.parse::<u16>().ok()) {
+            Some(found) if found == code => Ok(reply),
+            _ => Err(format!("expected {}, got `{}`", code, reply.trim_end())),
+        }
+    }
+
+    async fn command(&mut self, command: &str, code: u16) -> Result<String, String> {
+        self.stream
+            .get_mut()
+            .write_all(format!("{}\r\n", command).as_bytes())
+            .await
+            .map_err(|e| e.to_string())?;
+        self.expect(code).await
+    }
+}
+
+fn tls_connector() -> Result<TlsConnector, String> {
+    native_tls::TlsConnector::new()
+        .map(TlsConnector::from)
+        .map_err(|e| e.to_string())
+}
+
+/// Encodes a header value with non-ASCII characters as an RFC 2047 encoded word
+fn header_value(value: &str) -> String {
+    let value = value.replace(['\r', '\n'], " ");
+    if value.is_ascii() {
+        value
+    } else {
+        format!("=?UTF-8?B?{}?=", BASE64.encode(value))
+    }
+}
+
+/// The message with its headers, the body in base64 so that no line needs escaping
+fn message(from: &str, to: &str, subject: &str, body: &str, host: &str) -> String {
+    let encoded = BASE64.encode(body.replace("\r\n", "\n").replace('\n', "\r\n"));
+    let lines: Vec<&str> = encoded
+        .as_bytes()
+        .chunks(76)
+        // Base64 is ASCII
+        .map(|chunk| std::str::from_utf8(chunk).unwrap_or_default())
+        .collect();
+    format!(
+        "From: {}\r\nTo: {}\r\nSubject: {}\r\nDate: {}\r\nMessage-ID: <{}@{}>\r\nMIME-Version: 1.0\r\nContent-Type: text/plain; charset=utf-8\r\nContent-Transfer-Encoding: base64\r\n\r\n{}\r\n.",
+        from,
+        to,
+        header_value(subject),
+        Utc::now().to_rfc2822(),
+        Uuid::now_v7().simple(),
+        host,
+        lines.join("\r\n")
+    )
+}
+
+/// Sends email notifications through an SMTP server
+#[derive(Debug, Clone)]
+pub struct SmtpNotifier {
+    config: SmtpConfig,
+}
+
+impl SmtpNotifier {
+    pub fn new(config: SmtpConfig) -> Self {
+        Self { config }
+    }
+
+    async fn connect(&self) -> Result<Session, String> {
+        let config = &self.config;
+        let tcp = TcpStream::connect((config.host.as_str(), config.port))
+            .await
+            .map_err(|e| e.to_string())?;
+        let stream: Box<dyn Stream> = match config.tls {
+            SmtpTls::Implicit => Box::new(
+                tls_connector()?
+                    .connect(&config.host, tcp)
+                    .await
+                    .map_err(|e| e.to_string())?,
+            ),
+            SmtpTls::StartTls | SmtpTls::None => Box::new(tcp),
+        };
+        let mut session = Session {
+            stream: BufReader::new(stream),
+        };
+        session.expect(220).await?;
+        let ehlo = format!("EHLO {}", config.helo);
+        let capabilities = session.command(&ehlo, 250).await?;
+        if config.tls == SmtpTls::StartTls {
+            if !capabilities.to_ascii_uppercase().contains("STARTTLS") {
+                return Err("the server does not offer STARTTLS".to_string());
+            }
+            session.command("STARTTLS", 220).await?;
+            let tls = tls_connector()?
+                .connect(&config.host, session.stream.into_inner())
+                .await
+                .map_err(|e| e.to_string())?;
+            session = Session {
+                stream: BufReader::new(Box::new(tls)),
+            };
+            session.command(&ehlo, 250).await?;
+        }
+        if let Some((username, password)) = &config.credentials {
+            let token = BASE64.encode(format!("\0{}\0{}", username, password));
+            session
+                .command(&format!("AUTH PLAIN {}", token), 235)
+                .await?;
+        }
+        Ok(session)
+    }
+
+    async fn deliver(&self, to: &str, subject: &str, body: &str) -> Result<(), String> {
+        if to.contains(['<', '>', '\r', '\n']) || !to.contains('@') {
+            return Err("invalid email address".to_string());
+        }
+        let mut session = self.connect().await?;
+        session
+            .command(&format!("MAIL FROM:<{}>", self.config.from), 250)
+            .await?;
+        session.command(&format!("RCPT TO:<{}>", to), 250).await?;
+        session.command("DATA", 354).await?;
+        let message = message(&self.config.from, to, subject, body, &self.config.helo);
+        session.command(&message, 250).await?;
+        // The message is accepted, a failed goodbye does not matter
+        let _ = session.command("QUIT", 221).await;
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl Notifier for SmtpNotifier {
+    async fn send(&self, notification: &Notification) -> Result<(), NotifierError> {
+        if notification.channel != Channel::Email {
+            return Err(NotifierError::Delivery(
+                notification.to.clone(),
+                format!("SMTP cannot send {} messages", notification.channel),
+            ));
+        }
+        let subject = notification.subject.as_deref().unwrap_or_default();
+        tokio::time::timeout(
+            TIMEOUT,
+            self.deliver(&notification.to, subject, &notification.body),
+        )
+        .await
+        .unwrap_or_else(|_| Err("timed out".to_string()))
+        .map_err(|e| NotifierError::Delivery(notification.to.clone(), e))
+    }
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used)]
+mod tests {
+    use super::*;
+    use tokio::net::TcpListener;
+
+    /// Accepts one session and returns the lines the client sent
+    async fn fake_server(listener: TcpListener) -> Vec<String> {
+        let (socket, _) = listener.accept().await.unwrap();
+        let mut socket = BufReader::new(socket);
+        let mut received = Vec::new();
+        socket
+            .get_mut()
+            .write_all(b"220 fake ESMTP\r\n")
+            .await
+            .unwrap();
+        let mut in_data = false;
+        loop {
+            let mut line = String::new();
+            if socket.read_line(&mut line).await.unwrap() == 0 {
+                break;
+            }
+            let line = line.trim_end().to_string();
+            let reply: &[u8] = if in_data {
+                if line == "." {
+                    in_data = false;
+                    b"250 queued\r\n"
+                } else {
+                    b""
+                }
+            } else if line.starts_with("EHLO") {
+                b"250-fake\r\n250 8BITMIME\r\n"
+            } else if line == "DATA" {
+                in_data = true;
+                b"354 go ahead\r\n"
+            } else if line == "QUIT" {
+                b"221 bye\r\n"
+            } else {
+                b"250 ok\r\n"
+            };
+            received.push(line.clone());
+            socket.get_mut().write_all(reply).await.unwrap();
+            if line == "QUIT" {
+                break;
+            }
+        }
+        received
+    }
+
+    #[actix_web::test]
+    async fn test_smtp_notifier_sends_a_message() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let server = tokio::spawn(fake_server(listener));
+        let notifier = SmtpNotifier::new(SmtpConfig {
+            host: "127.0.0.1".to_string(),
+            port,
+            tls: SmtpTls::None,
+            credentials: None,
+            from: "registry@example.com".to_string(),
+            helo: "registry.example.com".to_string(),
+        });
+
+        notifier
+            .send(&Notification {
+                channel: Channel::Email,
+                to: "asha@example.com".to_string(),
+                subject: Some("Consent requested".to_string()),
+                body: "Hello Asha\n.".to_string(),
+            })
+            .await
+            .unwrap();
+
+        let received = server.await.unwrap();
+        assert_eq!(received[0], "EHLO registry.example.com");
+        assert_eq!(received[1], "MAIL FROM:<registry@example.com>");
+        assert_eq!(received[2], "RCPT TO:<asha@example.com>");
+        assert!(received.contains(&"Subject: Consent requested".to_string()));
+        assert!(received.contains(&BASE64.encode("Hello Asha\r\n.")));
+        assert_eq!(received.last().map(String::as_str), Some("QUIT"));
+
+        let sms = notifier
+            .send(&Notification {
+                channel: Channel::Sms,
+                to: "+1".to_string(),
+                subject: None,
+                body: String::new(),
+            })
+            .await;
+        assert!(sms.is_err());
+    }
+
+    #[test]
+    fn test_headers_cannot_be_injected() {
+        assert_eq!(header_value("Hi\r\nBcc: x"), "Hi  Bcc: x");
+        assert_eq!(header_value("Café"), "=?UTF-8?B?Q2Fmw6k=?=");
+    }
+}
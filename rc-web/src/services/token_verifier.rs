@@ -0,0 +1,727 @@
+//! Verification of the bearer tokens of API callers.
+//!
+//! A [`TokenVerifier`] trusts a list of issuers, each with its own source of keys:
+//! - [`KeySource::Discovery`]: the `jwks_uri` of the OpenID provider configuration of the issuer,
+//!   e.g. a Keycloak realm or an Okta authorization server
+//! - [`KeySource::JwksUri`]: a JWKS endpoint, e.g. `https://{domain}/.well-known/jwks.json` of Auth0
+//! - [`KeySource::Jwks`]: a static JWKS, e.g. read from a file
+//! - [`KeySource::Secret`]: an HMAC secret, for tokens minted locally by tests
+//!
+//! A token is verified with the keys of the issuer named by its `iss` claim. JWKS keys sign with
+//! `RS*`, `PS*`, `ES256`, `ES384` or `EdDSA`, secrets with `HS*`. The verified claims are mapped
+//! into [`Claims`] by a [`ClaimMapping`]. Fetched JWKS are kept in a shared [`JwksCache`].
+//!
+//! [`TokenVerifier::from_env`] reads the trusted issuers from:
+//! - `OIDC_ISSUERS`: comma separated issuer URLs, their keys found with OIDC discovery
+//! - `OIDC_AUDIENCES`: comma separated audiences accepted from these issuers
+//! - `OIDC_JWKS_FILE` or `OIDC_HMAC_SECRET`: keys of the tokens issued by `OIDC_LOCAL_ISSUER`,
+//!   [`base_url`] by default, not both
+//! - `OIDC_ROLES_CLAIM`: path of the roles claim, e.g. `realm_access.roles` for Keycloak
+//! - `AUTH0_DOMAIN` and `AUTH0_AUDIENCE`: an Auth0 tenant, as before OIDC issuers were supported
+//! - `OIDC_SKIP_AUDIENCE`: `true` to accept tokens of any audience from the issuers configured
+//!   without one, which are refused otherwise
+//! - `JWKS_CACHE_TTL_SECS` and `JWKS_MAX_STALE_SECS`: see [`JwksCacheConfig::from_env`]
+use crate::base_url;
+use crate::middleware::claims::Claims;
+use crate::services::jwks_cache::{JwksCache, JwksCacheConfig};
+use base64::engine::general_purpose::URL_SAFE_NO_PAD as BASE64URL;
+use base64::Engine;
+use jsonwebtoken::jwk::JwkSet;
+use jsonwebtoken::{decode, decode_header, Algorithm, DecodingKey, Header, Validation};
+use log::warn;
+use serde_json::Value;
+use std::collections::{HashMap, HashSet};
+use std::env;
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
+use thiserror::Error;
+
+pub const OIDC_ISSUERS: &str = "OIDC_ISSUERS";
+pub const OIDC_AUDIENCES: &str = "OIDC_AUDIENCES";
+pub const OIDC_JWKS_FILE: &str = "OIDC_JWKS_FILE";
+pub const OIDC_HMAC_SECRET: &str = "OIDC_HMAC_SECRET";
+pub const OIDC_LOCAL_ISSUER: &str = "OIDC_LOCAL_ISSUER";
+pub const OIDC_ROLES_CLAIM: &str = "OIDC_ROLES_CLAIM";
+pub const AUTH0_DOMAIN: &str = "AUTH0_DOMAIN";
+pub const AUTH0_AUDIENCE: &str = "AUTH0_AUDIENCE";
+pub const OIDC_SKIP_AUDIENCE: &str = "OIDC_SKIP_AUDIENCE";
+const DISCOVERY_PATH: &str = "/.well-known/openid-configuration";
+const FETCH_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Algorithms of the keys of a JWKS
+const JWKS_ALGORITHMS: [Algorithm; 9] = [
+    Algorithm::RS256,
+    Algorithm::RS384,
+    Algorithm::RS512,
+    Algorithm::PS256,
+    Algorithm::PS384,
+    Algorithm::PS512,
+    Algorithm::ES256,
+    Algorithm::ES384,
+    Algorithm::EdDSA,
+];
+const SECRET_ALGORITHMS: [Algorithm; 3] = [Algorithm::HS256, Algorithm::HS384, Algorithm::HS512];
+
+#[derive(Debug, Error)]
+pub enum TokenError {
+    #[error("Malformed token: {0}")]
+    Malformed(String),
+    #[error("Untrusted issuer `{0}`")]
+    UntrustedIssuer(String),
+    #[error("Algorithm {0:?} is not accepted from {1}")]
+    UnsupportedAlgorithm(Algorithm, String),
+    #[error("No key `{0}` in the JWKS of {1}")]
+    UnknownKey(String, String),
+    #[error("Failed to fetch the keys of {0}: {1}")]
+    KeyFetch(String, String),
+    #[error("Invalid token: {0}")]
+    Invalid(#[from] jsonwebtoken::errors::Error),
+    #[error("No audience is configured for {0}")]
+    NoAudience(String),
+    #[error("Invalid token verifier configuration: {0}")]
+    Configuration(String),
+}
+
+/// Where the keys of an issuer come from
+#[derive(Debug, Clone)]
+pub enum KeySource {
+    Discovery,
+    JwksUri(String),
+    Jwks(JwkSet),
+    Secret(Vec<u8>),
+}
+
+/// An issuer whose tokens are accepted
+#[derive(Debug, Clone)]
+pub struct TrustedIssuer {
+    /// The `iss` claim of its tokens
+    pub issuer: String,
+    pub keys: KeySource,
+    /// Accepted `aud` claims, tokens are rejected when empty unless `any_audience` is set
+    pub audiences: Vec<String>,
+    /// Skips the `aud` check of the issuer without audiences, see [`OIDC_SKIP_AUDIENCE`]
+    pub any_audience: bool,
+}
+
+impl TrustedIssuer {
+    pub fn new(issuer: &str, keys: KeySource) -> Self {
+        Self {
+            issuer: issuer.to_string(),
+            keys,
+            audiences: vec![],
+            any_audience: false,
+        }
+    }
+
+    pub fn with_audiences(mut self, audiences: Vec<String>) -> Self {
+        self.audiences = audiences;
+        self
+    }
+
+    /// Accepts tokens of any audience when no audience is configured
+    pub fn with_any_audience(mut self) -> Self {
+        self.any_audience = true;
+        self
+    }
+
+    /// Issuers are compared without their trailing slash: Auth0 adds one, Keycloak does not
+    fn matches(&self, issuer: &str) -> bool {
+        self.issuer.trim_end_matches('/') == issuer.trim_end_matches('/')
+    }
+}
+
+/// Maps the claims of a verified token into [`Claims`]
+#[derive(Debug, Clone)]
+pub struct ClaimMapping {
+    /// Dotted path of the roles, e.g. `realm_access.roles`
+    pub roles: String,
+    /// Dotted path of the permissions, merged with the `scope` and `scp` claims
+    pub permissions: String,
+}
+
+impl Default for ClaimMapping {
+    fn default() -> Self {
+        Self {
+            roles: "roles".to_string(),
+            permissions: "permissions".to_string(),
+        }
+    }
+}
+
+impl ClaimMapping {
+    pub fn claims(&self, payload: &Value) -> Claims {
+        let text = |name: &str| payload.get(name).and_then(Value::as_str).map(String::from);
+        let permissions = [
+            claim_values(payload, &self.permissions),
+            claim_values(payload, "scope"),
+            claim_values(payload, "scp"),
+        ]
+        .into_iter()
+        .flatten()
+        .reduce(|mut permissions, more| {
+            permissions.extend(more);
+            permissions
+        });
+        Claims {
+            sub: text("sub"),
+            email: text("email"),
+            iss: text("iss"),
+            roles: claim_values(payload, &self.roles),
+            permissions,
+            client_id: text("azp").or_else(|| text("client_id")),
+            act: actor_chain(payload),
+        }
+    }
+}
+
+/// Subjects of the nested `act` claims of RFC 8693, the current actor first
+fn actor_chain(payload: &Value) -> Vec<String> {
+    std::iter::successors(payload.get("act"), |act| act.get("act"))
+        .filter_map(|act| act.get("sub").and_then(Value::as_str).map(String::from))
+        .collect()
+}
+
+/// Strings of the claim at a dotted path: an array, or a space separated string as `scope`
+fn claim_values(payload: &Value, path: &str) -> Option<HashSet<String>> {
+    let claim = path
+        .split('.')
+        .try_fold(payload, |node, name| node.get(name))?;
+    match claim {
+        Value::Array(values) => Some(
+            values
+                .iter()
+                .filter_map(Value::as_str)
+                .map(String::from)
+                .collect(),
+        ),
+        Value::String(values) => Some(values.split_whitespace().map(String::from).collect()),
+        _ => None,
+    }
+}
+
+/// The `iss` claim of a token, read before its signature is checked to pick the keys
+fn unverified_issuer(token: &str) -> Result<String, TokenError> {
+    let payload = token
+        .split('.')
+        .nth(1)
+        .ok_or_else(|| TokenError::Malformed("expected a compact JWT".to_string()))?;
+    let payload: Value = BASE64URL
+        .decode(payload)
+        .ok()
+        .and_then(|bytes| serde_json::from_slice(&bytes).ok())
+        .ok_or_else(|| TokenError::Malformed("the payload is not base64url JSON".to_string()))?;
+    payload
+        .get("iss")
+        .and_then(Value::as_str)
+        .map(String::from)
+        .ok_or_else(|| TokenError::Malformed("the token has no `iss` claim".to_string()))
+}
+
+/// Rejects an issuer trusted twice, e.g. with both `OIDC_JWKS_FILE` and `OIDC_HMAC_SECRET`: only
+/// the keys of the first one would ever be used
+fn distinct_issuers(issuers: &[TrustedIssuer]) -> Result<(), TokenError> {
+    for (i, trusted) in issuers.iter().enumerate() {
+        if issuers[..i]
+            .iter()
+            .any(|other| other.matches(&trusted.issuer))
+        {
+            return Err(TokenError::Configuration(format!(
+                "`{}` is trusted more than once, set only one of {} and {} and keep {} out of {}",
+                trusted.issuer, OIDC_JWKS_FILE, OIDC_HMAC_SECRET, OIDC_LOCAL_ISSUER, OIDC_ISSUERS
+            )));
+        }
+    }
+    Ok(())
+}
+
+/// Rejects an issuer without audiences, whose tokens minted for any other service would be
+/// accepted, unless `skip_audience` explicitly allows it
+fn require_audiences(
+    issuers: Vec<TrustedIssuer>,
+    skip_audience: bool,
+) -> Result<Vec<TrustedIssuer>, TokenError> {
+    issuers
+        .into_iter()
+        .map(
+            |trusted| match (trusted.audiences.is_empty(), skip_audience) {
+                (false, _) => Ok(trusted),
+                (true, true) => {
+                    warn!(
+                        "Tokens of any audience are accepted from {}",
+                        trusted.issuer
+                    );
+                    Ok(trusted.with_any_audience())
+                }
+                (true, false) => Err(TokenError::Configuration(format!(
+                    "no audience is configured for `{}`, set {} or {}, or {}=true to accept any",
+                    trusted.issuer, OIDC_AUDIENCES, AUTH0_AUDIENCE, OIDC_SKIP_AUDIENCE
+                ))),
+            },
+        )
+        .collect()
+}
+
+fn comma_separated(name: &str) -> Vec<String> {
+    env::var(name)
+        .unwrap_or_default()
+        .split(',')
+        .map(str::trim)
+        .filter(|value| !value.is_empty())
+        .map(String::from)
+        .collect()
+}
+
+pub struct TokenVerifier {
+    issuers: Vec<TrustedIssuer>,
+    mapping: ClaimMapping,
+    client: reqwest::Client,
+    /// `jwks_uri` of the discovered issuers
+    discovered: RwLock<HashMap<String, String>>,
+    jwks: Arc<JwksCache>,
+}
+
+impl TokenVerifier {
+    pub fn new(issuers: Vec<TrustedIssuer>) -> Self {
+        Self {
+            issuers,
+            mapping: ClaimMapping::default(),
+            client: reqwest::Client::builder()
+                .timeout(FETCH_TIMEOUT)
+                .build()
+                .unwrap_or_default(),
+            discovered: RwLock::new(HashMap::new()),
+            jwks: Arc::new(JwksCache::default()),
+        }
+    }
+
+    pub fn with_jwks_cache(mut self, jwks: Arc<JwksCache>) -> Self {
+        self.jwks = jwks;
+        self
+    }
+
+    /// The cache of the fetched JWKS, to refresh it in the background
+    pub fn jwks_cache(&self) -> Arc<JwksCache> {
+        Arc::clone(&self.jwks)
+    }
+
+    pub fn with_mapping(mut self, mapping: ClaimMapping) -> Self {
+        self.mapping = mapping;
+        self
+    }
+
+    /// The verifier configured by the `OIDC_*` and `AUTH0_*` variables, see the module docs
+    pub fn from_env() -> Result<Self, TokenError> {
+        let audiences = comma_separated(OIDC_AUDIENCES);
+        let mut issuers: Vec<TrustedIssuer> = comma_separated(OIDC_ISSUERS)
+            .iter()
+            .map(|issuer| {
+                TrustedIssuer::new(issuer, KeySource::Discovery).with_audiences(audiences.clone())
+            })
+            .collect();
+
+        let local_issuer = env::var(OIDC_LOCAL_ISSUER).unwrap_or_else(|_| base_url().to_string());
+        if let Ok(path) = env::var(OIDC_JWKS_FILE) {
+            let jwks = std::fs::read_to_string(&path)
+                .map_err(|e| e.to_string())
+                .and_then(|jwks| serde_json::from_str(&jwks).map_err(|e| e.to_string()))
+                .map_err(|e| TokenError::Configuration(format!("{}: {}", path, e)))?;
+            issuers.push(
+                TrustedIssuer::new(&local_issuer, KeySource::Jwks(jwks))
+                    .with_audiences(audiences.clone()),
+            );
+        }
+        if let Ok(secret) = env::var(OIDC_HMAC_SECRET) {
+            issuers.push(
+                TrustedIssuer::new(&local_issuer, KeySource::Secret(secret.into_bytes()))
+                    .with_audiences(audiences.clone()),
+            );
+        }
+
+        if let Ok(domain) = env::var(AUTH0_DOMAIN) {
+            issuers.push(
+                TrustedIssuer::new(
+                    &format!("https://{}/", domain),
+                    KeySource::JwksUri(format!("https://{}/.well-known/jwks.json", domain)),
+                )
+                .with_audiences(env::var(AUTH0_AUDIENCE).into_iter().collect()),
+            );
+        }
+
+        distinct_issuers(&issuers)?;
+        let skip_audience = env::var(OIDC_SKIP_AUDIENCE).is_ok_and(|skip| skip == "true");
+        let issuers = require_audiences(issuers, skip_audience)?;
+        if issuers.is_empty() {
+            warn!(
+                "No token issuer is trusted, set {} or {} to authenticate callers",
+                OIDC_ISSUERS, OIDC_JWKS_FILE
+            );
+        }
+        let mut mapping = ClaimMapping::default();
+        if let Ok(roles) = env::var(OIDC_ROLES_CLAIM) {
+            mapping.roles = roles;
+        }
+        Ok(Self::new(issuers)
+            .with_mapping(mapping)
+            .with_jwks_cache(Arc::new(JwksCache::new(JwksCacheConfig::from_env()))))
+    }
+
+    /// Checks the signature, issuer, audience and expiry of a bearer token and maps its claims
+    pub async fn verify(&self, token: &str) -> Result<Claims, TokenError> {
+        let header = decode_header(token)?;
+        let issuer = unverified_issuer(token)?;
+        let trusted = self
+            .issuers
+            .iter()
+            .find(|trusted| trusted.matches(&issuer))
+            .ok_or_else(|| TokenError::UntrustedIssuer(issuer.clone()))?;
+        let key = self.decoding_key(trusted, &header).await?;
+
+        let mut validation = Validation::new(header.alg);
+        validation.set_issuer(&[&issuer]);
+        if !trusted.audiences.is_empty() {
+            validation.set_audience(&trusted.audiences);
+        } else if trusted.any_audience {
+            validation.validate_aud = false;
+        } else {
+            return Err(TokenError::NoAudience(trusted.issuer.clone()));
+        }
+        let token_data = decode::<Value>(token, &key, &validation)?;
+        Ok(self.mapping.claims(&token_data.claims))
+    }
+
+    async fn decoding_key(
+        &self,
+        trusted: &TrustedIssuer,
+        header: &Header,
+    ) -> Result<DecodingKey, TokenError> {
+        let accepted: &[Algorithm] = match trusted.keys {
+            KeySource::Secret(_) => &SECRET_ALGORITHMS,
+            _ => &JWKS_ALGORITHMS,
+        };
+        if !accepted.contains(&header.alg) {
+            return Err(TokenError::UnsupportedAlgorithm(
+                header.alg,
+                trusted.issuer.clone(),
+            ));
+        }
+        let jwks = match &trusted.keys {
+            KeySource::Secret(secret) => return Ok(DecodingKey::from_secret(secret)),
+            KeySource::Jwks(jwks) => jwks.clone(),
+            KeySource::JwksUri(jwks_uri) => {
+                self.fetch_jwks(&trusted.issuer, jwks_uri, header).await?
+            }
+            KeySource::Discovery => {
+                let jwks_uri = self.discover(&trusted.issuer).await?;
+                self.fetch_jwks(&trusted.issuer, &jwks_uri, header).await?
+            }
+        };
+        // A token without `kid` is accepted from issuers publishing a single key
+        let jwk = match &header.kid {
+            Some(kid) => jwks.find(kid),
+            None if jwks.keys.len() == 1 => jwks.keys.first(),
+            None => None,
+        }
+        .ok_or_else(|| {
+            TokenError::UnknownKey(
+                header.kid.clone().unwrap_or_default(),
+                trusted.issuer.clone(),
+            )
+        })?;
+        Ok(DecodingKey::from_jwk(jwk)?)
+    }
+
+    /// The `jwks_uri` of the OpenID provider configuration of an issuer
+    async fn discover(&self, issuer: &str) -> Result<String, TokenError> {
+        if let Some(jwks_uri) = self
+            .discovered
+            .read()
+            .ok()
+            .and_then(|discovered| discovered.get(issuer).cloned())
+        {
+            return Ok(jwks_uri);
+        }
+        let url = format!("{}{}", issuer.trim_end_matches('/'), DISCOVERY_PATH);
+        let configuration: Value = self.fetch(issuer, &url).await?;
+        if configuration.get("issuer").and_then(Value::as_str) != Some(issuer) {
+            return Err(TokenError::KeyFetch(
+                issuer.to_string(),
+                format!("the configuration at {} names another issuer", url),
+            ));
+        }
+        let jwks_uri = configuration
+            .get("jwks_uri")
+            .and_then(Value::as_str)
+            .map(String::from)
+            .ok_or_else(|| {
+                TokenError::KeyFetch(
+                    issuer.to_string(),
+                    format!("the configuration at {} has no jwks_uri", url),
+                )
+            })?;
+        if let Ok(mut discovered) = self.discovered.write() {
+            discovered.insert(issuer.to_string(), jwks_uri.clone());
+        }
+        Ok(jwks_uri)
+    }
+
+    async fn fetch_jwks(
+        &self,
+        issuer: &str,
+        jwks_uri: &str,
+        header: &Header,
+    ) -> Result<JwkSet, TokenError> {
+        self.jwks
+            .jwks(jwks_uri, header.kid.as_deref())
+            .await
+            .map_err(|e| TokenError::KeyFetch(issuer.to_string(), e.to_string()))
+    }
+
+    async fn fetch<T: serde::de::DeserializeOwned>(
+        &self,
+        issuer: &str,
+        url: &str,
+    ) -> Result<T, TokenError> {
+        let fetch_error =
+            |e: reqwest::Error| TokenError::KeyFetch(issuer.to_string(), e.to_string());
+        self.client
+            .get(url)
+            .send()
+            .await
+            .and_then(reqwest::Response::error_for_status)
+            .map_err(fetch_error)?
+            .json()
+            .await
+            .map_err(fetch_error)
+    }
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used)]
+mod tests {
+    use super::*;
+    use actix_web::{web, App, HttpResponse, HttpServer};
+    use definitions_core::audit::Actor;
+    use definitions_core::signing::IssuerKey;
+    use jsonwebtoken::{encode, EncodingKey};
+    use serde_json::json;
+
+    const SECRET: &[u8] = b"test-secret";
+
+    fn expiry() -> i64 {
+        chrono::Utc::now().timestamp() + 300
+    }
+
+    fn hmac_token(claims: Value) -> String {
+        encode(
+            &Header::new(Algorithm::HS256),
+            &claims,
+            &EncodingKey::from_secret(SECRET),
+        )
+        .unwrap()
+    }
+
+    fn jwks(key: &IssuerKey) -> JwkSet {
+        serde_json::from_value(json!({ "keys": [key.public_key().to_jwk()] })).unwrap()
+    }
+
+    /// Serves the OpenID configuration and the JWKS of an issuer on a local server, returning
+    /// the issuer URL
+    fn oidc_stand_in(jwks: JwkSet) -> String {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let issuer = format!("http://{}/realms/rc", listener.local_addr().unwrap());
+        let configuration = json!({
+            "issuer": issuer,
+            "jwks_uri": format!("{}/protocol/openid-connect/certs", issuer),
+        });
+        let server = HttpServer::new(move || {
+            let configuration = configuration.clone();
+            let jwks = jwks.clone();
+            App::new()
+                .route(
+                    "/realms/rc/.well-known/openid-configuration",
+                    web::get().to(move || {
+                        let configuration = configuration.clone();
+                        async move { HttpResponse::Ok().json(configuration) }
+                    }),
+                )
+                .route(
+                    "/realms/rc/protocol/openid-connect/certs",
+                    web::get().to(move || {
+                        let jwks = jwks.clone();
+                        async move { HttpResponse::Ok().json(jwks) }
+                    }),
+                )
+        })
+        .workers(1)
+        .listen(listener)
+        .unwrap()
+        .run();
+        tokio::spawn(server);
+        issuer
+    }
+
+    #[actix_web::test]
+    async fn test_hmac_token_maps_standard_claims() {
+        let verifier = TokenVerifier::new(vec![TrustedIssuer::new(
+            "http://localhost:8000",
+            KeySource::Secret(SECRET.to_vec()),
+        )
+        .with_audiences(vec!["rc-web".to_string()])]);
+        let claims = verifier
+            .verify(&hmac_token(json!({
+                "iss": "http://localhost:8000/",
+                "aud": "rc-web",
+                "exp": expiry(),
+                "sub": "user-1",
+                "email": "asha@example.com",
+                "roles": ["Insurer"],
+                "permissions": ["read:entities"],
+                "scope": "openid write:entities"
+            })))
+            .await
+            .unwrap();
+
+        assert_eq!(claims.sub.as_deref(), Some("user-1"));
+        assert_eq!(claims.email.as_deref(), Some("asha@example.com"));
+        let principal = claims.principal();
+        assert_eq!(principal.roles, vec!["Insurer"]);
+        assert_eq!(
+            principal.permissions,
+            vec!["openid", "read:entities", "write:entities"]
+        );
+        assert_eq!(principal.actor(), Actor::new("user-1"));
+
+        let impersonated = verifier
+            .verify(&hmac_token(json!({
+                "iss": "http://localhost:8000",
+                "aud": "rc-web",
+                "exp": expiry(),
+                "sub": "user-1",
+                "azp": "support-console",
+                "act": { "sub": "agent-7", "act": { "sub": "helpdesk" } }
+            })))
+            .await
+            .unwrap();
+        assert_eq!(impersonated.client_id.as_deref(), Some("support-console"));
+        assert_eq!(impersonated.act, vec!["agent-7", "helpdesk"]);
+        assert_eq!(
+            impersonated.principal().actor(),
+            Actor::on_behalf_of("agent-7", "user-1")
+        );
+
+        let wrong_audience = hmac_token(json!({
+            "iss": "http://localhost:8000", "aud": "other", "exp": expiry(), "sub": "user-1"
+        }));
+        assert!(matches!(
+            verifier.verify(&wrong_audience).await,
+            Err(TokenError::Invalid(_))
+        ));
+        let untrusted = hmac_token(json!({
+            "iss": "https://evil.example.com", "exp": expiry(), "sub": "user-1"
+        }));
+        assert!(matches!(
+            verifier.verify(&untrusted).await,
+            Err(TokenError::UntrustedIssuer(_))
+        ));
+    }
+
+    #[actix_web::test]
+    async fn test_static_jwks_rejects_hmac_tokens() {
+        let key = IssuerKey::from_seed("local-1", [3; 32]);
+        let verifier = TokenVerifier::new(vec![TrustedIssuer::new(
+            "http://localhost:8000",
+            KeySource::Jwks(jwks(&key)),
+        )
+        .with_any_audience()]);
+        let claims = json!({ "iss": "http://localhost:8000", "exp": expiry(), "sub": "user-1" });
+
+        let verified = verifier.verify(&key.sign_jwt(&claims)).await.unwrap();
+        assert_eq!(verified.sub.as_deref(), Some("user-1"));
+        assert!(matches!(
+            verifier.verify(&hmac_token(claims)).await,
+            Err(TokenError::UnsupportedAlgorithm(Algorithm::HS256, _))
+        ));
+        let unknown = IssuerKey::from_seed("local-2", [4; 32]);
+        assert!(matches!(
+            verifier
+                .verify(&unknown.sign_jwt(&json!({"iss": "http://localhost:8000"})))
+                .await,
+            Err(TokenError::UnknownKey(..))
+        ));
+    }
+
+    #[test]
+    fn test_an_issuer_with_two_key_sources_is_rejected() {
+        let key = IssuerKey::from_seed("local-1", [3; 32]);
+        let issuers = vec![
+            TrustedIssuer::new("http://localhost:8000", KeySource::Jwks(jwks(&key))),
+            TrustedIssuer::new("http://localhost:8000/", KeySource::Secret(SECRET.to_vec())),
+        ];
+
+        assert!(matches!(
+            distinct_issuers(&issuers),
+            Err(TokenError::Configuration(_))
+        ));
+        assert!(distinct_issuers(&issuers[..1]).is_ok());
+    }
+
+    #[actix_web::test]
+    async fn test_an_audience_is_required_unless_skipped() {
+        let issuer =
+            || TrustedIssuer::new("http://localhost:8000", KeySource::Secret(SECRET.to_vec()));
+        assert!(matches!(
+            require_audiences(vec![issuer()], false),
+            Err(TokenError::Configuration(_))
+        ));
+        let skipped = require_audiences(vec![issuer()], true).unwrap();
+        assert!(skipped[0].any_audience);
+        let configured = require_audiences(
+            vec![issuer().with_audiences(vec!["rc-web".to_string()])],
+            false,
+        )
+        .unwrap();
+        assert!(!configured[0].any_audience);
+
+        let claims = json!({ "iss": "http://localhost:8000", "aud": "other", "exp": expiry() });
+        assert!(matches!(
+            TokenVerifier::new(vec![issuer()])
+                .verify(&hmac_token(claims.clone()))
+                .await,
+            Err(TokenError::NoAudience(_))
+        ));
+        assert!(matches!(
+            TokenVerifier::new(configured)
+                .verify(&hmac_token(claims.clone()))
+                .await,
+            Err(TokenError::Invalid(_))
+        ));
+        assert!(TokenVerifier::new(skipped)
+            .verify(&hmac_token(claims))
+            .await
+            .is_ok());
+    }
+
+    #[actix_web::test]
+    async fn test_discovered_keys_with_keycloak_roles() {
+        let key = IssuerKey::from_seed("kc-1", [5; 32]);
+        let issuer = oidc_stand_in(jwks(&key));
+        let verifier = TokenVerifier::new(vec![
+            TrustedIssuer::new(&issuer, KeySource::Discovery).with_any_audience()
+        ])
+        .with_mapping(ClaimMapping {
+            roles: "realm_access.roles".to_string(),
+            ..ClaimMapping::default()
+        });
+
+        let claims = verifier
+            .verify(&key.sign_jwt(&json!({
+                "iss": issuer,
+                "exp": expiry(),
+                "sub": "user-1",
+                "realm_access": { "roles": ["admin"] }
+            })))
+            .await
+            .unwrap();
+        assert_eq!(claims.principal().roles, vec!["admin"]);
+    }
+}
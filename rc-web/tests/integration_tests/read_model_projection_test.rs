@@ -44,7 +44,7 @@ fn create_test_def_cmd() -> CreateDefinitionCmd {
         title: "Student".to_string(),
         definitions: vec!["Student".to_string()],
         json_schema_string: STUDENT_SCHEMA_JSON.to_string(),
-        created_by: "test_user".to_string(),
+        created_by: "test_user".into(),
     }
 }
 
@@ -52,7 +52,7 @@ fn create_test_activate_cmd() -> ActivateDefinitionCmd {
     ActivateDefinitionCmd {
         id: generate_id_from_title("Student"),
         activated_at: Utc::now(),
-        activated_by: "test_user".to_string(),
+        activated_by: "test_user".into(),
     }
 }
 
@@ -61,7 +61,10 @@ fn create_test_entity_cmd() -> CreateEntityCmd {
         id: Uuid::now_v7(),
         entity_body: STUDENT_ENTITY_JSON.to_string(),
         entity_type: "Student".to_string(),
-        created_by: "test_user".to_string(),
+        created_by: "test_user".into(),
+        referenced_ids: vec![],
+        cipher: None,
+        signer: None,
     }
 }
 
@@ -138,6 +141,7 @@ async fn test_read_model_projection_entity_created_with_new_columns() -> anyhow:
             created_at,
             created_by,
             version,
+            ..
         } = event.deref()
         {
             entity_created_data = Some((
@@ -284,7 +284,7 @@ async fn test_column_type_mapping() {
         let attr = result
             .iter()
             .find(|a| a.attribute_name == attr_name)
-            .expect(&format!("Attribute '{}' should be present", attr_name));
+            .unwrap_or_else(|| panic!("Attribute '{}' should be present", attr_name));
 
         assert_eq!(
             attr.column_type, expected_type,
@@ -588,7 +588,8 @@ async fn test_generate_create_table_statement_from_student_schema() {
     // Check that some expected generated columns exist
     assert!(result.contains("student_identitydetails_fullname TEXT GENERATED ALWAYS AS"));
     assert!(result.contains("student_identitydetails_gender TEXT GENERATED ALWAYS AS"));
-    assert!(result.contains("student_identitydetails_dob TEXT GENERATED ALWAYS AS"));
+    // `dob` is listed in privateFields, it is stored encrypted and never projected
+    assert!(!result.contains("student_identitydetails_dob"));
     assert!(result.contains("student_contactdetails_email TEXT GENERATED ALWAYS AS"));
     assert!(result.contains("student_contactdetails_mobile TEXT GENERATED ALWAYS AS"));
     assert!(result.contains("student_contactdetails_address TEXT GENERATED ALWAYS AS"));